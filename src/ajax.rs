@@ -1,5 +1,7 @@
 //! Simple dynamic loading functionality without external dependencies
 
+use axum::http::HeaderMap;
+
 /// Generate inline JavaScript for dynamic content loading (replaces HTMX)
 pub fn dynamic_script() -> &'static str {
     r#"<script>
@@ -49,20 +51,14 @@ document.addEventListener('DOMContentLoaded', function() {
             directoryBody.setAttribute('data-current-path', currentPath);
         }
         const prefix = currentPath ? currentPath + '/' : '';
-        return fetch('/api/files')
+        return fetch('/api/tree?depth=1&path=' + encodeURIComponent(currentPath))
             .then(r => r.json())
             .then(data => {
                 if (!data || !Array.isArray(data.files)) return;
 
                 const items = data.files
-                    .filter(name => {
-                        if (currentPath) {
-                            if (!name.startsWith(prefix)) return false;
-                            return !name.slice(prefix.length).includes('/');
-                        }
-                        return !name.includes('/');
-                    })
-                    .map(name => {
+                    .map(file => {
+                        const name = file.name;
                         const displayName = currentPath ? name.slice(prefix.length) : name;
                         const shortName = displayName.split('/').pop() || displayName;
                         const href = escapeHtml(toViewHref(name));
@@ -97,57 +93,250 @@ document.addEventListener('DOMContentLoaded', function() {
             .catch(() => {});
     };
 
-    const handleUpload = (file) => {
-        if (!file) return;
-        const currentPath = uploadArea.dataset.currentPath || '';
-        const name = file.name.toLowerCase();
-        if (!(name.endsWith('.md') || name.endsWith('.markdown'))) {
-            setStatus(uploadArea.dataset.invalid || 'Invalid file type', 'error');
-            return;
+    // Files above this size go through the resumable tus endpoint in chunks
+    const RESUMABLE_THRESHOLD = 4 * 1024 * 1024;
+    const CHUNK_SIZE = 4 * 1024 * 1024;
+    const MAX_RETRIES = 3;
+
+    const encodeMetadata = (value) => btoa(unescape(encodeURIComponent(value)));
+
+    const readJson = async (response) => {
+        try {
+            return await response.json();
+        } catch (_) {
+            return {};
         }
+    };
 
-        uploadArea.classList.add('uploading');
-        setStatus(uploadArea.dataset.uploading || 'Uploading…', 'info');
+    const joinPath = (...parts) => parts.filter(Boolean).join('/');
+
+    const isMarkdown = (name) => {
+        const lowered = name.toLowerCase();
+        return lowered.endsWith('.md') || lowered.endsWith('.markdown');
+    };
+
+    const isArchive = (name) => name.toLowerCase().endsWith('.zip');
+
+    // Entries are { file, path } where path keeps the folders of a dropped directory
+    const postForm = (url, entries, currentPath, policy) => {
+        const form = new FormData();
+        entries.forEach(entry => form.append('file', entry.file, entry.path));
 
-        fetch('/api/upload', {
+        return fetch(url, {
             method: 'POST',
-            body: file,
+            body: form,
             headers: {
-                'X-File-Name': file.name,
-                'Content-Type': 'application/octet-stream',
-                'X-Directory-Path': currentPath
+                'X-Requested-With': 'XMLHttpRequest',
+                'X-Directory-Path': currentPath,
+                'X-Conflict-Policy': policy
             }
-        })
-            .then(async response => {
-                let data = {};
-                try {
-                    data = await response.json();
-                } catch (_) {
-                    data = {};
-                }
+        }).then(readJson);
+    };
+
+    const uploadBatch = (url, entries, currentPath, policy) =>
+        postForm(url, entries, currentPath, policy)
+            .then(data => entries.map((entry, index) => {
+                const result = (data.files && data.files[index]) || { success: false, message: data.message };
+                return { entry, ok: !!result.success, code: result.code, data: result };
+            }));
+
+    // The server unpacks archives and reports each entry; a taken name in any of
+    // them lets the whole archive be retried with overwrite
+    const uploadArchive = (entry, currentPath, policy) =>
+        postForm('/api/upload', [entry], currentPath, policy).then(data => {
+            const files = data.files || [];
+            const conflict = files.some(file => file.code === 'conflict');
+            return { entry, ok: !!data.success, code: conflict ? 'conflict' : data.code, data };
+        });
+
+    const uploadResumable = async (entry, currentPath, policy) => {
+        const file = entry.file;
+        const parts = entry.path.split('/');
+        const fileName = parts.pop();
+        const directory = joinPath(currentPath, ...parts);
+        const failed = (code) => ({ entry, ok: false, code, data: {} });
 
-                uploadArea.classList.remove('uploading');
+        const key = 'rsmd-upload:' + [directory, fileName, file.size, file.lastModified].join(':');
+        let location = localStorage.getItem(key);
+        let offset = 0;
 
-                if (response.ok && data && data.success) {
-                    setStatus(data.message || uploadArea.dataset.success || '', 'success');
-                    const uploadedFile = data.file;
-                    refreshFileList(uploadedFile);
-                } else {
-                    setStatus((data && data.message) || uploadArea.dataset.error || 'Upload failed', 'error');
+        if (location) {
+            const response = await fetch(location, { method: 'HEAD', headers: { 'Tus-Resumable': '1.0.0' } });
+            if (response.ok) {
+                offset = Number(response.headers.get('Upload-Offset')) || 0;
+            } else {
+                location = null;
+            }
+        }
+
+        if (!location) {
+            const metadata = ['filename ' + encodeMetadata(fileName), 'conflict ' + encodeMetadata(policy)];
+            if (directory) {
+                metadata.push('directory ' + encodeMetadata(directory));
+            }
+            const response = await fetch('/api/uploads', {
+                method: 'POST',
+                headers: {
+                    'Tus-Resumable': '1.0.0',
+                    'Upload-Length': String(file.size),
+                    'Upload-Metadata': metadata.join(',')
                 }
-            })
-            .catch(() => {
-                uploadArea.classList.remove('uploading');
-                setStatus(uploadArea.dataset.error || 'Upload failed', 'error');
             });
+            location = response.headers.get('Location');
+            if (response.status !== 201 || !location) {
+                return failed(response.status === 409 ? 'conflict' : null);
+            }
+            localStorage.setItem(key, location);
+        }
+
+        // A finished upload that conflicted is kept, and an empty PATCH commits
+        // it again with the policy of this attempt
+        let retries = 0;
+        let sent = false;
+        while (offset < file.size || !sent) {
+            try {
+                const response = await fetch(location, {
+                    method: 'PATCH',
+                    body: file.slice(offset, offset + CHUNK_SIZE),
+                    headers: {
+                        'Tus-Resumable': '1.0.0',
+                        'Upload-Offset': String(offset),
+                        'Content-Type': 'application/offset+octet-stream',
+                        'X-Conflict-Policy': policy
+                    }
+                });
+                // Without an offset a 409 means the finished file conflicts with its target
+                if (!response.headers.has('Upload-Offset') && (response.status === 409 || response.status === 412)) {
+                    return failed(response.status === 409 ? 'conflict' : 'stale');
+                }
+                if (response.status !== 204 && response.status !== 409) {
+                    throw new Error('upload failed');
+                }
+                sent = response.status === 204;
+                offset = Number(response.headers.get('Upload-Offset')) || offset;
+                retries = 0;
+                const percent = Math.floor((offset / file.size) * 100);
+                setStatus((uploadArea.dataset.uploading || 'Uploading…') + ' ' + percent + '%', 'info');
+            } catch (_) {
+                if (++retries > MAX_RETRIES) {
+                    return failed(null);
+                }
+                const response = await fetch(location, { method: 'HEAD', headers: { 'Tus-Resumable': '1.0.0' } });
+                if (!response.ok) {
+                    localStorage.removeItem(key);
+                    return failed(null);
+                }
+                offset = Number(response.headers.get('Upload-Offset')) || 0;
+            }
+        }
+
+        localStorage.removeItem(key);
+        return { entry, ok: true, data: { file: joinPath(directory, fileName) } };
     };
 
+    // Small documents share one multipart request and large ones are sent resumably
+    // one by one; everything else goes to the attachment endpoint
+    const uploadAll = async (entries, currentPath, policy) => {
+        const documents = entries.filter(entry => isMarkdown(entry.file.name));
+        const archives = entries.filter(entry => isArchive(entry.file.name));
+        const attachments = entries.filter(entry => !isMarkdown(entry.file.name) && !isArchive(entry.file.name));
+        const small = documents.filter(entry => entry.file.size <= RESUMABLE_THRESHOLD);
+        const large = documents.filter(entry => entry.file.size > RESUMABLE_THRESHOLD);
+        const results = small.length ? await uploadBatch('/api/upload', small, currentPath, policy) : [];
+        if (attachments.length) {
+            results.push(...await uploadBatch('/api/assets', attachments, currentPath, policy));
+        }
+        for (const entry of large) {
+            results.push(await uploadResumable(entry, currentPath, policy));
+        }
+        for (const entry of archives) {
+            results.push(await uploadArchive(entry, currentPath, policy));
+        }
+        return results;
+    };
+
+    // Paste-ready markdown for uploaded attachments
+    const showSnippets = (snippets) => {
+        const text = snippets.join('\n');
+        const label = uploadArea.dataset.snippetCopied || '';
+        const copied = navigator.clipboard ? navigator.clipboard.writeText(text) : Promise.reject();
+        copied.catch(() => {}).finally(() => setStatus(label + '\n' + text, 'success'));
+    };
+
+    const handleUpload = async (entries) => {
+        const currentPath = uploadArea.dataset.currentPath || '';
+        if (!entries.length) return;
+
+        uploadArea.classList.add('uploading');
+        setStatus(uploadArea.dataset.uploading || 'Uploading…', 'info');
+
+        try {
+            let results = await uploadAll(entries, currentPath, 'reject');
+
+            // Existing files are only replaced once the user confirms
+            const conflicts = results.filter(result => result.code === 'conflict');
+            if (conflicts.length && window.confirm(uploadArea.dataset.confirmOverwrite || 'Replace the existing file?')) {
+                const retried = await uploadAll(conflicts.map(result => result.entry), currentPath, 'overwrite');
+                results = results.map(result => retried.find(retry => retry.entry === result.entry) || result);
+            }
+
+            uploadArea.classList.remove('uploading');
+            const stored = results.filter(result => result.ok);
+            const failed = results.find(result => !result.ok);
+            const snippets = stored.map(result => result.data.snippet).filter(Boolean);
+            if (!failed && snippets.length) {
+                showSnippets(snippets);
+                refreshFileList(null);
+            } else if (!failed) {
+                setStatus(uploadArea.dataset.success || '', 'success');
+                refreshFileList(stored.length === 1 ? stored[0].data.file : null);
+            } else {
+                const message = stored.length
+                    ? uploadArea.dataset.partial
+                    : failed.data.message || uploadArea.dataset.error;
+                setStatus(message || 'Upload failed', 'error');
+                if (stored.length) {
+                    refreshFileList(null);
+                }
+            }
+        } catch (_) {
+            uploadArea.classList.remove('uploading');
+            setStatus(uploadArea.dataset.error || 'Upload failed', 'error');
+        }
+    };
+
+    const fromFileList = (files) => Array.from(files || []).map(file => ({
+        file,
+        path: file.webkitRelativePath || file.name
+    }));
+
+    // Walk a dropped folder, keeping each file's path relative to the drop
+    const readEntry = (entry, prefix) => new Promise(resolve => {
+        if (entry.isFile) {
+            entry.file(file => resolve([{ file, path: prefix + file.name }]), () => resolve([]));
+        } else if (entry.isDirectory) {
+            const reader = entry.createReader();
+            const children = [];
+            const readBatch = () => reader.readEntries(batch => {
+                if (!batch.length) {
+                    Promise.all(children.map(child => readEntry(child, prefix + entry.name + '/')))
+                        .then(lists => resolve(lists.flat()));
+                    return;
+                }
+                children.push(...batch);
+                readBatch();
+            }, () => resolve([]));
+            readBatch();
+        } else {
+            resolve([]);
+        }
+    });
+
     if (browseButton && fileInput) {
         browseButton.addEventListener('click', () => fileInput.click());
         fileInput.addEventListener('change', (event) => {
             const target = event.target;
-            const file = target.files && target.files[0];
-            handleUpload(file);
+            handleUpload(fromFileList(target.files));
             target.value = '';
         });
     }
@@ -167,8 +356,19 @@ document.addEventListener('DOMContentLoaded', function() {
     uploadArea.addEventListener('drop', (event) => {
         event.preventDefault();
         uploadArea.classList.remove('dragover');
-        const file = event.dataTransfer && event.dataTransfer.files && event.dataTransfer.files[0];
-        handleUpload(file);
+        const transfer = event.dataTransfer;
+        if (!transfer) return;
+
+        // Entries must be taken before the handler returns
+        const entries = Array.from(transfer.items || [])
+            .map(item => item.webkitGetAsEntry && item.webkitGetAsEntry())
+            .filter(Boolean);
+        if (!entries.length) {
+            handleUpload(fromFileList(transfer.files));
+            return;
+        }
+        Promise.all(entries.map(entry => readEntry(entry, '')))
+            .then(lists => handleUpload(lists.flat()));
     });
 
     document.addEventListener('click', (event) => {
@@ -186,6 +386,196 @@ document.addEventListener('DOMContentLoaded', function() {
 </script>"#
 }
 
+/// Generate inline JavaScript that loads collapsed sidebar folders on first expand
+pub fn navigation_script() -> &'static str {
+    r#"<script>
+document.addEventListener('DOMContentLoaded', function() {
+    const sidebar = document.querySelector('.nav-sidebar');
+    if (!sidebar) return;
+
+    const escapeHtml = (value) => {
+        if (value == null) return '';
+        return value
+            .replace(/&/g, '&amp;')
+            .replace(/</g, '&lt;')
+            .replace(/>/g, '&gt;')
+            .replace(/"/g, '&quot;')
+            .replace(/'/g, '&#x27;');
+    };
+
+    // Prefix of the version being browsed, such as `/v/v2.1`
+    const root = document.body.dataset.root || '';
+
+    const toViewHref = (name) =>
+        root + '/view/' + name.split('/').map(part => encodeURIComponent(part)).join('/');
+
+    const renderFolder = (folder) => `<li><details class="nav-folder" data-path="${escapeHtml(folder.path)}">
+<summary>📁 ${escapeHtml(folder.name)}</summary><ul class="nav-tree"></ul></details></li>`;
+
+    const renderFile = (file) => {
+        const shortName = file.name.split('/').pop() || file.name;
+        return `<li><a class="nav-file" href="${escapeHtml(toViewHref(file.name))}">📄 ${escapeHtml(shortName)}</a></li>`;
+    };
+
+    sidebar.addEventListener('toggle', (event) => {
+        const folder = event.target;
+        if (!(folder instanceof HTMLDetailsElement) || !folder.open) return;
+        if (!folder.classList.contains('nav-folder') || folder.dataset.loaded) return;
+        folder.dataset.loaded = 'true';
+
+        fetch(root + '/api/tree?depth=1&path=' + encodeURIComponent(folder.dataset.path || ''))
+            .then(r => r.json())
+            .then(data => {
+                const list = folder.querySelector('ul.nav-tree');
+                if (!list || !data) return;
+                list.innerHTML = (data.folders || []).map(renderFolder).join('')
+                    + (data.files || []).map(renderFile).join('');
+            })
+            .catch(() => {
+                delete folder.dataset.loaded;
+            });
+    }, true);
+});
+</script>"#
+}
+
+/// Generate inline JavaScript that fills the git panel of a document page from
+/// the log API
+pub fn git_log_script() -> &'static str {
+    r#"<script>
+document.addEventListener('DOMContentLoaded', function() {
+    const list = document.querySelector('.git-commits__list[data-log]');
+    if (!list) return;
+
+    const escapeHtml = (value) => {
+        if (value == null) return '';
+        return String(value)
+            .replace(/&/g, '&amp;')
+            .replace(/</g, '&lt;')
+            .replace(/>/g, '&gt;')
+            .replace(/"/g, '&quot;')
+            .replace(/'/g, '&#x27;');
+    };
+
+    const renderCommit = (path, commit) => {
+        const href = '/view/' + path.split('/').map(part => encodeURIComponent(part)).join('/')
+            + '?commit=' + encodeURIComponent(commit.id);
+        const date = new Date(commit.time * 1000).toUTCString();
+        const active = commit.id === list.dataset.active ? ' class="active"' : '';
+        return `<li${active}><a href="${escapeHtml(href)}">${escapeHtml(commit.summary)}</a>
+<span class="git-meta" title="${escapeHtml(commit.email)}">${escapeHtml(commit.author)} · <time>${escapeHtml(date)}</time></span></li>`;
+    };
+
+    fetch(list.dataset.log)
+        .then(r => r.ok ? r.json() : null)
+        .then(data => {
+            if (!data || !Array.isArray(data.commits)) return;
+            list.innerHTML = data.commits.length
+                ? data.commits.map(commit => renderCommit(data.path, commit)).join('')
+                : `<li class="git-commits__empty">${escapeHtml(list.dataset.empty)}</li>`;
+        })
+        .catch(() => {});
+});
+</script>"#
+}
+
+/// Generate inline JavaScript for the editor: live preview and saving
+pub fn editor_script() -> &'static str {
+    r#"<script>
+document.addEventListener('DOMContentLoaded', function() {
+    const form = document.getElementById('editor');
+    if (!form) return;
+
+    const textarea = form.querySelector('textarea');
+    const preview = document.getElementById('editor-preview');
+    const status = document.getElementById('editor-status');
+    const saveButton = form.querySelector('button[type="submit"]');
+    const url = '/api/markdown/' + form.dataset.name.split('/').map(part => encodeURIComponent(part)).join('/');
+    let etag = form.dataset.etag;
+    let saved = textarea.value;
+    let timer = null;
+    let latest = 0;
+
+    const isDirty = () => textarea.value !== saved;
+
+    const setStatus = (message, type) => {
+        status.textContent = message || '';
+        status.className = 'editor-status' + (type ? ' ' + type : '');
+    };
+
+    // Rendered by the server so the preview matches the document page
+    const renderPreview = () => {
+        const request = ++latest;
+        fetch('/api/render', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ markdown: textarea.value })
+        })
+            .then(response => response.ok ? response.json() : null)
+            .then(data => {
+                if (data && request === latest) {
+                    preview.innerHTML = data.html;
+                }
+            })
+            .catch(() => {});
+    };
+
+    const save = async () => {
+        const markdown = textarea.value;
+        saveButton.disabled = true;
+        setStatus(form.dataset.saving, 'info');
+        try {
+            // If-Match makes the server refuse to overwrite someone else's change
+            const response = await fetch(url, {
+                method: 'PUT',
+                headers: { 'Content-Type': 'application/json', 'If-Match': etag },
+                body: JSON.stringify({ markdown })
+            });
+            const data = await response.json().catch(() => ({}));
+            if (response.ok) {
+                etag = response.headers.get('ETag') || data.etag || etag;
+                saved = markdown;
+                setStatus(isDirty() ? form.dataset.unsaved : form.dataset.saved, 'success');
+            } else if (response.status === 412) {
+                setStatus(form.dataset.stale, 'error');
+            } else {
+                setStatus(data.message || form.dataset.error, 'error');
+            }
+        } catch (_) {
+            setStatus(form.dataset.error, 'error');
+        } finally {
+            saveButton.disabled = false;
+        }
+    };
+
+    textarea.addEventListener('input', () => {
+        setStatus(isDirty() ? form.dataset.unsaved : '', '');
+        clearTimeout(timer);
+        timer = setTimeout(renderPreview, 250);
+    });
+
+    form.addEventListener('submit', (event) => {
+        event.preventDefault();
+        save();
+    });
+
+    document.addEventListener('keydown', (event) => {
+        if ((event.ctrlKey || event.metaKey) && event.key === 's') {
+            event.preventDefault();
+            save();
+        }
+    });
+
+    window.addEventListener('beforeunload', (event) => {
+        if (isDirty()) {
+            event.preventDefault();
+            event.returnValue = '';
+        }
+    });
+});
+</script>"#
+}
+
 /// Render content-only HTML (for HTMX partial updates)
 pub fn render_partial_content(html_content: &str) -> String {
     html_content.to_string()
@@ -200,3 +590,24 @@ pub fn is_dynamic_request(hx_request_header: Option<&str>, xhr_header: Option<&s
     // Check for X-Requested-With header (vanilla JS fetch)
     xhr_header.is_some_and(|v| v == "XMLHttpRequest")
 }
+
+/// Check that a request changing files was sent by rsmd's own pages
+///
+/// Any site can make a browser post a form here, but only same-origin scripts
+/// may add `X-Requested-With`. Without it, `Sec-Fetch-Site` or `Origin` has to
+/// name this server; a request carrying none of the three is refused.
+pub fn is_same_origin(headers: &HeaderMap) -> bool {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    if header("x-requested-with").is_some() {
+        return true;
+    }
+    if let Some(site) = header("sec-fetch-site") {
+        return site == "same-origin" || site == "none";
+    }
+    match (header("origin"), header("host")) {
+        (Some(origin), Some(host)) => origin
+            .split_once("://")
+            .is_some_and(|(_, authority)| authority.eq_ignore_ascii_case(host)),
+        _ => false,
+    }
+}
@@ -0,0 +1,144 @@
+//! Reading uploaded zip archives without trusting them
+//!
+//! Archives are read entry by entry with every size taken from the bytes actually
+//! inflated rather than the headers, so a zip bomb stops at [`MAX_ENTRIES`],
+//! [`MAX_UNCOMPRESSED_SIZE`] or [`MAX_COMPRESSION_RATIO`]. Entry names are only
+//! screened for absolute paths and links here; the caller still validates them
+//! like any other uploaded path before writing anything.
+
+use std::io::{Read, Seek};
+use zip::ZipArchive;
+use zip::result::ZipError;
+
+/// Most entries, directories included, an archive may contain
+pub const MAX_ENTRIES: usize = 1000;
+
+/// Most bytes all entries of an archive may inflate to
+pub const MAX_UNCOMPRESSED_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Largest ratio of inflated to compressed size accepted for an entry
+pub const MAX_COMPRESSION_RATIO: u64 = 100;
+
+/// Entries inflating to at most this many bytes are exempt from the ratio check,
+/// since small text files often compress that well
+const RATIO_EXEMPT_SIZE: u64 = 64 * 1024;
+
+/// Why an archive or one of its entries was refused
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveError {
+    /// Not a readable zip archive
+    Invalid,
+    /// More than [`MAX_ENTRIES`] entries
+    TooManyEntries,
+    /// The entry is larger than allowed, or the archive passed
+    /// [`MAX_UNCOMPRESSED_SIZE`]
+    TooLarge,
+    /// The entry inflates beyond [`MAX_COMPRESSION_RATIO`]
+    CompressionRatio,
+    /// An absolute path, a symbolic link or a name escaping the archive
+    UnsafePath,
+    /// Encrypted, or compressed with an unsupported method
+    Unsupported,
+    /// The caller does not accept files of this name
+    Skipped,
+}
+
+/// A file entry of an archive
+#[derive(Debug)]
+pub struct Entry {
+    /// Path inside the archive, always `/`-separated
+    pub name: String,
+    pub contents: Result<Vec<u8>, ArchiveError>,
+}
+
+/// Read every file entry of `reader` in order, passing each to `visit`
+///
+/// `max_size` gives the largest accepted size for an entry name, or `None` to
+/// skip it unread. Directories are not reported. Reading stops early when
+/// `visit` returns `false` or the archive passes [`MAX_UNCOMPRESSED_SIZE`]; the
+/// remaining entries are then not reported either.
+pub fn read_entries<R: Read + Seek>(
+    reader: R,
+    max_size: impl Fn(&str) -> Option<u64>,
+    mut visit: impl FnMut(Entry) -> bool,
+) -> Result<(), ArchiveError> {
+    let mut archive = ZipArchive::new(reader).map_err(|_| ArchiveError::Invalid)?;
+    if archive.len() > MAX_ENTRIES {
+        return Err(ArchiveError::TooManyEntries);
+    }
+
+    let mut inflated = 0;
+    for index in 0..archive.len() {
+        let name = archive
+            .name_for_index(index)
+            .unwrap_or_default()
+            .replace('\\', "/");
+        let mut file = match archive.by_index(index) {
+            Ok(file) => file,
+            Err(ZipError::UnsupportedArchive(_)) => {
+                if !visit(Entry {
+                    name,
+                    contents: Err(ArchiveError::Unsupported),
+                }) {
+                    break;
+                }
+                continue;
+            }
+            Err(_) => return Err(ArchiveError::Invalid),
+        };
+        if file.is_dir() {
+            continue;
+        }
+
+        let contents = if name.starts_with('/')
+            || name.contains(':')
+            || file.is_symlink()
+            || file.enclosed_name().is_none()
+        {
+            Err(ArchiveError::UnsafePath)
+        } else {
+            match max_size(&name) {
+                None => Err(ArchiveError::Skipped),
+                Some(limit) => {
+                    let ratio_limit = file
+                        .compressed_size()
+                        .saturating_mul(MAX_COMPRESSION_RATIO)
+                        .max(RATIO_EXEMPT_SIZE);
+                    let remaining = MAX_UNCOMPRESSED_SIZE - inflated;
+                    match inflate(&mut file, limit.min(ratio_limit).min(remaining)) {
+                        Ok(contents) => {
+                            inflated += contents.len() as u64;
+                            Ok(contents)
+                        }
+                        Err(ArchiveError::TooLarge) if remaining < limit.min(ratio_limit) => {
+                            visit(Entry {
+                                name,
+                                contents: Err(ArchiveError::TooLarge),
+                            });
+                            return Ok(());
+                        }
+                        Err(ArchiveError::TooLarge) if ratio_limit < limit => {
+                            Err(ArchiveError::CompressionRatio)
+                        }
+                        Err(err) => Err(err),
+                    }
+                }
+            }
+        };
+        if !visit(Entry { name, contents }) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Inflate `file`, failing once it passes `limit` bytes
+fn inflate(file: &mut impl Read, limit: u64) -> Result<Vec<u8>, ArchiveError> {
+    let mut contents = Vec::new();
+    match file.take(limit + 1).read_to_end(&mut contents) {
+        Ok(_) if contents.len() as u64 > limit => Err(ArchiveError::TooLarge),
+        Ok(_) => Ok(contents),
+        // Corrupt data or a CRC mismatch
+        Err(_) => Err(ArchiveError::Invalid),
+    }
+}
@@ -0,0 +1,120 @@
+//! Frontend assets compiled into the binary
+//!
+//! Assets are served under [`ASSET_PREFIX`], away from `/static` which holds the
+//! user's own document assets. Each asset is reachable by its plain name and by a
+//! fingerprinted name containing a hash of its contents; only the latter is cached
+//! indefinitely.
+
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+use crate::conditional;
+
+/// Route prefix for bundled assets
+pub const ASSET_PREFIX: &str = "/_rsmd/assets";
+
+/// Route serving the client-side app shell
+pub const APP_SHELL_PATH: &str = "/_rsmd/app";
+
+/// Cache policy for fingerprinted URLs
+pub const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// Cache policy for plain asset names and the app shell
+pub const REVALIDATE_CACHE_CONTROL: &str = "no-cache";
+
+const BUNDLED: &[(&str, &[u8])] = &[
+    ("app.js", include_bytes!("../static/app.js")),
+    ("style.css", include_bytes!("../static/style.css")),
+];
+
+const APP_SHELL: &str = include_str!("../static/index.html");
+
+/// A bundled frontend file
+#[derive(Debug)]
+pub struct Asset {
+    pub name: &'static str,
+    pub contents: &'static [u8],
+    pub content_type: &'static str,
+    /// `name` with a content hash before the extension, e.g. `app.1a2b3c4d5e6f.js`
+    pub fingerprinted_name: String,
+}
+
+impl Asset {
+    fn new(name: &'static str, contents: &'static [u8]) -> Self {
+        let digest = Sha256::digest(contents);
+        let hash = conditional::hex(&digest[..6]);
+        let fingerprinted_name = match name.rsplit_once('.') {
+            Some((stem, ext)) => format!("{}.{}.{}", stem, hash, ext),
+            None => format!("{}.{}", name, hash),
+        };
+
+        Self {
+            name,
+            contents,
+            content_type: content_type(name),
+            fingerprinted_name,
+        }
+    }
+
+    /// Cache-busting URL for this asset
+    pub fn url(&self) -> String {
+        format!("{}/{}", ASSET_PREFIX, self.fingerprinted_name)
+    }
+}
+
+/// All bundled assets
+pub fn all() -> &'static [Asset] {
+    static ASSETS: OnceLock<Vec<Asset>> = OnceLock::new();
+    ASSETS.get_or_init(|| {
+        BUNDLED
+            .iter()
+            .map(|(name, contents)| Asset::new(name, contents))
+            .collect()
+    })
+}
+
+/// Look up an asset by plain or fingerprinted name
+///
+/// The flag is `true` when the fingerprinted name was used, meaning the response
+/// may be cached forever.
+pub fn find(name: &str) -> Option<(&'static Asset, bool)> {
+    all().iter().find_map(|asset| {
+        if asset.fingerprinted_name == name {
+            Some((asset, true))
+        } else if asset.name == name {
+            Some((asset, false))
+        } else {
+            None
+        }
+    })
+}
+
+/// Fingerprinted URL for the bundled asset `name`
+pub fn url(name: &str) -> Option<String> {
+    find(name).map(|(asset, _)| asset.url())
+}
+
+/// The app shell with its asset links pointing at fingerprinted URLs
+pub fn app_shell() -> &'static str {
+    static SHELL: OnceLock<String> = OnceLock::new();
+    SHELL.get_or_init(|| {
+        all().iter().fold(APP_SHELL.to_string(), |html, asset| {
+            html.replace(
+                &format!("\"{}/{}\"", ASSET_PREFIX, asset.name),
+                &format!("\"{}\"", asset.url()),
+            )
+        })
+    })
+}
+
+fn content_type(name: &str) -> &'static str {
+    match name.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("html") => "text/html; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,146 @@
+//! Images and other files that can be uploaded next to documents
+//!
+//! A file is only accepted when its extension names one of [`TYPES`] and its
+//! first bytes carry that type's signature, so a renamed executable or HTML page
+//! is rejected whatever it is called.
+
+use crate::html;
+
+/// An accepted attachment format
+#[derive(Debug)]
+pub struct AttachmentType {
+    pub mime: &'static str,
+    pub extensions: &'static [&'static str],
+    /// Embedded with `![…](…)` rather than linked
+    pub image: bool,
+    signature: fn(&[u8]) -> bool,
+}
+
+/// Bytes needed by [`sniff`] to recognize every type
+pub const SNIFF_LEN: usize = 16;
+
+pub const TYPES: &[AttachmentType] = &[
+    AttachmentType {
+        mime: "image/png",
+        extensions: &["png"],
+        image: true,
+        signature: |head| head.starts_with(b"\x89PNG\r\n\x1a\n"),
+    },
+    AttachmentType {
+        mime: "image/jpeg",
+        extensions: &["jpg", "jpeg"],
+        image: true,
+        signature: |head| head.starts_with(b"\xff\xd8\xff"),
+    },
+    AttachmentType {
+        mime: "image/gif",
+        extensions: &["gif"],
+        image: true,
+        signature: |head| head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a"),
+    },
+    AttachmentType {
+        mime: "image/webp",
+        extensions: &["webp"],
+        image: true,
+        signature: |head| riff(head, b"WEBP"),
+    },
+    AttachmentType {
+        mime: "image/avif",
+        extensions: &["avif"],
+        image: true,
+        signature: |head| ftyp(head, &[b"avif", b"avis"]),
+    },
+    AttachmentType {
+        mime: "image/bmp",
+        extensions: &["bmp"],
+        image: true,
+        signature: |head| head.starts_with(b"BM"),
+    },
+    AttachmentType {
+        mime: "image/x-icon",
+        extensions: &["ico"],
+        image: true,
+        signature: |head| head.starts_with(b"\x00\x00\x01\x00"),
+    },
+    AttachmentType {
+        mime: "application/pdf",
+        extensions: &["pdf"],
+        image: false,
+        signature: |head| head.starts_with(b"%PDF-"),
+    },
+    AttachmentType {
+        mime: "video/mp4",
+        extensions: &["mp4", "m4v"],
+        image: false,
+        signature: |head| {
+            ftyp(
+                head,
+                &[b"isom", b"iso2", b"mp41", b"mp42", b"avc1", b"M4V "],
+            )
+        },
+    },
+    AttachmentType {
+        mime: "video/webm",
+        extensions: &["webm"],
+        image: false,
+        signature: |head| head.starts_with(b"\x1a\x45\xdf\xa3"),
+    },
+    AttachmentType {
+        mime: "audio/mpeg",
+        extensions: &["mp3"],
+        image: false,
+        signature: |head| {
+            head.starts_with(b"ID3")
+                || (head.len() > 1 && head[0] == 0xff && head[1] & 0xe0 == 0xe0)
+        },
+    },
+    AttachmentType {
+        mime: "audio/ogg",
+        extensions: &["ogg", "oga"],
+        image: false,
+        signature: |head| head.starts_with(b"OggS"),
+    },
+    AttachmentType {
+        mime: "audio/wav",
+        extensions: &["wav"],
+        image: false,
+        signature: |head| riff(head, b"WAVE"),
+    },
+];
+
+fn riff(head: &[u8], form: &[u8; 4]) -> bool {
+    head.len() >= 12 && head.starts_with(b"RIFF") && &head[8..12] == form
+}
+
+fn ftyp(head: &[u8], brands: &[&[u8; 4]]) -> bool {
+    head.len() >= 12 && &head[4..8] == b"ftyp" && brands.iter().any(|brand| &head[8..12] == *brand)
+}
+
+/// The type a file name claims by its extension
+pub fn for_name(name: &str) -> Option<&'static AttachmentType> {
+    let (_, extension) = name.rsplit_once('.')?;
+    let extension = extension.to_ascii_lowercase();
+    TYPES
+        .iter()
+        .find(|kind| kind.extensions.contains(&extension.as_str()))
+}
+
+/// The type whose signature starts `head`
+pub fn sniff(head: &[u8]) -> Option<&'static AttachmentType> {
+    TYPES.iter().find(|kind| (kind.signature)(head))
+}
+
+/// Markdown that embeds or links the attachment at relative `link`
+pub fn snippet(link: &str) -> String {
+    let file_name = link.rsplit('/').next().unwrap_or(link);
+    let url = html::encode_path(link);
+    match for_name(file_name) {
+        Some(kind) if kind.image => {
+            let alt = file_name
+                .rsplit_once('.')
+                .map_or(file_name, |(stem, _)| stem);
+            format!("![{}]({})", alt, url)
+        }
+        _ => format!("[{}]({})", file_name, url),
+    }
+}
@@ -0,0 +1,165 @@
+//! Command line argument parsing
+
+use std::path::PathBuf;
+use tower_http::CompressionLevel;
+
+use crate::git::Identity;
+
+/// Options given on the command line
+#[derive(Debug, Default, PartialEq)]
+pub struct CliOptions {
+    /// Markdown file or directory to serve
+    pub path: String,
+    /// Stylesheet injected into every page
+    pub theme_css: Option<PathBuf>,
+    /// Directory of templates overriding the built-in ones
+    pub templates: Option<PathBuf>,
+    /// Memory budget of the render cache in MiB
+    pub cache_size: Option<usize>,
+    /// `Cache-Control` value sent with documents
+    pub cache_control: Option<String>,
+    /// Quality of compressed responses
+    pub compression_level: Option<CompressionLevel>,
+    /// Revisions kept per document; `0` turns the history off
+    ///
+    /// Defaults to [`DEFAULT_MAX_REVISIONS`](crate::history::DEFAULT_MAX_REVISIONS).
+    pub history_revisions: Option<usize>,
+    /// Days a revision is kept
+    pub history_days: Option<u64>,
+    /// Commit changes to the git work tree the directory is in
+    pub git_commit: bool,
+    /// Author of those commits
+    pub git_author: Option<Identity>,
+    /// Header naming the user a request's commits are authored by, set or
+    /// stripped by a proxy in front of rsmd
+    pub git_user_header: Option<String>,
+    /// Branch, tag or commit served read-only instead of the files on disk
+    pub git_ref: Option<String>,
+}
+
+impl CliOptions {
+    /// Parse arguments, excluding the program name
+    ///
+    /// Options accept both `--name value` and `--name=value`; flags take no
+    /// value.
+    pub fn parse<I>(args: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut path = None;
+        let mut theme_css = None;
+        let mut templates = None;
+        let mut cache_size = None;
+        let mut cache_control = None;
+        let mut compression_level = None;
+        let mut history_revisions = None;
+        let mut history_days = None;
+        let mut git_commit = false;
+        let mut git_author = None;
+        let mut git_user_header = None;
+        let mut git_ref = None;
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value)),
+                _ => (arg.clone(), None),
+            };
+
+            let mut value = |option: &str, kind: &str| match inline_value {
+                Some(value) => Ok(value.to_string()),
+                None => args
+                    .next()
+                    .ok_or_else(|| format!("{} requires {}", option, kind)),
+            };
+
+            match name.as_str() {
+                "--theme-css" => theme_css = Some(value("--theme-css", "a path")?.into()),
+                "--templates" => templates = Some(value("--templates", "a path")?.into()),
+                "--cache-control" => cache_control = Some(value("--cache-control", "a value")?),
+                "--compression-level" => {
+                    let level = value("--compression-level", "a level")?;
+                    compression_level = Some(parse_compression_level(&level)?);
+                }
+                "--cache-size" => {
+                    let size = value("--cache-size", "a size in MiB")?;
+                    cache_size = Some(
+                        size.parse()
+                            .map_err(|_| format!("Invalid cache size: {}", size))?,
+                    );
+                }
+                "--history-revisions" => {
+                    let count = value("--history-revisions", "a number")?;
+                    history_revisions = Some(
+                        count
+                            .parse()
+                            .map_err(|_| format!("Invalid revision count: {}", count))?,
+                    );
+                }
+                "--history-days" => {
+                    let days = value("--history-days", "a number of days")?;
+                    history_days = Some(
+                        days.parse()
+                            .map_err(|_| format!("Invalid number of days: {}", days))?,
+                    );
+                }
+                "--git-commit" if inline_value.is_none() => git_commit = true,
+                "--git-author" => {
+                    let author = value("--git-author", "\"Name <email>\"")?;
+                    git_author = Some(
+                        Identity::parse(&author)
+                            .ok_or_else(|| format!("Invalid git author: {}", author))?,
+                    );
+                }
+                "--git-user-header" => {
+                    git_user_header = Some(value("--git-user-header", "a header name")?)
+                }
+                "--git-ref" => git_ref = Some(value("--git-ref", "a branch, tag or commit")?),
+                option if option.starts_with("--") => {
+                    return Err(format!("Unknown option: {}", option));
+                }
+                _ if path.is_none() => path = Some(arg),
+                _ => return Err(format!("Unexpected argument: {}", arg)),
+            }
+        }
+
+        if git_commit && git_ref.is_some() {
+            return Err("--git-commit cannot be combined with --git-ref".to_string());
+        }
+
+        Ok(Self {
+            path: path.ok_or_else(|| "Missing markdown file or directory".to_string())?,
+            theme_css,
+            templates,
+            cache_size,
+            cache_control,
+            compression_level,
+            history_revisions,
+            history_days,
+            git_commit,
+            git_author,
+            git_user_header,
+            git_ref,
+        })
+    }
+
+    pub fn usage(program: &str) -> String {
+        format!(
+            "Usage: {} <markdown-file.md|directory|archive.zip|archive.tar> [--theme-css <file.css>] [--templates <dir>] [--cache-size <MiB>] [--cache-control <value>] [--compression-level <fastest|default|best|N>] [--history-revisions <N>] [--history-days <N>] [--git-commit] [--git-author <\"Name <email>\">] [--git-user-header <header>] [--git-ref <ref>]",
+            program
+        )
+    }
+}
+
+/// `fastest`, `default`, `best` or an algorithm-specific number
+fn parse_compression_level(level: &str) -> Result<CompressionLevel, String> {
+    match level {
+        "fastest" => Ok(CompressionLevel::Fastest),
+        "default" => Ok(CompressionLevel::Default),
+        "best" => Ok(CompressionLevel::Best),
+        _ => level
+            .parse()
+            .map(CompressionLevel::Precise)
+            .map_err(|_| format!("Invalid compression level: {}", level)),
+    }
+}
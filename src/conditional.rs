@@ -0,0 +1,240 @@
+//! Conditional GET for document responses
+//!
+//! Handlers mark a response as a document by attaching a [`DocumentVersion`]
+//! extension. [`validate`] then adds a strong `ETag` computed from the response
+//! body (or the file, see [`DocumentVersion::digest`]), `Last-Modified` from the
+//! file's modification time and the configured `Cache-Control`, and answers
+//! `If-None-Match` / `If-Modified-Since` with `304 Not Modified`.
+//!
+//! Only bodies that depend on nothing but the file get a modification time;
+//! document pages also show the sidebar, git history and the theme, so they are
+//! validated by their `ETag` alone and vary by the theme cookie. Bodies over
+//! [`MAX_TAGGED_SIZE`] are streamed without an `ETag` rather than buffered.
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::Request,
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::{StreamExt, stream};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::server::ServerConfig;
+
+/// Content codings whose responses get their own entity tag
+const ENCODINGS: &[&str] = &["gzip", "br", "zstd", "deflate"];
+
+/// Cache policy for documents unless configured otherwise: always revalidate
+pub const DEFAULT_DOCUMENT_CACHE_CONTROL: &str = "no-cache";
+
+/// Largest body buffered to compute its `ETag`
+pub const MAX_TAGGED_SIZE: usize = 8 * 1024 * 1024;
+
+/// Marks a response as a document that supports conditional requests
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DocumentVersion {
+    /// Last modification time in seconds since the Unix epoch, if known
+    pub modified: Option<u64>,
+    /// SHA-256 of the file, when the entity tag should be the file's own rather
+    /// than the body's, so it can be sent back as `If-Match` when saving
+    pub digest: Option<[u8; 32]>,
+    /// Whether the body is a page styled by the theme cookie, which is sent
+    /// with `Vary: Cookie`
+    pub themed: bool,
+}
+
+/// Strong entity tag for `body`
+pub fn etag(body: &[u8]) -> String {
+    digest_etag(&Sha256::digest(body))
+}
+
+/// The [`etag`] of a body whose SHA-256 `digest` is already known
+pub fn digest_etag(digest: &[u8]) -> String {
+    format!("\"{}\"", hex(&digest[..16]))
+}
+
+/// Lowercase hexadecimal digits of `bytes`
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Whether an `If-Match` header value is satisfied by the `current` tag
+///
+/// Uses strong comparison, so weak tags never match. `*` matches any existing
+/// resource. The tag of a compressed variant (`"…-gzip"`) matches as well, since
+/// browsers only expose the tag of the response they decoded.
+pub fn if_match(header: &str, current: Option<&str>) -> bool {
+    let Some(current) = current else {
+        return false;
+    };
+    header.split(',').map(str::trim).any(|candidate| {
+        candidate == "*"
+            || (!candidate.starts_with("W/") && opaque_tag(candidate) == opaque_tag(current))
+    })
+}
+
+/// Middleware adding validators to document responses and answering with 304
+pub async fn validate(request: Request, next: Next) -> Response {
+    let is_get = matches!(*request.method(), Method::GET | Method::HEAD);
+    let request_headers = request.headers().clone();
+    let cache_control = request
+        .extensions()
+        .get::<Arc<ServerConfig>>()
+        .map(|config| config.document_cache_control.clone());
+    let response = next.run(request).await;
+
+    let Some(version) = response.extensions().get::<DocumentVersion>().copied() else {
+        return response;
+    };
+    if !is_get || response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let (tag, body) = match version.digest {
+        Some(digest) => (Some(digest_etag(&digest)), body),
+        None => match buffer(body, MAX_TAGGED_SIZE).await {
+            Ok(Buffered::Complete(bytes)) => (Some(etag(&bytes)), Body::from(bytes)),
+            Ok(Buffered::TooLarge(body)) => (None, body),
+            Err(err) => {
+                tracing::error!(error = %err, "Failed to buffer document response");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        },
+    };
+    let modified = version
+        .modified
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+    let headers = &mut parts.headers;
+    if let Some(value) = tag
+        .as_deref()
+        .and_then(|tag| HeaderValue::from_str(tag).ok())
+    {
+        headers.insert(header::ETAG, value);
+    }
+    if let Some(modified) = modified
+        && let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(modified))
+    {
+        headers.insert(header::LAST_MODIFIED, value);
+    }
+    let cache_control = cache_control
+        .as_deref()
+        .unwrap_or(DEFAULT_DOCUMENT_CACHE_CONTROL);
+    if let Ok(value) = HeaderValue::from_str(cache_control) {
+        headers.insert(header::CACHE_CONTROL, value);
+    }
+    if version.themed {
+        headers.append(header::VARY, HeaderValue::from_static("cookie"));
+    }
+
+    if let Some(matched) = not_modified(&request_headers, tag.as_deref(), modified) {
+        // Echo the tag of the variant the client holds, which may be a compressed one
+        if let Some(value) = matched.and_then(|matched| HeaderValue::from_str(matched).ok()) {
+            parts.headers.insert(header::ETAG, value);
+        }
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(header::CONTENT_TYPE);
+        parts.headers.remove(header::CONTENT_LENGTH);
+        return Response::from_parts(parts, Body::empty());
+    }
+
+    Response::from_parts(parts, body)
+}
+
+/// A response body read into memory, unless it was too large
+enum Buffered {
+    Complete(Bytes),
+    /// The whole body, still streaming from where buffering stopped
+    TooLarge(Body),
+}
+
+/// Read `body` into memory if it is at most `limit` bytes
+async fn buffer(body: Body, limit: usize) -> Result<Buffered, axum::Error> {
+    if body.size_hint().lower() > limit as u64 {
+        return Ok(Buffered::TooLarge(body));
+    }
+    let mut data = body.into_data_stream();
+    let mut chunks = Vec::new();
+    let mut size = 0;
+    while let Some(chunk) = data.next().await {
+        let chunk = chunk?;
+        size += chunk.len();
+        chunks.push(chunk);
+        if size > limit {
+            let read = stream::iter(chunks.into_iter().map(Ok));
+            return Ok(Buffered::TooLarge(Body::from_stream(read.chain(data))));
+        }
+    }
+    Ok(Buffered::Complete(match chunks.len() {
+        1 => chunks.swap_remove(0),
+        _ => chunks.concat().into(),
+    }))
+}
+
+/// Give compressed responses their own entity tag, e.g. `"1a2b…-gzip"`
+///
+/// Runs outside the compression layer; [`validate`] strips the suffix again when
+/// comparing `If-None-Match`.
+pub async fn tag_encoding(mut response: Response) -> Response {
+    let Some(encoding) = response
+        .headers()
+        .get(header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .filter(|encoding| ENCODINGS.contains(encoding))
+    else {
+        return response;
+    };
+    let Some(tag) = response
+        .headers()
+        .get(header::ETAG)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|tag| tag.strip_suffix('"'))
+    else {
+        return response;
+    };
+
+    if let Ok(value) = HeaderValue::from_str(&format!("{}-{}\"", tag, encoding)) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    response
+}
+
+/// Opaque part of an entity tag, without weak prefix, quotes or content coding
+fn opaque_tag(tag: &str) -> &str {
+    let tag = tag.strip_prefix("W/").unwrap_or(tag).trim_matches('"');
+    ENCODINGS
+        .iter()
+        .find_map(|encoding| tag.strip_suffix(encoding)?.strip_suffix('-'))
+        .unwrap_or(tag)
+}
+
+/// Whether the client's cached copy is still current
+///
+/// Returns the matching `If-None-Match` tag when that header decided it.
+/// `If-Modified-Since` is only consulted when there is no `If-None-Match`.
+fn not_modified<'a>(
+    headers: &'a HeaderMap,
+    tag: Option<&str>,
+    modified: Option<SystemTime>,
+) -> Option<Option<&'a str>> {
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        let tag = tag?;
+        return if_none_match
+            .to_str()
+            .ok()?
+            .split(',')
+            .map(str::trim)
+            .find(|candidate| *candidate == "*" || opaque_tag(candidate) == opaque_tag(tag))
+            .map(|candidate| (candidate != "*").then_some(candidate));
+    }
+
+    let since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| httpdate::parse_http_date(value).ok())?;
+    (modified? <= since).then_some(None)
+}
@@ -0,0 +1,205 @@
+//! Line diffs between two versions of a text
+//!
+//! Used by the revision history to compare versions of a document, by git blame
+//! to carry lines over from a parent commit, and by moves to show the links a
+//! dry run would rewrite as a [`unified`] diff.
+
+use serde::{Deserialize, Serialize};
+
+/// Most changed lines [`line_diff`] aligns before showing a plain replacement
+const MAX_DIFF_EDITS: usize = 1000;
+
+/// Unchanged lines shown around each change by [`hunks`]
+const CONTEXT_LINES: usize = 3;
+
+/// Whether a line was kept, removed or added
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Same,
+    Removed,
+    Added,
+}
+
+/// A line of a [`line_diff`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffLine {
+    pub change: Change,
+    /// 1-based line number in the old text, unless added
+    pub old: Option<usize>,
+    /// 1-based line number in the new text, unless removed
+    pub new: Option<usize>,
+    pub text: String,
+}
+
+/// Line-by-line difference between `old` and `new`
+///
+/// Uses Myers' algorithm after trimming the common start and end. Texts with
+/// more than [`MAX_DIFF_EDITS`] changed lines are shown as removing every
+/// differing line and adding the new ones.
+pub fn line_diff(old: &str, new: &str) -> Vec<DiffLine> {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut changes = vec![Change::Same; prefix];
+    changes.extend(myers(a_mid, b_mid).unwrap_or_else(|| {
+        let mut replaced = vec![Change::Removed; a_mid.len()];
+        replaced.extend(vec![Change::Added; b_mid.len()]);
+        replaced
+    }));
+    changes.extend(vec![Change::Same; suffix]);
+
+    let (mut i, mut j) = (0, 0);
+    changes
+        .into_iter()
+        .map(|change| {
+            let line = match change {
+                Change::Same => DiffLine {
+                    change,
+                    old: Some(i + 1),
+                    new: Some(j + 1),
+                    text: a[i].to_string(),
+                },
+                Change::Removed => DiffLine {
+                    change,
+                    old: Some(i + 1),
+                    new: None,
+                    text: a[i].to_string(),
+                },
+                Change::Added => DiffLine {
+                    change,
+                    old: None,
+                    new: Some(j + 1),
+                    text: b[j].to_string(),
+                },
+            };
+            if change != Change::Added {
+                i += 1;
+            }
+            if change != Change::Removed {
+                j += 1;
+            }
+            line
+        })
+        .collect()
+}
+
+/// Shortest edit script from `a` to `b`, or `None` past [`MAX_DIFF_EDITS`]
+fn myers(a: &[&str], b: &[&str]) -> Option<Vec<Change>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (a.len() + b.len()).min(MAX_DIFF_EDITS) as isize;
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    let mut trace = Vec::new();
+
+    let mut found = None;
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let index = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                v[index + 1]
+            } else {
+                v[index - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index] = x;
+            if x >= n && y >= m {
+                found = Some(d);
+                break 'search;
+            }
+        }
+    }
+    let depth = found?;
+
+    // Walk back through the saved frontiers
+    let mut changes = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..=depth).rev() {
+        let v = &trace[d as usize];
+        let k = x - y;
+        let previous_k =
+            if k == -d || (k != d && v[(k - 1 + offset) as usize] < v[(k + 1 + offset) as usize]) {
+                k + 1
+            } else {
+                k - 1
+            };
+        let previous_x = v[(previous_k + offset) as usize];
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            changes.push(Change::Same);
+            x -= 1;
+            y -= 1;
+        }
+        changes.push(if x == previous_x {
+            Change::Added
+        } else {
+            Change::Removed
+        });
+        x = previous_x;
+        y = previous_y;
+    }
+    changes.extend(std::iter::repeat_n(Change::Same, x as usize));
+    changes.reverse();
+    Some(changes)
+}
+
+/// Groups of changed lines with [`CONTEXT_LINES`] of unchanged ones around
+/// them; an empty result means the texts are the same
+pub fn hunks(diff: &[DiffLine]) -> Vec<Vec<DiffLine>> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (index, _) in diff
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.change != Change::Same)
+    {
+        let start = index.saturating_sub(CONTEXT_LINES);
+        let stop = (index + CONTEXT_LINES + 1).min(diff.len());
+        match ranges.last_mut() {
+            Some((_, last_stop)) if start <= *last_stop => *last_stop = stop,
+            _ => ranges.push((start, stop)),
+        }
+    }
+    ranges
+        .into_iter()
+        .map(|(start, stop)| diff[start..stop].to_vec())
+        .collect()
+}
+
+/// `old` and `new` versions of the file `name` as a unified diff, with the
+/// [`hunks`] of their [`line_diff`]
+pub fn unified(name: &str, old: &str, new: &str) -> String {
+    let mut out = format!("--- a/{}\n+++ b/{}\n", name, name);
+    for hunk in hunks(&line_diff(old, new)) {
+        let old_lines = hunk.iter().filter(|line| line.old.is_some()).count();
+        let new_lines = hunk.iter().filter(|line| line.new.is_some()).count();
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            hunk.iter().find_map(|line| line.old).unwrap_or(0),
+            old_lines,
+            hunk.iter().find_map(|line| line.new).unwrap_or(0),
+            new_lines
+        ));
+        for line in hunk {
+            let marker = match line.change {
+                Change::Same => ' ',
+                Change::Removed => '-',
+                Change::Added => '+',
+            };
+            out.push_str(&format!("{}{}\n", marker, line.text));
+        }
+    }
+    out
+}
@@ -1,138 +1,1215 @@
-use std::collections::BTreeSet;
-use std::fs;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{RwLock, broadcast};
+
+use crate::source::{Change, DocumentSource, FsSource, Stat};
 
 /// Represents a markdown file in a directory
 #[derive(Clone, Debug)]
 pub struct MarkdownFile {
     pub name: String,
+    /// Location on disk; empty for files of a source that is not a directory
     pub path: PathBuf,
 }
 
-/// Scans a directory for markdown files
-pub fn scan_markdown_files(dir_path: &str) -> io::Result<Vec<MarkdownFile>> {
-    let base_path = Path::new(dir_path);
+/// Whether `name` is a markdown document: `.md` or `.markdown` in any case
+///
+/// The tree indexes exactly the files uploads accept as documents.
+pub fn is_markdown(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown"))
+}
+
+/// Whether `name` is a file or directory rsmd keeps for itself, such as
+/// unfinished uploads
+pub fn is_internal(name: &str) -> bool {
+    name.starts_with(".rsmd")
+}
+
+/// Whether a segment of the `/`-separated `path` is hidden, like `.git`,
+/// `.env` or rsmd's own files
+///
+/// Hidden files are neither served as assets nor changed through the API.
+/// `.` and `..` count as hidden.
+pub fn is_hidden_path(path: &str) -> bool {
+    path.split(['/', '\\'])
+        .any(|segment| segment.starts_with('.'))
+}
+
+/// Normalize a `/`-separated path inside the served directory
+///
+/// Backslashes separate segments too. Drops leading, trailing and repeated
+/// slashes and `.` segments. Returns `None` when a segment is `..`, which would
+/// escape the directory.
+pub fn normalize_path(path: &str) -> Option<String> {
+    let segments: Vec<&str> = path
+        .split(['/', '\\'])
+        .filter(|seg| !seg.is_empty() && *seg != ".")
+        .collect();
+    if segments.contains(&"..") {
+        return None;
+    }
+    Some(segments.join("/"))
+}
+
+/// Represents a sub-directory entry in the markdown library
+#[derive(Clone, Debug)]
+pub struct DirectoryEntry {
+    pub name: String,
+    pub path: String,
+}
+
+/// Represents the current directory view (sub-directories + files)
+#[derive(Clone, Debug, Default)]
+pub struct DirectoryListing {
+    pub current_path: String,
+    pub parent: Option<String>,
+    pub directories: Vec<DirectoryEntry>,
+    pub files: Vec<MarkdownFile>,
+}
+
+/// Maximum number of directories read concurrently by a background scan
+const SCAN_CONCURRENCY: usize = 16;
+
+/// Snapshot of the background scan progress
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ScanStatus {
+    pub complete: bool,
+    pub directories_scanned: usize,
+    pub files_found: usize,
+}
+
+/// Hierarchical, lazily populated index of the markdown files in a directory tree
+///
+/// Directory nodes are read from the [`DocumentSource`] the first time they are
+/// requested, so listings cost O(children) and the server can start before the
+/// whole tree has been walked. [`FileTree::scan`] fills in the rest in the
+/// background. Changes the source reports are applied before the next lookup.
+/// Hidden files and folders such as `.git` are left out, as they are not served
+/// either (see [`is_hidden_path`]).
+pub struct FileTree {
+    source: Arc<dyn DocumentSource>,
+    changes: Option<std::sync::Mutex<broadcast::Receiver<Change>>>,
+    root: RwLock<DirNode>,
+    directories_scanned: AtomicUsize,
+    complete: AtomicBool,
+    /// Bumped whenever the tree is rebuilt, so scans of the old one stop
+    generation: AtomicUsize,
+    /// The tree itself once [`FileTree::spawn_scan`] ran, to scan it again
+    /// after a rebuild
+    rescan: std::sync::Mutex<Option<Weak<FileTree>>>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Delta {
+    files: isize,
+    unloaded: isize,
+}
+
+impl std::ops::Add for Delta {
+    type Output = Delta;
+
+    fn add(self, other: Delta) -> Delta {
+        Delta {
+            files: self.files + other.files,
+            unloaded: self.unloaded + other.unloaded,
+        }
+    }
+}
+
+/// A directory in the tree along with aggregate counts for its subtree
+#[derive(Default)]
+struct DirNode {
+    loaded: bool,
+    dirs: BTreeMap<String, DirNode>,
+    files: BTreeMap<String, MarkdownFile>,
+    /// Modification times of the files as read from the source, until they are
+    /// replaced
+    modified: HashMap<String, SystemTime>,
+    /// Markdown files in the loaded part of this subtree
+    total_files: usize,
+    /// Directories in this subtree (including itself) not read from disk yet
+    unloaded: usize,
+}
+
+/// Entries read from a single directory on disk
+#[derive(Default)]
+struct DirContents {
+    dirs: Vec<String>,
+    files: Vec<(MarkdownFile, Option<SystemTime>)>,
+}
+
+impl DirNode {
+    fn new(loaded: bool) -> Self {
+        Self {
+            loaded,
+            unloaded: usize::from(!loaded),
+            ..Default::default()
+        }
+    }
+
+    fn apply(&mut self, delta: Delta) {
+        self.total_files = self.total_files.saturating_add_signed(delta.files);
+        self.unloaded = self.unloaded.saturating_add_signed(delta.unloaded);
+    }
+
+    /// Whether this directory should be shown in listings
+    fn is_visible(&self) -> bool {
+        self.total_files > 0 || self.unloaded > 0
+    }
+
+    fn get(&self, segments: &[&str]) -> Option<&DirNode> {
+        let mut node = self;
+        for segment in segments {
+            node = node.dirs.get(*segment)?;
+        }
+        Some(node)
+    }
+
+    fn child_entry(&mut self, segment: &str) -> (&mut DirNode, Delta) {
+        let loaded = self.loaded;
+        let mut created = Delta::default();
+        let child = self.dirs.entry(segment.to_string()).or_insert_with(|| {
+            created.unloaded = isize::from(!loaded);
+            DirNode::new(loaded)
+        });
+        (child, created)
+    }
+
+    fn insert(&mut self, segments: &[&str], file: MarkdownFile) -> Delta {
+        let delta = match segments.split_first() {
+            None => {
+                let key = file
+                    .name
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or(&file.name)
+                    .to_string();
+                self.modified.remove(&key);
+                Delta {
+                    files: isize::from(self.files.insert(key, file).is_none()),
+                    unloaded: 0,
+                }
+            }
+            Some((segment, rest)) => {
+                let (child, created) = self.child_entry(segment);
+                child.insert(rest, file) + created
+            }
+        };
+        self.apply(delta);
+        delta
+    }
+
+    fn remove(&mut self, segments: &[&str], file_name: &str) -> Option<MarkdownFile> {
+        let removed = match segments.split_first() {
+            None => {
+                self.modified.remove(file_name);
+                self.files.remove(file_name)?
+            }
+            Some((segment, rest)) => self.dirs.get_mut(*segment)?.remove(rest, file_name)?,
+        };
+        self.apply(Delta {
+            files: -1,
+            unloaded: 0,
+        });
+        Some(removed)
+    }
+
+    fn remove_dir(&mut self, segments: &[&str]) -> Option<DirNode> {
+        let removed = match segments.split_first()? {
+            (segment, []) => self.dirs.remove(*segment)?,
+            (segment, rest) => self.dirs.get_mut(*segment)?.remove_dir(rest)?,
+        };
+        self.apply(Delta {
+            files: -(removed.total_files as isize),
+            unloaded: -(removed.unloaded as isize),
+        });
+        Some(removed)
+    }
+
+    /// Put an unread directory at `segments`, replacing whatever was there
+    fn add_dir(&mut self, segments: &[&str]) -> Delta {
+        let delta = match segments.split_first() {
+            None => return Delta::default(),
+            Some((segment, [])) => {
+                let replaced = self.dirs.insert(segment.to_string(), DirNode::new(false));
+                let mut delta = Delta {
+                    files: 0,
+                    unloaded: 1,
+                };
+                if let Some(replaced) = replaced {
+                    delta.files -= replaced.total_files as isize;
+                    delta.unloaded -= replaced.unloaded as isize;
+                }
+                delta
+            }
+            Some((segment, rest)) => {
+                let (child, created) = self.child_entry(segment);
+                child.add_dir(rest) + created
+            }
+        };
+        self.apply(delta);
+        delta
+    }
+
+    fn merge(&mut self, segments: &[&str], contents: DirContents) -> Delta {
+        let delta = match segments.split_first() {
+            None => {
+                if self.loaded {
+                    return Delta::default();
+                }
+                self.loaded = true;
+                let mut delta = Delta {
+                    files: 0,
+                    unloaded: -1,
+                };
+                for dir in contents.dirs {
+                    self.dirs.entry(dir).or_insert_with(|| {
+                        delta.unloaded += 1;
+                        DirNode::new(false)
+                    });
+                }
+                for (file, modified) in contents.files {
+                    let key = file
+                        .name
+                        .rsplit('/')
+                        .next()
+                        .unwrap_or(&file.name)
+                        .to_string();
+                    if self.files.contains_key(&key) {
+                        continue;
+                    }
+                    if let Some(modified) = modified {
+                        self.modified.insert(key.clone(), modified);
+                    }
+                    self.files.insert(key, file);
+                    delta.files += 1;
+                }
+                delta
+            }
+            Some((segment, rest)) => {
+                let (child, created) = self.child_entry(segment);
+                child.merge(rest, contents) + created
+            }
+        };
+        self.apply(delta);
+        delta
+    }
+
+    /// Every file below with a known modification time
+    fn collect_modified(&self, acc: &mut Vec<(SystemTime, MarkdownFile)>) {
+        acc.extend(
+            self.modified
+                .iter()
+                .filter_map(|(key, modified)| Some((*modified, self.files.get(key)?.clone()))),
+        );
+        for child in self.dirs.values() {
+            child.collect_modified(acc);
+        }
+    }
+
+    fn collect_files(&self, acc: &mut Vec<MarkdownFile>) {
+        acc.extend(self.files.values().cloned());
+        for child in self.dirs.values() {
+            child.collect_files(acc);
+        }
+    }
+
+    /// Paths of the directories below `path` not read yet, topmost first
+    fn collect_unloaded(&self, path: &str, acc: &mut Vec<String>) {
+        if !self.loaded {
+            acc.push(path.to_string());
+            return;
+        }
+        for (name, child) in &self.dirs {
+            if child.unloaded > 0 {
+                let child_path = if path.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}/{}", path, name)
+                };
+                child.collect_unloaded(&child_path, acc);
+            }
+        }
+    }
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    path.split('/').filter(|seg| !seg.is_empty()).collect()
+}
+
+/// The entry for the file `name` of `source`
+fn source_file(source: &dyn DocumentSource, name: String) -> MarkdownFile {
+    let path = source
+        .local_path()
+        .map(|base_dir| base_dir.join(&name))
+        .unwrap_or_default();
+    MarkdownFile { name, path }
+}
+
+/// Read the directory `relative`, with the modification time of each document
+fn read_dir_contents(source: &dyn DocumentSource, relative: &str) -> io::Result<DirContents> {
+    let entries = source.list(relative)?;
+    let qualified = |file_name: String| {
+        if relative.is_empty() {
+            file_name
+        } else {
+            format!("{}/{}", relative, file_name)
+        }
+    };
+
+    Ok(DirContents {
+        dirs: entries
+            .dirs
+            .into_iter()
+            .filter(|name| !is_hidden_path(name))
+            .collect(),
+        files: entries
+            .files
+            .into_iter()
+            .filter(|name| !is_hidden_path(name) && is_markdown(name))
+            .map(|name| {
+                let name = qualified(name);
+                let modified = source.stat(&name).ok().and_then(|stat| stat.modified);
+                (source_file(source, name), modified)
+            })
+            .collect(),
+    })
+}
+
+impl FileTree {
+    /// Create a tree backed by `base_dir` whose directories are read on demand
+    pub fn new(base_dir: PathBuf) -> Self {
+        Self::with_source(Arc::new(FsSource::new(base_dir)))
+    }
+
+    /// Create a tree of the files of `source`, read on demand
+    pub fn with_source(source: Arc<dyn DocumentSource>) -> Self {
+        Self {
+            changes: source.watch().map(std::sync::Mutex::new),
+            source,
+            root: RwLock::new(DirNode::new(false)),
+            directories_scanned: AtomicUsize::new(0),
+            complete: AtomicBool::new(false),
+            generation: AtomicUsize::new(0),
+            rescan: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Create a fully populated tree of the documents `names` of `source`,
+    /// without listing its directories
+    pub fn with_files(
+        source: Arc<dyn DocumentSource>,
+        names: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        let mut root = DirNode::new(true);
+        for name in names {
+            let file = source_file(source.as_ref(), name.into());
+            let name = file.name.clone();
+            let segments = split_path(&name);
+            let parent = &segments[..segments.len().saturating_sub(1)];
+            root.insert(parent, file);
+        }
+
+        Self {
+            changes: source.watch().map(std::sync::Mutex::new),
+            source,
+            root: RwLock::new(root),
+            directories_scanned: AtomicUsize::new(0),
+            complete: AtomicBool::new(true),
+            generation: AtomicUsize::new(0),
+            rescan: std::sync::Mutex::new(None),
+        }
+    }
 
-    if !base_path.is_dir() {
-        return Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            "Path is not a directory",
-        ));
+    /// The source files are read from
+    pub fn source(&self) -> &Arc<dyn DocumentSource> {
+        &self.source
     }
 
-    let mut md_files = Vec::new();
+    /// Size and modification time of `file`
+    pub async fn stat(&self, file: &MarkdownFile) -> io::Result<Stat> {
+        let source = self.source.clone();
+        let name = file.name.clone();
+        blocking(move || source.stat(&name)).await
+    }
 
-    fn visit(base: &Path, current: &Path, acc: &mut Vec<MarkdownFile>) -> io::Result<()> {
-        for entry in fs::read_dir(current)? {
-            let entry = entry?;
-            let path = entry.path();
+    /// Contents of `file`
+    pub async fn read(&self, file: &MarkdownFile) -> io::Result<Vec<u8>> {
+        let source = self.source.clone();
+        let name = file.name.clone();
+        blocking(move || source.read(&name)).await
+    }
 
-            if path.is_dir() {
-                visit(base, &path, acc)?;
-            } else if path.is_file() && path.extension().map(|ext| ext == "md").unwrap_or(false) {
-                let relative_name = path
-                    .strip_prefix(base)
-                    .unwrap_or(&path)
-                    .to_string_lossy()
-                    .replace('\\', "/");
+    /// Apply the changes the source reported since the last call
+    async fn sync(&self) {
+        let Some(changes) = &self.changes else {
+            return;
+        };
+        let mut received = Vec::new();
+        let mut lagged = false;
+        {
+            let mut changes = changes.lock().unwrap_or_else(|err| err.into_inner());
+            loop {
+                match changes.try_recv() {
+                    Ok(change) => received.push(change),
+                    Err(broadcast::error::TryRecvError::Lagged(_)) => lagged = true,
+                    Err(_) => break,
+                }
+            }
+        }
+        if received.is_empty() && !lagged {
+            return;
+        }
 
-                acc.push(MarkdownFile {
-                    name: relative_name,
-                    path: path.clone(),
+        let mut root = self.root.write().await;
+        if lagged {
+            // Too much changed to catch up; read directories again as needed
+            *root = DirNode::new(false);
+            self.generation.fetch_add(1, Ordering::AcqRel);
+            self.directories_scanned.store(0, Ordering::Relaxed);
+            self.complete.store(false, Ordering::Release);
+            drop(root);
+            let tree = self
+                .rescan
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .as_ref()
+                .and_then(Weak::upgrade);
+            if let Some(tree) = tree {
+                tracing::warn!("Missed changes to the documents; scanning them again");
+                tokio::spawn(async move { tree.scan().await });
+            }
+            return;
+        }
+        let source = &self.source;
+        for change in received {
+            match change {
+                Change::Written(name) if is_markdown(&name) => {
+                    if is_hidden_path(&name) {
+                        continue;
+                    }
+                    let segments = split_path(&name);
+                    let parent = &segments[..segments.len().saturating_sub(1)];
+                    root.insert(parent, source_file(source.as_ref(), name.clone()));
+                }
+                Change::Removed(name) => {
+                    let segments = split_path(&name);
+                    if let Some((file_name, parent)) = segments.split_last() {
+                        root.remove(parent, file_name);
+                    }
+                }
+                Change::Written(_) => {}
+            }
+        }
+    }
+
+    /// Read a single directory from the source and merge it into the tree
+    async fn load_dir(&self, segments: &[&str]) {
+        let source = self.source.clone();
+        let relative = segments.join("/");
+
+        let result = tokio::task::spawn_blocking(move || {
+            let contents = read_dir_contents(source.as_ref(), &relative);
+            (relative, contents)
+        })
+        .await;
+
+        let contents = match result {
+            Ok((_, Ok(contents))) => contents,
+            Ok((relative, Err(err))) => {
+                tracing::warn!(error = %err, path = %relative, "Failed to read directory");
+                DirContents::default()
+            }
+            Err(err) => {
+                tracing::error!(error = %err, "Directory read task failed");
+                return;
+            }
+        };
+
+        self.root.write().await.merge(segments, contents);
+    }
+
+    /// Make sure every directory along `segments` has been read from the source
+    ///
+    /// Returns `false` if one of the directories does not exist in the tree.
+    async fn ensure_loaded(&self, segments: &[&str]) -> bool {
+        self.sync().await;
+        for depth in 0..=segments.len() {
+            let prefix = &segments[..depth];
+            let loaded = match self.root.read().await.get(prefix) {
+                Some(node) => node.loaded,
+                None => return false,
+            };
+            if !loaded {
+                self.load_dir(prefix).await;
+            }
+        }
+        true
+    }
+
+    /// Walk the whole tree in the background, reading directories in parallel
+    ///
+    /// Stops early if the tree is rebuilt meanwhile.
+    pub async fn scan(&self) {
+        let source = &self.source;
+        let generation = self.generation.load(Ordering::Acquire);
+        let mut queue = vec![String::new()];
+        let mut pending = tokio::task::JoinSet::new();
+
+        loop {
+            if self.generation.load(Ordering::Acquire) != generation {
+                return;
+            }
+            while pending.len() < SCAN_CONCURRENCY
+                && let Some(relative) = queue.pop()
+            {
+                let source = source.clone();
+                pending.spawn_blocking(move || {
+                    let contents = read_dir_contents(source.as_ref(), &relative);
+                    (relative, contents)
+                });
+            }
+
+            let Some(result) = pending.join_next().await else {
+                break;
+            };
+
+            let (relative, contents) = match result {
+                Ok(value) => value,
+                Err(err) => {
+                    tracing::error!(error = %err, "Directory scan task failed");
+                    continue;
+                }
+            };
+
+            let contents = contents.unwrap_or_else(|err| {
+                tracing::warn!(error = %err, path = %relative, "Failed to read directory");
+                DirContents::default()
+            });
+
+            for dir in &contents.dirs {
+                queue.push(if relative.is_empty() {
+                    dir.clone()
+                } else {
+                    format!("{}/{}", relative, dir)
                 });
             }
+
+            let segments = split_path(&relative);
+            let mut root = self.root.write().await;
+            if self.generation.load(Ordering::Acquire) != generation {
+                return;
+            }
+            root.merge(&segments, contents);
+            self.directories_scanned.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if self.generation.load(Ordering::Acquire) != generation {
+            return;
+        }
+        self.complete.store(true, Ordering::Release);
+        let status = self.status().await;
+        tracing::info!(
+            files = status.files_found,
+            directories = status.directories_scanned,
+            "Finished scanning markdown files",
+        );
+    }
+
+    /// Start [`FileTree::scan`] on a background task
+    ///
+    /// The tree is scanned again the same way if it has to be rebuilt because
+    /// the source reported more changes than could be kept.
+    pub fn spawn_scan(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        *self.rescan.lock().unwrap_or_else(|err| err.into_inner()) = Some(Arc::downgrade(self));
+        let tree = Arc::clone(self);
+        tokio::spawn(async move { tree.scan().await })
+    }
+
+    /// Current scan progress
+    pub async fn status(&self) -> ScanStatus {
+        ScanStatus {
+            complete: self.complete.load(Ordering::Acquire),
+            directories_scanned: self.directories_scanned.load(Ordering::Relaxed),
+            files_found: self.root.read().await.total_files,
+        }
+    }
+
+    /// Build a listing of the direct children of `path`
+    ///
+    /// Returns `None` if the directory does not exist or holds no markdown files.
+    pub async fn list(&self, path: &str) -> Option<DirectoryListing> {
+        let normalized = path.trim_matches('/');
+        let segments = split_path(normalized);
+        if !self.ensure_loaded(&segments).await {
+            return None;
+        }
+
+        let root = self.root.read().await;
+        let node = root.get(&segments)?;
+        if !segments.is_empty() && !node.is_visible() {
+            return None;
+        }
+
+        let directories = node
+            .dirs
+            .iter()
+            .filter(|(_, child)| child.is_visible())
+            .map(|(name, _)| DirectoryEntry {
+                name: name.clone(),
+                path: if normalized.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}/{}", normalized, name)
+                },
+            })
+            .collect();
+
+        let parent = if normalized.is_empty() {
+            None
+        } else if let Some((parent, _)) = normalized.rsplit_once('/') {
+            Some(parent.to_string())
+        } else {
+            Some(String::new())
+        };
+
+        Some(DirectoryListing {
+            current_path: normalized.to_string(),
+            parent,
+            directories,
+            files: node.files.values().cloned().collect(),
+        })
+    }
+
+    /// Look up a markdown file by its relative name
+    pub async fn get(&self, name: &str) -> Option<MarkdownFile> {
+        let segments = split_path(name);
+        let (file_name, parent) = segments.split_last()?;
+        if !self.ensure_loaded(parent).await {
+            return None;
+        }
+        let root = self.root.read().await;
+        root.get(parent)?.files.get(*file_name).cloned()
+    }
+
+    /// Add or replace a file in the tree
+    pub async fn insert(&self, file: MarkdownFile) {
+        let name = file.name.clone();
+        let segments = split_path(&name);
+        let parent = &segments[..segments.len().saturating_sub(1)];
+        self.root.write().await.insert(parent, file);
+    }
+
+    /// Remove a file from the tree, returning it if it was present
+    pub async fn remove(&self, name: &str) -> Option<MarkdownFile> {
+        let segments = split_path(name);
+        let (file_name, parent) = segments.split_last()?;
+        self.root.write().await.remove(parent, file_name)
+    }
+
+    /// Remove a directory and everything below it from the tree, returning the
+    /// files that were loaded from it
+    pub async fn remove_dir(&self, path: &str) -> Vec<MarkdownFile> {
+        let segments = split_path(path);
+        let mut files = Vec::new();
+        if let Some(removed) = self.root.write().await.remove_dir(&segments) {
+            removed.collect_files(&mut files);
+        }
+        files
+    }
+
+    /// Add the directory `path`, just created or moved there in the source, and
+    /// read everything below it, returning the files found
+    pub async fn add_dir(&self, path: &str) -> Vec<MarkdownFile> {
+        let segments = split_path(path);
+        if segments.is_empty() || is_hidden_path(path) {
+            return Vec::new();
+        }
+        self.sync().await;
+        self.root.write().await.add_dir(&segments);
+
+        let mut previous = Vec::new();
+        loop {
+            let mut unloaded = Vec::new();
+            if let Some(node) = self.root.read().await.get(&segments) {
+                node.collect_unloaded(&segments.join("/"), &mut unloaded);
+            }
+            // A directory that failed to load is given up on rather than retried
+            if unloaded.is_empty() || unloaded == previous {
+                break;
+            }
+            for dir in &unloaded {
+                self.load_dir(&split_path(dir)).await;
+            }
+            previous = unloaded;
         }
 
-        Ok(())
+        let mut files = Vec::new();
+        if let Some(node) = self.root.read().await.get(&segments) {
+            node.collect_files(&mut files);
+        }
+        files
     }
 
-    visit(base_path, base_path, &mut md_files)?;
+    /// The `count` most recently modified files read so far, newest first
+    ///
+    /// Uses the modification times seen when their directories were read, so
+    /// nothing is read from the source; files written since are left out.
+    pub async fn recent(&self, count: usize) -> Vec<MarkdownFile> {
+        self.sync().await;
+        let mut files = Vec::new();
+        self.root.read().await.collect_modified(&mut files);
+        if files.len() > count && count > 0 {
+            files.select_nth_unstable_by(count - 1, |a, b| b.0.cmp(&a.0));
+        }
+        files.truncate(count);
+        files.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+        files.into_iter().map(|(_, file)| file).collect()
+    }
 
-    // Sort alphabetically
-    md_files.sort_by(|a, b| a.name.cmp(&b.name));
+    /// Every file in the tree, sorted by name, reading the directories the
+    /// scan has not reached yet
+    pub async fn all_files(&self) -> Vec<MarkdownFile> {
+        let mut previous = Vec::new();
+        loop {
+            self.sync().await;
+            let mut unloaded = Vec::new();
+            self.root.read().await.collect_unloaded("", &mut unloaded);
+            // A directory that failed to load is given up on rather than retried
+            if unloaded.is_empty() || unloaded == previous {
+                break;
+            }
+            for path in &unloaded {
+                self.load_dir(&split_path(path)).await;
+            }
+            previous = unloaded;
+        }
+        self.files().await
+    }
 
-    Ok(md_files)
+    /// All files loaded so far, sorted by name
+    pub async fn files(&self) -> Vec<MarkdownFile> {
+        self.sync().await;
+        let mut files = Vec::new();
+        self.root.read().await.collect_files(&mut files);
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        files
+    }
 }
 
-/// Represents a sub-directory entry in the markdown library
-#[derive(Clone, Debug)]
-pub struct DirectoryEntry {
+/// Metadata for a markdown file returned by tree queries
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct FileMetadata {
+    pub name: String,
+    pub size: u64,
+    /// Last modification time in seconds since the Unix epoch
+    pub modified: Option<u64>,
+}
+
+impl FileMetadata {
+    /// Metadata for `name` from an already performed `stat`
+    pub fn from_std(name: &str, metadata: &std::fs::Metadata) -> Self {
+        Self::from_stat(name, &Stat::from(metadata))
+    }
+
+    /// Metadata for `name` from the `stat` of a [`DocumentSource`]
+    pub fn from_stat(name: &str, stat: &Stat) -> Self {
+        Self {
+            name: name.to_string(),
+            size: stat.size,
+            modified: stat
+                .modified
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_secs()),
+        }
+    }
+}
+
+/// Run a call to a [`DocumentSource`] on a blocking thread
+async fn blocking<T: Send + 'static>(
+    call: impl FnOnce() -> io::Result<T> + Send + 'static,
+) -> io::Result<T> {
+    tokio::task::spawn_blocking(call)
+        .await
+        .unwrap_or_else(|err| Err(io::Error::other(err)))
+}
+
+/// A folder returned by [`FileTree::subtree`]
+///
+/// `folders` and `files` are only populated for nodes within the requested depth.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TreeNode {
     pub name: String,
     pub path: String,
+    /// Number of direct sub-folders containing markdown
+    pub folder_count: usize,
+    /// Number of markdown files directly in this folder
+    pub file_count: usize,
+    /// Number of markdown files in the whole subtree found so far
+    pub total_files: usize,
+    /// Whether every folder in the subtree has been read from disk
+    pub complete: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folders: Option<Vec<TreeNode>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<FileMetadata>>,
 }
 
-/// Represents the current directory view (sub-directories + files)
+/// One page of results from [`FileTree::find_files`]
 #[derive(Clone, Debug)]
-pub struct DirectoryListing {
-    pub current_path: String,
-    pub parent: Option<String>,
-    pub directories: Vec<DirectoryEntry>,
+pub struct FilePage {
     pub files: Vec<MarkdownFile>,
+    /// Cursor to pass back to fetch the next page, if there is one
+    pub next_cursor: Option<String>,
+    /// Whether every folder searched has been read; while the background scan
+    /// is running, later pages may miss files it has not reached yet
+    pub complete: bool,
 }
 
-/// Build a directory listing for the provided relative path
-pub fn list_directory_contents(all_files: &[MarkdownFile], current_path: &str) -> DirectoryListing {
-    let normalized = current_path.trim_matches('/');
-    let prefix = if normalized.is_empty() {
-        String::new()
-    } else {
-        format!("{}/", normalized)
-    };
+/// Filters and bounds of a [`FileTree::find_files`] walk
+struct FileWalk<'a, F> {
+    prefix: &'a str,
+    cursor: Option<&'a str>,
+    /// Files to collect before stopping
+    wanted: usize,
+    matches: F,
+}
 
-    let mut directories = BTreeSet::new();
-    let mut files = Vec::new();
+impl DirNode {
+    /// Collect the files below this node, whose names all start with `path`
+    /// (empty or `/`-terminated), in name order
+    ///
+    /// Only the children that can sort after the cursor are visited, and the
+    /// walk stops once enough files were found. Returns `true` then.
+    fn walk_files<F: Fn(&str) -> bool>(
+        &self,
+        path: &str,
+        walk: &FileWalk<'_, F>,
+        acc: &mut Vec<MarkdownFile>,
+    ) -> bool {
+        // The cursor relative to this folder, if it points inside it
+        let rest = walk.cursor.and_then(|cursor| cursor.strip_prefix(path));
+        let files = match rest {
+            Some(rest) => self
+                .files
+                .range::<str, _>((std::ops::Bound::Excluded(rest), std::ops::Bound::Unbounded)),
+            None => self.files.range::<str, _>(..),
+        };
 
-    for file in all_files {
-        let name = &file.name;
-        if !prefix.is_empty() {
-            if !name.starts_with(&prefix) {
-                continue;
+        // Folders sort by their name with a trailing slash, not by their name
+        let mut dirs: Vec<(String, &DirNode)> = self
+            .dirs
+            .iter()
+            .map(|(name, child)| (format!("{}{}/", path, name), child))
+            .filter(|(key, _)| {
+                let relevant =
+                    key.starts_with(walk.prefix) || walk.prefix.starts_with(key.as_str());
+                let after_cursor = walk
+                    .cursor
+                    .is_none_or(|cursor| key.as_str() > cursor || cursor.starts_with(key.as_str()));
+                relevant && after_cursor
+            })
+            .collect();
+        dirs.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut files = files.peekable();
+        let mut dirs = dirs.into_iter().peekable();
+        loop {
+            let file_first = match (files.peek(), dirs.peek()) {
+                (None, None) => return false,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (Some((_, file)), Some((key, _))) => file.name.as_str() < key.as_str(),
+            };
+            if file_first {
+                let Some((_, file)) = files.next() else {
+                    return false;
+                };
+                if file.name.starts_with(walk.prefix) && (walk.matches)(&file.name) {
+                    acc.push(file.clone());
+                    if acc.len() >= walk.wanted {
+                        return true;
+                    }
+                }
+            } else if let Some((key, child)) = dirs.next()
+                && child.walk_files(&key, walk, acc)
+            {
+                return true;
             }
+        }
+    }
 
-            let remainder = &name[prefix.len()..];
-            if remainder.is_empty() {
-                continue;
+    fn to_tree_node(&self, name: &str, path: &str, depth: usize) -> TreeNode {
+        let visible = || self.dirs.iter().filter(|(_, child)| child.is_visible());
+        let folders = (depth > 0).then(|| {
+            visible()
+                .map(|(child_name, child)| {
+                    let child_path = if path.is_empty() {
+                        child_name.clone()
+                    } else {
+                        format!("{}/{}", path, child_name)
+                    };
+                    child.to_tree_node(child_name, &child_path, depth - 1)
+                })
+                .collect()
+        });
+        let files = (depth > 0).then(|| {
+            self.files
+                .values()
+                .map(|file| FileMetadata {
+                    name: file.name.clone(),
+                    size: 0,
+                    modified: None,
+                })
+                .collect()
+        });
+
+        TreeNode {
+            name: name.to_string(),
+            path: path.to_string(),
+            folder_count: visible().count(),
+            file_count: self.files.len(),
+            total_files: self.total_files,
+            complete: self.unloaded == 0,
+            folders,
+            files,
+        }
+    }
+}
+
+impl FileTree {
+    async fn fill_metadata(&self, node: &mut TreeNode, files: &HashMap<String, MarkdownFile>) {
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            if let Some(entries) = node.files.as_mut() {
+                for entry in entries.iter_mut() {
+                    let Some(file) = files.get(&entry.name) else {
+                        continue;
+                    };
+                    if let Ok(stat) = self.stat(file).await {
+                        *entry = FileMetadata::from_stat(&entry.name, &stat);
+                    }
+                }
+            }
+            if let Some(folders) = node.folders.as_mut() {
+                stack.extend(folders.iter_mut());
             }
+        }
+    }
 
-            if let Some(pos) = remainder.find('/') {
-                directories.insert(remainder[..pos].to_string());
-            } else {
-                files.push(file.clone());
+    /// Build a nested view of the folder at `path`, expanded `depth` levels deep
+    pub async fn subtree(&self, path: &str, depth: usize) -> Option<TreeNode> {
+        let normalized = path.trim_matches('/');
+        let segments = split_path(normalized);
+        if !self.ensure_loaded(&segments).await {
+            return None;
+        }
+
+        // Read every folder that will be expanded, one level at a time
+        let mut level = vec![normalized.to_string()];
+        for _ in 1..depth {
+            let mut next = Vec::new();
+            for dir in &level {
+                let children: Vec<(String, bool)> = {
+                    let root = self.root.read().await;
+                    let Some(node) = root.get(&split_path(dir)) else {
+                        continue;
+                    };
+                    node.dirs
+                        .iter()
+                        .map(|(name, child)| (name.clone(), child.loaded))
+                        .collect()
+                };
+                for (name, loaded) in children {
+                    let child = if dir.is_empty() {
+                        name
+                    } else {
+                        format!("{}/{}", dir, name)
+                    };
+                    if !loaded {
+                        self.load_dir(&split_path(&child)).await;
+                    }
+                    next.push(child);
+                }
             }
-        } else if let Some(pos) = name.find('/') {
-            directories.insert(name[..pos].to_string());
-        } else {
-            files.push(file.clone());
+            level = next;
         }
+
+        let (mut tree, files) = {
+            let root = self.root.read().await;
+            let node = root.get(&segments)?;
+            if !segments.is_empty() && !node.is_visible() {
+                return None;
+            }
+            let name = segments.last().copied().unwrap_or_default();
+            let tree = node.to_tree_node(name, normalized, depth);
+
+            let mut files = HashMap::new();
+            let mut stack = vec![(node, depth)];
+            while let Some((node, remaining)) = stack.pop() {
+                if remaining == 0 {
+                    continue;
+                }
+                for file in node.files.values() {
+                    files.insert(file.name.clone(), file.clone());
+                }
+                stack.extend(node.dirs.values().map(|child| (child, remaining - 1)));
+            }
+            (tree, files)
+        };
+
+        self.fill_metadata(&mut tree, &files).await;
+        Some(tree)
     }
 
-    files.sort_by(|a, b| a.name.cmp(&b.name));
+    /// Find files in name order, starting after `cursor`
+    ///
+    /// Only the folder containing `prefix` is searched, so narrow prefixes stay cheap
+    /// on large trees, and a page only visits the files up to its end.
+    /// `matches` can filter further (e.g. by glob pattern).
+    pub async fn find_files(
+        &self,
+        prefix: &str,
+        cursor: Option<&str>,
+        limit: usize,
+        matches: impl Fn(&str) -> bool,
+    ) -> FilePage {
+        let prefix = prefix.trim_start_matches('/');
+        let dir = prefix.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+        let segments = split_path(dir);
+        if !self.ensure_loaded(&segments).await {
+            return FilePage {
+                files: Vec::new(),
+                next_cursor: None,
+                complete: true,
+            };
+        }
 
-    let directories = directories
-        .into_iter()
-        .map(|name| {
-            let path = if normalized.is_empty() {
-                name.clone()
-            } else {
-                format!("{}/{}", normalized, name)
+        // One file past the page tells whether there is another one
+        let walk = FileWalk {
+            prefix,
+            cursor,
+            wanted: limit.saturating_add(1),
+            matches,
+        };
+        let mut files = Vec::new();
+        let root = self.root.read().await;
+        let Some(node) = root.get(&segments) else {
+            return FilePage {
+                files,
+                next_cursor: None,
+                complete: true,
             };
-            DirectoryEntry { name, path }
+        };
+        let path = match segments.is_empty() {
+            true => String::new(),
+            false => format!("{}/", segments.join("/")),
+        };
+        node.walk_files(&path, &walk, &mut files);
+        let complete = node.unloaded == 0;
+        drop(root);
+
+        let next_cursor = if files.len() > limit {
+            files.truncate(limit);
+            files.last().map(|file| file.name.clone())
+        } else {
+            None
+        };
+
+        FilePage {
+            files,
+            next_cursor,
+            complete,
+        }
+    }
+}
+
+/// Navigation context for a document page in directory mode
+#[derive(Clone, Debug)]
+pub struct DocumentNav {
+    pub file: MarkdownFile,
+    /// Folder tree from the root, expanded only along the path to `file`
+    pub tree: TreeNode,
+    pub previous: Option<MarkdownFile>,
+    pub next: Option<MarkdownFile>,
+}
+
+impl FileTree {
+    /// Build the sidebar tree and sibling links for the document `name`
+    pub async fn navigation(&self, name: &str) -> Option<DocumentNav> {
+        let segments = split_path(name);
+        let (file_name, parent) = segments.split_last()?;
+        if !self.ensure_loaded(parent).await {
+            return None;
+        }
+
+        let root = self.root.read().await;
+        let folder = root.get(parent)?;
+        let file = folder.files.get(*file_name)?.clone();
+
+        let siblings: Vec<&MarkdownFile> = folder.files.values().collect();
+        let position = siblings.iter().position(|f| f.name == file.name)?;
+        let previous = position.checked_sub(1).map(|index| siblings[index].clone());
+        let next = siblings.get(position + 1).map(|f| (*f).clone());
+
+        Some(DocumentNav {
+            file,
+            tree: expand(&root, parent)?,
+            previous,
+            next,
         })
-        .collect();
+    }
 
-    let parent = if normalized.is_empty() {
-        None
-    } else if let Some((parent, _)) = normalized.rsplit_once('/') {
-        Some(parent.to_string())
-    } else {
-        Some(String::new())
-    };
+    /// Like [`navigation`](Self::navigation), for a document that may no
+    /// longer exist, such as one shown as it was at an older commit
+    ///
+    /// A missing document has no sibling links, and the tree is expanded along
+    /// the part of its path that still exists.
+    pub async fn navigation_at(&self, name: &str) -> Option<DocumentNav> {
+        if let Some(nav) = self.navigation(name).await {
+            return Some(nav);
+        }
+        let segments = split_path(name);
+        let (_, parent) = segments.split_last()?;
+        self.ensure_loaded(parent).await;
 
-    DirectoryListing {
-        current_path: normalized.to_string(),
-        parent,
-        directories,
-        files,
+        let root = self.root.read().await;
+        let depth = (0..=parent.len())
+            .rev()
+            .find(|depth| root.get(&parent[..*depth]).is_some())?;
+        Some(DocumentNav {
+            file: MarkdownFile {
+                name: segments.join("/"),
+                path: PathBuf::new(),
+            },
+            tree: expand(&root, &parent[..depth])?,
+            previous: None,
+            next: None,
+        })
+    }
+}
+
+/// The tree from `root`, with each folder along `parent` expanded one level
+fn expand(root: &DirNode, parent: &[&str]) -> Option<TreeNode> {
+    // Expand each ancestor one level, innermost first, and nest it in its parent
+    let mut expanded: Option<TreeNode> = None;
+    for depth in (0..=parent.len()).rev() {
+        let node = root.get(&parent[..depth])?;
+        let name = if depth == 0 { "" } else { parent[depth - 1] };
+        let mut tree = node.to_tree_node(name, &parent[..depth].join("/"), 1);
+        if let (Some(child), Some(folders)) = (expanded.take(), tree.folders.as_mut())
+            && let Some(slot) = folders.iter_mut().find(|f| f.path == child.path)
+        {
+            *slot = child;
+        }
+        expanded = Some(tree);
     }
+    expanded
 }
@@ -0,0 +1,134 @@
+//! Editing documents in the browser
+//!
+//! `/edit/{file}` shows a document's markdown next to a live preview rendered by
+//! `POST /api/render`. Saving sends the text to `PUT /api/markdown/{file}` with
+//! the entity tag the editor was opened with as `If-Match`, so a file changed by
+//! someone else in the meantime is never overwritten. Saved documents are
+//! written and registered exactly like uploads; see [`upload::save_document`].
+//! Documents of a writable source other than a directory on disk can only be
+//! saved through the API, which writes them to the source.
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{Html, IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::conditional;
+use crate::error::AppError;
+use crate::html::{self, Document, PageOptions};
+use crate::markdown::MarkdownParser;
+use crate::server::AppState;
+use crate::upload::{self, UploadError};
+
+/// Markdown sent by the editor, to preview or to save
+#[derive(Serialize, Deserialize)]
+pub struct EditRequest {
+    pub markdown: String,
+}
+
+/// Rendering of unsaved markdown returned by `POST /api/render`
+#[derive(Serialize, Deserialize)]
+pub struct PreviewResponse {
+    /// Text of the first level-one heading
+    pub title: Option<String>,
+    pub html: String,
+}
+
+/// Handler for the editor page of a document
+pub async fn serve_editor(
+    State(state): State<Arc<AppState>>,
+    Path(filename): Path<String>,
+    options: PageOptions,
+) -> Result<Html<String>, AppError> {
+    match state.as_ref() {
+        AppState::Directory {
+            store, language, ..
+        } => {
+            let document = store.get(&filename).await?;
+            let page = Document {
+                name: &document.name,
+                html: &document.html,
+                metadata: Some(document.metadata.clone()),
+            };
+            let etag = conditional::etag(document.markdown.as_bytes());
+            Ok(Html(html::render_edit_page(
+                &page,
+                &document.markdown,
+                &etag,
+                language,
+                &options,
+            )))
+        }
+        _ => Err(AppError::WrongMode),
+    }
+}
+
+/// API: Render markdown that has not been saved yet
+pub async fn render_preview(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<EditRequest>,
+) -> Result<Json<PreviewResponse>, AppError> {
+    let AppState::Directory { .. } = state.as_ref() else {
+        return Err(AppError::WrongMode);
+    };
+    tokio::task::spawn_blocking(move || {
+        let parser = MarkdownParser::new(request.markdown);
+        Json(PreviewResponse {
+            title: parser.title(),
+            html: parser.to_html(),
+        })
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(error = %err, "Markdown rendering task failed");
+        AppError::ReadFailure
+    })
+}
+
+/// API: Save a document edited in the browser
+///
+/// Requires `If-Match` with the `ETag` of `GET /api/markdown/{file}` to replace
+/// a document, or `If-None-Match: *` to create one. Answers in the
+/// `/api/upload` envelope with the new `ETag`; `412` when the file changed
+/// meanwhile and `428` without either header.
+pub async fn save_markdown(
+    State(state): State<Arc<AppState>>,
+    Path(filename): Path<String>,
+    headers: HeaderMap,
+    Json(request): Json<EditRequest>,
+) -> Response {
+    let AppState::Directory {
+        store, language, ..
+    } = state.as_ref()
+    else {
+        return AppError::WrongMode.into_response();
+    };
+
+    let header_value = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    let if_match = header_value(header::IF_MATCH);
+    let creates = header_value(header::IF_NONE_MATCH).is_some_and(|value| value.trim() == "*");
+    if if_match.is_none() && !creates {
+        return UploadError::PreconditionRequired.response(language);
+    }
+
+    let saved = upload::save_document(
+        store,
+        language,
+        &filename,
+        request.markdown,
+        if_match,
+        &headers,
+    )
+    .await;
+    match saved {
+        Ok(saved) => {
+            let etag = saved.etag.clone().unwrap_or_default();
+            (StatusCode::OK, [(header::ETAG, etag)], Json(saved)).into_response()
+        }
+        Err(err) => err.response(language),
+    }
+}
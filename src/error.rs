@@ -0,0 +1,144 @@
+//! Error type shared by all request handlers
+//!
+//! Handlers return [`AppError`]; its response carries the error in an extension so
+//! [`render_errors`] can turn it into a localized HTML page for browsers or a JSON
+//! envelope for `/api/*` clients.
+
+use axum::{
+    Json,
+    extract::{FromRequestParts, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::html::{self, PageOptions};
+use crate::i18n::Language;
+use crate::server::AppState;
+
+/// A failed request
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AppError {
+    /// The requested file or folder does not exist
+    NotFound,
+    /// The path is malformed or escapes the served directory
+    BadPath,
+    /// The file exists but could not be read
+    ReadFailure,
+    /// The route is not available in the current serving mode
+    WrongMode,
+    /// The destination of a create or move already exists
+    Conflict,
+    /// The served directory could not be changed
+    WriteFailure,
+    /// The git repository is in the middle of a merge or rebase
+    RepositoryBusy,
+    /// A form was posted from another site
+    CrossSite,
+}
+
+/// JSON body returned by `/api/*` routes on failure
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+}
+
+impl AppError {
+    pub fn status(self) -> StatusCode {
+        match self {
+            AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::BadPath => StatusCode::BAD_REQUEST,
+            AppError::ReadFailure => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::WrongMode => StatusCode::METHOD_NOT_ALLOWED,
+            AppError::Conflict => StatusCode::CONFLICT,
+            AppError::WriteFailure => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::RepositoryBusy => StatusCode::CONFLICT,
+            AppError::CrossSite => StatusCode::FORBIDDEN,
+        }
+    }
+
+    /// Stable machine-readable identifier
+    pub fn code(self) -> &'static str {
+        match self {
+            AppError::NotFound => "not_found",
+            AppError::BadPath => "bad_path",
+            AppError::ReadFailure => "read_failure",
+            AppError::WrongMode => "wrong_mode",
+            AppError::Conflict => "conflict",
+            AppError::WriteFailure => "write_failure",
+            AppError::RepositoryBusy => "repository_busy",
+            AppError::CrossSite => "cross_site",
+        }
+    }
+
+    /// Localized, human-readable description
+    pub fn message(self, language: &Language) -> &'static str {
+        language.text(match self {
+            AppError::NotFound => "error_not_found",
+            AppError::BadPath => "error_bad_path",
+            AppError::ReadFailure => "error_reading_file",
+            AppError::WrongMode => "error_invalid_mode",
+            AppError::Conflict => "error_conflict",
+            AppError::WriteFailure => "error_writing_file",
+            AppError::RepositoryBusy => "error_repository_busy",
+            AppError::CrossSite => "error_cross_site",
+        })
+    }
+
+    fn json(self, language: &Language) -> Response {
+        (
+            self.status(),
+            Json(ErrorResponse {
+                code: self.code().to_string(),
+                message: self.message(language).to_string(),
+            }),
+        )
+            .into_response()
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let mut response = self.json(&Language::English);
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+/// Middleware rendering [`AppError`] responses in the language of the server
+pub async fn render_errors(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let is_api = request.uri().path().starts_with("/api/");
+    // Page options are only needed for an error, after the request is gone
+    let (mut parts, _) = Request::new(()).into_parts();
+    parts.headers = request.headers().clone();
+    parts.extensions = request.extensions().clone();
+    let response = next.run(request).await;
+
+    let Some(error) = response.extensions().get::<AppError>().copied() else {
+        return response;
+    };
+    let language = state.language();
+
+    if is_api {
+        error.json(language)
+    } else {
+        let Ok(options) = PageOptions::from_request_parts(&mut parts, &state).await;
+        (
+            error.status(),
+            Html(html::render_error_page(
+                error.status(),
+                error.message(language),
+                language,
+                &options,
+            )),
+        )
+            .into_response()
+    }
+}
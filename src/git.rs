@@ -0,0 +1,986 @@
+//! Reading and committing to the git work tree of a served directory
+//!
+//! When the served directory is inside a git work tree, every document page
+//! lists the commits that changed it, which the page fetches from
+//! `/api/git/log/{file}` so that rendering it never walks the history. A
+//! document can be shown as it was at one of them, and `/blame/{file}` maps its
+//! lines to the commits that last changed them, following the parent a merge
+//! took the file from.
+//!
+//! With `--git-commit`, every upload, save, move or delete in a served
+//! directory inside a git work tree is committed with a message such as
+//! `Upload guides/setup.md`. Only the paths a request changed are staged, on top
+//! of `HEAD`, so other uncommitted work in the tree is left alone, and the index
+//! is updated to match. A move carries over the committed tree of what it moved
+//! instead of scanning the work tree. Ignored files are never added. While a
+//! merge or rebase is in progress, [`refuse_while_busy`] turns writes away.
+
+use axum::{
+    Json,
+    extract::{Path as UrlPath, Query, Request, State},
+    http::{HeaderMap, HeaderName},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+};
+use gix::bstr::{BStr, ByteSlice};
+use gix::objs::tree::EntryKind;
+use gix::revision::walk::Sorting;
+use gix::state::InProgress;
+use gix::traverse::commit::simple::CommitTimeOrder;
+use gix::{ObjectId, ThreadSafeRepository};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::diff::{Change, line_diff};
+use crate::directory;
+use crate::error::AppError;
+use crate::html::{self, DocumentGit, PageOptions};
+use crate::manage;
+use crate::markdown::MarkdownParser;
+use crate::server::AppState;
+use crate::store::DocumentStore;
+
+/// Errors of the git library, reported in the log
+pub type GitError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Author used when neither `--git-author` nor the repository configures one
+const DEFAULT_AUTHOR: (&str, &str) = ("rsmd", "rsmd@localhost");
+
+/// The only request with an unsafe method that does not write
+const PREVIEW_PATH: &str = "/api/render";
+
+/// Commits listed in the panel of a document page
+pub const LOG_LIMIT: usize = 20;
+
+/// Commits looked at when searching the history of one document
+const MAX_WALK: usize = 5000;
+
+/// Name and email of a commit author
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Identity {
+    pub name: String,
+    pub email: String,
+}
+
+impl Identity {
+    /// Parse `Name <email>`
+    pub fn parse(value: &str) -> Option<Self> {
+        let (name, rest) = value.split_once('<')?;
+        let email = rest.trim_end().strip_suffix('>')?.trim();
+        let name = name.trim();
+        if name.is_empty() || email.is_empty() || email.contains(['<', '>']) {
+            return None;
+        }
+        Some(Self {
+            name: name.to_string(),
+            email: email.to_string(),
+        })
+    }
+}
+
+/// How the repository is used
+#[derive(Clone, Debug, Default)]
+pub struct GitOptions {
+    /// Commit changes made through rsmd
+    pub commit: bool,
+    /// Author of commits; defaults to the repository's `user.name` and
+    /// `user.email`
+    pub author: Option<Identity>,
+    /// Request header carrying the user an authenticating proxy let through,
+    /// who then becomes the author of that request's commits
+    ///
+    /// The header is trusted as sent, so the proxy has to set or strip it on
+    /// every request; clients reaching rsmd directly could otherwise commit as
+    /// anyone.
+    pub user_header: Option<String>,
+}
+
+/// What a request did to a path relative to the served directory
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Touched {
+    /// A file was written or removed, a folder removed, or a folder put back
+    /// from the trash. The files of such a folder are added to what `HEAD`
+    /// has there.
+    Path(String),
+    /// A file or folder was moved, taking along what `HEAD` has at `from` and
+    /// leaving uncommitted changes inside it uncommitted
+    Moved { from: String, to: String },
+}
+
+/// The git work tree a served directory belongs to
+pub struct Repository {
+    repo: ThreadSafeRepository,
+    /// The served directory relative to the work tree, `/`-terminated, or empty
+    prefix: String,
+    /// Committer of every commit, and author unless a request names a user
+    author: Identity,
+    user_header: Option<HeaderName>,
+    commits: bool,
+    /// The last [`log`](Self::log) of each document
+    logs: Mutex<HashMap<String, CachedLog>>,
+    /// Serializes commits, which each read and write `HEAD` and the index
+    lock: Mutex<()>,
+}
+
+/// A [`Repository::log`] with the `HEAD` and limit it was read with
+struct CachedLog {
+    head: ObjectId,
+    limit: usize,
+    commits: Vec<CommitInfo>,
+}
+
+impl std::fmt::Debug for Repository {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Repository")
+            .field("path", &self.repo.path())
+            .field("prefix", &self.prefix)
+            .field("author", &self.author)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Repository {
+    /// Open the work tree containing `base_dir`
+    pub fn open(base_dir: &Path, options: GitOptions) -> Result<Self, GitError> {
+        let repo = ThreadSafeRepository::discover(base_dir)?;
+        let local = repo.to_thread_local();
+        let workdir = local.workdir().ok_or("the repository has no work tree")?;
+        let prefix = served_prefix(workdir, base_dir)?;
+
+        let author = match options.author {
+            Some(author) => author,
+            None => match local.committer() {
+                Some(Ok(signature)) => Identity {
+                    name: signature.name.to_string(),
+                    email: signature.email.to_string(),
+                },
+                _ => Identity {
+                    name: DEFAULT_AUTHOR.0.to_string(),
+                    email: DEFAULT_AUTHOR.1.to_string(),
+                },
+            },
+        };
+        let user_header = options
+            .user_header
+            .map(|header| HeaderName::try_from(header.as_str()))
+            .transpose()?;
+        drop(local);
+        Ok(Self {
+            repo,
+            prefix,
+            author,
+            user_header,
+            commits: options.commit,
+            logs: Mutex::default(),
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// The git directory
+    pub fn path(&self) -> &Path {
+        self.repo.path()
+    }
+
+    /// Whether changes made through rsmd are committed
+    pub fn commits(&self) -> bool {
+        self.commits
+    }
+
+    /// The operation in progress that writes have to wait for, if any
+    ///
+    /// Looks for the files such operations leave in the git directory, on a
+    /// blocking thread.
+    pub async fn busy(self: &Arc<Self>) -> Option<InProgress> {
+        let repository = self.clone();
+        tokio::task::spawn_blocking(move || repository.repo.to_thread_local().state())
+            .await
+            .ok()
+            .flatten()
+    }
+
+    /// Who the changes of a request with `headers` are committed as
+    ///
+    /// The [`user_header`](GitOptions::user_header) is taken as sent; see there.
+    pub fn author(&self, headers: &HeaderMap) -> Identity {
+        let user = self
+            .user_header
+            .as_ref()
+            .and_then(|header| headers.get(header))
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|user| !user.is_empty());
+        match user {
+            Some(user) => Identity::parse(user).unwrap_or_else(|| Identity {
+                name: user.to_string(),
+                email: match user.contains('@') {
+                    true => user.to_string(),
+                    false => self.author.email.clone(),
+                },
+            }),
+            None => self.author.clone(),
+        }
+    }
+
+    /// Commit what a request did to `touched`, relative to the served
+    /// directory, on top of `HEAD`
+    ///
+    /// Returns `None` when nothing changed.
+    pub async fn commit(
+        self: &Arc<Self>,
+        touched: Vec<Touched>,
+        message: String,
+        author: Identity,
+    ) -> Result<Option<ObjectId>, GitError> {
+        let repository = self.clone();
+        tokio::task::spawn_blocking(move || repository.commit_blocking(&touched, &message, &author))
+            .await?
+    }
+
+    fn commit_blocking(
+        &self,
+        touched: &[Touched],
+        message: &str,
+        author: &Identity,
+    ) -> Result<Option<ObjectId>, GitError> {
+        let _guard = self
+            .lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let repo = self.repo.to_thread_local();
+        if let Some(operation) = repo.state() {
+            return Err(format!("{:?} in progress", operation).into());
+        }
+        let workdir = repo.workdir().ok_or("the repository has no work tree")?;
+
+        let index_path = repo.index_path();
+        let mut index = if index_path.exists() {
+            repo.open_index()?
+        } else {
+            let state = gix::index::State::new(repo.object_hash());
+            gix::index::File::from_state(state, index_path)
+        };
+
+        let head = repo.head_commit().ok();
+        let parent_tree = match &head {
+            Some(commit) => commit.tree_id()?.detach(),
+            None => ObjectId::empty_tree(repo.object_hash()),
+        };
+        let head_tree = repo.find_tree(parent_tree)?;
+        let mut editor = repo.edit_tree(parent_tree)?;
+
+        // Paths whose index entries are replaced, and moved entries to copy there
+        let mut removed = Vec::new();
+        let mut moved = Vec::new();
+        let mut files = Vec::new();
+        {
+            let source =
+                gix::worktree::stack::state::ignore::Source::WorktreeThenIdMappingIfNotSkipped;
+            let mut excludes = repo.excludes(&index, None, source)?;
+            for change in touched {
+                match change {
+                    Touched::Path(name) => {
+                        let path = self.repo_path(name);
+                        match std::fs::symlink_metadata(workdir.join(&path)) {
+                            Ok(metadata) if metadata.is_dir() => {
+                                collect_files(workdir, &path, &index, &mut excludes, &mut files)?;
+                            }
+                            Ok(metadata) if metadata.is_file() => {
+                                if is_tracked(&index, &path)
+                                    || !excludes.at_entry(path.as_str(), None)?.is_excluded()
+                                {
+                                    files.push(path);
+                                }
+                            }
+                            Ok(_) => {}
+                            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                                editor.remove(path.as_str())?;
+                                removed.push(path);
+                            }
+                            Err(err) => return Err(err.into()),
+                        }
+                    }
+                    Touched::Moved { from, to } => {
+                        let (from, to) = (self.repo_path(from), self.repo_path(to));
+                        let entry = head_tree.lookup_entry_by_path(&from)?;
+                        editor.remove(from.as_str())?;
+                        removed.push(from);
+                        if let Some(entry) = entry {
+                            editor.upsert(to.as_str(), entry.mode().kind(), entry.object_id())?;
+                            removed.push(to.clone());
+                            moved.push(to);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut staged = Vec::with_capacity(files.len());
+        for path in files {
+            let full = workdir.join(&path);
+            let metadata = gix::index::fs::Metadata::from_path_no_follow(&full)?;
+            let id = repo.write_blob(std::fs::read(&full)?)?.detach();
+            let (kind, mode) = if metadata.is_executable() {
+                (
+                    EntryKind::BlobExecutable,
+                    gix::index::entry::Mode::FILE_EXECUTABLE,
+                )
+            } else {
+                (EntryKind::Blob, gix::index::entry::Mode::FILE)
+            };
+            editor.upsert(path.as_str(), kind, id)?;
+            staged.push((path, id, mode, gix::index::entry::Stat::from_fs(&metadata)?));
+        }
+        let tree = editor.write()?.detach();
+        if tree == parent_tree {
+            return Ok(None);
+        }
+
+        let now = gix::date::Time::now_local_or_utc();
+        let signature = |identity: &Identity| gix::actor::Signature {
+            name: identity.name.as_str().into(),
+            email: identity.email.as_str().into(),
+            time: now,
+        };
+        let (committer, author) = (signature(&self.author), signature(author));
+        let (mut committer_time, mut author_time) = Default::default();
+        let parents: Vec<ObjectId> = head.iter().map(|commit| commit.id).collect();
+        let id = repo
+            .commit_as(
+                committer.to_ref(&mut committer_time),
+                author.to_ref(&mut author_time),
+                "HEAD",
+                message,
+                tree,
+                parents,
+            )?
+            .detach();
+
+        // Bring the index in line with the new commit for the paths it touched.
+        // Moved files get no stat, so git compares their contents again.
+        let mut entries = Vec::new();
+        let new_tree = repo.find_tree(tree)?;
+        for to in &moved {
+            entries.extend(
+                tree_files(&new_tree, to)?
+                    .into_iter()
+                    .filter(|(path, ..)| !staged.iter().any(|(staged, ..)| staged == path))
+                    .map(|(path, id, mode)| (path, id, mode, Default::default())),
+            );
+        }
+        entries.extend(staged);
+        let replaced = |path: &BStr| {
+            removed.iter().any(|removed| is_within(path, removed))
+                || entries.iter().any(|(entry, ..)| path == entry.as_str())
+        };
+        index.remove_entries(|_, path, _| replaced(path));
+        for (path, id, mode, stat) in entries {
+            index.dangerously_push_entry(
+                stat,
+                id,
+                gix::index::entry::Flags::empty(),
+                mode,
+                path.as_bytes().as_bstr(),
+            );
+        }
+        index.sort_entries();
+        index.remove_tree();
+        index.write(Default::default())?;
+        Ok(Some(id))
+    }
+}
+
+/// A commit as listed in the history of a document
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitInfo {
+    pub id: String,
+    pub author: String,
+    pub email: String,
+    /// Author date in seconds since the Unix epoch
+    pub time: i64,
+    /// First line of the message
+    pub summary: String,
+}
+
+/// A line of a document with the commit that last changed it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlameLine {
+    /// Id of one of [`Blame::commits`]
+    pub commit: String,
+    pub text: String,
+}
+
+/// Every line of a document at a commit, with the commits that last changed them
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Blame {
+    /// The commit the document is shown at
+    pub commit: CommitInfo,
+    /// Commits the lines are attributed to, in order of first appearance
+    pub commits: Vec<CommitInfo>,
+    pub lines: Vec<BlameLine>,
+}
+
+/// Reading the history of documents
+///
+/// Revisions may be anything git understands, such as `HEAD~2`, a tag or a
+/// commit id. Methods return `None` for an unknown revision or a document that
+/// does not exist at it.
+impl Repository {
+    /// Run `read` on a blocking thread
+    async fn read<T: Send + 'static>(
+        self: &Arc<Self>,
+        read: impl FnOnce(&Self, &gix::Repository) -> Result<T, GitError> + Send + 'static,
+    ) -> Result<T, GitError> {
+        let repository = self.clone();
+        tokio::task::spawn_blocking(move || read(&repository, &repository.repo.to_thread_local()))
+            .await?
+    }
+
+    /// Up to `limit` commits that changed the document `name`, newest first
+    pub async fn log(
+        self: &Arc<Self>,
+        name: &str,
+        limit: usize,
+    ) -> Result<Vec<CommitInfo>, GitError> {
+        let path = self.repo_path(name);
+        self.read(move |repository, repo| {
+            let Ok(head) = repo.head_id() else {
+                return Ok(Vec::new());
+            };
+            let head = head.detach();
+            let mut logs = repository
+                .logs
+                .lock()
+                .unwrap_or_else(|err| err.into_inner());
+            if let Some(cached) = logs.get(&path)
+                && cached.head == head
+                && cached.limit >= limit
+            {
+                return Ok(cached.commits.iter().take(limit).cloned().collect());
+            }
+            drop(logs);
+
+            let mut blobs = BlobCache::new(&path);
+            let mut commits = Vec::new();
+            let walk = repo
+                .rev_walk([head])
+                .sorting(Sorting::ByCommitTime(CommitTimeOrder::NewestFirst))
+                .all()?;
+            for info in walk.take(MAX_WALK) {
+                let info = info?;
+                let Some(blob) = blobs.get(repo, info.id)? else {
+                    continue;
+                };
+                let mut changed = true;
+                for parent in &info.parent_ids {
+                    if blobs.get(repo, *parent)? == Some(blob) {
+                        changed = false;
+                        break;
+                    }
+                }
+                if changed {
+                    commits.push(commit_info(&repo.find_commit(info.id)?)?);
+                    if commits.len() == limit {
+                        break;
+                    }
+                }
+            }
+            logs = repository
+                .logs
+                .lock()
+                .unwrap_or_else(|err| err.into_inner());
+            let cached = CachedLog {
+                head,
+                limit,
+                commits: commits.clone(),
+            };
+            logs.insert(path, cached);
+            Ok(commits)
+        })
+        .await
+    }
+
+    /// The document `name` at `revision`, with the commit it resolves to
+    pub async fn show(
+        self: &Arc<Self>,
+        name: &str,
+        revision: &str,
+    ) -> Result<Option<(CommitInfo, Vec<u8>)>, GitError> {
+        let path = self.repo_path(name);
+        let revision = revision.to_string();
+        self.read(move |_, repo| {
+            let Some(commit) = resolve(repo, &revision) else {
+                return Ok(None);
+            };
+            let Some(blob) = BlobCache::new(&path).get(repo, commit.id)? else {
+                return Ok(None);
+            };
+            let contents = repo.find_blob(blob)?.detach().data;
+            Ok(Some((commit_info(&commit)?, contents)))
+        })
+        .await
+    }
+
+    /// Attribute every line of the document `name` at `revision`, or at `HEAD`,
+    /// to the commit that last changed it
+    pub async fn blame(
+        self: &Arc<Self>,
+        name: &str,
+        revision: Option<&str>,
+    ) -> Result<Option<Blame>, GitError> {
+        let path = self.repo_path(name);
+        let revision = revision.map(str::to_string);
+        self.read(move |_, repo| {
+            let start = match &revision {
+                Some(revision) => resolve(repo, revision),
+                None => repo.head_commit().ok(),
+            };
+            let Some(start) = start else {
+                return Ok(None);
+            };
+            let mut blobs = BlobCache::new(&path);
+            let Some(blob) = blobs.get(repo, start.id)? else {
+                return Ok(None);
+            };
+            let text = blob_text(repo, blob)?;
+            let lines: Vec<String> = text.lines().map(str::to_string).collect();
+            let mut owners: Vec<Option<ObjectId>> = vec![None; lines.len()];
+
+            // Lines of the version being looked at, mapped to lines of `text`
+            let mut current = (start.id, blob, text.clone());
+            let mut pending: Vec<Option<usize>> = (0..lines.len()).map(Some).collect();
+            for _ in 0..MAX_WALK {
+                if pending.iter().all(Option::is_none) {
+                    break;
+                }
+                let (id, blob) = (current.0, current.1);
+                let commit = repo.find_commit(id)?;
+                let mut parents = Vec::new();
+                for parent in commit.parent_ids() {
+                    parents.push((parent.detach(), blobs.get(repo, parent.detach())?));
+                }
+                // A parent with the same version is where the lines came from
+                if let Some(&(parent, _)) = parents.iter().find(|(_, b)| *b == Some(blob)) {
+                    current.0 = parent;
+                    continue;
+                }
+                let Some(&(parent, Some(parent_blob))) = parents.first() else {
+                    break;
+                };
+                let older = blob_text(repo, parent_blob)?;
+                let mut mapped = vec![None; older.lines().count()];
+                for line in line_diff(&older, &current.2) {
+                    match (line.change, line.old, line.new) {
+                        (Change::Same, Some(old), Some(new)) => mapped[old - 1] = pending[new - 1],
+                        (Change::Added, _, Some(new)) => {
+                            if let Some(index) = pending[new - 1] {
+                                owners[index] = Some(id);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                pending = mapped;
+                current = (parent, parent_blob, older);
+            }
+            // Whatever is left was there when the document was added
+            for index in pending.into_iter().flatten() {
+                owners[index] = Some(current.0);
+            }
+
+            let mut commits: Vec<CommitInfo> = Vec::new();
+            let mut lines_out = Vec::with_capacity(lines.len());
+            for (text, owner) in lines.into_iter().zip(owners) {
+                let id = owner.unwrap_or(start.id).to_string();
+                if !commits.iter().any(|commit| commit.id == id) {
+                    let owner = owner.unwrap_or(start.id);
+                    commits.push(commit_info(&repo.find_commit(owner)?)?);
+                }
+                lines_out.push(BlameLine { commit: id, text });
+            }
+            Ok(Some(Blame {
+                commit: commit_info(&start)?,
+                commits,
+                lines: lines_out,
+            }))
+        })
+        .await
+    }
+
+    /// Path of the document `name` in the repository
+    fn repo_path(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name.trim_matches('/'))
+    }
+}
+
+/// Blob ids of one path in commits, looked up once each
+struct BlobCache<'a> {
+    path: &'a str,
+    blobs: HashMap<ObjectId, Option<ObjectId>>,
+}
+
+impl<'a> BlobCache<'a> {
+    fn new(path: &'a str) -> Self {
+        Self {
+            path,
+            blobs: HashMap::new(),
+        }
+    }
+
+    /// The blob at the path in `commit`, if it is a file there
+    fn get(
+        &mut self,
+        repo: &gix::Repository,
+        commit: ObjectId,
+    ) -> Result<Option<ObjectId>, GitError> {
+        if let Some(blob) = self.blobs.get(&commit) {
+            return Ok(*blob);
+        }
+        let tree = repo.find_commit(commit)?.tree()?;
+        let blob = tree
+            .lookup_entry_by_path(self.path)?
+            .filter(|entry| entry.mode().is_blob())
+            .map(|entry| entry.object_id());
+        self.blobs.insert(commit, blob);
+        Ok(blob)
+    }
+}
+
+/// `base_dir` relative to `workdir`, `/`-terminated, or empty
+pub(crate) fn served_prefix(workdir: &Path, base_dir: &Path) -> Result<String, GitError> {
+    Ok(base_dir
+        .canonicalize()?
+        .strip_prefix(workdir.canonicalize()?)?
+        .components()
+        .map(|component| format!("{}/", component.as_os_str().to_string_lossy()))
+        .collect())
+}
+
+/// The commit `revision` names, if any
+///
+/// Only a full or abbreviated commit id, or the name of a branch or tag, is
+/// looked up; revision expressions such as `HEAD~1` or `:/text` could walk or
+/// search the whole history on every request.
+pub(crate) fn resolve<'repo>(
+    repo: &'repo gix::Repository,
+    revision: &str,
+) -> Option<gix::Commit<'repo>> {
+    let by_id = || {
+        let prefix = gix::hash::Prefix::from_hex(revision).ok()?;
+        let id = repo.objects.lookup_prefix(prefix, None).ok()??.ok()?;
+        repo.find_object(id).ok()
+    };
+    let by_name = || {
+        let mut reference = ["refs/heads/", "refs/tags/"].iter().find_map(|namespace| {
+            repo.try_find_reference(format!("{}{}", namespace, revision).as_str())
+                .ok()
+                .flatten()
+        })?;
+        reference.peel_to_id().ok()?.object().ok()
+    };
+    let object = revision
+        .bytes()
+        .all(|b| b.is_ascii_hexdigit())
+        .then(by_id)
+        .flatten()
+        .or_else(by_name)?;
+    object.peel_to_commit().ok()
+}
+
+fn commit_info(commit: &gix::Commit<'_>) -> Result<CommitInfo, GitError> {
+    let author = commit.author()?;
+    Ok(CommitInfo {
+        id: commit.id.to_string(),
+        author: author.name.to_string(),
+        email: author.email.to_string(),
+        time: author.seconds(),
+        summary: commit.message()?.summary().to_string(),
+    })
+}
+
+fn blob_text(repo: &gix::Repository, blob: ObjectId) -> Result<String, GitError> {
+    Ok(String::from_utf8_lossy(&repo.find_blob(blob)?.data).into_owned())
+}
+
+/// Whether `path` is `dir` or lies below it
+fn is_within(path: &BStr, dir: &str) -> bool {
+    path.strip_prefix(dir.as_bytes())
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(b"/"))
+}
+
+/// Whether the index has an entry for the file `path`
+fn is_tracked(index: &gix::index::File, path: &str) -> bool {
+    index.entry_by_path(path.as_bytes().as_bstr()).is_some()
+}
+
+/// Every file in `tree` at `path`, with its id and index mode
+fn tree_files(
+    tree: &gix::Tree<'_>,
+    path: &str,
+) -> Result<Vec<(String, ObjectId, gix::index::entry::Mode)>, GitError> {
+    let Some(entry) = tree.lookup_entry_by_path(path)? else {
+        return Ok(Vec::new());
+    };
+    if !entry.mode().is_tree() {
+        let mode = index_mode(entry.mode().kind());
+        return Ok(vec![(path.to_string(), entry.object_id(), mode)]);
+    }
+    let mut recorder = gix::traverse::tree::Recorder::default();
+    entry
+        .object()?
+        .into_tree()
+        .traverse()
+        .breadthfirst(&mut recorder)?;
+    Ok(recorder
+        .records
+        .into_iter()
+        .filter(|record| !record.mode.is_tree())
+        .map(|record| {
+            let file = format!("{}/{}", path, record.filepath);
+            (file, record.oid, index_mode(record.mode.kind()))
+        })
+        .collect())
+}
+
+fn index_mode(kind: EntryKind) -> gix::index::entry::Mode {
+    match kind {
+        EntryKind::BlobExecutable => gix::index::entry::Mode::FILE_EXECUTABLE,
+        EntryKind::Link => gix::index::entry::Mode::SYMLINK,
+        EntryKind::Commit => gix::index::entry::Mode::COMMIT,
+        EntryKind::Blob | EntryKind::Tree => gix::index::entry::Mode::FILE,
+    }
+}
+
+/// Every file below the folder `dir` that is tracked, or neither ignored nor
+/// rsmd's own
+fn collect_files(
+    workdir: &Path,
+    dir: &str,
+    index: &gix::index::File,
+    excludes: &mut gix::AttributeStack<'_>,
+    files: &mut Vec<String>,
+) -> Result<(), GitError> {
+    let mut pending = vec![dir.to_string()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(workdir.join(&dir))? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name == ".git" || directory::is_internal(&name) {
+                continue;
+            }
+            let path = format!("{}/{}", dir, name);
+            let file_type = entry.file_type()?;
+            let mode = file_type.is_dir().then_some(gix::index::entry::Mode::DIR);
+            let tracked = file_type.is_file() && is_tracked(index, &path);
+            if !tracked && excludes.at_entry(path.as_str(), mode)?.is_excluded() {
+                continue;
+            }
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_file() {
+                files.push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `Upload guides/setup.md`, or `Upload guides/setup.md and 2 more files`
+pub fn message(verb: &str, names: &[String]) -> String {
+    match names {
+        [] => verb.to_string(),
+        [name] => format!("{} {}", verb, name),
+        [name, rest @ ..] => format!("{} {} and {} more files", verb, name, rest.len()),
+    }
+}
+
+/// Commit the files a request wrote or removed, if the store commits to git
+///
+/// `names` are relative to the served directory. A failed commit is only
+/// logged, since the change itself has already been made.
+pub async fn commit_changes(
+    store: &DocumentStore,
+    headers: &HeaderMap,
+    message: String,
+    names: Vec<String>,
+) {
+    let touched = names.into_iter().map(Touched::Path).collect();
+    commit_touched(store, headers, message, touched).await;
+}
+
+/// Like [`commit_changes`], for requests that also moved files or folders
+pub async fn commit_touched(
+    store: &DocumentStore,
+    headers: &HeaderMap,
+    message: String,
+    touched: Vec<Touched>,
+) {
+    let Some(git) = store.git().filter(|git| git.commits()) else {
+        return;
+    };
+    if touched.is_empty() {
+        return;
+    }
+    match git
+        .commit(touched, message.clone(), git.author(headers))
+        .await
+    {
+        Ok(Some(id)) => tracing::info!(commit = %id, message = %message, "Changes committed"),
+        Ok(None) => {}
+        Err(err) => tracing::error!(error = %err, message = %message, "Failed to commit changes"),
+    }
+}
+
+/// Middleware refusing requests that may write while the repository is in the
+/// middle of a merge, rebase or similar operation
+pub async fn refuse_while_busy(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !request.method().is_safe()
+        && request.uri().path() != PREVIEW_PATH
+        && let AppState::Directory { store, .. } = state.as_ref()
+        && let Some(git) = store.git().filter(|git| git.commits())
+        && let Some(operation) = git.busy().await
+    {
+        tracing::warn!(?operation, "Refusing a write while the repository is busy");
+        return AppError::RepositoryBusy.into_response();
+    }
+    next.run(request).await
+}
+
+/// Query of pages showing a document at a commit
+#[derive(Debug, Default, Deserialize)]
+pub struct RevisionQuery {
+    /// A full or abbreviated commit id, a tag or a branch; `HEAD` if missing
+    pub commit: Option<String>,
+}
+
+/// Body of `GET /api/git/log/{file}`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogResponse {
+    pub path: String,
+    /// Newest first, at most [`LOG_LIMIT`]
+    pub commits: Vec<CommitInfo>,
+}
+
+/// Body of `GET /api/git/blame/{file}`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlameResponse {
+    pub path: String,
+    #[serde(flatten)]
+    pub blame: Blame,
+}
+
+fn git_state(state: &AppState) -> Result<(&DocumentStore, &Arc<Repository>), AppError> {
+    let (store, _) = manage::directory_state(state)?;
+    let git = store.git().ok_or(AppError::NotFound)?;
+    Ok((store, git))
+}
+
+fn read_failure(err: GitError) -> AppError {
+    tracing::error!(error = %err, "Failed to read the git repository");
+    AppError::ReadFailure
+}
+
+/// The page of the document `filename` as it was at `revision`
+pub(crate) async fn serve_at_commit(
+    state: &AppState,
+    filename: &str,
+    revision: &str,
+    options: &PageOptions,
+) -> Result<Html<String>, AppError> {
+    let (store, git) = git_state(state)?;
+    let name = manage::document_path(filename)?;
+    let (commit, contents) = git
+        .show(&name, revision)
+        .await
+        .map_err(read_failure)?
+        .ok_or(AppError::NotFound)?;
+    // The document may since have been moved or deleted
+    let nav = store
+        .files()
+        .navigation_at(&name)
+        .await
+        .ok_or(AppError::NotFound)?;
+    let markdown = String::from_utf8_lossy(&contents).into_owned();
+    let rendered = tokio::task::spawn_blocking(move || MarkdownParser::new(markdown).to_html())
+        .await
+        .map_err(|err| {
+            tracing::error!(error = %err, "Markdown rendering task failed");
+            AppError::ReadFailure
+        })?;
+    let document = html::Document {
+        name: &name,
+        html: &rendered,
+        metadata: None,
+    };
+    let git = DocumentGit {
+        commit: Some(&commit),
+    };
+    Ok(Html(html::render_document_page_with_git(
+        &document,
+        &nav,
+        Some(&git),
+        state.language(),
+        options,
+    )))
+}
+
+/// Handler for `/blame/`: the lines of a document with the commits that last
+/// changed them
+pub async fn serve_blame(
+    State(state): State<Arc<AppState>>,
+    UrlPath(filename): UrlPath<String>,
+    Query(query): Query<RevisionQuery>,
+    options: PageOptions,
+) -> Result<Html<String>, AppError> {
+    let (name, blame) = blame(&state, &filename, query.commit.as_deref()).await?;
+    Ok(Html(html::render_blame_page(
+        &name,
+        &blame,
+        state.language(),
+        &options,
+    )))
+}
+
+/// API: Commits that changed a document, newest first
+pub async fn api_get_log(
+    State(state): State<Arc<AppState>>,
+    UrlPath(filename): UrlPath<String>,
+) -> Result<Json<LogResponse>, AppError> {
+    let (_, git) = git_state(&state)?;
+    let name = manage::document_path(&filename)?;
+    let commits = git.log(&name, LOG_LIMIT).await.map_err(read_failure)?;
+    Ok(Json(LogResponse {
+        path: name,
+        commits,
+    }))
+}
+
+/// API: Blame of a document at `?commit=`, or at `HEAD`
+pub async fn api_get_blame(
+    State(state): State<Arc<AppState>>,
+    UrlPath(filename): UrlPath<String>,
+    Query(query): Query<RevisionQuery>,
+) -> Result<Json<BlameResponse>, AppError> {
+    let (path, blame) = blame(&state, &filename, query.commit.as_deref()).await?;
+    Ok(Json(BlameResponse { path, blame }))
+}
+
+async fn blame(
+    state: &AppState,
+    filename: &str,
+    revision: Option<&str>,
+) -> Result<(String, Blame), AppError> {
+    let (_, git) = git_state(state)?;
+    let name = manage::document_path(filename)?;
+    let blame = git
+        .blame(&name, revision)
+        .await
+        .map_err(read_failure)?
+        .ok_or(AppError::NotFound)?;
+    Ok((name, blame))
+}
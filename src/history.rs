@@ -0,0 +1,635 @@
+//! Revision history of documents written through rsmd
+//!
+//! Before a write replaces a document, [`commit`](crate::write::commit) hands the
+//! old version to [`History::record`]. Contents are stored once per SHA-256 under
+//! `.rsmd/history/objects`, and each document has a log of its revisions under
+//! `.rsmd/history/log/{name}.json`. [`Retention`] caps how many revisions are
+//! kept per document and for how long; contents no log refers to any more are
+//! deleted by [`History::collect_garbage`], which runs in the background every
+//! [`GC_INTERVAL`] rather than on every save.
+//!
+//! `/history/{file}` lists the revisions of a document with a line diff between
+//! any two of them, and restores one with a form post; `/api/history/{file}`
+//! does the same as JSON.
+
+use axum::{
+    Form, Json,
+    extract::{Path as UrlPath, Query, State},
+    http::HeaderMap,
+    response::{Html, Redirect},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::ajax;
+use crate::conditional;
+use crate::diff::{DiffLine, hunks, line_diff};
+use crate::error::AppError;
+use crate::git;
+use crate::html::{self, PageOptions};
+use crate::manage;
+use crate::server::AppState;
+use crate::store::DocumentStore;
+use crate::write::{self, ConflictPolicy, TempFile, WriteError};
+
+/// Folder of the served directory holding the history
+pub const HISTORY_DIR: &str = ".rsmd/history";
+
+/// Revisions kept per document unless configured otherwise
+pub const DEFAULT_MAX_REVISIONS: usize = 50;
+
+/// How often [`History::spawn_garbage_collection`] looks for unused contents
+pub const GC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How much history to keep
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Retention {
+    /// Revisions kept per document, oldest dropped first
+    pub max_revisions: usize,
+    /// Revisions older than this are dropped
+    pub max_age: Option<Duration>,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            max_revisions: DEFAULT_MAX_REVISIONS,
+            max_age: None,
+        }
+    }
+}
+
+/// What replaced a revision
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Reason {
+    /// An upload overwrote the document
+    #[default]
+    Upload,
+    /// The document was saved in the editor
+    Edit,
+    /// The document was renamed or moved
+    Rename,
+    /// Links in the document were rewritten after another document moved
+    Links,
+    /// An older revision was restored
+    Restore,
+}
+
+/// A past version of a document
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Revision {
+    /// Hex SHA-256 of the contents
+    pub id: String,
+    pub size: u64,
+    /// When this version was replaced, in seconds since the Unix epoch
+    pub saved: u64,
+    pub reason: Reason,
+    /// Previous name of a renamed document
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+}
+
+/// The version a [`commit`](crate::write::commit) replaces, recorded before it
+/// is gone
+pub struct Snapshot<'a> {
+    pub history: &'a History,
+    /// Name of the document being replaced
+    pub name: &'a str,
+    pub reason: Reason,
+}
+
+/// What changed while [`History::collect_garbage`] was reading the logs
+#[derive(Debug, Default)]
+struct Collecting {
+    /// Contents logged since, which must be kept even if unreferenced before
+    logged: HashSet<String>,
+    /// Whether a log moved, possibly past the reader
+    moved: bool,
+}
+
+/// Content-addressed store of past document versions
+#[derive(Debug)]
+pub struct History {
+    root: PathBuf,
+    retention: Retention,
+    /// Serializes updates of the logs
+    lock: Mutex<()>,
+    /// Set while garbage is collected
+    collecting: std::sync::Mutex<Option<Collecting>>,
+}
+
+impl History {
+    /// History of the documents in `base_dir`, kept in [`HISTORY_DIR`]
+    pub fn new(base_dir: &Path, retention: Retention) -> Self {
+        Self {
+            root: base_dir.join(HISTORY_DIR),
+            retention,
+            lock: Mutex::new(()),
+            collecting: std::sync::Mutex::new(None),
+        }
+    }
+
+    pub fn retention(&self) -> Retention {
+        self.retention
+    }
+
+    /// Record the file at `path` as a revision of `name`, if it exists
+    pub async fn record(&self, name: &str, path: &Path, reason: Reason) -> io::Result<()> {
+        match fs::read(path).await {
+            Ok(contents) => self.record_contents(name, &contents, reason, None).await,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Record `contents` as a revision of `name`
+    ///
+    /// Nothing is added when the latest revision has the same contents, unless
+    /// the document was renamed `from` somewhere else.
+    pub async fn record_contents(
+        &self,
+        name: &str,
+        contents: &[u8],
+        reason: Reason,
+        from: Option<&str>,
+    ) -> io::Result<()> {
+        let id = conditional::hex(&Sha256::digest(contents));
+        // Under the lock, so garbage collection cannot delete it before it is logged
+        let _guard = self.lock.lock().await;
+        self.store_object(&id, contents).await?;
+        let mut revisions = self.read_log(name).await?;
+        if from.is_none() && revisions.first().is_some_and(|latest| latest.id == id) {
+            return Ok(());
+        }
+        revisions.insert(
+            0,
+            Revision {
+                id: id.clone(),
+                size: contents.len() as u64,
+                saved: now(),
+                reason,
+                from: from.map(str::to_string),
+            },
+        );
+
+        revisions.truncate(self.retained(&revisions));
+        self.write_log(name, &revisions).await?;
+        if let Some(collecting) = self.collecting().as_mut() {
+            collecting.logged.insert(id);
+        }
+        Ok(())
+    }
+
+    /// Past versions of `name`, newest first
+    pub async fn revisions(&self, name: &str) -> io::Result<Vec<Revision>> {
+        self.read_log(name).await
+    }
+
+    /// Contents of the revision `id`, or `None` if it is not stored
+    pub async fn contents(&self, id: &str) -> io::Result<Option<Vec<u8>>> {
+        if id.len() != 64 || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Ok(None);
+        }
+        match fs::read(self.object_path(id)).await {
+            Ok(contents) => Ok(Some(contents)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Let the history of `from` follow a document or folder moved to `to`
+    pub async fn rename(&self, from: &str, to: &str, folder: bool) -> io::Result<()> {
+        let _guard = self.lock.lock().await;
+        let (source, destination) = if folder {
+            (
+                self.root.join("log").join(from),
+                self.root.join("log").join(to),
+            )
+        } else {
+            (self.log_path(from), self.log_path(to))
+        };
+        if fs::symlink_metadata(&source).await.is_err() {
+            return Ok(());
+        }
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        if let Some(collecting) = self.collecting().as_mut() {
+            collecting.moved = true;
+        }
+        fs::rename(source, destination).await
+    }
+
+    /// Run [`History::collect_garbage`] now and then every [`GC_INTERVAL`], until
+    /// the history is dropped
+    pub fn spawn_garbage_collection(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let history = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(GC_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some(history) = history.upgrade() else {
+                    return;
+                };
+                match history.collect_garbage().await {
+                    Ok(0) => {}
+                    Ok(deleted) => tracing::info!(deleted, "Deleted unused history contents"),
+                    Err(err) => tracing::warn!(error = %err, "History garbage collection failed"),
+                }
+            }
+        })
+    }
+
+    /// Delete stored contents no log refers to any more, returning how many
+    ///
+    /// Logs are read without blocking saves; contents logged meanwhile are kept,
+    /// and nothing is deleted if a log moved meanwhile or cannot be read, as its
+    /// contents would be lost.
+    pub async fn collect_garbage(&self) -> io::Result<usize> {
+        {
+            let mut collecting = self.collecting();
+            if collecting.is_some() {
+                return Ok(0);
+            }
+            *collecting = Some(Collecting::default());
+        }
+        let deleted = self.sweep().await;
+        *self.collecting() = None;
+        deleted
+    }
+
+    /// How many of `revisions`, newest first, the retention keeps
+    fn retained(&self, revisions: &[Revision]) -> usize {
+        let cutoff = self
+            .retention
+            .max_age
+            .map(|max_age| now().saturating_sub(max_age.as_secs()));
+        revisions
+            .iter()
+            .take(self.retention.max_revisions)
+            .take_while(|revision| cutoff.is_none_or(|cutoff| revision.saved >= cutoff))
+            .count()
+    }
+
+    fn log_path(&self, name: &str) -> PathBuf {
+        self.root.join("log").join(format!("{}.json", name))
+    }
+
+    fn object_path(&self, id: &str) -> PathBuf {
+        self.root.join("objects").join(&id[..2]).join(&id[2..])
+    }
+
+    async fn store_object(&self, id: &str, contents: &[u8]) -> io::Result<()> {
+        let path = self.object_path(id);
+        if fs::try_exists(&path).await? {
+            return Ok(());
+        }
+        write_atomically(&path, contents).await
+    }
+
+    /// Revisions of `name`; a log that does not parse is
+    /// [`InvalidData`](io::ErrorKind::InvalidData) rather than empty, so it is
+    /// not overwritten
+    async fn read_log(&self, name: &str) -> io::Result<Vec<Revision>> {
+        match fs::read(self.log_path(name)).await {
+            Ok(bytes) => parse_log(&bytes).inspect_err(|err| {
+                tracing::error!(file = %name, error = %err, "Unreadable history log");
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn write_log(&self, name: &str, revisions: &[Revision]) -> io::Result<()> {
+        write_atomically(&self.log_path(name), &serde_json::to_vec(revisions)?).await
+    }
+
+    fn collecting(&self) -> std::sync::MutexGuard<'_, Option<Collecting>> {
+        self.collecting
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// The work of [`History::collect_garbage`] once it is marked as running
+    async fn sweep(&self) -> io::Result<usize> {
+        let mut referenced = HashSet::new();
+        let mut pending = vec![self.root.join("log")];
+        while let Some(dir) = pending.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if entry.file_type().await?.is_dir() {
+                    pending.push(path);
+                } else {
+                    let revisions = parse_log(&fs::read(&path).await?).inspect_err(|err| {
+                        tracing::error!(
+                            log = %path.display(),
+                            error = %err,
+                            "Not collecting history garbage past an unreadable log",
+                        );
+                    })?;
+                    referenced.extend(revisions.into_iter().map(|revision| revision.id));
+                }
+            }
+        }
+
+        let mut unreferenced = Vec::new();
+        let mut buckets = match fs::read_dir(self.root.join("objects")).await {
+            Ok(buckets) => buckets,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err),
+        };
+        while let Some(bucket) = buckets.next_entry().await? {
+            let prefix = bucket.file_name().to_string_lossy().to_string();
+            let mut objects = fs::read_dir(bucket.path()).await?;
+            while let Some(object) = objects.next_entry().await? {
+                let id = format!("{}{}", prefix, object.file_name().to_string_lossy());
+                if !referenced.contains(&id) {
+                    unreferenced.push((id, object.path()));
+                }
+            }
+        }
+
+        // Saves wait only for the deletes, which see everything logged since
+        let _guard = self.lock.lock().await;
+        let collecting = self.collecting().take().unwrap_or_default();
+        if collecting.moved {
+            tracing::debug!("History logs moved while collecting garbage; trying again later");
+            return Ok(0);
+        }
+        let mut deleted = 0;
+        for (id, path) in unreferenced {
+            if collecting.logged.contains(&id) {
+                continue;
+            }
+            match fs::remove_file(path).await {
+                Ok(()) => deleted += 1,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(deleted)
+    }
+}
+
+fn parse_log(bytes: &[u8]) -> io::Result<Vec<Revision>> {
+    serde_json::from_slice(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Write `contents` to `path` through a temporary file, creating its folder
+async fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let parent = path.parent().unwrap_or(path);
+    fs::create_dir_all(parent).await?;
+    let (temp, mut file) = TempFile::create_in(parent).await?;
+    file.write_all(contents).await?;
+    file.sync_all().await?;
+    drop(file);
+    fs::rename(&temp.path, path).await
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Query of `/history/{file}`: the two versions to compare, as revision ids or
+/// `current`
+#[derive(Debug, Default, Deserialize)]
+pub struct CompareQuery {
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub to: Option<String>,
+}
+
+/// Body of a restore, as a form or JSON
+#[derive(Serialize, Deserialize)]
+pub struct RestoreRequest {
+    pub revision: String,
+}
+
+/// Revisions of a document returned by `GET /api/history/{file}`
+#[derive(Serialize, Deserialize)]
+pub struct HistoryResponse {
+    pub path: String,
+    pub revisions: Vec<Revision>,
+}
+
+/// Result of `POST /api/history/{file}`
+#[derive(Serialize, Deserialize)]
+pub struct RestoreResponse {
+    pub path: String,
+    /// Entity tag of the restored document
+    pub etag: String,
+}
+
+/// Two versions of a document and the changed lines between them
+#[derive(Serialize)]
+pub struct Comparison {
+    pub from: String,
+    pub to: String,
+    pub hunks: Vec<Vec<DiffLine>>,
+}
+
+/// Version id naming the document as it is on disk
+const CURRENT: &str = "current";
+
+/// The store, history and served directory of a request
+fn history_state(state: &AppState) -> Result<(&DocumentStore, &History, &Path), AppError> {
+    let (store, base_dir) = manage::directory_state(state)?;
+    let history = store.history().ok_or(AppError::NotFound)?;
+    Ok((store, history, base_dir))
+}
+
+fn read_failure(err: io::Error) -> AppError {
+    tracing::error!(error = %err, "Failed to read the revision history");
+    AppError::ReadFailure
+}
+
+/// Text of the version `id` of `name`, or `None` for a deleted document
+async fn version(
+    history: &History,
+    base_dir: &Path,
+    name: &str,
+    id: &str,
+) -> Result<Option<String>, AppError> {
+    let contents = if id == CURRENT {
+        match fs::read(base_dir.join(name)).await {
+            Ok(contents) => Some(contents),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(read_failure(err)),
+        }
+    } else {
+        let contents = history.contents(id).await.map_err(read_failure)?;
+        Some(contents.ok_or(AppError::NotFound)?)
+    };
+    Ok(contents.map(|contents| String::from_utf8_lossy(&contents).into_owned()))
+}
+
+/// Handler for the history page of a document
+///
+/// Compares the latest revision with the current version unless `from` and
+/// `to` say otherwise.
+pub async fn serve_history(
+    State(state): State<Arc<AppState>>,
+    UrlPath(filename): UrlPath<String>,
+    Query(query): Query<CompareQuery>,
+    options: PageOptions,
+) -> Result<Html<String>, AppError> {
+    let (_, history, base_dir) = history_state(&state)?;
+    let name = manage::document_path(&filename)?;
+    let revisions = history.revisions(&name).await.map_err(read_failure)?;
+    let current = version(history, base_dir, &name, CURRENT).await?;
+    if revisions.is_empty() && current.is_none() {
+        return Err(AppError::NotFound);
+    }
+
+    let from = query
+        .from
+        .or_else(|| revisions.first().map(|revision| revision.id.clone()));
+    let comparison = match from {
+        Some(from) => {
+            let to = query.to.unwrap_or_else(|| CURRENT.to_string());
+            let old = version(history, base_dir, &name, &from).await?;
+            let new = version(history, base_dir, &name, &to).await?;
+            let diff = line_diff(old.as_deref().unwrap_or(""), new.as_deref().unwrap_or(""));
+            Some(Comparison {
+                from,
+                to,
+                hunks: hunks(&diff),
+            })
+        }
+        None => None,
+    };
+
+    let language = state.language();
+    Ok(Html(html::render_history_page(
+        &name,
+        &revisions,
+        comparison.as_ref(),
+        language,
+        &options,
+    )))
+}
+
+/// API: Revisions of a document, newest first
+pub async fn api_get_history(
+    State(state): State<Arc<AppState>>,
+    UrlPath(filename): UrlPath<String>,
+) -> Result<Json<HistoryResponse>, AppError> {
+    let (_, history, _) = history_state(&state)?;
+    let name = manage::document_path(&filename)?;
+    let revisions = history.revisions(&name).await.map_err(read_failure)?;
+    Ok(Json(HistoryResponse {
+        path: name,
+        revisions,
+    }))
+}
+
+/// Handler for the restore buttons of the history page
+///
+/// Only forms posted from rsmd's own pages are accepted.
+pub async fn restore_form(
+    State(state): State<Arc<AppState>>,
+    UrlPath(filename): UrlPath<String>,
+    headers: HeaderMap,
+    Form(request): Form<RestoreRequest>,
+) -> Result<Redirect, AppError> {
+    if !ajax::is_same_origin(&headers) {
+        return Err(AppError::CrossSite);
+    }
+    let restored = restore(&state, &filename, &request.revision, &headers).await?;
+    Ok(Redirect::to(&format!(
+        "/history/{}",
+        html::encode_path(&restored.path)
+    )))
+}
+
+/// API: Restore a revision of a document
+///
+/// The version it replaces is kept in the history, so a restore can be undone.
+pub async fn api_restore(
+    State(state): State<Arc<AppState>>,
+    UrlPath(filename): UrlPath<String>,
+    headers: HeaderMap,
+    Json(request): Json<RestoreRequest>,
+) -> Result<Json<RestoreResponse>, AppError> {
+    Ok(Json(
+        restore(&state, &filename, &request.revision, &headers).await?,
+    ))
+}
+
+/// Write the revision `id` of the document `filename` back into place
+async fn restore(
+    state: &AppState,
+    filename: &str,
+    id: &str,
+    headers: &HeaderMap,
+) -> Result<RestoreResponse, AppError> {
+    let (store, history, base_dir) = history_state(state)?;
+    let name = manage::document_path(filename)?;
+    let revisions = history.revisions(&name).await.map_err(read_failure)?;
+    if !revisions.iter().any(|revision| revision.id == id) {
+        return Err(AppError::NotFound);
+    }
+    let contents = history
+        .contents(id)
+        .await
+        .map_err(read_failure)?
+        .ok_or(AppError::NotFound)?;
+
+    let path = base_dir.join(&name);
+    let parent = path.parent().ok_or(AppError::BadPath)?;
+    fs::create_dir_all(parent)
+        .await
+        .map_err(manage::write_failure)?;
+    let (temp, mut file) = TempFile::create_in(parent)
+        .await
+        .map_err(manage::write_failure)?;
+    file.write_all(&contents)
+        .await
+        .map_err(manage::write_failure)?;
+    file.sync_all().await.map_err(manage::write_failure)?;
+    drop(file);
+    let snapshot = Snapshot {
+        history,
+        name: &name,
+        reason: Reason::Restore,
+    };
+    match write::commit(
+        &temp,
+        &path,
+        ConflictPolicy::Overwrite,
+        None,
+        Some(snapshot),
+    )
+    .await
+    {
+        Ok(_) => {}
+        Err(WriteError::Exists | WriteError::Stale) => return Err(AppError::Conflict),
+        Err(WriteError::Io(err)) => return Err(manage::write_failure(err)),
+    }
+
+    manage::index_file(store, &name, path).await;
+    tracing::info!(file = %name, revision = %id, "Revision restored");
+    let message = format!("Restore {} to revision {}", name, id);
+    git::commit_changes(store, headers, message, vec![name.clone()]).await;
+    Ok(RestoreResponse {
+        path: name,
+        etag: conditional::etag(&contents),
+    })
+}
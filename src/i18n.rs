@@ -1,8 +1,91 @@
 mod en;
 mod ko;
 
+use std::collections::BTreeMap;
 use std::env;
 
+/// Every key with a localized string, exposed to page templates
+pub const KEYS: &[&str] = &[
+    "title_viewer",
+    "title_raw",
+    "title_directory",
+    "title_edit",
+    "title_history",
+    "title_blame",
+    "directory_label",
+    "directory_path",
+    "no_files",
+    "folders_label",
+    "files_heading",
+    "breadcrumb_root",
+    "back_to_parent",
+    "error_invalid_mode",
+    "error_not_found",
+    "error_bad_path",
+    "error_reading_file",
+    "error_conflict",
+    "error_writing_file",
+    "error_repository_busy",
+    "error_cross_site",
+    "upload_title",
+    "upload_instructions",
+    "upload_browse",
+    "upload_success",
+    "upload_error",
+    "upload_invalid_type",
+    "upload_too_large",
+    "upload_conflict",
+    "upload_stale",
+    "upload_partial",
+    "upload_invalid_attachment",
+    "upload_content_mismatch",
+    "upload_snippet_copied",
+    "upload_confirm_overwrite",
+    "upload_invalid_archive",
+    "upload_archive_limit",
+    "upload_uploading",
+    "edit_link",
+    "edit_save",
+    "edit_view",
+    "edit_preview",
+    "edit_saving",
+    "edit_saved",
+    "edit_unsaved",
+    "edit_stale",
+    "edit_error",
+    "edit_precondition_required",
+    "history_link",
+    "history_current",
+    "history_empty",
+    "history_changes",
+    "history_compare",
+    "history_restore",
+    "history_no_changes",
+    "history_reason_upload",
+    "history_reason_edit",
+    "history_reason_rename",
+    "history_reason_links",
+    "history_reason_restore",
+    "git_commits",
+    "git_no_commits",
+    "git_blame",
+    "git_viewing_commit",
+    "git_current",
+    "version_label",
+    "version_work_tree",
+    "version_branches",
+    "version_tags",
+    "nav_files",
+    "nav_previous",
+    "nav_next",
+    "theme_label",
+    "theme_auto",
+    "theme_light",
+    "theme_dark",
+    "theme_sepia",
+    "theme_high_contrast",
+];
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Language {
     English,
@@ -27,4 +110,9 @@ impl Language {
             Language::Korean => ko::get_text(key),
         }
     }
+
+    /// All localized strings keyed by name
+    pub fn strings(&self) -> BTreeMap<&'static str, &'static str> {
+        KEYS.iter().map(|key| (*key, self.text(key))).collect()
+    }
 }
@@ -4,6 +4,9 @@ pub fn get_text(key: &str) -> &'static str {
         "title_viewer" => "Markdown Viewer",
         "title_raw" => "Raw Markdown",
         "title_directory" => "Markdown Directory",
+        "title_edit" => "Edit Markdown",
+        "title_history" => "Revision History",
+        "title_blame" => "Blame",
         "directory_label" => "Markdown Files",
         "directory_path" => "Directory",
         "no_files" => "No markdown files found in this directory.",
@@ -13,14 +16,73 @@ pub fn get_text(key: &str) -> &'static str {
         "back_to_parent" => "Back to parent directory",
         "error_invalid_mode" => "Error: Invalid mode",
         "error_not_found" => "404 - File not found",
+        "error_bad_path" => "400 - Invalid path",
         "error_reading_file" => "Error reading file",
+        "error_conflict" => "409 - A file or folder with that name already exists",
+        "error_writing_file" => "Error writing file",
+        "error_repository_busy" => "409 - The repository is in the middle of a merge or rebase",
+        "error_cross_site" => "403 - Changes can only be made from this site",
         "upload_title" => "Add markdown file",
-        "upload_instructions" => "Drag & drop a markdown file here or click to browse.",
+        "upload_instructions" => {
+            "Drag & drop markdown files, folders, images or zip archives here or click to browse."
+        }
         "upload_browse" => "Browse file",
         "upload_success" => "Upload complete! Loading file...",
         "upload_error" => "Failed to upload file.",
         "upload_invalid_type" => "Only markdown (.md) files are supported.",
+        "upload_too_large" => "The file is too large to upload.",
+        "upload_conflict" => "A file with this name already exists.",
+        "upload_stale" => "The file was changed since you last loaded it.",
+        "upload_partial" => "Some files could not be uploaded.",
+        "upload_invalid_attachment" => "This file type cannot be uploaded.",
+        "upload_content_mismatch" => "The file's contents do not match its extension.",
+        "upload_snippet_copied" => "Uploaded. Markdown copied to the clipboard:",
+        "upload_confirm_overwrite" => "A file with this name already exists. Replace it?",
+        "upload_invalid_archive" => "The zip archive could not be read.",
+        "upload_archive_limit" => "The zip archive is too large to unpack safely.",
         "upload_uploading" => "Uploading…",
+        "edit_link" => "Edit",
+        "edit_save" => "Save",
+        "edit_view" => "View",
+        "edit_preview" => "Preview",
+        "edit_saving" => "Saving…",
+        "edit_saved" => "Saved.",
+        "edit_unsaved" => "Unsaved changes",
+        "edit_stale" => {
+            "This file was changed elsewhere since you opened it. Copy your changes and reload to edit the new version."
+        }
+        "edit_error" => "Failed to save the file.",
+        "edit_precondition_required" => "Saving requires the version being replaced (If-Match).",
+        "history_link" => "History",
+        "history_current" => "Current version",
+        "history_empty" => "No earlier versions have been recorded yet.",
+        "history_changes" => "Changes",
+        "history_compare" => "Compare with current",
+        "history_restore" => "Restore",
+        "history_no_changes" => "The versions are identical.",
+        "history_reason_upload" => "Replaced by an upload",
+        "history_reason_edit" => "Replaced by an edit",
+        "history_reason_rename" => "Before a rename",
+        "history_reason_links" => "Before links were updated",
+        "history_reason_restore" => "Replaced by a restore",
+        "git_commits" => "Commits",
+        "git_no_commits" => "Not committed yet",
+        "git_blame" => "Blame",
+        "git_viewing_commit" => "As of commit",
+        "git_current" => "Current version",
+        "version_label" => "Version",
+        "version_work_tree" => "Working copy",
+        "version_branches" => "Branches",
+        "version_tags" => "Tags",
+        "nav_files" => "Files",
+        "nav_previous" => "Previous",
+        "nav_next" => "Next",
+        "theme_label" => "Theme",
+        "theme_auto" => "System",
+        "theme_light" => "Light",
+        "theme_dark" => "Dark",
+        "theme_sepia" => "Sepia",
+        "theme_high_contrast" => "High contrast",
         _ => "",
     }
 }
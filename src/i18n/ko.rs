@@ -4,6 +4,9 @@ pub fn get_text(key: &str) -> &'static str {
         "title_viewer" => "마크다운 뷰어",
         "title_raw" => "원본 마크다운",
         "title_directory" => "마크다운 디렉토리",
+        "title_edit" => "마크다운 편집",
+        "title_history" => "변경 기록",
+        "title_blame" => "줄별 변경 내역",
         "directory_label" => "마크다운 파일 목록",
         "directory_path" => "디렉토리",
         "no_files" => "이 디렉토리에서 마크다운 파일을 찾을 수 없습니다.",
@@ -13,14 +16,73 @@ pub fn get_text(key: &str) -> &'static str {
         "back_to_parent" => "상위 폴더로 돌아가기",
         "error_invalid_mode" => "오류: 잘못된 모드",
         "error_not_found" => "404 - 파일을 찾을 수 없습니다",
+        "error_bad_path" => "400 - 잘못된 경로",
         "error_reading_file" => "파일 읽기 오류",
+        "error_conflict" => "409 - 같은 이름의 파일이나 폴더가 이미 있습니다",
+        "error_writing_file" => "파일 쓰기 오류",
+        "error_repository_busy" => "409 - 저장소에서 병합이나 리베이스가 진행 중입니다",
+        "error_cross_site" => "403 - 이 사이트에서만 변경할 수 있습니다",
         "upload_title" => "마크다운 파일 추가",
-        "upload_instructions" => "마크다운 파일을 끌어다 놓거나 파일 찾기를 클릭하세요.",
+        "upload_instructions" => {
+            "마크다운 파일, 폴더, 이미지 또는 ZIP 압축 파일을 끌어다 놓거나 파일 찾기를 클릭하세요."
+        }
         "upload_browse" => "파일 선택",
         "upload_success" => "업로드 완료! 파일을 불러오는 중...",
         "upload_error" => "파일 업로드에 실패했습니다.",
         "upload_invalid_type" => "md 확장자 파일만 지원됩니다.",
+        "upload_too_large" => "파일이 너무 커서 업로드할 수 없습니다.",
+        "upload_conflict" => "같은 이름의 파일이 이미 있습니다.",
+        "upload_stale" => "마지막으로 불러온 뒤 파일이 변경되었습니다.",
+        "upload_partial" => "일부 파일을 업로드하지 못했습니다.",
+        "upload_invalid_attachment" => "업로드할 수 없는 파일 형식입니다.",
+        "upload_content_mismatch" => "파일 내용이 확장자와 일치하지 않습니다.",
+        "upload_snippet_copied" => "업로드했습니다. 마크다운을 클립보드에 복사했습니다:",
+        "upload_confirm_overwrite" => "같은 이름의 파일이 이미 있습니다. 바꾸시겠습니까?",
+        "upload_invalid_archive" => "ZIP 압축 파일을 읽을 수 없습니다.",
+        "upload_archive_limit" => "ZIP 압축 파일이 너무 커서 안전하게 풀 수 없습니다.",
         "upload_uploading" => "업로드 중...",
+        "edit_link" => "편집",
+        "edit_save" => "저장",
+        "edit_view" => "보기",
+        "edit_preview" => "미리보기",
+        "edit_saving" => "저장 중…",
+        "edit_saved" => "저장했습니다.",
+        "edit_unsaved" => "저장하지 않은 변경 사항",
+        "edit_stale" => {
+            "이 파일은 편집을 시작한 뒤 다른 곳에서 변경되었습니다. 변경 내용을 복사한 뒤 새로 고쳐서 새 버전을 편집하세요."
+        }
+        "edit_error" => "파일을 저장하지 못했습니다.",
+        "edit_precondition_required" => "저장하려면 바꿀 버전(If-Match)이 필요합니다.",
+        "history_link" => "기록",
+        "history_current" => "현재 버전",
+        "history_empty" => "아직 기록된 이전 버전이 없습니다.",
+        "history_changes" => "변경 내용",
+        "history_compare" => "현재 버전과 비교",
+        "history_restore" => "복원",
+        "history_no_changes" => "두 버전이 같습니다.",
+        "history_reason_upload" => "업로드로 바뀜",
+        "history_reason_edit" => "편집으로 바뀜",
+        "history_reason_rename" => "이름 바꾸기 전",
+        "history_reason_links" => "링크 갱신 전",
+        "history_reason_restore" => "복원으로 바뀜",
+        "git_commits" => "커밋",
+        "git_no_commits" => "아직 커밋되지 않음",
+        "git_blame" => "줄별 변경 내역",
+        "git_viewing_commit" => "커밋 시점",
+        "git_current" => "현재 버전",
+        "version_label" => "버전",
+        "version_work_tree" => "작업 사본",
+        "version_branches" => "브랜치",
+        "version_tags" => "태그",
+        "nav_files" => "파일",
+        "nav_previous" => "이전",
+        "nav_next" => "다음",
+        "theme_label" => "테마",
+        "theme_auto" => "시스템 설정",
+        "theme_light" => "라이트",
+        "theme_dark" => "다크",
+        "theme_sepia" => "세피아",
+        "theme_high_contrast" => "고대비",
         _ => "",
     }
 }
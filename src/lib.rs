@@ -1,9 +1,29 @@
 pub mod ajax;
+pub mod archive;
+pub mod assets;
+pub mod attachment;
+pub mod cli;
+pub mod conditional;
+pub mod diff;
 pub mod directory;
+pub mod edit;
+pub mod error;
+pub mod git;
+pub mod history;
 pub mod html;
 pub mod i18n;
+pub mod links;
+pub mod manage;
 pub mod markdown;
 pub mod server;
+pub mod source;
+pub mod store;
+pub mod templates;
+pub mod theme;
+pub mod trash;
+pub mod upload;
+pub mod versions;
+pub mod write;
 
 pub use i18n::Language;
 pub use markdown::MarkdownParser;
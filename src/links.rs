@@ -0,0 +1,476 @@
+//! Rewriting links when documents move
+//!
+//! [`rewrite`] walks a document with pulldown-cmark's source offsets and replaces
+//! only the destinations of inline links, images, reference definitions and
+//! wiki-links whose target moved, so the rest of the file stays byte-for-byte
+//! the same, and [`LinkIndex`] finds the documents a move affects.
+
+use pulldown_cmark::{Event, LinkType, Options, Parser, Tag};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::Mutex;
+
+use crate::source::{DocumentSource, Stat};
+
+/// Route prefixes of absolute links into the served directory, e.g.
+/// `/view/guides/setup.md`
+const ROUTES: &[&str] = &["/view/", "/raw/", "/edit/", "/static/"];
+
+/// A document or folder moving from `from` to `to`, both relative to the
+/// served directory
+#[derive(Clone, Debug)]
+pub struct Move {
+    pub from: String,
+    pub to: String,
+    /// Whether everything below `from` moves along
+    pub folder: bool,
+}
+
+impl Move {
+    /// Where `path` ends up after the move
+    pub fn apply(&self, path: &str) -> String {
+        if path == self.from {
+            return self.to.clone();
+        }
+        if self.folder
+            && let Some(rest) = path
+                .strip_prefix(&self.from)
+                .and_then(|rest| rest.strip_prefix('/'))
+        {
+            return format!("{}/{}", self.to, rest);
+        }
+        path.to_string()
+    }
+}
+
+/// How a destination is written in the source
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Form {
+    /// `[text](dest)`, percent-encoded
+    Plain,
+    /// `[text](<dest>)`
+    Angle,
+    /// `[[dest]]`, taken literally
+    Wiki,
+}
+
+/// `markdown` of the document `name` with its links updated for `moved`
+///
+/// Links are resolved from where the document is now and written relative to
+/// where it ends up, so links out of a moved document are fixed as well as
+/// links into it. Returns the new markdown and how many links changed, or
+/// `None` when nothing needs changing.
+pub fn rewrite(markdown: &str, name: &str, moved: &Move) -> Option<(String, usize)> {
+    let new_name = moved.apply(name);
+    let mut edits: Vec<(Range<usize>, String)> = destinations(markdown)
+        .into_iter()
+        .filter_map(|(range, form, dest)| {
+            retarget(&dest, form, name, &new_name, moved).map(|replacement| (range, replacement))
+        })
+        .collect();
+
+    if edits.is_empty() {
+        return None;
+    }
+    edits.sort_by_key(|(range, _)| range.start);
+    edits.dedup_by_key(|(range, _)| range.start);
+
+    let mut rewritten = String::with_capacity(markdown.len());
+    let mut copied = 0;
+    for (range, replacement) in &edits {
+        rewritten.push_str(&markdown[copied..range.start]);
+        rewritten.push_str(replacement);
+        copied = range.end;
+    }
+    rewritten.push_str(&markdown[copied..]);
+    Some((rewritten, edits.len()))
+}
+
+/// Paths in the served directory the links of the document `name` point to
+///
+/// A move changes the document only if it moves itself or one of these
+/// targets moves; see [`LinkIndex`].
+pub fn targets(markdown: &str, name: &str) -> Vec<String> {
+    destinations(markdown)
+        .into_iter()
+        .filter_map(|(_, form, dest)| Some(link_target(&dest, form, name)?.target))
+        .collect()
+}
+
+/// Resolved link targets of every document, kept until the document changes
+///
+/// Each document is checked against its size and modification time like the
+/// render cache, so a move only reads the documents edited since the last one
+/// instead of the whole tree.
+#[derive(Default)]
+pub struct LinkIndex {
+    documents: Mutex<HashMap<String, Indexed>>,
+}
+
+struct Indexed {
+    stat: Stat,
+    targets: Vec<String>,
+}
+
+impl LinkIndex {
+    /// The documents among `names` whose links [`rewrite`] may change for
+    /// `moved`: those moving along and those linking to something that moves
+    ///
+    /// Stats every document in `source` and reads those not indexed yet, so it
+    /// must run on a blocking thread. Documents that cannot be read are left
+    /// out.
+    pub fn affected(
+        &self,
+        source: &dyn DocumentSource,
+        names: &[String],
+        moved: &Move,
+    ) -> Vec<String> {
+        let mut documents = self.documents.lock().unwrap_or_else(|err| err.into_inner());
+        let known: HashSet<&str> = names.iter().map(String::as_str).collect();
+        documents.retain(|name, _| known.contains(name.as_str()));
+
+        let mut affected = Vec::new();
+        for name in names {
+            let Ok(stat) = source.stat(name) else {
+                documents.remove(name);
+                continue;
+            };
+            if documents
+                .get(name)
+                .is_none_or(|indexed| indexed.stat != stat)
+            {
+                let markdown = source
+                    .read(name)
+                    .ok()
+                    .and_then(|bytes| String::from_utf8(bytes).ok());
+                let Some(markdown) = markdown else {
+                    tracing::warn!(file = %name, "Skipping unreadable document for link rewriting");
+                    documents.remove(name);
+                    continue;
+                };
+                let targets = targets(&markdown, name);
+                documents.insert(name.clone(), Indexed { stat, targets });
+            }
+            let moves = |path: &String| moved.apply(path) != *path;
+            if moves(name) || documents[name].targets.iter().any(moves) {
+                affected.push(name.clone());
+            }
+        }
+        affected
+    }
+}
+
+/// Every link destination of `markdown` that can be rewritten in place, with
+/// where it is written
+fn destinations(markdown: &str) -> Vec<(Range<usize>, Form, String)> {
+    let mut found = Vec::new();
+    let mut push = |location: Option<(Range<usize>, Form)>, dest: &str| {
+        if let Some((range, form)) = location {
+            found.push((range, form, dest.to_string()));
+        }
+    };
+
+    let mut events = Parser::new_ext(markdown, Options::all()).into_offset_iter();
+    for (event, range) in events.by_ref() {
+        let (Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            ..
+        })
+        | Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            ..
+        })) = event
+        else {
+            continue;
+        };
+        // Reference links are rewritten at their definition below
+        let location = match link_type {
+            LinkType::Inline => find_destination(markdown, range, "](", &dest_url),
+            LinkType::WikiLink { .. } => find_wiki_destination(markdown, range, &dest_url),
+            _ => None,
+        };
+        push(location, &dest_url);
+    }
+    for (_, definition) in events.reference_definitions().iter() {
+        let location = find_destination(markdown, definition.span.clone(), "]:", &definition.dest);
+        push(location, &definition.dest);
+    }
+    found
+}
+
+/// A link destination resolved against the served directory
+struct LinkTarget<'a> {
+    /// The destination without its fragment or query, decoded
+    path: String,
+    /// The fragment or query, as written
+    suffix: &'a str,
+    /// The route prefix of an absolute link
+    route: Option<&'static str>,
+    /// The path in the served directory the link points to
+    target: String,
+    /// Whether the link is a wiki-link without the `.md` extension
+    bare: bool,
+}
+
+/// Where `dest` in the document `name` points, or `None` if it does not point
+/// into the served directory
+fn link_target<'a>(dest: &'a str, form: Form, name: &str) -> Option<LinkTarget<'a>> {
+    let (path, suffix) = dest.split_at(dest.find(['#', '?']).unwrap_or(dest.len()));
+    if path.is_empty() || path.starts_with("//") || has_scheme(path) {
+        return None;
+    }
+    let path = match form {
+        Form::Wiki => path.to_string(),
+        Form::Plain | Form::Angle => percent_decode(path)?,
+    };
+
+    let route = ROUTES
+        .iter()
+        .find(|route| path.starts_with(**route))
+        .copied();
+    let target = match route {
+        Some(route) => resolve("", &path[route.len()..])?,
+        None if path.starts_with('/') => return None,
+        None => resolve(parent(name), &path)?,
+    };
+    // `[[setup]]` names `setup.md`
+    let bare = form == Form::Wiki && !target.rsplit('/').next()?.contains('.');
+    let target = if bare {
+        format!("{}.md", target)
+    } else {
+        target
+    };
+    Some(LinkTarget {
+        path,
+        suffix,
+        route,
+        target,
+        bare,
+    })
+}
+
+/// The new destination for `dest`, or `None` if it does not point into the
+/// served directory or is unaffected by the move
+fn retarget(dest: &str, form: Form, name: &str, new_name: &str, moved: &Move) -> Option<String> {
+    let LinkTarget {
+        path,
+        suffix,
+        route,
+        target,
+        bare,
+    } = link_target(dest, form, name)?;
+
+    let new_target = moved.apply(&target);
+    // Links between documents that move together keep working as written
+    let still_valid = match route {
+        Some(_) => new_target == target,
+        None => resolve(parent(new_name), &path)
+            .map(|resolved| {
+                if bare {
+                    format!("{}.md", resolved)
+                } else {
+                    resolved
+                }
+            })
+            .is_some_and(|resolved| resolved == new_target),
+    };
+    if still_valid {
+        return None;
+    }
+    let mut new_path = match route {
+        Some(route) => format!("{}{}", route, new_target),
+        None => relative(parent(new_name), &new_target),
+    };
+    if bare {
+        new_path.truncate(new_path.len() - ".md".len());
+    }
+    if path.ends_with('/') {
+        new_path.push('/');
+    }
+
+    Some(match form {
+        Form::Wiki => format!("{}{}", new_path, suffix),
+        Form::Plain | Form::Angle => format!("{}{}", percent_encode(&new_path, form), suffix),
+    })
+}
+
+/// Locate the destination following `marker` (`](` or `]:`) in `range`
+///
+/// Every occurrence is tried until one parses to exactly `dest`, so markers
+/// inside link text or code spans are skipped.
+fn find_destination(
+    markdown: &str,
+    range: Range<usize>,
+    marker: &str,
+    dest: &str,
+) -> Option<(Range<usize>, Form)> {
+    let source = markdown.get(range.clone())?;
+    let mut search = 0;
+    while let Some(found) = source[search..].find(marker) {
+        search += found + marker.len();
+        if let Some((raw, form)) = scan_destination(source, search)
+            && unescape(&source[raw.clone()]) == dest
+        {
+            return Some((range.start + raw.start..range.start + raw.end, form));
+        }
+    }
+    None
+}
+
+/// The raw destination starting at `start`, after optional whitespace
+fn scan_destination(source: &str, start: usize) -> Option<(Range<usize>, Form)> {
+    let offset = source[start..].len() - source[start..].trim_start().len();
+    let begin = start + offset;
+    let rest = &source[begin..];
+
+    if let Some(inner) = rest.strip_prefix('<') {
+        let mut escaped = false;
+        for (index, c) in inner.char_indices() {
+            match c {
+                '\n' => return None,
+                '>' if !escaped => return Some((begin + 1..begin + 1 + index, Form::Angle)),
+                _ => {}
+            }
+            escaped = c == '\\' && !escaped;
+        }
+        return None;
+    }
+
+    let mut depth = 0usize;
+    let mut escaped = false;
+    for (index, c) in rest.char_indices() {
+        if !escaped {
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 0 => return Some((begin..begin + index, Form::Plain)),
+                ')' => depth -= 1,
+                c if c.is_whitespace() => return Some((begin..begin + index, Form::Plain)),
+                _ => {}
+            }
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    Some((begin..source.len(), Form::Plain))
+}
+
+/// Locate the target of a `[[dest]]` or `[[dest|text]]` wiki-link
+fn find_wiki_destination(
+    markdown: &str,
+    range: Range<usize>,
+    dest: &str,
+) -> Option<(Range<usize>, Form)> {
+    let source = markdown.get(range.clone())?;
+    let start = source.find("[[")? + 2;
+    source[start..].starts_with(dest).then(|| {
+        (
+            range.start + start..range.start + start + dest.len(),
+            Form::Wiki,
+        )
+    })
+}
+
+/// Remove backslash escapes of ASCII punctuation
+fn unescape(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\'
+            && let Some(next) = chars.peek().filter(|next| next.is_ascii_punctuation())
+        {
+            out.push(*next);
+            chars.next();
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn has_scheme(path: &str) -> bool {
+    let Some((scheme, _)) = path.split_once(':') else {
+        return false;
+    };
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+/// Decode `%XX` sequences, keeping a `%` that does not start one
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Encode the characters that would end or break a link destination
+///
+/// Spaces and parentheses are fine between angle brackets.
+fn percent_encode(path: &str, form: Form) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            ' ' | '(' | ')' if form == Form::Angle => encoded.push(c),
+            '%' | ' ' | '(' | ')' | '<' | '>' | '\\' => {
+                encoded.push_str(&format!("%{:02X}", c as u32));
+            }
+            c => encoded.push(c),
+        }
+    }
+    encoded
+}
+
+/// Folder of the document `name`
+fn parent(name: &str) -> &str {
+    name.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+/// Join `path` onto the folder `dir`, or `None` if it escapes the served
+/// directory or names the directory itself
+fn resolve(dir: &str, path: &str) -> Option<String> {
+    let mut segments: Vec<&str> = dir.split('/').filter(|seg| !seg.is_empty()).collect();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    (!segments.is_empty()).then(|| segments.join("/"))
+}
+
+/// Path of `target` relative to the folder `dir`
+fn relative(dir: &str, target: &str) -> String {
+    let from: Vec<&str> = dir.split('/').filter(|seg| !seg.is_empty()).collect();
+    let to: Vec<&str> = target.split('/').collect();
+    let common = from
+        .iter()
+        .zip(&to)
+        .take_while(|(a, b)| a == b)
+        .count()
+        .min(to.len() - 1);
+    let mut parts = vec![".."; from.len() - common];
+    parts.extend(&to[common..]);
+    parts.join("/")
+}
@@ -1,9 +1,22 @@
-use rsmd::{Language, ServerConfig, directory, markdown::MarkdownParser, server, server::AppState};
-use std::collections::HashMap;
+use rsmd::{
+    Language, ServerConfig,
+    cli::CliOptions,
+    directory::FileTree,
+    git::{self, GitOptions},
+    history::{self, History, Retention},
+    markdown::MarkdownParser,
+    server,
+    server::AppState,
+    source::{DocumentSource, TarSource, ZipSource},
+    store::{self, DocumentStore},
+    templates::Templates,
+    upload,
+    versions::Versions,
+};
 use std::env;
 use std::path::Path;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
@@ -20,18 +33,107 @@ async fn main() {
     // Detect system language
     let language = Language::detect();
 
-    // Get path from command line arguments
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: {} <markdown-file.md|directory>", args[0]);
+    // Parse command line arguments
+    let mut args = env::args();
+    let program = args.next().unwrap_or_else(|| "rsmd".to_string());
+    let options = CliOptions::parse(args).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        eprintln!("{}", CliOptions::usage(&program));
         std::process::exit(1);
+    });
+
+    // Server configuration
+    let mut config = ServerConfig::default();
+    if let Some(css_path) = &options.theme_css {
+        let css = std::fs::read_to_string(css_path).unwrap_or_else(|err| {
+            eprintln!("Error reading theme CSS '{}': {}", css_path.display(), err);
+            std::process::exit(1);
+        });
+        config = config.with_theme_css(css);
+    }
+    if let Some(cache_control) = &options.cache_control {
+        config = config.with_document_cache_control(cache_control.as_str());
+    }
+    if let Some(level) = options.compression_level {
+        config = config.with_compression_level(level);
+    }
+    if let Some(dir) = &options.templates {
+        let templates = Templates::with_overrides(dir).unwrap_or_else(|err| {
+            eprintln!("Error loading templates from '{}': {}", dir.display(), err);
+            std::process::exit(1);
+        });
+        config = config.with_templates(templates);
     }
 
-    let path = &args[1];
+    let path = &options.path;
     let path_obj = Path::new(path);
 
     // Determine if path is a file or directory
-    let state = if path_obj.is_file() {
+    let state = if let Some(reference) = &options.git_ref {
+        // A ref of the repository, read from git objects and never written
+        let versions = Versions::open(path_obj, Some(reference.clone())).unwrap_or_else(|err| {
+            eprintln!("Error opening the git repository of '{}': {}", path, err);
+            std::process::exit(1);
+        });
+        let snapshot = match versions.snapshot(reference).await {
+            Ok(Some(snapshot)) => snapshot,
+            Ok(None) => {
+                eprintln!(
+                    "Error: '{}' has no commit '{}' with this directory",
+                    path, reference
+                );
+                std::process::exit(1);
+            }
+            Err(err) => {
+                eprintln!("Error reading '{}' from '{}': {}", reference, path, err);
+                std::process::exit(1);
+            }
+        };
+        tracing::info!(
+            git_dir = %versions.path().display(),
+            reference = %reference,
+            commit = %snapshot.commit(),
+            "Serving a git ref",
+        );
+
+        let mut store = snapshot.into_store().with_versions(versions);
+        store.files().spawn_scan();
+        if let Some(mib) = options.cache_size {
+            store = store.with_cache_limit(mib * 1024 * 1024);
+        }
+        Arc::new(AppState::Directory {
+            dir_path: format!("{} @ {}", path, reference),
+            store: Arc::new(store),
+            language: language.clone(),
+        })
+    } else if path_obj.is_file()
+        && path_obj
+            .extension()
+            .is_some_and(|ext| ext == "zip" || ext == "tar")
+    {
+        // The documents of a zip or tar archive, served read-only
+        let archive: std::io::Result<Arc<dyn DocumentSource>> =
+            match path_obj.extension().is_some_and(|ext| ext == "zip") {
+                true => ZipSource::open(path_obj).map(|source| Arc::new(source) as _),
+                false => TarSource::open(path_obj).map(|source| Arc::new(source) as _),
+            };
+        let archive = archive.unwrap_or_else(|err| {
+            eprintln!("Error reading archive '{}': {}", path, err);
+            std::process::exit(1);
+        });
+        let files = Arc::new(FileTree::with_source(archive));
+        files.spawn_scan();
+
+        let mut store = DocumentStore::new(files);
+        if let Some(mib) = options.cache_size {
+            store = store.with_cache_limit(mib * 1024 * 1024);
+        }
+        Arc::new(AppState::Directory {
+            dir_path: path.to_string(),
+            store: Arc::new(store),
+            language: language.clone(),
+        })
+    } else if path_obj.is_file() {
         // Single file mode
         let parser = MarkdownParser::from_file(path).unwrap_or_else(|err| {
             eprintln!("Error reading file '{}': {}", path, err);
@@ -51,22 +153,76 @@ async fn main() {
             base_dir,
         })
     } else if path_obj.is_dir() {
-        // Directory mode
-        let files = directory::scan_markdown_files(path).unwrap_or_else(|err| {
-            eprintln!("Error scanning directory '{}': {}", path, err);
-            std::process::exit(1);
-        });
+        // Directory mode: the tree is indexed lazily and scanned in the background
+        let files = Arc::new(FileTree::new(path_obj.to_path_buf()));
+        let scan = files.spawn_scan();
 
-        if files.is_empty() {
-            eprintln!("Warning: No markdown files found in directory '{}'", path);
+        let mut store = DocumentStore::new(files);
+        if let Some(mib) = options.cache_size {
+            store = store.with_cache_limit(mib * 1024 * 1024);
+        }
+        let retention = Retention {
+            max_revisions: options
+                .history_revisions
+                .unwrap_or(history::DEFAULT_MAX_REVISIONS),
+            max_age: options
+                .history_days
+                .map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+        };
+        if retention.max_revisions > 0 {
+            store = store.with_history(History::new(path_obj, retention));
+        }
+        // Documents in a git work tree show their commits
+        let git_options = GitOptions {
+            commit: options.git_commit,
+            author: options.git_author.clone(),
+            user_header: options.git_user_header.clone(),
+        };
+        match git::Repository::open(path_obj, git_options) {
+            Ok(repository) => {
+                tracing::info!(
+                    git_dir = %repository.path().display(),
+                    commit = repository.commits(),
+                    "Serving a git work tree",
+                );
+                if let Some(header) = &options.git_user_header {
+                    tracing::warn!(
+                        %header,
+                        "Commit authors are taken from this header as sent; only expose rsmd behind a proxy that sets or strips it",
+                    );
+                }
+                store = store.with_git(repository);
+                // Its branches and tags are served under /v/
+                match Versions::open(path_obj, None) {
+                    Ok(versions) => store = store.with_versions(versions),
+                    Err(err) => tracing::warn!(error = %err, "Not serving git refs"),
+                }
+            }
+            Err(err) if options.git_commit => {
+                eprintln!("Error opening the git repository of '{}': {}", path, err);
+                std::process::exit(1);
+            }
+            Err(err) => tracing::debug!(error = %err, "Not serving a git work tree"),
+        }
+        let store = Arc::new(store);
+        if let Some(history) = store.history() {
+            history.spawn_garbage_collection();
+        }
+        if let Some(dir) = store.local_dir() {
+            upload::spawn_upload_expiry(dir.to_path_buf());
         }
 
+        // Pre-render recently edited documents once the tree is known
+        let warm_store = store.clone();
+        tokio::spawn(async move {
+            let _ = scan.await;
+            warm_store.warm(store::WARM_FILE_COUNT).await;
+        });
+
         Arc::new(AppState::Directory {
             dir_path: path.to_string(),
-            files: Arc::new(RwLock::new(files)),
-            file_cache: Arc::new(RwLock::new(HashMap::new())),
+            store,
             language: language.clone(),
-            base_dir: path_obj.to_path_buf(),
         })
     } else {
         eprintln!("Error: '{}' is not a valid file or directory", path);
@@ -74,11 +230,8 @@ async fn main() {
     };
 
     // Create router
-    let app = server::create_router(state.clone());
-
-    // Server configuration
-    let config = ServerConfig::default();
     let addr = config.address();
+    let app = server::create_router_with_config(state.clone(), config);
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
 
     // Display appropriate startup message based on mode
@@ -88,11 +241,13 @@ async fn main() {
             println!("   View rendered: http://{}/", addr);
             println!("   View raw:      http://{}/raw", addr);
         }
-        AppState::Directory { files, .. } => {
-            let count = files.read().await.len();
+        AppState::Directory { .. } => {
             println!("🚀 Markdown directory viewer running at http://{}", addr);
             println!("   Directory listing: http://{}/", addr);
-            println!("   Found {} markdown file(s)", count);
+            println!("   Scan progress:     http://{}/api/scan", addr);
+            if let Some(reference) = &options.git_ref {
+                println!("   Serving git ref:   {}", reference);
+            }
         }
     }
 
@@ -0,0 +1,672 @@
+//! Creating, moving and deleting documents and folders
+//!
+//! `POST /api/files` creates a document from a template, and `PATCH` / `DELETE`
+//! on `/api/files/{file}` rename, move or delete one; `/api/folders` does the
+//! same for folders. Moves update the links of every document pointing at what
+//! moved; see [`links::rewrite`]. Deletes go to the [`trash`] and can be restored
+//! through `/api/trash`. Paths are normalized like `/dir/{path}` and internal
+//! names are refused. After every change the
+//! [`FileTree`](crate::directory::FileTree) and the document cache are updated
+//! to match the disk.
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use crate::ajax;
+use crate::conditional;
+use crate::diff;
+use crate::directory::{self, MarkdownFile};
+use crate::error::AppError;
+use crate::git::{self, Touched};
+use crate::history::{Reason, Snapshot};
+use crate::links::{self, Move};
+use crate::server::AppState;
+use crate::store::DocumentStore;
+use crate::trash::{self, ItemKind, TrashEntry};
+use crate::write::{self, ConflictPolicy, TempFile, WriteError};
+
+/// Markdown of a document created without a template
+const DEFAULT_TEMPLATE: &str = "# {{title}}\n";
+
+/// Body of `POST /api/files`
+#[derive(Serialize, Deserialize)]
+pub struct CreateFileRequest {
+    /// Path of the new document, e.g. `guides/setup.md`
+    pub path: String,
+    /// Existing document whose markdown the new one starts from
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Replaces `{{title}}` in the template; defaults to the file name
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+/// Body of `POST /api/folders`
+#[derive(Serialize, Deserialize)]
+pub struct CreateFolderRequest {
+    pub path: String,
+}
+
+/// Body of a `PATCH` renaming or moving a document or folder
+#[derive(Serialize, Deserialize)]
+pub struct MoveRequest {
+    /// New path, relative to the served directory
+    pub to: String,
+    /// Only report the link rewrites the move would make
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// A document whose links were rewritten by a move
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LinkUpdate {
+    /// Name of the document after the move
+    pub file: String,
+    /// How many links changed
+    pub links: usize,
+    /// The edit as a unified diff
+    pub diff: String,
+}
+
+/// Result of a file or folder operation
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ManageResponse {
+    /// Path of the document or folder after the operation
+    pub path: String,
+    /// Entity tag of a created document, to send as `If-Match` when saving
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// Where a deleted item went
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trash: Option<TrashEntry>,
+    /// Documents whose links a move rewrote, or would rewrite in a dry run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<LinkUpdate>,
+    /// Set when nothing was changed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+}
+
+impl ManageResponse {
+    fn at(path: String) -> Self {
+        Self {
+            path,
+            ..Default::default()
+        }
+    }
+}
+
+/// A document whose links a move will change
+struct LinkRewrite {
+    /// Name of the document after the move
+    name: String,
+    original: String,
+    rewritten: String,
+    links: usize,
+}
+
+impl LinkRewrite {
+    fn update(&self) -> LinkUpdate {
+        LinkUpdate {
+            file: self.name.clone(),
+            links: self.links,
+            diff: diff::unified(&self.name, &self.original, &self.rewritten),
+        }
+    }
+}
+
+/// The store of directory mode and the directory on disk it reads from
+pub(crate) fn directory_state(state: &AppState) -> Result<(&DocumentStore, &FsPath), AppError> {
+    match state {
+        AppState::Directory { store, .. } => {
+            let base_dir = store.local_dir().ok_or(AppError::WrongMode)?;
+            Ok((store, base_dir))
+        }
+        _ => Err(AppError::WrongMode),
+    }
+}
+
+/// Normalize `path` like `/dir/{path}`, refusing the root and hidden names
+fn managed_path(path: &str) -> Result<String, AppError> {
+    // Written paths are taken literally, so `.` is refused rather than dropped
+    if directory::is_hidden_path(path) {
+        return Err(AppError::BadPath);
+    }
+    let path = directory::normalize_path(path).ok_or(AppError::BadPath)?;
+    if path.is_empty() {
+        return Err(AppError::BadPath);
+    }
+    Ok(path)
+}
+
+/// A [`managed_path`] naming a markdown document
+pub(crate) fn document_path(path: &str) -> Result<String, AppError> {
+    let path = managed_path(path)?;
+    if !directory::is_markdown(&path) {
+        return Err(AppError::BadPath);
+    }
+    Ok(path)
+}
+
+pub(crate) fn write_failure(err: io::Error) -> AppError {
+    if err.kind() == io::ErrorKind::AlreadyExists {
+        return AppError::Conflict;
+    }
+    tracing::error!(error = %err, "Failed to change the served directory");
+    AppError::WriteFailure
+}
+
+async fn is_dir(path: &FsPath) -> bool {
+    fs::symlink_metadata(path)
+        .await
+        .is_ok_and(|metadata| metadata.is_dir())
+}
+
+/// Move `from` to `to` unless something already exists there
+///
+/// Callers hold [`write::lock`].
+async fn move_path(from: &FsPath, to: &FsPath) -> Result<(), AppError> {
+    if fs::symlink_metadata(to).await.is_ok() {
+        return Err(AppError::Conflict);
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).await.map_err(write_failure)?;
+    }
+    fs::rename(from, to).await.map_err(write_failure)
+}
+
+/// Add the document `name` at `path` to the tree and drop any cached copy
+pub(crate) async fn index_file(store: &DocumentStore, name: &str, path: PathBuf) {
+    store
+        .files()
+        .insert(MarkdownFile {
+            name: name.to_string(),
+            path,
+        })
+        .await;
+    store.invalidate(name).await;
+}
+
+/// Remove the document `name` from the tree and the cache
+async fn forget_file(store: &DocumentStore, name: &str) {
+    store.files().remove(name).await;
+    store.invalidate(name).await;
+}
+
+/// Add every document below the folder `name` to the tree and drop any cached
+/// copies
+async fn index_folder(store: &DocumentStore, name: &str) {
+    for file in store.files().add_dir(name).await {
+        store.invalidate(&file.name).await;
+    }
+}
+
+/// Remove the folder `name` and every document below it from the tree and the
+/// cache
+async fn forget_folder(store: &DocumentStore, name: &str) {
+    for file in store.files().remove_dir(name).await {
+        store.invalidate(&file.name).await;
+    }
+}
+
+/// Let the history of what `moved` to `path` follow it
+///
+/// A renamed document also gets a revision recording its old name.
+async fn move_history(store: &DocumentStore, moved: &Move, path: &FsPath) {
+    let Some(history) = store.history() else {
+        return;
+    };
+    let followed = async {
+        history.rename(&moved.from, &moved.to, moved.folder).await?;
+        if !moved.folder {
+            let contents = fs::read(path).await?;
+            history
+                .record_contents(&moved.to, &contents, Reason::Rename, Some(&moved.from))
+                .await?;
+        }
+        Ok::<_, io::Error>(())
+    };
+    if let Err(err) = followed.await {
+        tracing::warn!(from = %moved.from, to = %moved.to, error = %err, "History not moved");
+    }
+}
+
+/// Commit a move together with the documents whose links it rewrote
+async fn commit_move(
+    store: &DocumentStore,
+    headers: &HeaderMap,
+    moved: &Move,
+    links: &[LinkUpdate],
+) {
+    let message = format!("Move {} to {}", moved.from, moved.to);
+    let mut touched = vec![Touched::Moved {
+        from: moved.from.clone(),
+        to: moved.to.clone(),
+    }];
+    touched.extend(
+        links
+            .iter()
+            .map(|update| Touched::Path(update.file.clone())),
+    );
+    git::commit_touched(store, headers, message, touched).await;
+}
+
+/// Find every document with links affected by `moved`
+///
+/// Walks the whole tree index, reading the directories the scan has not
+/// reached yet, and reads only the documents the [`LinkIndex`](links::LinkIndex)
+/// finds affected.
+async fn plan_link_rewrites(
+    store: &DocumentStore,
+    moved: Move,
+) -> Result<Vec<LinkRewrite>, AppError> {
+    let names: Vec<String> = store
+        .files()
+        .all_files()
+        .await
+        .into_iter()
+        .map(|file| file.name)
+        .collect();
+    let source = store.source().clone();
+    let index = store.links().clone();
+    tokio::task::spawn_blocking(move || {
+        let mut rewrites = Vec::new();
+        for name in index.affected(source.as_ref(), &names, &moved) {
+            let original = source
+                .read(&name)
+                .ok()
+                .and_then(|bytes| String::from_utf8(bytes).ok());
+            let Some(original) = original else {
+                tracing::warn!(file = %name, "Skipping unreadable document for link rewriting");
+                continue;
+            };
+            if let Some((rewritten, links)) = links::rewrite(&original, &name, &moved) {
+                rewrites.push(LinkRewrite {
+                    name: moved.apply(&name),
+                    original,
+                    rewritten,
+                    links,
+                });
+            }
+        }
+        rewrites
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(error = %err, "Link planning task failed");
+        AppError::ReadFailure
+    })
+}
+
+/// Write planned link rewrites once the move is done
+///
+/// A document changed since it was planned is left alone rather than
+/// overwritten.
+async fn apply_link_rewrites(
+    store: &DocumentStore,
+    base_dir: &FsPath,
+    rewrites: Vec<LinkRewrite>,
+) -> Vec<LinkUpdate> {
+    let mut updates = Vec::new();
+    for rewrite in rewrites {
+        let path = base_dir.join(&rewrite.name);
+        let if_match = conditional::etag(rewrite.original.as_bytes());
+        let snapshot = store.history().map(|history| Snapshot {
+            history,
+            name: &rewrite.name,
+            reason: Reason::Links,
+        });
+        let rewritten = rewrite.rewritten.as_bytes();
+        match replace_file(&path, rewritten, &if_match, snapshot).await {
+            Ok(()) => {
+                store.invalidate(&rewrite.name).await;
+                updates.push(rewrite.update());
+            }
+            Err(err) => {
+                tracing::warn!(file = %rewrite.name, error = ?err, "Links not rewritten");
+            }
+        }
+    }
+    updates
+}
+
+/// Atomically replace the file at `path` if it still matches `if_match`
+async fn replace_file(
+    path: &FsPath,
+    contents: &[u8],
+    if_match: &str,
+    snapshot: Option<Snapshot<'_>>,
+) -> Result<(), WriteError> {
+    let parent = path.parent().unwrap_or(path);
+    let (temp, mut file) = TempFile::create_in(parent).await?;
+    file.write_all(contents).await?;
+    file.sync_all().await?;
+    drop(file);
+    write::commit(
+        &temp,
+        path,
+        ConflictPolicy::Reject,
+        Some(if_match),
+        snapshot,
+    )
+    .await?;
+    Ok(())
+}
+
+/// API: Create a document
+///
+/// Starts from `template`, another document, or a level-one heading, with
+/// `{{title}}` replaced. Answers `201` with the new document's `ETag`, or `409`
+/// if the path is taken.
+pub async fn create_file(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<CreateFileRequest>,
+) -> Result<(StatusCode, Json<ManageResponse>), AppError> {
+    let (store, base_dir) = directory_state(&state)?;
+    let name = document_path(&request.path)?;
+    let template = match &request.template {
+        Some(template) => store.get(&document_path(template)?).await?.markdown.clone(),
+        None => DEFAULT_TEMPLATE.to_string(),
+    };
+    let title = request.title.unwrap_or_else(|| {
+        FsPath::new(&name)
+            .file_stem()
+            .map_or_else(|| name.clone(), |stem| stem.to_string_lossy().to_string())
+    });
+    let markdown = template.replace("{{title}}", &title);
+
+    let path = base_dir.join(&name);
+    let parent = path.parent().ok_or(AppError::BadPath)?;
+    fs::create_dir_all(parent).await.map_err(write_failure)?;
+    let (temp, mut file) = TempFile::create_in(parent).await.map_err(write_failure)?;
+    file.write_all(markdown.as_bytes())
+        .await
+        .map_err(write_failure)?;
+    file.sync_all().await.map_err(write_failure)?;
+    drop(file);
+    match write::commit(&temp, &path, ConflictPolicy::Reject, None, None).await {
+        Ok(_) => {}
+        Err(WriteError::Exists | WriteError::Stale) => return Err(AppError::Conflict),
+        Err(WriteError::Io(err)) => return Err(write_failure(err)),
+    }
+
+    index_file(store, &name, path).await;
+    tracing::info!(file = %name, "Document created");
+    let names = vec![name.clone()];
+    git::commit_changes(store, &headers, git::message("Create", &names), names).await;
+    Ok((
+        StatusCode::CREATED,
+        Json(ManageResponse {
+            path: name,
+            etag: Some(conditional::etag(markdown.as_bytes())),
+            ..Default::default()
+        }),
+    ))
+}
+
+/// API: Rename or move a document
+///
+/// Links to it from other documents, and its own relative links, are rewritten
+/// to match. With `dry_run` only the planned rewrites are reported.
+pub async fn move_file(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(filename): Path<String>,
+    Json(request): Json<MoveRequest>,
+) -> Result<Json<ManageResponse>, AppError> {
+    let (store, base_dir) = directory_state(&state)?;
+    let from = document_path(&filename)?;
+    let to = document_path(&request.to)?;
+    let file = store.files().get(&from).await.ok_or(AppError::NotFound)?;
+    if from == to {
+        return Ok(Json(ManageResponse::at(to)));
+    }
+    let destination = base_dir.join(&to);
+    if fs::symlink_metadata(&destination).await.is_ok() {
+        return Err(AppError::Conflict);
+    }
+
+    let moved = Move {
+        from: from.clone(),
+        to: to.clone(),
+        folder: false,
+    };
+    let rewrites = plan_link_rewrites(store, moved.clone()).await?;
+    if request.dry_run {
+        return Ok(Json(dry_run(to, &rewrites)));
+    }
+
+    {
+        let _guard = write::lock().await;
+        move_path(&file.path, &destination).await?;
+    }
+    forget_file(store, &from).await;
+    index_file(store, &to, destination.clone()).await;
+    move_history(store, &moved, &destination).await;
+    let links = apply_link_rewrites(store, base_dir, rewrites).await;
+    tracing::info!(from = %from, to = %to, links = links.len(), "Document moved");
+    commit_move(store, &headers, &moved, &links).await;
+    Ok(Json(ManageResponse {
+        path: to,
+        links,
+        ..Default::default()
+    }))
+}
+
+fn dry_run(path: String, rewrites: &[LinkRewrite]) -> ManageResponse {
+    ManageResponse {
+        path,
+        links: rewrites.iter().map(LinkRewrite::update).collect(),
+        dry_run: true,
+        ..Default::default()
+    }
+}
+
+/// API: Move a document to the trash
+pub async fn delete_file(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(filename): Path<String>,
+) -> Result<Json<ManageResponse>, AppError> {
+    let (store, base_dir) = directory_state(&state)?;
+    let name = document_path(&filename)?;
+    store.files().get(&name).await.ok_or(AppError::NotFound)?;
+
+    let entry = {
+        let _guard = write::lock().await;
+        trash::put(base_dir, &name, ItemKind::File)
+            .await
+            .map_err(write_failure)?
+    };
+    forget_file(store, &name).await;
+    tracing::info!(file = %name, trash = %entry.id, "Document deleted");
+    let names = vec![name.clone()];
+    git::commit_changes(store, &headers, git::message("Delete", &names), names).await;
+    Ok(Json(ManageResponse {
+        path: name,
+        trash: Some(entry),
+        ..Default::default()
+    }))
+}
+
+/// API: Create an empty folder
+///
+/// Like any folder it is only listed once it holds a document.
+pub async fn create_folder(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<CreateFolderRequest>,
+) -> Result<(StatusCode, Json<ManageResponse>), AppError> {
+    let (_, base_dir) = directory_state(&state)?;
+    let name = managed_path(&request.path)?;
+    let path = base_dir.join(&name);
+
+    let _guard = write::lock().await;
+    if fs::symlink_metadata(&path).await.is_ok() {
+        return Err(AppError::Conflict);
+    }
+    fs::create_dir_all(&path).await.map_err(write_failure)?;
+    Ok((StatusCode::CREATED, Json(ManageResponse::at(name))))
+}
+
+/// API: Rename or move a folder with everything in it
+///
+/// Links are rewritten and `dry_run` honoured as for a single document.
+pub async fn move_folder(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(path): Path<String>,
+    Json(request): Json<MoveRequest>,
+) -> Result<Json<ManageResponse>, AppError> {
+    let (store, base_dir) = directory_state(&state)?;
+    let from = managed_path(&path)?;
+    let to = managed_path(&request.to)?;
+    let source = base_dir.join(&from);
+    if !is_dir(&source).await {
+        return Err(AppError::NotFound);
+    }
+    if from == to {
+        return Ok(Json(ManageResponse::at(to)));
+    }
+    if to.starts_with(&format!("{}/", from)) {
+        return Err(AppError::BadPath);
+    }
+    let destination = base_dir.join(&to);
+    if fs::symlink_metadata(&destination).await.is_ok() {
+        return Err(AppError::Conflict);
+    }
+
+    let moved = Move {
+        from: from.clone(),
+        to: to.clone(),
+        folder: true,
+    };
+    let rewrites = plan_link_rewrites(store, moved.clone()).await?;
+    if request.dry_run {
+        return Ok(Json(dry_run(to, &rewrites)));
+    }
+
+    {
+        let _guard = write::lock().await;
+        move_path(&source, &destination).await?;
+    }
+    forget_folder(store, &from).await;
+    index_folder(store, &to).await;
+    move_history(store, &moved, &destination).await;
+    let links = apply_link_rewrites(store, base_dir, rewrites).await;
+    tracing::info!(from = %from, to = %to, links = links.len(), "Folder moved");
+    commit_move(store, &headers, &moved, &links).await;
+    Ok(Json(ManageResponse {
+        path: to,
+        links,
+        ..Default::default()
+    }))
+}
+
+/// API: Move a folder with everything in it to the trash
+pub async fn delete_folder(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(path): Path<String>,
+) -> Result<Json<ManageResponse>, AppError> {
+    let (store, base_dir) = directory_state(&state)?;
+    let name = managed_path(&path)?;
+    if !is_dir(&base_dir.join(&name)).await {
+        return Err(AppError::NotFound);
+    }
+
+    let entry = {
+        let _guard = write::lock().await;
+        trash::put(base_dir, &name, ItemKind::Folder)
+            .await
+            .map_err(write_failure)?
+    };
+    forget_folder(store, &name).await;
+    tracing::info!(folder = %name, trash = %entry.id, "Folder deleted");
+    let names = vec![name.clone()];
+    git::commit_changes(store, &headers, git::message("Delete", &names), names).await;
+    Ok(Json(ManageResponse {
+        path: name,
+        trash: Some(entry),
+        ..Default::default()
+    }))
+}
+
+/// API: List the trash, most recently deleted first
+pub async fn list_trash(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<TrashEntry>>, AppError> {
+    let (_, base_dir) = directory_state(&state)?;
+    trash::list(base_dir).await.map(Json).map_err(|err| {
+        tracing::error!(error = %err, "Failed to list the trash");
+        AppError::ReadFailure
+    })
+}
+
+/// API: Put a trashed document or folder back where it was deleted from
+///
+/// Answers `409` if something has taken its place since.
+pub async fn restore(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ManageResponse>, AppError> {
+    // Without a body to require a content type of, any site can post this
+    if !ajax::is_same_origin(&headers) {
+        return Err(AppError::CrossSite);
+    }
+    let (store, base_dir) = directory_state(&state)?;
+
+    let guard = write::lock().await;
+    let entry = trash::entry(base_dir, &id)
+        .await
+        .map_err(write_failure)?
+        .ok_or(AppError::NotFound)?;
+    let name = match entry.kind {
+        ItemKind::File => document_path(&entry.path)?,
+        ItemKind::Folder => managed_path(&entry.path)?,
+    };
+    let destination = base_dir.join(&name);
+    move_path(&trash::location(base_dir, &entry.id), &destination).await?;
+    if let Err(err) = trash::forget(base_dir, &entry.id).await {
+        tracing::warn!(trash = %entry.id, error = %err, "Failed to remove trash record");
+    }
+    drop(guard);
+
+    match entry.kind {
+        ItemKind::File => index_file(store, &name, destination).await,
+        ItemKind::Folder => index_folder(store, &name).await,
+    }
+    tracing::info!(path = %name, trash = %entry.id, "Restored from trash");
+    let names = vec![name.clone()];
+    git::commit_changes(store, &headers, git::message("Restore", &names), names).await;
+    Ok(Json(ManageResponse::at(name)))
+}
+
+/// API: Delete a trashed document or folder for good
+pub async fn purge(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<TrashEntry>, AppError> {
+    let (_, base_dir) = directory_state(&state)?;
+
+    let _guard = write::lock().await;
+    let entry = trash::entry(base_dir, &id)
+        .await
+        .map_err(write_failure)?
+        .ok_or(AppError::NotFound)?;
+    trash::purge(base_dir, &entry)
+        .await
+        .map_err(write_failure)?;
+    tracing::info!(path = %entry.path, trash = %entry.id, "Purged from trash");
+    Ok(Json(entry))
+}
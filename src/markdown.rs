@@ -1,7 +1,19 @@
-use pulldown_cmark::{Options, Parser, html};
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd, html};
+use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io;
 
+/// A heading in a document's outline
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Heading {
+    /// 1 for `#` through 6 for `######`
+    pub level: u8,
+    pub text: String,
+    /// Explicit anchor set with `{#id}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
 /// Markdown parser that converts markdown text to HTML
 pub struct MarkdownParser {
     content: String,
@@ -24,6 +36,11 @@ impl MarkdownParser {
         &self.content
     }
 
+    /// Take the raw markdown content
+    pub fn into_content(self) -> String {
+        self.content
+    }
+
     /// Convert markdown to HTML
     pub fn to_html(&self) -> String {
         let parser = Parser::new_ext(&self.content, Options::all());
@@ -31,4 +48,55 @@ impl MarkdownParser {
         html::push_html(&mut html_output, parser);
         html_output
     }
+
+    /// Text of the first level-one heading, if any
+    pub fn title(&self) -> Option<String> {
+        let mut title: Option<String> = None;
+        for event in Parser::new_ext(&self.content, Options::all()) {
+            match event {
+                Event::Start(Tag::Heading {
+                    level: HeadingLevel::H1,
+                    ..
+                }) => title = Some(String::new()),
+                Event::End(TagEnd::Heading(HeadingLevel::H1)) if title.is_some() => break,
+                Event::Text(text) | Event::Code(text) => {
+                    if let Some(title) = title.as_mut() {
+                        title.push_str(&text);
+                    }
+                }
+                _ => {}
+            }
+        }
+        title.map(|title| title.trim().to_string())
+    }
+
+    /// Every heading in document order
+    pub fn outline(&self) -> Vec<Heading> {
+        let mut outline = Vec::new();
+        let mut current: Option<Heading> = None;
+        for event in Parser::new_ext(&self.content, Options::all()) {
+            match event {
+                Event::Start(Tag::Heading { level, id, .. }) => {
+                    current = Some(Heading {
+                        level: level as u8,
+                        text: String::new(),
+                        id: id.map(|id| id.to_string()),
+                    });
+                }
+                Event::End(TagEnd::Heading(_)) => {
+                    if let Some(mut heading) = current.take() {
+                        heading.text = heading.text.trim().to_string();
+                        outline.push(heading);
+                    }
+                }
+                Event::Text(text) | Event::Code(text) => {
+                    if let Some(heading) = current.as_mut() {
+                        heading.text.push_str(&text);
+                    }
+                }
+                _ => {}
+            }
+        }
+        outline
+    }
 }
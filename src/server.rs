@@ -1,31 +1,53 @@
 use axum::{
-    Json, Router, body,
-    extract::{Path, Request, State},
-    http::{HeaderMap, StatusCode},
-    response::{Html, IntoResponse},
-    routing::{get, post},
+    Extension, Json, Router,
+    body::Body,
+    extract::{DefaultBodyLimit, FromRequestParts, Path, Query, Request, State},
+    http::{HeaderMap, StatusCode, header, request::Parts},
+    middleware,
+    response::{Html, IntoResponse, Response},
+    routing::{any, delete, get, head, patch, post, put},
 };
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::fs;
-use tokio::sync::RwLock;
+use tower::ServiceExt;
+use tower_http::CompressionLevel;
+use tower_http::compression::{
+    CompressionLayer, Predicate,
+    predicate::{NotForContentType, SizeAbove},
+};
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
 
 use crate::{
-    ajax,
-    directory::{self, MarkdownFile},
-    html,
+    ajax, assets, attachment,
+    conditional::{self, DocumentVersion},
+    directory::{self, FileMetadata, ScanStatus, TreeNode},
+    edit,
+    error::{self, AppError},
+    git, history,
+    html::{self, Document, DocumentGit, PageOptions},
     i18n::Language,
-    markdown::MarkdownParser,
+    manage,
+    source::DocumentSource,
+    store::{CacheStats, DocumentStore},
+    templates::Templates,
+    theme::Theme,
+    upload,
+    versions::{self, VersionScope},
 };
 
 // JSON response structures
 #[derive(Serialize, Deserialize)]
 pub struct FilesResponse {
     pub files: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    /// `false` while the folders searched are still being scanned, so files
+    /// may be missing
+    #[serde(default)]
+    pub complete: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -33,6 +55,16 @@ pub struct MarkdownResponse {
     pub markdown: String,
 }
 
+/// Server-rendered document returned by `/api/render`
+#[derive(Serialize, Deserialize)]
+pub struct RenderResponse {
+    pub name: String,
+    /// Text of the first level-one heading
+    pub title: Option<String>,
+    pub html: String,
+    pub metadata: FileMetadata,
+}
+
 #[derive(Clone)]
 pub enum AppState {
     SingleFile {
@@ -41,18 +73,79 @@ pub enum AppState {
         language: Language,
         base_dir: PathBuf,
     },
+    /// Documents of a [`DocumentSource`]. Uploads, moves, deletes, the trash
+    /// and history keep their state next to the documents, so they are only
+    /// offered when the source is a directory on disk.
     Directory {
         dir_path: String,
-        files: Arc<RwLock<Vec<MarkdownFile>>>,
-        file_cache: Arc<RwLock<HashMap<String, (String, String)>>>, // filename -> (markdown, html)
+        store: Arc<DocumentStore>,
         language: Language,
-        base_dir: PathBuf,
     },
 }
 
+impl AppState {
+    pub fn language(&self) -> &Language {
+        match self {
+            AppState::SingleFile { language, .. } | AppState::Directory { language, .. } => {
+                language
+            }
+        }
+    }
+
+    /// The source the documents of directory mode are read from
+    pub fn source(&self) -> Option<&Arc<dyn DocumentSource>> {
+        match self {
+            AppState::Directory { store, .. } => Some(store.source()),
+            AppState::SingleFile { .. } => None,
+        }
+    }
+}
+
+/// Marks the pages of a server whose documents cannot be edited in the browser
+#[derive(Clone, Copy, Debug)]
+struct ReadOnly;
+
+/// A document response eligible for conditional GET
+type Versioned<T> = (Extension<DocumentVersion>, T);
+
+fn versioned<T>(modified: Option<u64>, body: T) -> Versioned<T> {
+    (
+        Extension(DocumentVersion {
+            modified,
+            digest: None,
+            themed: false,
+        }),
+        body,
+    )
+}
+
+/// A [`versioned`] page in the theme picked by the request's cookie
+///
+/// Only give pages a `modified` time when nothing but the file and the theme
+/// goes into them.
+fn themed<T>(modified: Option<u64>, body: T) -> Versioned<T> {
+    (
+        Extension(DocumentVersion {
+            modified,
+            digest: None,
+            themed: true,
+        }),
+        body,
+    )
+}
+
+#[derive(Clone)]
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
+    /// Custom stylesheet injected into every page after the built-in styles
+    pub theme_css: Option<Arc<str>>,
+    /// Templates used to render HTML pages
+    pub templates: Arc<Templates>,
+    /// `Cache-Control` sent with documents
+    pub document_cache_control: Arc<str>,
+    /// Quality used for gzip, brotli and zstd responses
+    pub compression_level: CompressionLevel,
 }
 
 impl Default for ServerConfig {
@@ -60,13 +153,41 @@ impl Default for ServerConfig {
         Self {
             host: "127.0.0.1".to_string(),
             port: 3000,
+            theme_css: None,
+            templates: Templates::shared_builtin(),
+            document_cache_control: conditional::DEFAULT_DOCUMENT_CACHE_CONTROL.into(),
+            compression_level: CompressionLevel::Default,
         }
     }
 }
 
 impl ServerConfig {
     pub fn new(host: String, port: u16) -> Self {
-        Self { host, port }
+        Self {
+            host,
+            port,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_theme_css(mut self, css: impl Into<Arc<str>>) -> Self {
+        self.theme_css = Some(css.into());
+        self
+    }
+
+    pub fn with_templates(mut self, templates: Templates) -> Self {
+        self.templates = Arc::new(templates);
+        self
+    }
+
+    pub fn with_document_cache_control(mut self, cache_control: impl Into<Arc<str>>) -> Self {
+        self.document_cache_control = cache_control.into();
+        self
+    }
+
+    pub fn with_compression_level(mut self, level: CompressionLevel) -> Self {
+        self.compression_level = level;
+        self
     }
 
     pub fn address(&self) -> String {
@@ -74,78 +195,304 @@ impl ServerConfig {
     }
 }
 
+/// Page options come from the server config, the user's theme cookie and the
+/// version of the documents being served
+impl<S: Send + Sync> FromRequestParts<S> for PageOptions {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let theme = parts
+            .headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .find_map(Theme::from_cookie_header);
+        let mut options = PageOptions {
+            theme,
+            ..PageOptions::default()
+        };
+        if let Some(scope) = parts.extensions.get::<VersionScope>() {
+            options.root = scope.root.clone();
+            options.read_only = scope.reference.is_some();
+            options.versions = Some(scope.menu().await);
+        }
+        if parts.extensions.get::<ReadOnly>().is_some() {
+            options.read_only = true;
+        }
+        if let Some(config) = parts.extensions.get::<Arc<ServerConfig>>() {
+            options.custom_css = config.theme_css.clone();
+            options.templates = config.templates.clone();
+        }
+        Ok(options)
+    }
+}
+
+/// Largest request body of the editor: the document as JSON, whose escaping can
+/// double its size
+const EDIT_BODY_LIMIT: usize = 2 * upload::MAX_UPLOAD_SIZE as usize;
+
+/// Responses smaller than this are sent uncompressed
+const MIN_COMPRESS_SIZE: u16 = 1024;
+
 /// Create the application router with all routes
 pub fn create_router(state: Arc<AppState>) -> Router {
-    let base_dir = match state.as_ref() {
-        AppState::SingleFile { base_dir, .. } => base_dir.clone(),
-        AppState::Directory { base_dir, .. } => base_dir.clone(),
-    };
+    create_router_with_config(state, ServerConfig::default())
+}
 
-    match state.as_ref() {
+/// Create the application router using runtime options from `config`
+pub fn create_router_with_config(state: Arc<AppState>, config: ServerConfig) -> Router {
+    let config = Arc::new(config);
+    let router = match state.as_ref() {
         AppState::SingleFile { .. } => Router::new()
             .route("/", get(serve_html))
-            .route("/raw", get(serve_raw))
-            .nest_service("/static", ServeDir::new(base_dir))
-            .with_state(state)
-            .layer(TraceLayer::new_for_http()),
-        AppState::Directory { .. } => Router::new()
-            .route("/", get(serve_directory))
-            .route("/dir/{*path}", get(serve_directory_path))
-            .route("/view/{*filename}", get(serve_file_html))
-            .route("/raw/{*filename}", get(serve_file_raw))
-            .route("/api/content/{*filename}", get(serve_partial_content))
-            .route("/api/files", get(api_get_files))
-            .route("/api/markdown/{*filename}", get(api_get_markdown))
-            .route("/api/upload", post(handle_upload))
-            .nest_service("/static", ServeDir::new("static"))
-            .with_state(state)
-            .layer(TraceLayer::new_for_http()),
-    }
+            .route("/raw", get(serve_raw)),
+        AppState::Directory { store, .. } => {
+            let router = if store.local_dir().is_some() {
+                write_routes(directory_routes())
+            } else if store.source().writable() {
+                // Without a directory on disk only saving through the API is offered
+                directory_routes().route(
+                    "/api/markdown/{*filename}",
+                    put(edit::save_markdown).layer(DefaultBodyLimit::max(EDIT_BODY_LIMIT)),
+                )
+            } else {
+                directory_routes()
+            };
+            match store.versions() {
+                Some(_) => router
+                    .route("/v/{reference}", any(versions::serve_version))
+                    .route("/v/{reference}/", any(versions::serve_version))
+                    .route("/v/{reference}/{*path}", any(versions::serve_version)),
+                None => router,
+            }
+        }
+    };
+
+    // Already compressed formats are not worth compressing again
+    let compress_when = SizeAbove::new(MIN_COMPRESS_SIZE)
+        .and(NotForContentType::GRPC)
+        .and(NotForContentType::IMAGES)
+        .and(NotForContentType::SSE)
+        .and(NotForContentType::const_new("font/woff2"))
+        .and(NotForContentType::const_new("application/zip"))
+        .and(NotForContentType::const_new("application/gzip"));
+    let compression = CompressionLayer::new()
+        .quality(config.compression_level)
+        .compress_when(compress_when);
+
+    let scope = match state.as_ref() {
+        AppState::Directory { store, .. } => store.versions().cloned().map(VersionScope::root),
+        AppState::SingleFile { .. } => None,
+    };
+    let read_only =
+        matches!(state.as_ref(), AppState::Directory { store, .. } if store.local_dir().is_none());
+    let router = router
+        .route("/_rsmd/assets/{*name}", get(serve_asset))
+        .route("/static/{*path}", get(serve_static))
+        .fallback(not_found)
+        .layer(middleware::from_fn(conditional::validate))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            git::refuse_while_busy,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            error::render_errors,
+        ))
+        .with_state(state);
+    let router = match scope {
+        Some(scope) => router.layer(Extension(scope)),
+        None => router,
+    };
+    let router = match read_only {
+        true => router.layer(Extension(ReadOnly)),
+        false => router,
+    };
+    router
+        .layer(compression)
+        .layer(middleware::map_response(conditional::tag_encoding))
+        .layer(Extension(config))
+        .layer(TraceLayer::new_for_http())
+}
+
+/// Routes of directory mode that only read
+fn directory_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(serve_directory))
+        .route("/dir/{*path}", get(serve_directory_path))
+        .route("/view/{*filename}", get(serve_view))
+        .route("/raw/{*filename}", get(serve_file_raw))
+        .route("/blame/{*filename}", get(git::serve_blame))
+        .route("/api/content/{*filename}", get(serve_partial_content))
+        .route("/api/files", get(api_get_files))
+        .route("/api/tree", get(api_get_tree))
+        .route("/api/scan", get(api_get_scan_status))
+        .route("/api/cache", get(api_get_cache_stats))
+        .route("/api/markdown/{*filename}", get(api_get_markdown))
+        .route("/api/render/{*filename}", get(api_get_render))
+        .route("/api/git/log/{*filename}", get(git::api_get_log))
+        .route("/api/git/blame/{*filename}", get(git::api_get_blame))
+        .route(assets::APP_SHELL_PATH, get(serve_app_shell))
+}
+
+/// `routes` with those of directory mode that edit, upload, move or delete
+/// documents, or show and restore their history
+fn write_routes(routes: Router<Arc<AppState>>) -> Router<Arc<AppState>> {
+    routes
+        .route("/edit/{*filename}", get(edit::serve_editor))
+        .route(
+            "/history/{*filename}",
+            get(history::serve_history).post(history::restore_form),
+        )
+        .route("/api/files", post(manage::create_file))
+        .route(
+            "/api/files/{*filename}",
+            patch(manage::move_file).delete(manage::delete_file),
+        )
+        .route("/api/folders", post(manage::create_folder))
+        .route(
+            "/api/folders/{*path}",
+            patch(manage::move_folder).delete(manage::delete_folder),
+        )
+        .route("/api/trash", get(manage::list_trash))
+        .route("/api/trash/{id}", delete(manage::purge))
+        .route("/api/trash/{id}/restore", post(manage::restore))
+        .route(
+            "/api/history/{*filename}",
+            get(history::api_get_history).post(history::api_restore),
+        )
+        .route(
+            "/api/markdown/{*filename}",
+            put(edit::save_markdown).layer(DefaultBodyLimit::max(EDIT_BODY_LIMIT)),
+        )
+        .route(
+            "/api/render",
+            post(edit::render_preview).layer(DefaultBodyLimit::max(EDIT_BODY_LIMIT)),
+        )
+        .route(
+            "/api/upload",
+            post(upload::handle_upload).layer(DefaultBodyLimit::max(upload::MAX_MULTIPART_SIZE)),
+        )
+        .route(
+            "/api/assets",
+            post(upload::handle_attachment_upload)
+                .layer(DefaultBodyLimit::max(upload::MAX_MULTIPART_SIZE)),
+        )
+        .route(
+            upload::UPLOADS_PATH,
+            post(upload::tus_create).options(upload::tus_options),
+        )
+        .route(
+            "/api/uploads/{id}",
+            head(upload::tus_head)
+                .patch(upload::tus_patch)
+                .delete(upload::tus_delete),
+        )
+}
+
+/// Router of a version served from git under `/v/{ref}/`, given requests with
+/// that prefix removed
+///
+/// Responses are validated and compressed by the router that passed the request on.
+pub(crate) fn version_router(state: Arc<AppState>) -> Router {
+    directory_routes()
+        .route("/static/{*path}", get(serve_static))
+        .fallback(not_found)
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            error::render_errors,
+        ))
+        .with_state(state)
+}
+
+/// Fallback for routes that do not exist in the current mode
+async fn not_found() -> AppError {
+    AppError::NotFound
+}
+
+/// Handler for bundled frontend assets
+async fn serve_asset(Path(name): Path<String>) -> impl IntoResponse {
+    let Some((asset, fingerprinted)) = assets::find(&name) else {
+        return AppError::NotFound.into_response();
+    };
+    let cache_control = if fingerprinted {
+        assets::IMMUTABLE_CACHE_CONTROL
+    } else {
+        assets::REVALIDATE_CACHE_CONTROL
+    };
+
+    (
+        [
+            (header::CONTENT_TYPE, asset.content_type),
+            (header::CACHE_CONTROL, cache_control),
+        ],
+        asset.contents,
+    )
+        .into_response()
+}
+
+/// Handler for the client-side app shell (directory mode)
+async fn serve_app_shell() -> impl IntoResponse {
+    (
+        [(header::CACHE_CONTROL, assets::REVALIDATE_CACHE_CONTROL)],
+        Html(assets::app_shell()),
+    )
 }
 
 /// Handler for rendering markdown as HTML (single file mode)
-async fn serve_html(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+async fn serve_html(
+    State(state): State<Arc<AppState>>,
+    options: PageOptions,
+) -> Result<Versioned<Html<String>>, AppError> {
     match state.as_ref() {
         AppState::SingleFile {
             html_content,
             language,
             ..
-        } => Html(html::render_page(html_content, language)),
-        _ => Html("<h1>Error: Invalid mode</h1>".to_string()),
+        } => Ok(themed(
+            None,
+            Html(html::render_page(html_content, language, &options)),
+        )),
+        _ => Err(AppError::WrongMode),
     }
 }
 
 /// Handler for displaying raw markdown (single file mode)
-async fn serve_raw(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+async fn serve_raw(
+    State(state): State<Arc<AppState>>,
+    options: PageOptions,
+) -> Result<Versioned<Html<String>>, AppError> {
     match state.as_ref() {
         AppState::SingleFile {
             markdown_content,
             language,
             ..
-        } => Html(html::render_raw_page(markdown_content, language)),
-        _ => Html("<h1>Error: Invalid mode</h1>".to_string()),
+        } => Ok(themed(
+            None,
+            Html(html::render_raw_page(markdown_content, language, &options)),
+        )),
+        _ => Err(AppError::WrongMode),
     }
 }
 
 /// Handler for directory listing
-async fn serve_directory(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+async fn serve_directory(
+    State(state): State<Arc<AppState>>,
+    options: PageOptions,
+) -> Result<Html<String>, AppError> {
     match state.as_ref() {
         AppState::Directory {
             dir_path,
-            files,
+            store,
             language,
             ..
         } => {
-            let current_files = {
-                let guard = files.read().await;
-                guard.clone()
-            };
-            let listing = directory::list_directory_contents(&current_files, "");
-            Html(html::render_directory_page(
-                &listing, dir_path, language, true,
-            ))
+            let listing = store.files().list("").await.unwrap_or_default();
+            Ok(Html(html::render_directory_page(
+                &listing, dir_path, language, true, &options,
+            )))
         }
-        _ => Html("<h1>Error: Invalid mode</h1>".to_string()),
+        _ => Err(AppError::WrongMode),
     }
 }
 
@@ -153,59 +500,27 @@ async fn serve_directory(State(state): State<Arc<AppState>>) -> impl IntoRespons
 async fn serve_directory_path(
     State(state): State<Arc<AppState>>,
     Path(path): Path<String>,
-) -> impl IntoResponse {
+    options: PageOptions,
+) -> Result<Html<String>, AppError> {
     match state.as_ref() {
         AppState::Directory {
             dir_path,
-            files,
+            store,
             language,
             ..
         } => {
-            let requested = path.trim_matches('/');
-            let segments: Vec<&str> = requested.split('/').filter(|seg| !seg.is_empty()).collect();
-            if segments.contains(&"..") {
-                return (
-                    StatusCode::BAD_REQUEST,
-                    Html(language.text("error_not_found").to_string()),
-                )
-                    .into_response();
-            }
-
-            let normalized = segments.join("/");
-
-            let snapshot = {
-                let guard = files.read().await;
-                guard.clone()
-            };
-
-            let exists = if normalized.is_empty() {
-                true
-            } else {
-                let prefix = format!("{}/", normalized);
-                snapshot
-                    .iter()
-                    .any(|file| file.name == normalized || file.name.starts_with(&prefix))
-            };
-
-            if !exists {
-                return (
-                    StatusCode::NOT_FOUND,
-                    Html(html::render_page(
-                        &format!("<h1>{}</h1>", language.text("error_not_found")),
-                        language,
-                    )),
-                )
-                    .into_response();
-            }
-
-            let listing = directory::list_directory_contents(&snapshot, &normalized);
-
-            Html(html::render_directory_page(
-                &listing, dir_path, language, true,
-            ))
-            .into_response()
+            let normalized = directory::normalize_path(&path).ok_or(AppError::BadPath)?;
+            let listing = store
+                .files()
+                .list(&normalized)
+                .await
+                .ok_or(AppError::NotFound)?;
+
+            Ok(Html(html::render_directory_page(
+                &listing, dir_path, language, true, &options,
+            )))
         }
-        _ => Html("<h1>Error: Invalid mode</h1>".to_string()).into_response(),
+        _ => Err(AppError::WrongMode),
     }
 }
 
@@ -214,7 +529,7 @@ async fn serve_partial_content(
     State(state): State<Arc<AppState>>,
     Path(filename): Path<String>,
     headers: HeaderMap,
-) -> impl IntoResponse {
+) -> Result<Response, AppError> {
     let is_dynamic = ajax::is_dynamic_request(
         headers.get("hx-request").and_then(|v| v.to_str().ok()),
         headers
@@ -224,99 +539,181 @@ async fn serve_partial_content(
 
     if !is_dynamic {
         // If not dynamic request, redirect to full page
-        return Html("<script>window.location.reload()</script>".to_string());
+        return Ok(Html("<script>window.location.reload()</script>").into_response());
     }
 
     match state.as_ref() {
-        AppState::Directory {
-            files,
-            file_cache,
-            language,
-            ..
-        } => {
-            let files_snapshot = {
-                let guard = files.read().await;
-                guard.clone()
-            };
-            // Check if file exists in the directory
-            if !files_snapshot.iter().any(|f| f.name == filename) {
-                return Html(format!("<h1>{}</h1>", language.text("error_not_found")));
-            }
+        AppState::Directory { store, .. } => {
+            let document = store.get(&filename).await?;
+            Ok(versioned(
+                document.metadata.modified,
+                Html(ajax::render_partial_content(&document.html)),
+            )
+            .into_response())
+        }
+        _ => Err(AppError::WrongMode),
+    }
+}
 
-            // Get from cache or load
-            if let Some((_, html_content)) = {
-                let cache = file_cache.read().await;
-                cache.get(&filename).cloned()
-            } {
-                return Html(ajax::render_partial_content(&html_content));
-            }
+/// Handler for `/view/`: renders documents and serves the attachments next to
+/// them, so relative links such as `![](img/logo.png)` resolve
+///
+/// With `?commit=` a document is rendered as it was at that git revision.
+async fn serve_view(
+    state: State<Arc<AppState>>,
+    path: Path<String>,
+    Query(query): Query<git::RevisionQuery>,
+    options: PageOptions,
+    request: Request,
+) -> Response {
+    if attachment::for_name(&path.0).is_some() {
+        return match serve_attachment(&state, &path.0, request).await {
+            Ok(response) => response,
+            Err(err) => err.into_response(),
+        };
+    }
+    if let Some(revision) = &query.commit {
+        return git::serve_at_commit(&state, &path.0, revision, &options)
+            .await
+            .into_response();
+    }
+    serve_file_html(state, path, options).await.into_response()
+}
 
-            // If not in cache, load it
-            if let Some(file) = files_snapshot.iter().find(|f| f.name == filename) {
-                match MarkdownParser::from_file(file.path.to_str().unwrap()) {
-                    Ok(parser) => {
-                        let html_content = parser.to_html();
-                        let markdown_content = parser.raw_content().to_string();
-                        let mut cache = file_cache.write().await;
-                        cache.insert(filename.clone(), (markdown_content, html_content.clone()));
-                        Html(ajax::render_partial_content(&html_content))
-                    }
-                    Err(_) => Html(format!("<h1>{}</h1>", language.text("error_reading_file"))),
-                }
-            } else {
-                Html(format!("<h1>{}</h1>", language.text("error_not_found")))
-            }
+/// Handler for `/static/`: any file next to the served documents, except the
+/// trash, history and upload state rsmd keeps there
+async fn serve_static(
+    State(state): State<Arc<AppState>>,
+    Path(path): Path<String>,
+    request: Request,
+) -> Result<Response, AppError> {
+    let base_dir = match state.as_ref() {
+        AppState::SingleFile { base_dir, .. } => base_dir,
+        AppState::Directory { store, .. } => match store.local_dir() {
+            Some(base_dir) => base_dir,
+            None => return serve_source_file(store.source(), &path).await,
+        },
+    };
+    serve_file_from(base_dir, &path, request).await
+}
+
+/// Serve an attachment from the served directory
+async fn serve_attachment(
+    state: &AppState,
+    filename: &str,
+    request: Request,
+) -> Result<Response, AppError> {
+    let AppState::Directory { store, .. } = state else {
+        return Err(AppError::WrongMode);
+    };
+    match store.local_dir() {
+        Some(base_dir) => serve_file_from(base_dir, filename, request).await,
+        None => serve_source_file(store.source(), filename).await,
+    }
+}
+
+/// Serve the file `filename` of a source that is not a directory on disk
+async fn serve_source_file(
+    source: &Arc<dyn DocumentSource>,
+    filename: &str,
+) -> Result<Response, AppError> {
+    let name = directory::normalize_path(filename).ok_or(AppError::BadPath)?;
+    if directory::is_hidden_path(&name) {
+        return Err(AppError::NotFound);
+    }
+    let content_type = mime_guess::from_path(&name)
+        .first_or_octet_stream()
+        .to_string();
+    let read = {
+        let source = source.clone();
+        tokio::task::spawn_blocking(move || {
+            Ok::<_, std::io::Error>((source.stat(&name)?, source.read(&name)?))
+        })
+    };
+    let (stat, contents) = match read.await {
+        Ok(Ok(file)) => file,
+        Ok(Err(err)) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err(AppError::NotFound);
+        }
+        Ok(Err(err)) => {
+            tracing::error!(error = %err, file = %filename, "Failed to read a file");
+            return Err(AppError::ReadFailure);
+        }
+        Err(err) => {
+            tracing::error!(error = %err, "File read task failed");
+            return Err(AppError::ReadFailure);
         }
-        _ => Html("<h1>Error: Invalid mode</h1>".to_string()),
+    };
+    let metadata = FileMetadata::from_stat(filename, &stat);
+    Ok(versioned(
+        metadata.modified,
+        ([(header::CONTENT_TYPE, content_type)], contents),
+    )
+    .into_response())
+}
+
+/// Serve the file `filename` below `base_dir` with `ServeDir`, honouring the
+/// range and conditional headers of `request`
+async fn serve_file_from(
+    base_dir: &std::path::Path,
+    filename: &str,
+    request: Request,
+) -> Result<Response, AppError> {
+    if directory::is_hidden_path(filename) {
+        return Err(AppError::NotFound);
     }
+
+    let (mut parts, body) = request.into_parts();
+    parts.uri = format!("/{}", html::encode_path(filename))
+        .parse()
+        .map_err(|_| AppError::BadPath)?;
+    let response = ServeDir::new(base_dir)
+        .oneshot(Request::from_parts(parts, body))
+        .await
+        .map_err(|_| AppError::ReadFailure)?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Err(AppError::NotFound);
+    }
+    Ok(response.map(Body::new))
 }
 
 /// Handler for viewing a specific file in directory mode
 async fn serve_file_html(
     State(state): State<Arc<AppState>>,
     Path(filename): Path<String>,
-) -> impl IntoResponse {
+    options: PageOptions,
+) -> Result<Versioned<Html<String>>, AppError> {
     match state.as_ref() {
         AppState::Directory {
-            files,
-            file_cache,
-            language,
-            ..
+            store, language, ..
         } => {
-            let files_snapshot = {
-                let guard = files.read().await;
-                guard.clone()
+            let document = store.get(&filename).await?;
+            let nav = store
+                .files()
+                .navigation(&document.name)
+                .await
+                .ok_or(AppError::NotFound)?;
+
+            let page = Document {
+                name: &document.name,
+                html: &document.html,
+                metadata: Some(document.metadata.clone()),
             };
-            // Check if file exists in the directory
-            if !files_snapshot.iter().any(|f| f.name == filename) {
-                return Html(format!("<h1>{}</h1>", language.text("error_not_found")));
-            }
-
-            // Get from cache or load
-            if let Some((_, html_content)) = {
-                let cache = file_cache.read().await;
-                cache.get(&filename).cloned()
-            } {
-                return Html(html::render_page(&html_content, language));
-            }
-
-            // If not in cache, load it
-            if let Some(file) = files_snapshot.iter().find(|f| f.name == filename) {
-                match MarkdownParser::from_file(file.path.to_str().unwrap()) {
-                    Ok(parser) => {
-                        let html_content = parser.to_html();
-                        let markdown_content = parser.raw_content().to_string();
-                        let mut cache = file_cache.write().await;
-                        cache.insert(filename.clone(), (markdown_content, html_content.clone()));
-                        Html(html::render_page(&html_content, language))
-                    }
-                    Err(_) => Html(format!("<h1>{}</h1>", language.text("error_reading_file"))),
-                }
-            } else {
-                Html(format!("<h1>{}</h1>", language.text("error_not_found")))
-            }
+            // The page loads the commits itself, so rendering walks no history
+            let git = store.git().map(|_| DocumentGit { commit: None });
+            // Also shows the tree, so only the ETag validates it
+            Ok(themed(
+                None,
+                Html(html::render_document_page_with_git(
+                    &page,
+                    &nav,
+                    git.as_ref(),
+                    language,
+                    &options,
+                )),
+            ))
         }
-        _ => Html("<h1>Error: Invalid mode</h1>".to_string()),
+        _ => Err(AppError::WrongMode),
     }
 }
 
@@ -324,331 +721,184 @@ async fn serve_file_html(
 async fn serve_file_raw(
     State(state): State<Arc<AppState>>,
     Path(filename): Path<String>,
-) -> impl IntoResponse {
+    options: PageOptions,
+) -> Result<Versioned<Html<String>>, AppError> {
     match state.as_ref() {
         AppState::Directory {
-            files,
-            file_cache,
-            language,
-            ..
+            store, language, ..
         } => {
-            let files_snapshot = {
-                let guard = files.read().await;
-                guard.clone()
-            };
-            // Check if file exists in the directory
-            if !files_snapshot.iter().any(|f| f.name == filename) {
-                return Html(format!("<h1>{}</h1>", language.text("error_not_found")));
-            }
-
-            // Get from cache or load
-            if let Some((markdown_content, _)) = {
-                let cache = file_cache.read().await;
-                cache.get(&filename).cloned()
-            } {
-                return Html(html::render_raw_page(&markdown_content, language));
-            }
-
-            // If not in cache, load it
-            if let Some(file) = files_snapshot.iter().find(|f| f.name == filename) {
-                match MarkdownParser::from_file(file.path.to_str().unwrap()) {
-                    Ok(parser) => {
-                        let html_content = parser.to_html();
-                        let markdown_content = parser.raw_content().to_string();
-                        let mut cache = file_cache.write().await;
-                        cache.insert(filename.clone(), (markdown_content.clone(), html_content));
-                        Html(html::render_raw_page(markdown_content.as_str(), language))
-                    }
-                    Err(_) => Html(format!("<h1>{}</h1>", language.text("error_reading_file"))),
-                }
-            } else {
-                Html(format!("<h1>{}</h1>", language.text("error_not_found")))
-            }
+            let document = store.get(&filename).await?;
+            Ok(themed(
+                document.metadata.modified,
+                Html(html::render_raw_page(
+                    &document.markdown,
+                    language,
+                    &options,
+                )),
+            ))
         }
-        _ => Html("<h1>Error: Invalid mode</h1>".to_string()),
+        _ => Err(AppError::WrongMode),
     }
 }
 
-/// API: Get list of markdown files
-async fn api_get_files(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+/// Query parameters accepted by `/api/files`
+#[derive(Deserialize)]
+pub struct FilesQuery {
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+    pub prefix: Option<String>,
+    pub glob: Option<String>,
+}
+
+/// Query parameters accepted by `/api/tree`
+#[derive(Deserialize)]
+pub struct TreeQuery {
+    pub path: Option<String>,
+    pub depth: Option<usize>,
+}
+
+const DEFAULT_PAGE_SIZE: usize = 1000;
+const MAX_PAGE_SIZE: usize = 5000;
+const MAX_TREE_DEPTH: usize = 8;
+
+/// API: Get a page of markdown file names
+async fn api_get_files(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<FilesQuery>,
+) -> Result<Json<FilesResponse>, AppError> {
     match state.as_ref() {
-        AppState::Directory { files, .. } => {
-            let file_names: Vec<String> = {
-                let guard = files.read().await;
-                guard.iter().map(|f| f.name.clone()).collect()
+        AppState::Directory { store, .. } => {
+            let pattern = query
+                .glob
+                .as_deref()
+                .map(glob::Pattern::new)
+                .transpose()
+                .map_err(|err| {
+                    tracing::debug!(error = %err, "Invalid glob pattern");
+                    AppError::BadPath
+                })?;
+            let options = glob::MatchOptions {
+                require_literal_separator: true,
+                ..Default::default()
             };
-            Json(FilesResponse { files: file_names })
+            let limit = query
+                .limit
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE);
+
+            let page = store
+                .files()
+                .find_files(
+                    query.prefix.as_deref().unwrap_or(""),
+                    query.cursor.as_deref(),
+                    limit,
+                    |name| {
+                        pattern
+                            .as_ref()
+                            .is_none_or(|pattern| pattern.matches_with(name, options))
+                    },
+                )
+                .await;
+
+            Ok(Json(FilesResponse {
+                files: page.files.into_iter().map(|f| f.name).collect(),
+                next_cursor: page.next_cursor,
+                complete: page.complete,
+            }))
         }
-        _ => Json(FilesResponse { files: vec![] }),
+        _ => Err(AppError::WrongMode),
     }
 }
 
-/// API: Get markdown content for a specific file
-async fn api_get_markdown(
+/// API: Get a nested view of a folder with child counts and file metadata
+async fn api_get_tree(
     State(state): State<Arc<AppState>>,
-    Path(filename): Path<String>,
-) -> impl IntoResponse {
+    Query(query): Query<TreeQuery>,
+) -> Result<Json<TreeNode>, AppError> {
     match state.as_ref() {
-        AppState::Directory {
-            files, file_cache, ..
-        } => {
-            let files_snapshot = {
-                let guard = files.read().await;
-                guard.clone()
-            };
-            // Check if file exists in the directory
-            if !files_snapshot.iter().any(|f| f.name == filename) {
-                return Json(MarkdownResponse {
-                    markdown: String::from("# Error\n\nFile not found"),
-                });
-            }
-
-            // Get from cache or load
-            if let Some((markdown_content, _)) = {
-                let cache = file_cache.read().await;
-                cache.get(&filename).cloned()
-            } {
-                return Json(MarkdownResponse {
-                    markdown: markdown_content.clone(),
-                });
-            }
-
-            // If not in cache, load it
-            if let Some(file) = files_snapshot.iter().find(|f| f.name == filename) {
-                match MarkdownParser::from_file(file.path.to_str().unwrap()) {
-                    Ok(parser) => {
-                        let html_content = parser.to_html();
-                        let markdown_content = parser.raw_content().to_string();
-                        let mut cache = file_cache.write().await;
-                        cache.insert(filename.clone(), (markdown_content.clone(), html_content));
-                        Json(MarkdownResponse {
-                            markdown: markdown_content,
-                        })
-                    }
-                    Err(_) => Json(MarkdownResponse {
-                        markdown: String::from("# Error\n\nFailed to read file"),
-                    }),
-                }
-            } else {
-                Json(MarkdownResponse {
-                    markdown: String::from("# Error\n\nFile not found"),
-                })
+        AppState::Directory { store, .. } => {
+            let path = directory::normalize_path(&query.path.unwrap_or_default())
+                .ok_or(AppError::BadPath)?;
+            if directory::is_hidden_path(&path) {
+                return Err(AppError::NotFound);
             }
+            let depth = query.depth.unwrap_or(1).min(MAX_TREE_DEPTH);
+            let tree = store
+                .files()
+                .subtree(&path, depth)
+                .await
+                .ok_or(AppError::NotFound)?;
+            Ok(Json(tree))
         }
-        _ => Json(MarkdownResponse {
-            markdown: String::from("# Error\n\nInvalid mode"),
-        }),
+        _ => Err(AppError::WrongMode),
     }
 }
 
-#[derive(Serialize)]
-struct UploadResponse {
-    success: bool,
-    message: String,
-    file: Option<String>,
+/// API: Get progress of the background directory scan
+async fn api_get_scan_status(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ScanStatus>, AppError> {
+    match state.as_ref() {
+        AppState::Directory { store, .. } => Ok(Json(store.files().status().await)),
+        _ => Err(AppError::WrongMode),
+    }
 }
 
-const MAX_UPLOAD_SIZE: usize = 10 * 1024 * 1024;
-
-/// API: Upload a markdown file into the current directory
-async fn handle_upload(State(state): State<Arc<AppState>>, request: Request) -> impl IntoResponse {
+/// API: Get render cache statistics
+async fn api_get_cache_stats(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<CacheStats>, AppError> {
     match state.as_ref() {
-        AppState::Directory {
-            dir_path,
-            files,
-            file_cache,
-            language,
-            base_dir,
-        } => {
-            let (parts, body) = request.into_parts();
-            let Some(raw_name) = parts
-                .headers
-                .get("x-file-name")
-                .and_then(|v| v.to_str().ok())
-                .map(|v| v.trim())
-                .filter(|v| !v.is_empty())
-            else {
-                return (
-                    StatusCode::BAD_REQUEST,
-                    Json(UploadResponse {
-                        success: false,
-                        message: language.text("upload_error").to_string(),
-                        file: None,
-                    }),
-                );
-            };
-
-            let sanitized = std::path::Path::new(raw_name)
-                .file_name()
-                .map(|name| name.to_string_lossy().to_string())
-                .unwrap_or_default();
-
-            if sanitized.is_empty() {
-                return (
-                    StatusCode::BAD_REQUEST,
-                    Json(UploadResponse {
-                        success: false,
-                        message: language.text("upload_error").to_string(),
-                        file: None,
-                    }),
-                );
-            }
-
-            let lowered = sanitized.to_lowercase();
-            if !lowered.ends_with(".md") && !lowered.ends_with(".markdown") {
-                return (
-                    StatusCode::BAD_REQUEST,
-                    Json(UploadResponse {
-                        success: false,
-                        message: language.text("upload_invalid_type").to_string(),
-                        file: None,
-                    }),
-                );
-            }
-
-            let bytes = match body::to_bytes(body, MAX_UPLOAD_SIZE).await {
-                Ok(data) => data,
-                Err(err) => {
-                    tracing::error!(error = %err, "Failed to read upload body");
-                    return (
-                        StatusCode::BAD_REQUEST,
-                        Json(UploadResponse {
-                            success: false,
-                            message: language.text("upload_error").to_string(),
-                            file: None,
-                        }),
-                    );
-                }
-            };
-
-            if bytes.is_empty() {
-                return (
-                    StatusCode::BAD_REQUEST,
-                    Json(UploadResponse {
-                        success: false,
-                        message: language.text("upload_error").to_string(),
-                        file: None,
-                    }),
-                );
-            }
-
-            let file_name = sanitized;
-
-            let dir_header_raw = parts
-                .headers
-                .get("x-directory-path")
-                .and_then(|v| v.to_str().ok())
-                .map(|v| v.trim().replace('\\', "/"));
-            let dir_header = dir_header_raw.as_deref().unwrap_or("");
-
-            let dir_segments: Vec<&str> = dir_header
-                .split('/')
-                .map(str::trim)
-                .filter(|seg| !seg.is_empty())
-                .collect();
-
-            if dir_segments
-                .iter()
-                .any(|seg| *seg == "." || *seg == ".." || seg.contains(".."))
-            {
-                return (
-                    StatusCode::BAD_REQUEST,
-                    Json(UploadResponse {
-                        success: false,
-                        message: language.text("upload_error").to_string(),
-                        file: None,
-                    }),
-                );
-            }
-
-            let normalized_dir = dir_segments.join("/");
+        AppState::Directory { store, .. } => Ok(Json(store.stats().await)),
+        _ => Err(AppError::WrongMode),
+    }
+}
 
-            let destination = if normalized_dir.is_empty() {
-                base_dir.join(&file_name)
-            } else {
-                base_dir.join(&normalized_dir).join(&file_name)
-            };
+/// API: Get a file rendered to HTML by the server, with its metadata
+async fn api_get_render(
+    State(state): State<Arc<AppState>>,
+    Path(filename): Path<String>,
+) -> Result<Versioned<Json<RenderResponse>>, AppError> {
+    match state.as_ref() {
+        AppState::Directory { store, .. } => {
+            let document = store.get(&filename).await?;
+            Ok(versioned(
+                document.metadata.modified,
+                Json(RenderResponse {
+                    name: document.name.clone(),
+                    title: document.title().map(str::to_string),
+                    html: document.html.clone(),
+                    metadata: document.metadata.clone(),
+                }),
+            ))
+        }
+        _ => Err(AppError::WrongMode),
+    }
+}
 
-            let logical_name = if normalized_dir.is_empty() {
-                file_name.clone()
-            } else {
-                format!("{}/{}", normalized_dir, file_name)
+/// API: Get markdown content for a specific file
+///
+/// The `ETag` is that of the file itself, as expected by `If-Match` when the
+/// document is saved with `PUT`.
+async fn api_get_markdown(
+    State(state): State<Arc<AppState>>,
+    Path(filename): Path<String>,
+) -> Result<Versioned<Json<MarkdownResponse>>, AppError> {
+    match state.as_ref() {
+        AppState::Directory { store, .. } => {
+            let document = store.get(&filename).await?;
+            let version = DocumentVersion {
+                modified: document.metadata.modified,
+                digest: Some(Sha256::digest(&document.markdown).into()),
+                themed: false,
             };
-
-            if let Some(parent_dir) = destination.parent()
-                && let Err(err) = fs::create_dir_all(parent_dir).await
-            {
-                tracing::error!(
-                    error = %err,
-                    ?parent_dir,
-                    "Failed to create directory for uploaded markdown file",
-                );
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(UploadResponse {
-                        success: false,
-                        message: language.text("upload_error").to_string(),
-                        file: None,
-                    }),
-                );
-            }
-
-            if let Err(err) = fs::write(&destination, bytes.as_ref()).await {
-                tracing::error!(
-                    error = %err,
-                    ?destination,
-                    "Failed to write uploaded markdown file",
-                );
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(UploadResponse {
-                        success: false,
-                        message: language.text("upload_error").to_string(),
-                        file: None,
-                    }),
-                );
-            }
-
-            {
-                let mut guard = files.write().await;
-                if let Some(existing) = guard.iter_mut().find(|f| f.name == logical_name) {
-                    existing.path = destination.clone();
-                } else {
-                    guard.push(MarkdownFile {
-                        name: logical_name.clone(),
-                        path: destination.clone(),
-                    });
-                }
-                guard.sort_by(|a, b| a.name.cmp(&b.name));
-            }
-
-            {
-                let mut cache = file_cache.write().await;
-                cache.remove(&logical_name);
-            }
-
-            tracing::info!(
-                directory = %dir_path,
-                file = %logical_name,
-                "Markdown file uploaded",
-            );
-
-            (
-                StatusCode::OK,
-                Json(UploadResponse {
-                    success: true,
-                    message: language.text("upload_success").to_string(),
-                    file: Some(logical_name),
+            Ok((
+                Extension(version),
+                Json(MarkdownResponse {
+                    markdown: document.markdown.clone(),
                 }),
-            )
+            ))
         }
-        _ => (
-            StatusCode::BAD_REQUEST,
-            Json(UploadResponse {
-                success: false,
-                message: "Uploads are only available in directory mode".to_string(),
-                file: None,
-            }),
-        ),
+        _ => Err(AppError::WrongMode),
     }
 }
@@ -0,0 +1,568 @@
+//! Where the documents of directory mode come from
+//!
+//! A [`DocumentSource`] lists, reads and stats the files of a served tree by
+//! their `/`-separated names, and may accept writes and report changes. The
+//! [`FileTree`](crate::directory::FileTree) and
+//! [`DocumentStore`](crate::store::DocumentStore) only go through it, so every
+//! read-only route, and saving edits to a writable source, works the same over:
+//!
+//! - [`FsSource`], a directory on disk,
+//! - [`MemorySource`], files held in memory, for tests and embedding,
+//! - [`ZipSource`], a zip archive, read-only,
+//! - [`TarSource`], an uncompressed tar archive, read-only,
+//! - [`Snapshot`](crate::versions::Snapshot), the tree of a git commit.
+//!
+//! Sources are synchronous and called on blocking threads. Trash, history and
+//! uploads keep their state next to the documents, so they are only offered for
+//! sources with a [`local_path`](DocumentSource::local_path).
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use zip::ZipArchive;
+
+use crate::directory;
+use crate::write::{self, ConflictPolicy, WriteError};
+
+/// Changes buffered for each watcher before it has to rescan
+const WATCH_CAPACITY: usize = 256;
+
+/// The direct children of a directory
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Entries {
+    /// Names of sub-directories
+    pub dirs: Vec<String>,
+    /// Names of files of any type
+    pub files: Vec<String>,
+}
+
+/// Size and modification time of a file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stat {
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl From<&fs::Metadata> for Stat {
+    fn from(metadata: &fs::Metadata) -> Self {
+        Self {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
+    }
+}
+
+/// A file written or removed through a source
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    Written(String),
+    Removed(String),
+}
+
+/// A tree of files documents are served from
+///
+/// Names are relative to the root of the tree and `/`-separated; the empty
+/// name is the root directory.
+pub trait DocumentSource: Send + Sync + fmt::Debug {
+    /// The entries of the directory `dir`
+    fn list(&self, dir: &str) -> io::Result<Entries>;
+
+    /// Contents of the file `name`
+    fn read(&self, name: &str) -> io::Result<Vec<u8>>;
+
+    /// Size and modification time of the file `name`
+    fn stat(&self, name: &str) -> io::Result<Stat>;
+
+    /// Replace or create the file `name`
+    ///
+    /// May take [`write::lock`], so callers must not hold it.
+    fn write(&self, name: &str, contents: &[u8]) -> io::Result<()> {
+        let _ = (name, contents);
+        Err(read_only())
+    }
+
+    /// Whether [`write`](Self::write) is supported
+    fn writable(&self) -> bool {
+        false
+    }
+
+    /// Receive the changes made from now on, if the source reports them
+    fn watch(&self) -> Option<broadcast::Receiver<Change>> {
+        None
+    }
+
+    /// The directory on disk the files are in, if any
+    fn local_path(&self) -> Option<&Path> {
+        None
+    }
+}
+
+fn read_only() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "document source is read-only",
+    )
+}
+
+fn not_found(name: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("no such file: {}", name))
+}
+
+/// Normalize `name`, refusing paths that escape the tree
+fn checked(name: &str) -> io::Result<String> {
+    directory::normalize_path(name).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid path: {}", name),
+        )
+    })
+}
+
+/// The direct children of `dir` among the file `names`, sorted
+fn children<'a>(dir: &str, names: impl Iterator<Item = &'a str>) -> Option<Entries> {
+    let prefix = match dir.is_empty() {
+        true => String::new(),
+        false => format!("{}/", dir),
+    };
+    let mut found = dir.is_empty();
+    let mut dirs = BTreeSet::new();
+    let mut files = Vec::new();
+    for name in names {
+        let Some(rest) = name.strip_prefix(&prefix) else {
+            continue;
+        };
+        found = true;
+        match rest.split_once('/') {
+            Some((child, _)) => {
+                dirs.insert(child.to_string());
+            }
+            None => files.push(rest.to_string()),
+        }
+    }
+    found.then(|| Entries {
+        dirs: dirs.into_iter().collect(),
+        files,
+    })
+}
+
+/// A directory on disk
+///
+/// Changes made by other programs are not reported; the store notices edits to
+/// known files by their size and modification time.
+pub struct FsSource {
+    base_dir: PathBuf,
+    changes: broadcast::Sender<Change>,
+}
+
+impl fmt::Debug for FsSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FsSource")
+            .field("base_dir", &self.base_dir)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FsSource {
+    pub fn new(base_dir: PathBuf) -> Self {
+        Self {
+            base_dir,
+            changes: broadcast::channel(WATCH_CAPACITY).0,
+        }
+    }
+
+    fn path(&self, name: &str) -> io::Result<PathBuf> {
+        Ok(self.base_dir.join(checked(name)?))
+    }
+}
+
+impl DocumentSource for FsSource {
+    fn list(&self, dir: &str) -> io::Result<Entries> {
+        let mut entries = Entries::default();
+        for entry in fs::read_dir(self.path(dir)?)? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if path.is_dir() {
+                entries.dirs.push(file_name);
+            } else if path.is_file() {
+                entries.files.push(file_name);
+            }
+        }
+        Ok(entries)
+    }
+
+    fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.path(name)?)
+    }
+
+    fn stat(&self, name: &str) -> io::Result<Stat> {
+        fs::metadata(self.path(name)?).map(|metadata| Stat::from(&metadata))
+    }
+
+    /// Written to a [`TempFile`](write::TempFile) and moved into place with
+    /// [`write::commit_blocking`], like uploads
+    fn write(&self, name: &str, contents: &[u8]) -> io::Result<()> {
+        let name = checked(name)?;
+        let path = self.base_dir.join(&name);
+        let parent = path.parent().unwrap_or(&self.base_dir);
+        fs::create_dir_all(parent)?;
+        let (temp, mut file) = write::TempFile::create_in_blocking(parent)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        drop(file);
+        match write::commit_blocking(&temp, &path, ConflictPolicy::Overwrite) {
+            Ok(_) => {}
+            Err(WriteError::Io(err)) => return Err(err),
+            Err(err) => return Err(io::Error::other(format!("{:?}", err))),
+        }
+        let _ = self.changes.send(Change::Written(name));
+        Ok(())
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    /// Reports the writes made through this source
+    fn watch(&self) -> Option<broadcast::Receiver<Change>> {
+        Some(self.changes.subscribe())
+    }
+
+    fn local_path(&self) -> Option<&Path> {
+        Some(&self.base_dir)
+    }
+}
+
+struct MemoryFile {
+    contents: Vec<u8>,
+    modified: SystemTime,
+}
+
+/// Files held in memory, for tests and for embedding rsmd
+///
+/// Directories exist as long as they contain a file.
+pub struct MemorySource {
+    files: RwLock<BTreeMap<String, MemoryFile>>,
+    changes: broadcast::Sender<Change>,
+}
+
+impl fmt::Debug for MemorySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemorySource")
+            .field("files", &self.read_files().len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for MemorySource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        Self {
+            files: RwLock::default(),
+            changes: broadcast::channel(WATCH_CAPACITY).0,
+        }
+    }
+
+    /// Add the file `name`; panics if the name escapes the tree
+    pub fn with_file(self, name: &str, contents: impl Into<Vec<u8>>) -> Self {
+        self.write(name, &contents.into())
+            .expect("file name inside the tree");
+        self
+    }
+
+    /// Remove the file `name`, returning whether it existed
+    pub fn remove(&self, name: &str) -> bool {
+        let Ok(name) = checked(name) else {
+            return false;
+        };
+        let removed = self.write_files().remove(&name).is_some();
+        if removed {
+            let _ = self.changes.send(Change::Removed(name));
+        }
+        removed
+    }
+
+    fn read_files(&self) -> std::sync::RwLockReadGuard<'_, BTreeMap<String, MemoryFile>> {
+        self.files.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn write_files(&self) -> std::sync::RwLockWriteGuard<'_, BTreeMap<String, MemoryFile>> {
+        self.files.write().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl DocumentSource for MemorySource {
+    fn list(&self, dir: &str) -> io::Result<Entries> {
+        let dir = checked(dir)?;
+        let files = self.read_files();
+        children(&dir, files.keys().map(String::as_str)).ok_or_else(|| not_found(&dir))
+    }
+
+    fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let name = checked(name)?;
+        let files = self.read_files();
+        let file = files.get(&name).ok_or_else(|| not_found(&name))?;
+        Ok(file.contents.clone())
+    }
+
+    fn stat(&self, name: &str) -> io::Result<Stat> {
+        let name = checked(name)?;
+        let files = self.read_files();
+        let file = files.get(&name).ok_or_else(|| not_found(&name))?;
+        Ok(Stat {
+            size: file.contents.len() as u64,
+            modified: Some(file.modified),
+        })
+    }
+
+    fn write(&self, name: &str, contents: &[u8]) -> io::Result<()> {
+        let name = checked(name)?;
+        if name.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "empty file name",
+            ));
+        }
+        let mut files = self.write_files();
+        // Every write gets a later time, so cached renders of it turn stale
+        let mut modified = SystemTime::now();
+        if let Some(previous) = files.get(&name)
+            && modified <= previous.modified
+        {
+            modified = previous.modified + Duration::from_nanos(1);
+        }
+        files.insert(
+            name.clone(),
+            MemoryFile {
+                contents: contents.to_vec(),
+                modified,
+            },
+        );
+        drop(files);
+        let _ = self.changes.send(Change::Written(name));
+        Ok(())
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn watch(&self) -> Option<broadcast::Receiver<Change>> {
+        Some(self.changes.subscribe())
+    }
+}
+
+/// Anything an archive can be read from
+trait ArchiveReader: Read + Seek + Send {}
+
+impl<R: Read + Seek + Send> ArchiveReader for R {}
+
+/// A file entry of a zip archive
+struct ZipEntry {
+    index: usize,
+    stat: Stat,
+}
+
+/// The files of a zip archive, read-only
+///
+/// The archive is indexed once when opened. Entries with unsafe names, such as
+/// absolute paths or `..`, are left out.
+pub struct ZipSource {
+    archive: Mutex<ZipArchive<Box<dyn ArchiveReader>>>,
+    entries: HashMap<String, ZipEntry>,
+    /// Every file name, sorted
+    names: BTreeSet<String>,
+}
+
+impl fmt::Debug for ZipSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ZipSource")
+            .field("files", &self.entries.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ZipSource {
+    /// Open the archive at `path`
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::new(fs::File::open(path)?)
+    }
+
+    /// Read an archive from `reader`, such as a `Cursor` over its bytes
+    pub fn new(reader: impl Read + Seek + Send + 'static) -> io::Result<Self> {
+        let reader: Box<dyn ArchiveReader> = Box::new(reader);
+        let mut archive = ZipArchive::new(reader).map_err(io::Error::other)?;
+        let mut entries = HashMap::new();
+        let mut names = BTreeSet::new();
+        for index in 0..archive.len() {
+            let file = archive.by_index_raw(index).map_err(io::Error::other)?;
+            if !file.is_file() || file.enclosed_name().is_none() {
+                continue;
+            }
+            let Some(name) = directory::normalize_path(&file.name().replace('\\', "/")) else {
+                continue;
+            };
+            let stat = Stat {
+                size: file.size(),
+                modified: file.last_modified().and_then(zip_time),
+            };
+            names.insert(name.clone());
+            entries.insert(name, ZipEntry { index, stat });
+        }
+        Ok(Self {
+            archive: Mutex::new(archive),
+            entries,
+            names,
+        })
+    }
+
+    fn entry(&self, name: &str) -> io::Result<&ZipEntry> {
+        let name = checked(name)?;
+        self.entries.get(&name).ok_or_else(|| not_found(&name))
+    }
+}
+
+impl DocumentSource for ZipSource {
+    fn list(&self, dir: &str) -> io::Result<Entries> {
+        let dir = checked(dir)?;
+        children(&dir, self.names.iter().map(String::as_str)).ok_or_else(|| not_found(&dir))
+    }
+
+    fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let entry = self.entry(name)?;
+        let mut archive = self.archive.lock().unwrap_or_else(|err| err.into_inner());
+        let mut file = archive.by_index(entry.index).map_err(io::Error::other)?;
+        let mut contents = Vec::with_capacity(entry.stat.size.min(1 << 20) as usize);
+        file.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    fn stat(&self, name: &str) -> io::Result<Stat> {
+        Ok(self.entry(name)?.stat)
+    }
+}
+
+/// A zip timestamp, which has no time zone, taken as UTC
+fn zip_time(modified: zip::DateTime) -> Option<SystemTime> {
+    time::OffsetDateTime::try_from(modified)
+        .ok()
+        .map(SystemTime::from)
+}
+
+/// A file entry of a tar archive
+struct TarEntry {
+    /// Where its contents start in the archive
+    offset: u64,
+    stat: Stat,
+}
+
+/// The files of an uncompressed tar archive, read-only
+///
+/// The archive is indexed once when opened and files are read from their
+/// offset, so only regular files are served; sparse files, links and unsafe
+/// names such as absolute paths or `..` are left out. A name stored more than
+/// once serves its last copy, as `tar` would extract it.
+pub struct TarSource {
+    archive: Mutex<Box<dyn ArchiveReader>>,
+    entries: HashMap<String, TarEntry>,
+    /// Every file name, sorted
+    names: BTreeSet<String>,
+}
+
+impl fmt::Debug for TarSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TarSource")
+            .field("files", &self.entries.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl TarSource {
+    /// Open the archive at `path`
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::new(fs::File::open(path)?)
+    }
+
+    /// Read an archive from `reader`, such as a `Cursor` over its bytes
+    pub fn new(reader: impl Read + Seek + Send + 'static) -> io::Result<Self> {
+        let reader: Box<dyn ArchiveReader> = Box::new(reader);
+        let mut archive = tar::Archive::new(reader);
+        let mut entries = HashMap::new();
+        let mut names = BTreeSet::new();
+        for entry in archive.entries_with_seek()? {
+            let entry = entry?;
+            let header = entry.header();
+            if header.entry_type() != tar::EntryType::Regular {
+                continue;
+            }
+            let path = entry.path()?;
+            if path.has_root() {
+                continue;
+            }
+            let Some(name) = directory::normalize_path(&path.to_string_lossy()) else {
+                continue;
+            };
+            if name.is_empty() {
+                continue;
+            }
+            let stat = Stat {
+                size: entry.size(),
+                modified: header
+                    .mtime()
+                    .ok()
+                    .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds)),
+            };
+            let offset = entry.raw_file_position();
+            names.insert(name.clone());
+            entries.insert(name, TarEntry { offset, stat });
+        }
+        Ok(Self {
+            archive: Mutex::new(archive.into_inner()),
+            entries,
+            names,
+        })
+    }
+
+    fn entry(&self, name: &str) -> io::Result<&TarEntry> {
+        let name = checked(name)?;
+        self.entries.get(&name).ok_or_else(|| not_found(&name))
+    }
+}
+
+impl DocumentSource for TarSource {
+    fn list(&self, dir: &str) -> io::Result<Entries> {
+        let dir = checked(dir)?;
+        children(&dir, self.names.iter().map(String::as_str)).ok_or_else(|| not_found(&dir))
+    }
+
+    fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let entry = self.entry(name)?;
+        let mut archive = self.archive.lock().unwrap_or_else(|err| err.into_inner());
+        archive.seek(io::SeekFrom::Start(entry.offset))?;
+        let mut contents = Vec::with_capacity(entry.stat.size.min(1 << 20) as usize);
+        (&mut *archive)
+            .take(entry.stat.size)
+            .read_to_end(&mut contents)?;
+        if (contents.len() as u64) < entry.stat.size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("archive ends inside {}", name),
+            ));
+        }
+        Ok(contents)
+    }
+
+    fn stat(&self, name: &str) -> io::Result<Stat> {
+        Ok(self.entry(name)?.stat)
+    }
+}
@@ -0,0 +1,423 @@
+//! Rendered documents for directory mode
+//!
+//! [`DocumentStore`] resolves a file name through the [`FileTree`], reads it from
+//! the tree's [`DocumentSource`] and renders it once. Concurrent requests for a
+//! document that is not cached yet share a single read and render.
+//!
+//! Rendered documents are kept in a least-recently-used cache bounded by a byte
+//! budget. Every hit is checked against the file's size and modification time, so
+//! edits on disk are picked up on the next request.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+use tokio::sync::{Mutex, OnceCell};
+
+use crate::directory::{FileMetadata, FileTree, MarkdownFile};
+use crate::error::AppError;
+use crate::git::Repository;
+use crate::history::History;
+use crate::links::LinkIndex;
+use crate::markdown::{Heading, MarkdownParser};
+use crate::source::{DocumentSource, Stat};
+use crate::versions::Versions;
+
+/// Cache budget used unless configured otherwise
+pub const DEFAULT_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Number of recently modified files rendered by [`DocumentStore::warm`]
+pub const WARM_FILE_COUNT: usize = 32;
+
+/// A markdown file together with everything rendered from it
+#[derive(Clone, Debug, Serialize)]
+pub struct Document {
+    /// Path relative to the served directory
+    pub name: String,
+    pub markdown: String,
+    pub html: String,
+    pub metadata: FileMetadata,
+    pub outline: Vec<Heading>,
+}
+
+impl Document {
+    /// Text of the first level-one heading
+    pub fn title(&self) -> Option<&str> {
+        self.outline
+            .iter()
+            .find(|heading| heading.level == 1)
+            .map(|heading| heading.text.as_str())
+    }
+
+    /// Approximate memory held by this document
+    fn weight(&self) -> usize {
+        self.name.len()
+            + self.markdown.len()
+            + self.html.len()
+            + self
+                .outline
+                .iter()
+                .map(|heading| heading.text.len() + heading.id.as_ref().map_or(0, String::len))
+                .sum::<usize>()
+    }
+}
+
+/// Counters describing how well the render cache is doing
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    /// Renders performed because no valid copy was cached
+    pub misses: u64,
+    /// Cached renders dropped because the file changed on disk
+    pub stale: u64,
+    /// Cached renders dropped to stay within the byte budget
+    pub evictions: u64,
+    pub entries: usize,
+    pub bytes: usize,
+    pub capacity: usize,
+}
+
+/// Size and modification time a cached render was made from
+#[derive(Clone, Copy, PartialEq)]
+struct Fingerprint {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Fingerprint {
+    fn of(stat: &Stat) -> Self {
+        Self {
+            len: stat.size,
+            modified: stat.modified,
+        }
+    }
+}
+
+struct Entry {
+    document: Arc<Document>,
+    file: MarkdownFile,
+    fingerprint: Fingerprint,
+    bytes: usize,
+    last_used: u64,
+}
+
+/// Rendered documents ordered by last use
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<String, Entry>,
+    /// `last_used` tick -> name, oldest first
+    order: BTreeMap<u64, String>,
+    tick: u64,
+    bytes: usize,
+}
+
+impl Lru {
+    fn touch(&mut self, name: &str) -> Option<&Entry> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(name)?;
+        self.order.remove(&entry.last_used);
+        entry.last_used = self.tick;
+        self.order.insert(self.tick, name.to_string());
+        Some(entry)
+    }
+
+    fn remove(&mut self, name: &str) -> Option<Entry> {
+        let entry = self.entries.remove(name)?;
+        self.order.remove(&entry.last_used);
+        self.bytes -= entry.bytes;
+        Some(entry)
+    }
+
+    /// Insert `entry`, evicting the least recently used documents to fit `capacity`
+    ///
+    /// Returns the number of evicted documents. Documents larger than the whole
+    /// budget are not cached.
+    fn insert(&mut self, name: String, mut entry: Entry, capacity: usize) -> u64 {
+        self.remove(&name);
+        if entry.bytes > capacity {
+            return 0;
+        }
+
+        let mut evicted = 0;
+        while self.bytes + entry.bytes > capacity {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some(old) = self.entries.remove(&oldest) {
+                self.bytes -= old.bytes;
+                evicted += 1;
+            }
+        }
+
+        self.tick += 1;
+        entry.last_used = self.tick;
+        self.order.insert(self.tick, name.clone());
+        self.bytes += entry.bytes;
+        self.entries.insert(name, entry);
+        evicted
+    }
+}
+
+type Pending = Arc<OnceCell<Result<(Arc<Document>, Fingerprint), AppError>>>;
+
+/// Cache of rendered documents backed by a [`FileTree`]
+pub struct DocumentStore {
+    files: Arc<FileTree>,
+    history: Option<Arc<History>>,
+    links: Arc<LinkIndex>,
+    git: Option<Arc<Repository>>,
+    versions: Option<Arc<Versions>>,
+    capacity: usize,
+    cache: Mutex<Lru>,
+    pending: Mutex<HashMap<String, Pending>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    stale: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl DocumentStore {
+    pub fn new(files: Arc<FileTree>) -> Self {
+        Self {
+            files,
+            history: None,
+            links: Arc::default(),
+            git: None,
+            versions: None,
+            capacity: DEFAULT_CACHE_BYTES,
+            cache: Mutex::new(Lru::default()),
+            pending: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            stale: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Limit the memory held by cached renders to roughly `bytes`
+    pub fn with_cache_limit(mut self, bytes: usize) -> Self {
+        self.capacity = bytes;
+        self
+    }
+
+    /// Keep the versions that writes replace in `history`
+    pub fn with_history(mut self, history: History) -> Self {
+        self.history = Some(Arc::new(history));
+        self
+    }
+
+    /// Link targets of the documents, for finding the links a move affects
+    pub fn links(&self) -> &Arc<LinkIndex> {
+        &self.links
+    }
+
+    /// The revision history, if enabled
+    pub fn history(&self) -> Option<&Arc<History>> {
+        self.history.as_ref()
+    }
+
+    /// Show the commits of documents in `repository`, and commit to it if it
+    /// was opened to
+    pub fn with_git(mut self, repository: Repository) -> Self {
+        self.git = Some(Arc::new(repository));
+        self
+    }
+
+    /// The git repository the documents are in, if any
+    pub fn git(&self) -> Option<&Arc<Repository>> {
+        self.git.as_ref()
+    }
+
+    /// Serve the branches and tags of `versions` under `/v/{ref}/`
+    pub fn with_versions(mut self, versions: Versions) -> Self {
+        self.versions = Some(Arc::new(versions));
+        self
+    }
+
+    /// The refs served besides these documents, if any
+    pub fn versions(&self) -> Option<&Arc<Versions>> {
+        self.versions.as_ref()
+    }
+
+    /// The index of files this store serves
+    pub fn files(&self) -> &Arc<FileTree> {
+        &self.files
+    }
+
+    /// The source documents are read from
+    pub fn source(&self) -> &Arc<dyn DocumentSource> {
+        self.files.source()
+    }
+
+    /// The directory on disk documents live in, next to the trash, history
+    /// and upload state kept for them; `None` for other sources
+    pub fn local_dir(&self) -> Option<&Path> {
+        self.source().local_path()
+    }
+
+    /// Get the document `name`, reading and rendering it on a miss or when the
+    /// file changed since it was cached
+    pub async fn get(&self, name: &str) -> Result<Arc<Document>, AppError> {
+        if let Some(document) = self.cached(name).await {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(document);
+        }
+
+        let file = self.files.get(name).await.ok_or(AppError::NotFound)?;
+        let pending = {
+            let mut in_flight = self.pending.lock().await;
+            in_flight.entry(file.name.clone()).or_default().clone()
+        };
+        // The render may have been published since the first check. This stats
+        // the file, so it runs without the lock; a cell left unused here is
+        // simply initialized by the next miss.
+        if !pending.initialized()
+            && let Some(document) = self.cached(&file.name).await
+        {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(document);
+        }
+
+        let result = pending.get_or_init(|| self.load(&file)).await.clone();
+
+        // Whoever finishes first publishes the result; failures are retried next time
+        let mut in_flight = self.pending.lock().await;
+        if in_flight
+            .get(&file.name)
+            .is_some_and(|current| Arc::ptr_eq(current, &pending))
+        {
+            in_flight.remove(&file.name);
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            if let Ok((document, fingerprint)) = &result {
+                let entry = Entry {
+                    document: document.clone(),
+                    file: file.clone(),
+                    fingerprint: *fingerprint,
+                    bytes: document.weight(),
+                    last_used: 0,
+                };
+                let evicted =
+                    self.cache
+                        .lock()
+                        .await
+                        .insert(file.name.clone(), entry, self.capacity);
+                self.evictions.fetch_add(evicted, Ordering::Relaxed);
+            }
+        }
+        result.map(|(document, _)| document)
+    }
+
+    /// Drop the cached rendering of `name` so the next request reads it again
+    ///
+    /// A render already in progress for `name` is not cached when it completes.
+    pub async fn invalidate(&self, name: &str) {
+        let mut in_flight = self.pending.lock().await;
+        in_flight.remove(name);
+        self.cache.lock().await.remove(name);
+    }
+
+    /// Current cache counters
+    pub async fn stats(&self) -> CacheStats {
+        let cache = self.cache.lock().await;
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            stale: self.stale.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries: cache.entries.len(),
+            bytes: cache.bytes,
+            capacity: self.capacity,
+        }
+    }
+
+    /// Render the `count` most recently modified files seen by the tree's scan
+    ///
+    /// Stops once the cache is full so warming never evicts anything.
+    pub async fn warm(&self, count: usize) {
+        let recent = self.files.recent(count).await;
+
+        let mut warmed = 0;
+        for file in recent {
+            if self.get(&file.name).await.is_err() {
+                continue;
+            }
+            warmed += 1;
+            let cache = self.cache.lock().await;
+            if !cache.entries.contains_key(&file.name) || cache.bytes >= self.capacity {
+                break;
+            }
+        }
+        tracing::debug!(documents = warmed, "Render cache warmed");
+    }
+
+    /// A cached render of `name` that still matches the file in the source
+    async fn cached(&self, name: &str) -> Option<Arc<Document>> {
+        let (document, file, fingerprint) = {
+            let mut cache = self.cache.lock().await;
+            let entry = cache.touch(name)?;
+            (
+                entry.document.clone(),
+                entry.file.clone(),
+                entry.fingerprint,
+            )
+        };
+
+        let current = self.files.stat(&file).await.ok();
+        if current.map(|stat| Fingerprint::of(&stat)) == Some(fingerprint) {
+            return Some(document);
+        }
+
+        let mut cache = self.cache.lock().await;
+        if cache
+            .entries
+            .get(name)
+            .is_some_and(|entry| Arc::ptr_eq(&entry.document, &document))
+        {
+            cache.remove(name);
+            self.stale.fetch_add(1, Ordering::Relaxed);
+        }
+        None
+    }
+
+    async fn load(&self, file: &MarkdownFile) -> Result<(Arc<Document>, Fingerprint), AppError> {
+        let read_failure = |err: std::io::Error| {
+            tracing::error!(error = %err, file = %file.name, "Failed to read markdown file");
+            AppError::ReadFailure
+        };
+        // Fingerprint before reading so a concurrent write shows up as stale later
+        let stat = self.files.stat(file).await.map_err(read_failure)?;
+        let contents = self.files.read(file).await.map_err(read_failure)?;
+        let markdown = String::from_utf8(contents).map_err(|err| {
+            tracing::error!(error = %err, file = %file.name, "Markdown file is not UTF-8");
+            AppError::ReadFailure
+        })?;
+        let metadata = FileMetadata::from_stat(&file.name, &stat);
+        let document = render(file.name.clone(), markdown, metadata).await?;
+        Ok((document, Fingerprint::of(&stat)))
+    }
+}
+
+/// Render `markdown` on a blocking thread
+async fn render(
+    name: String,
+    markdown: String,
+    metadata: FileMetadata,
+) -> Result<Arc<Document>, AppError> {
+    tokio::task::spawn_blocking(move || {
+        let parser = MarkdownParser::new(markdown);
+        Arc::new(Document {
+            name,
+            html: parser.to_html(),
+            outline: parser.outline(),
+            metadata,
+            markdown: parser.into_content(),
+        })
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(error = %err, "Markdown rendering task failed");
+        AppError::ReadFailure
+    })
+}
@@ -0,0 +1,184 @@
+//! Page templates rendered with minijinja
+//!
+//! The built-in templates under `templates/` are embedded in the binary. A template
+//! directory given with `--templates` replaces any of them by relative file name.
+
+use minijinja::{AutoEscape, Environment, Value};
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use crate::html::{encode_path, escape_html};
+
+/// Built-in templates as `(name, source)` pairs
+pub const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    ("base.html", include_str!("../templates/base.html")),
+    ("document.html", include_str!("../templates/document.html")),
+    ("raw.html", include_str!("../templates/raw.html")),
+    ("edit.html", include_str!("../templates/edit.html")),
+    ("history.html", include_str!("../templates/history.html")),
+    ("blame.html", include_str!("../templates/blame.html")),
+    ("error.html", include_str!("../templates/error.html")),
+    (
+        "directory.html",
+        include_str!("../templates/directory.html"),
+    ),
+    (
+        "partials/macros.html",
+        include_str!("../templates/partials/macros.html"),
+    ),
+    (
+        "partials/theme-switcher.html",
+        include_str!("../templates/partials/theme-switcher.html"),
+    ),
+    (
+        "partials/version-switcher.html",
+        include_str!("../templates/partials/version-switcher.html"),
+    ),
+    (
+        "partials/directory-browser.html",
+        include_str!("../templates/partials/directory-browser.html"),
+    ),
+    (
+        "partials/file-entry.html",
+        include_str!("../templates/partials/file-entry.html"),
+    ),
+    (
+        "styles/themes.css",
+        include_str!("../templates/styles/themes.css"),
+    ),
+    (
+        "styles/document.css",
+        include_str!("../templates/styles/document.css"),
+    ),
+    (
+        "styles/navigation.css",
+        include_str!("../templates/styles/navigation.css"),
+    ),
+    (
+        "styles/raw.css",
+        include_str!("../templates/styles/raw.css"),
+    ),
+    (
+        "styles/edit.css",
+        include_str!("../templates/styles/edit.css"),
+    ),
+    (
+        "styles/history.css",
+        include_str!("../templates/styles/history.css"),
+    ),
+    (
+        "styles/git.css",
+        include_str!("../templates/styles/git.css"),
+    ),
+    (
+        "styles/directory.css",
+        include_str!("../templates/styles/directory.css"),
+    ),
+    (
+        "styles/directory-dynamic.css",
+        include_str!("../templates/styles/directory-dynamic.css"),
+    ),
+];
+
+/// A compiled set of page templates
+#[derive(Debug)]
+pub struct Templates {
+    env: Environment<'static>,
+}
+
+impl Templates {
+    /// Templates embedded in the binary
+    pub fn builtin() -> Self {
+        let mut env = base_environment();
+        for (name, source) in BUILTIN_TEMPLATES {
+            env.add_template(name, source)
+                .expect("built-in templates should compile");
+        }
+        Self { env }
+    }
+
+    /// Shared instance of the built-in templates
+    pub fn shared_builtin() -> Arc<Self> {
+        static BUILTIN: OnceLock<Arc<Templates>> = OnceLock::new();
+        BUILTIN.get_or_init(|| Arc::new(Self::builtin())).clone()
+    }
+
+    /// Built-in templates with every file under `dir` added on top
+    ///
+    /// Files are named by their path relative to `dir`, so `dir/styles/document.css`
+    /// replaces the built-in `styles/document.css`. Syntax errors are reported here
+    /// rather than on the first request.
+    pub fn with_overrides(dir: &Path) -> io::Result<Self> {
+        if !dir.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Template path is not a directory",
+            ));
+        }
+
+        let mut templates = Self::builtin();
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(current) = pending.pop() {
+            for entry in fs::read_dir(&current)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+
+                let name = path
+                    .strip_prefix(dir)
+                    .unwrap_or(&path)
+                    .to_string_lossy()
+                    .replace('\\', "/");
+                let source = fs::read_to_string(&path)?;
+                templates
+                    .env
+                    .add_template_owned(name.clone(), source)
+                    .map_err(|err| {
+                        io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", name, err))
+                    })?;
+            }
+        }
+
+        Ok(templates)
+    }
+
+    /// Render the template `name` with `context`
+    pub fn render<S: Serialize>(&self, name: &str, context: S) -> Result<String, minijinja::Error> {
+        self.env.get_template(name)?.render(context)
+    }
+}
+
+fn base_environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_auto_escape_callback(|name| {
+        if name.ends_with(".html") {
+            AutoEscape::Html
+        } else {
+            AutoEscape::None
+        }
+    });
+    // Escape like the rest of the crate; minijinja's default also escapes `/`
+    env.set_formatter(|out, state, value| {
+        if state.auto_escape() == AutoEscape::Html && !value.is_safe() {
+            if value.is_none() || value.is_undefined() {
+                return Ok(());
+            }
+            out.write_str(&escape_html(&value.to_string()))?;
+            Ok(())
+        } else {
+            minijinja::escape_formatter(out, state, value)
+        }
+    });
+    env.add_filter("urlpath", |path: &str| {
+        Value::from_safe_string(encode_path(path))
+    });
+    env.add_filter("basename", |path: &str| {
+        path.rsplit('/').next().unwrap_or(path).to_string()
+    });
+    env
+}
@@ -0,0 +1,52 @@
+//! Built-in color themes
+//!
+//! The palettes themselves live in `templates/styles/themes.css`.
+
+/// Name of the cookie that stores the user's theme choice
+pub const THEME_COOKIE: &str = "rsmd-theme";
+
+/// A built-in color theme
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Theme {
+    Light,
+    Dark,
+    Sepia,
+    HighContrast,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 4] = [Theme::Light, Theme::Dark, Theme::Sepia, Theme::HighContrast];
+
+    /// Identifier used in the `data-theme` attribute and the cookie
+    pub fn id(self) -> &'static str {
+        match self {
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+            Theme::Sepia => "sepia",
+            Theme::HighContrast => "high-contrast",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|theme| theme.id() == id)
+    }
+
+    /// Read the theme stored in a `Cookie` header value
+    pub fn from_cookie_header(header: &str) -> Option<Self> {
+        header
+            .split(';')
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(name, _)| *name == THEME_COOKIE)
+            .and_then(|(_, value)| Self::from_id(value.trim()))
+    }
+
+    /// i18n key for the theme name
+    pub fn label_key(self) -> &'static str {
+        match self {
+            Theme::Light => "theme_light",
+            Theme::Dark => "theme_dark",
+            Theme::Sepia => "theme_sepia",
+            Theme::HighContrast => "theme_high_contrast",
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Recoverable deletes
+//!
+//! Deleted documents and folders are moved into [`TRASH_DIR`] inside the served
+//! directory instead of being removed. Each item is stored under a random id next
+//! to an `{id}.json` [`TrashEntry`] recording where it came from, so it can be
+//! restored later or purged for good. Like every internal name, the folder is
+//! never listed or served.
+
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs;
+
+use crate::write;
+
+/// Folder of the served directory holding deleted items
+pub const TRASH_DIR: &str = ".rsmd-trash";
+
+/// Whether a trashed item was a document or a folder
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemKind {
+    File,
+    Folder,
+}
+
+/// A deleted document or folder
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrashEntry {
+    pub id: String,
+    /// Path the item was deleted from, relative to the served directory
+    pub path: String,
+    pub kind: ItemKind,
+    /// Deletion time in seconds since the Unix epoch
+    pub deleted: u64,
+}
+
+/// Move `path`, relative to `base_dir`, into the trash
+pub async fn put(base_dir: &Path, path: &str, kind: ItemKind) -> io::Result<TrashEntry> {
+    let dir = base_dir.join(TRASH_DIR);
+    fs::create_dir_all(&dir).await?;
+
+    let entry = TrashEntry {
+        id: write::unique_id(),
+        path: path.to_string(),
+        kind,
+        deleted: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs()),
+    };
+    let record = record_path(base_dir, &entry.id);
+    fs::write(&record, serde_json::to_vec(&entry)?).await?;
+    if let Err(err) = fs::rename(base_dir.join(path), location(base_dir, &entry.id)).await {
+        let _ = fs::remove_file(&record).await;
+        return Err(err);
+    }
+    Ok(entry)
+}
+
+/// Everything in the trash, most recently deleted first
+pub async fn list(base_dir: &Path) -> io::Result<Vec<TrashEntry>> {
+    let mut read_dir = match fs::read_dir(base_dir.join(TRASH_DIR)).await {
+        Ok(read_dir) => read_dir,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut entries = Vec::new();
+    while let Some(dir_entry) = read_dir.next_entry().await? {
+        let file_name = dir_entry.file_name().to_string_lossy().to_string();
+        let Some(id) = file_name.strip_suffix(".json") else {
+            continue;
+        };
+        if let Some(entry) = entry(base_dir, id).await? {
+            entries.push(entry);
+        }
+    }
+    entries.sort_by(|a, b| b.deleted.cmp(&a.deleted).then_with(|| a.id.cmp(&b.id)));
+    Ok(entries)
+}
+
+/// The trashed item `id`, or `None` if there is no such item
+pub async fn entry(base_dir: &Path, id: &str) -> io::Result<Option<TrashEntry>> {
+    if !is_id(id) {
+        return Ok(None);
+    }
+    let bytes = match fs::read(record_path(base_dir, id)).await {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    match serde_json::from_slice::<TrashEntry>(&bytes) {
+        Ok(entry) if entry.id == id => Ok(Some(entry)),
+        _ => {
+            tracing::warn!(id, "Ignoring unreadable trash record");
+            Ok(None)
+        }
+    }
+}
+
+/// Where the item `id` is kept while in the trash
+pub fn location(base_dir: &Path, id: &str) -> PathBuf {
+    base_dir.join(TRASH_DIR).join(id)
+}
+
+/// Drop the record of `id` once its item has been moved back out
+pub async fn forget(base_dir: &Path, id: &str) -> io::Result<()> {
+    fs::remove_file(record_path(base_dir, id)).await
+}
+
+/// Delete a trashed item and its record for good
+pub async fn purge(base_dir: &Path, entry: &TrashEntry) -> io::Result<()> {
+    let item = location(base_dir, &entry.id);
+    let removed = match entry.kind {
+        ItemKind::File => fs::remove_file(&item).await,
+        ItemKind::Folder => fs::remove_dir_all(&item).await,
+    };
+    match removed {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    forget(base_dir, &entry.id).await
+}
+
+fn record_path(base_dir: &Path, id: &str) -> PathBuf {
+    base_dir.join(TRASH_DIR).join(format!("{}.json", id))
+}
+
+/// Ids come from [`write::unique_id`]; anything else could name another file
+fn is_id(id: &str) -> bool {
+    id.len() == 32 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
@@ -0,0 +1,1558 @@
+//! Uploads into the served directory
+//!
+//! `POST /api/upload` streams a file to a temporary file next to its
+//! destination, hashing it and enforcing [`MAX_UPLOAD_SIZE`] as bytes arrive, and
+//! renames it into place once complete. The body is either the raw file or a
+//! `multipart/form-data` form carrying several files, whose names may include
+//! the relative folder they were dropped from. An existing file is only replaced when
+//! the request asks for it with `X-Conflict-Policy` or names the version it
+//! replaces with `If-Match`; see [`crate::write`].
+//!
+//! Larger files can be sent in pieces through the [tus 1.0] protocol under
+//! [`UPLOADS_PATH`] (core, `creation`, `termination`, `checksum` and
+//! `expiration`). Partial uploads are kept in [`STAGING_DIR`] inside the served
+//! directory, so an interrupted transfer can resume with `HEAD` + `PATCH`, even
+//! after a restart. A finished upload that conflicts with its target is kept, so
+//! a `PATCH` at its length with another `X-Conflict-Policy` or `If-Match` can
+//! commit it without sending it again. An upload that receives nothing for
+//! [`UPLOAD_EXPIRY`] is
+//! gone; [`spawn_upload_expiry`] deletes such leftovers at startup and then
+//! every [`EXPIRY_SWEEP_INTERVAL`].
+//!
+//! A `.zip` sent to `POST /api/upload` is unpacked into the target directory.
+//! Each entry is checked and stored like a separately uploaded file, after
+//! [`crate::archive`] has capped what the archive may inflate to.
+//!
+//! `POST /api/assets` takes images and other [attachments](crate::attachment)
+//! the same way and answers with a markdown snippet linking each file from
+//! documents in the target directory.
+//!
+//! [tus 1.0]: https://tus.io/protocols/resumable-upload
+
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{FromRequest, Multipart, Path as UrlPath, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use crate::ajax;
+use crate::archive::{self, ArchiveError};
+use crate::attachment;
+use crate::conditional;
+use crate::directory::{self, MarkdownFile};
+use crate::git;
+use crate::history::{History, Reason, Snapshot};
+use crate::i18n::Language;
+use crate::server::AppState;
+use crate::store::DocumentStore;
+use crate::write::{self, ConflictPolicy, TempFile, WriteError, unique_id};
+
+/// Largest file accepted by a single `POST /api/upload`
+pub const MAX_UPLOAD_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Largest attachment accepted by `POST /api/assets`
+pub const MAX_ATTACHMENT_SIZE: u64 = 25 * 1024 * 1024;
+
+/// Largest `multipart/form-data` request accepted by `POST /api/upload`
+pub const MAX_MULTIPART_SIZE: usize = 100 * 1024 * 1024;
+
+/// Largest zip archive accepted by `POST /api/upload`, before unpacking
+pub const MAX_ARCHIVE_SIZE: u64 = MAX_MULTIPART_SIZE as u64;
+
+/// Largest file accepted through resumable uploads
+pub const MAX_RESUMABLE_UPLOAD_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Route of the tus endpoint
+pub const UPLOADS_PATH: &str = "/api/uploads";
+
+/// Directory under the served directory holding unfinished resumable uploads
+pub const STAGING_DIR: &str = ".rsmd-uploads";
+
+/// Protocol version spoken by the tus endpoint
+pub const TUS_VERSION: &str = "1.0.0";
+
+/// How long a resumable upload is kept after its last `PATCH`
+pub const UPLOAD_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How often [`spawn_upload_expiry`] looks for expired uploads
+pub const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const TUS_EXTENSIONS: &str = "creation,termination,checksum,expiration";
+const TUS_CHECKSUM_ALGORITHMS: &str = "sha256";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+/// Status defined by the tus checksum extension for a chunk that failed verification
+const CHECKSUM_MISMATCH: u16 = 460;
+
+/// Serializes the check-and-write of saves to sources without a directory on disk
+static SOURCE_SAVE_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// What an upload endpoint stores
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Markdown,
+    /// One of the [`attachment::TYPES`]
+    Attachment,
+}
+
+impl Kind {
+    /// The kind a file of this name would be uploaded as, if any
+    fn for_name(name: &str) -> Option<Self> {
+        if directory::is_markdown(name) {
+            Some(Kind::Markdown)
+        } else {
+            attachment::for_name(name).map(|_| Kind::Attachment)
+        }
+    }
+
+    fn max_size(self) -> u64 {
+        match self {
+            Kind::Markdown => MAX_UPLOAD_SIZE,
+            Kind::Attachment => MAX_ATTACHMENT_SIZE,
+        }
+    }
+}
+
+/// Result of `POST /api/upload` and `POST /api/assets`
+#[derive(Serialize)]
+pub struct UploadResponse {
+    success: bool,
+    message: String,
+    /// [`UploadError::code`] of a failed upload
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    /// Hex SHA-256 of the stored file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    /// Entity tag to send as `If-Match` when replacing the file later
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) etag: Option<String>,
+    /// Markdown embedding an uploaded attachment, e.g. `![logo](img/logo.png)`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snippet: Option<String>,
+    /// One entry per file of a multipart upload
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    files: Vec<FileResult>,
+}
+
+/// Outcome for one file of a multipart upload
+#[derive(Serialize)]
+pub struct FileResult {
+    /// Name as submitted, including any relative folder
+    name: String,
+    #[serde(flatten)]
+    result: UploadResponse,
+}
+
+impl UploadResponse {
+    fn failure(message: &str) -> Self {
+        Self {
+            success: false,
+            message: message.to_string(),
+            code: None,
+            file: None,
+            sha256: None,
+            etag: None,
+            snippet: None,
+            files: Vec::new(),
+        }
+    }
+
+    fn stored(stored: Stored, kind: Kind, language: &Language) -> Self {
+        Self {
+            success: true,
+            message: language.text("upload_success").to_string(),
+            code: None,
+            snippet: (kind == Kind::Attachment).then(|| attachment::snippet(&stored.target.link)),
+            file: Some(stored.target.name),
+            sha256: Some(conditional::hex(&stored.digest)),
+            etag: Some(conditional::digest_etag(&stored.digest)),
+            files: Vec::new(),
+        }
+    }
+}
+
+/// Why an upload was refused
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UploadError {
+    /// No usable file name was given
+    MissingName,
+    /// The file is not markdown
+    InvalidType,
+    /// The file is not one of the allowed attachment types
+    InvalidAttachment,
+    /// The file's contents do not match the type named by its extension
+    ContentMismatch,
+    /// The target directory contains `.` or `..` segments
+    InvalidPath,
+    Empty,
+    TooLarge,
+    /// The body did not match the checksum sent by the client
+    ChecksumMismatch,
+    /// The request body could not be read
+    Interrupted,
+    /// `X-Conflict-Policy` is not one of `reject`, `overwrite` or `rename`
+    InvalidPolicy,
+    /// The file exists and the conflict policy is `reject`
+    Conflict,
+    /// The file does not match the `If-Match` tag
+    Stale,
+    /// A save named neither the version it replaces nor `If-None-Match: *`
+    PreconditionRequired,
+    /// A `.zip` upload is not a readable archive, or uses unsupported features
+    InvalidArchive,
+    /// The archive has more than [`archive::MAX_ENTRIES`] entries
+    TooManyEntries,
+    /// An archive entry inflates suspiciously well
+    CompressionRatio,
+    /// The file could not be written
+    Storage,
+    /// A multipart form was posted from another site
+    CrossSite,
+}
+
+impl UploadError {
+    fn status(self) -> StatusCode {
+        match self {
+            UploadError::TooLarge | UploadError::TooManyEntries => StatusCode::PAYLOAD_TOO_LARGE,
+            UploadError::Conflict => StatusCode::CONFLICT,
+            UploadError::Stale => StatusCode::PRECONDITION_FAILED,
+            UploadError::PreconditionRequired => StatusCode::PRECONDITION_REQUIRED,
+            UploadError::Storage => StatusCode::INTERNAL_SERVER_ERROR,
+            UploadError::CrossSite => StatusCode::FORBIDDEN,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// Stable machine-readable identifier
+    pub fn code(self) -> &'static str {
+        match self {
+            UploadError::MissingName => "missing_name",
+            UploadError::InvalidType => "invalid_type",
+            UploadError::InvalidAttachment => "invalid_attachment",
+            UploadError::ContentMismatch => "content_mismatch",
+            UploadError::InvalidPath => "invalid_path",
+            UploadError::Empty => "empty",
+            UploadError::TooLarge => "too_large",
+            UploadError::ChecksumMismatch => "checksum_mismatch",
+            UploadError::Interrupted => "interrupted",
+            UploadError::InvalidPolicy => "invalid_policy",
+            UploadError::Conflict => "conflict",
+            UploadError::Stale => "stale",
+            UploadError::PreconditionRequired => "precondition_required",
+            UploadError::InvalidArchive => "invalid_archive",
+            UploadError::TooManyEntries => "too_many_entries",
+            UploadError::CompressionRatio => "compression_ratio",
+            UploadError::Storage => "storage",
+            UploadError::CrossSite => "cross_site",
+        }
+    }
+
+    fn message(self, language: &Language) -> &'static str {
+        language.text(match self {
+            UploadError::InvalidType => "upload_invalid_type",
+            UploadError::InvalidAttachment => "upload_invalid_attachment",
+            UploadError::ContentMismatch => "upload_content_mismatch",
+            UploadError::TooLarge => "upload_too_large",
+            UploadError::Conflict => "upload_conflict",
+            UploadError::Stale => "upload_stale",
+            UploadError::PreconditionRequired => "edit_precondition_required",
+            UploadError::InvalidArchive => "upload_invalid_archive",
+            UploadError::TooManyEntries | UploadError::CompressionRatio => "upload_archive_limit",
+            UploadError::CrossSite => "error_cross_site",
+            _ => "upload_error",
+        })
+    }
+
+    /// JSON response in the `/api/upload` envelope
+    pub fn response(self, language: &Language) -> Response {
+        (self.status(), Json(self.envelope(language))).into_response()
+    }
+
+    fn envelope(self, language: &Language) -> UploadResponse {
+        UploadResponse {
+            code: Some(self.code()),
+            ..UploadResponse::failure(self.message(language))
+        }
+    }
+}
+
+/// Where an uploaded file will be stored
+#[derive(Clone, Debug, PartialEq)]
+pub struct Target {
+    /// Path relative to the served directory, as used in URLs
+    pub name: String,
+    /// Path relative to the directory the upload was made into
+    pub link: String,
+    pub path: PathBuf,
+}
+
+impl Target {
+    /// Validate an uploaded file name and its target directory
+    ///
+    /// Only the last component of `file_name` is used, and it has to suit
+    /// `kind`. `directory` is a `/`-separated path below `base_dir`; `.` and `..`
+    /// segments are rejected, as are hidden files such as `.git`.
+    pub fn resolve(
+        base_dir: &Path,
+        directory: &str,
+        file_name: &str,
+        kind: Kind,
+    ) -> Result<Self, UploadError> {
+        let file_name = Path::new(file_name.trim())
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .filter(|name| !name.is_empty())
+            .ok_or(UploadError::MissingName)?;
+        if directory::is_hidden_path(&file_name) {
+            return Err(UploadError::InvalidPath);
+        }
+
+        if Kind::for_name(&file_name) != Some(kind) {
+            return Err(match kind {
+                Kind::Markdown => UploadError::InvalidType,
+                Kind::Attachment => UploadError::InvalidAttachment,
+            });
+        }
+
+        let directory = normalize_directory(directory)?;
+        let (name, path) = if directory.is_empty() {
+            (file_name.clone(), base_dir.join(&file_name))
+        } else {
+            (
+                format!("{}/{}", directory, file_name),
+                base_dir.join(&directory).join(&file_name),
+            )
+        };
+        Ok(Self {
+            name,
+            link: file_name,
+            path,
+        })
+    }
+
+    /// Like [`Target::resolve`], but keeps the folders of a relative
+    /// `submitted` path such as `guides/setup.md` below `directory`
+    pub fn resolve_relative(
+        base_dir: &Path,
+        directory: &str,
+        submitted: &str,
+        kind: Kind,
+    ) -> Result<Self, UploadError> {
+        let submitted = submitted.replace('\\', "/");
+        let Some((folder, file_name)) = submitted.rsplit_once('/') else {
+            return Target::resolve(base_dir, directory, &submitted, kind);
+        };
+        let joined = format!("{}/{}", directory, folder);
+        let mut target = Target::resolve(base_dir, &joined, file_name, kind)?;
+        let base = normalize_directory(directory)?;
+        if let Some(link) = target.name.strip_prefix(&format!("{}/", base)) {
+            target.link = link.to_string();
+        } else if base.is_empty() {
+            target.link = target.name.clone();
+        }
+        Ok(target)
+    }
+
+    /// The same target stored under `path`, a sibling picked by
+    /// [`ConflictPolicy::Rename`]
+    fn moved_to(self, path: PathBuf) -> Self {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let sibling = |current: &str| match current.rsplit_once('/') {
+            Some((directory, _)) => format!("{}/{}", directory, file_name),
+            None => file_name.clone(),
+        };
+        Self {
+            name: sibling(&self.name),
+            link: sibling(&self.link),
+            path,
+        }
+    }
+}
+
+/// Normalize a `/`-separated directory below the served directory
+fn normalize_directory(directory: &str) -> Result<String, UploadError> {
+    let directory = directory.trim().replace('\\', "/");
+    let segments: Vec<&str> = directory
+        .split('/')
+        .map(str::trim)
+        .filter(|seg| !seg.is_empty())
+        .collect();
+    if directory::is_hidden_path(&segments.join("/")) {
+        return Err(UploadError::InvalidPath);
+    }
+    Ok(segments.join("/"))
+}
+
+/// Append `body` to `file`, hashing it and stopping past `limit` total bytes
+///
+/// Returns the number of bytes written. On error the bytes received so far
+/// stay in the file.
+async fn append_body<E: std::fmt::Display>(
+    file: &mut fs::File,
+    body: impl Stream<Item = Result<Bytes, E>>,
+    written: u64,
+    limit: u64,
+    hasher: &mut Sha256,
+) -> Result<u64, UploadError> {
+    let mut received = 0;
+    let mut stream = std::pin::pin!(body);
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|err| {
+            tracing::warn!(error = %err, "Upload body interrupted");
+            UploadError::Interrupted
+        })?;
+        if written + received + chunk.len() as u64 > limit {
+            return Err(UploadError::TooLarge);
+        }
+        hasher.update(&chunk);
+        file.write_all(&chunk).await.map_err(storage_error)?;
+        received += chunk.len() as u64;
+    }
+    file.flush().await.map_err(storage_error)?;
+    Ok(received)
+}
+
+/// A body that was received or built in memory
+fn in_memory(bytes: Bytes) -> impl Stream<Item = Result<Bytes, std::convert::Infallible>> {
+    futures_util::stream::iter([Ok(bytes)])
+}
+
+fn storage_error(err: std::io::Error) -> UploadError {
+    tracing::error!(error = %err, "Failed to write upload");
+    UploadError::Storage
+}
+
+impl From<ArchiveError> for UploadError {
+    fn from(err: ArchiveError) -> Self {
+        match err {
+            ArchiveError::Invalid | ArchiveError::Unsupported => UploadError::InvalidArchive,
+            ArchiveError::TooManyEntries => UploadError::TooManyEntries,
+            ArchiveError::TooLarge => UploadError::TooLarge,
+            ArchiveError::CompressionRatio => UploadError::CompressionRatio,
+            ArchiveError::UnsafePath => UploadError::InvalidPath,
+            ArchiveError::Skipped => UploadError::InvalidType,
+        }
+    }
+}
+
+impl From<WriteError> for UploadError {
+    fn from(err: WriteError) -> Self {
+        match err {
+            WriteError::Exists => UploadError::Conflict,
+            WriteError::Stale => UploadError::Stale,
+            WriteError::Io(err) => storage_error(err),
+        }
+    }
+}
+
+/// How an upload may treat an existing file
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Replace {
+    #[serde(default)]
+    policy: ConflictPolicy,
+    /// `If-Match` value the existing file has to satisfy
+    #[serde(default)]
+    if_match: Option<String>,
+    /// Where a replaced document is kept
+    #[serde(skip)]
+    history: Option<Arc<History>>,
+    #[serde(skip)]
+    reason: Reason,
+}
+
+impl Replace {
+    /// Read `X-Conflict-Policy` (default `reject`) and `If-Match`
+    fn from_headers(headers: &HeaderMap) -> Result<Self, UploadError> {
+        let policy = match header_str(headers, "x-conflict-policy") {
+            Some(value) => ConflictPolicy::parse(value).ok_or(UploadError::InvalidPolicy)?,
+            None => ConflictPolicy::default(),
+        };
+        let if_match = header_str(headers, "if-match").map(str::to_string);
+        Ok(Self {
+            policy,
+            if_match,
+            ..Self::default()
+        })
+    }
+
+    /// Keep documents this replaces in the history of `store`, if enabled
+    fn recorded_in(mut self, store: &DocumentStore, reason: Reason) -> Self {
+        self.history = store.history().cloned();
+        self.reason = reason;
+        self
+    }
+
+    /// Fail early when the upload could not be committed right now
+    async fn check(&self, target: &Target) -> Result<(), UploadError> {
+        let current = write::current_etag(&target.path)
+            .await
+            .map_err(storage_error)?;
+        match &self.if_match {
+            Some(if_match) if !conditional::if_match(if_match, current.as_deref()) => {
+                Err(UploadError::Stale)
+            }
+            None if self.policy == ConflictPolicy::Reject && current.is_some() => {
+                Err(UploadError::Conflict)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Move the finished `temp` file into place, returning where it ended up
+    async fn commit(
+        &self,
+        temp: &TempFile,
+        target: Target,
+        kind: Kind,
+    ) -> Result<Target, UploadError> {
+        let snapshot = self
+            .history
+            .as_deref()
+            .filter(|_| kind == Kind::Markdown)
+            .map(|history| Snapshot {
+                history,
+                name: &target.name,
+                reason: self.reason,
+            });
+        let if_match = self.if_match.as_deref();
+        let path = write::commit(temp, &target.path, self.policy, if_match, snapshot).await?;
+        Ok(target.moved_to(path))
+    }
+}
+
+/// Make an uploaded document visible to the tree and drop any stale render
+async fn register(store: &DocumentStore, target: &Target, kind: Kind) {
+    if kind != Kind::Markdown {
+        return;
+    }
+    store
+        .files()
+        .insert(MarkdownFile {
+            name: target.name.clone(),
+            path: target.path.clone(),
+        })
+        .await;
+    store.invalidate(&target.name).await;
+}
+
+/// Write `markdown` to the document `name` and register it like an upload
+///
+/// With `if_match` the existing file must match that tag; without it the
+/// document must not exist yet. Either way a mismatch is [`UploadError::Stale`].
+/// Documents of a source without a directory on disk are written with
+/// [`save_to_source`].
+pub(crate) async fn save_document(
+    store: &DocumentStore,
+    language: &Language,
+    name: &str,
+    markdown: String,
+    if_match: Option<&str>,
+    headers: &HeaderMap,
+) -> Result<UploadResponse, UploadError> {
+    let Some(base_dir) = store.local_dir() else {
+        return save_to_source(store, language, name, markdown, if_match).await;
+    };
+    let target = Target::resolve_relative(base_dir, "", name, Kind::Markdown)?;
+    let replace = Replace {
+        policy: ConflictPolicy::Reject,
+        if_match: if_match.map(str::to_string),
+        ..Replace::default()
+    }
+    .recorded_in(store, Reason::Edit);
+    let body = in_memory(markdown.into());
+    let stored = match write_upload(target, Kind::Markdown, body, None, &replace).await {
+        Err(UploadError::Conflict) => return Err(UploadError::Stale),
+        stored => stored?,
+    };
+    register(store, &stored.target, Kind::Markdown).await;
+    tracing::info!(file = %stored.target.name, size = stored.size, "Document saved");
+    let names = vec![stored.target.name.clone()];
+    git::commit_changes(store, headers, git::message("Edit", &names), names).await;
+    Ok(UploadResponse::stored(stored, Kind::Markdown, language))
+}
+
+/// Write `markdown` to the document `name` through the source of `store`,
+/// whose documents are not in a directory on disk
+///
+/// Checks `if_match` like [`save_document`]. No revision is kept and nothing is
+/// committed to git. The check and the write are kept together by
+/// [`SOURCE_SAVE_LOCK`] rather than [`write::lock`], which the source's own
+/// write may take.
+async fn save_to_source(
+    store: &DocumentStore,
+    language: &Language,
+    name: &str,
+    markdown: String,
+    if_match: Option<&str>,
+) -> Result<UploadResponse, UploadError> {
+    let mut target = Target::resolve_relative(Path::new(""), "", name, Kind::Markdown)?;
+    // Nothing is stored on disk
+    target.path = PathBuf::new();
+    let size = markdown.len() as u64;
+    if markdown.is_empty() {
+        return Err(UploadError::Empty);
+    }
+    if size > Kind::Markdown.max_size() {
+        return Err(UploadError::TooLarge);
+    }
+
+    let _guard = SOURCE_SAVE_LOCK.lock().await;
+    let write = {
+        let source = store.source().clone();
+        let name = target.name.clone();
+        let if_match = if_match.map(str::to_string);
+        tokio::task::spawn_blocking(move || {
+            let current = match source.read(&name) {
+                Ok(contents) => Some(conditional::etag(&contents)),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+                Err(err) => return Err(storage_error(err)),
+            };
+            let fresh = match &if_match {
+                Some(if_match) => conditional::if_match(if_match, current.as_deref()),
+                None => current.is_none(),
+            };
+            if !fresh {
+                return Err(UploadError::Stale);
+            }
+            source
+                .write(&name, markdown.as_bytes())
+                .map_err(storage_error)?;
+            Ok(Sha256::digest(markdown.as_bytes()).to_vec())
+        })
+    };
+    let digest = write.await.map_err(|err| {
+        tracing::error!(error = %err, "Document write task failed");
+        UploadError::Storage
+    })??;
+
+    register(store, &target, Kind::Markdown).await;
+    tracing::info!(file = %target.name, size, "Document saved");
+    let stored = Stored {
+        target,
+        size,
+        digest,
+    };
+    Ok(UploadResponse::stored(stored, Kind::Markdown, language))
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// API: Upload markdown files into the current directory
+///
+/// A raw body is a single file named by `X-File-Name`; a `multipart/form-data`
+/// body may carry many. Either way the folder comes from `X-Directory-Path` and
+/// existing files are kept unless `X-Conflict-Policy` is `overwrite` or
+/// `rename`, or `If-Match` names the current version. An optional
+/// `X-Content-SHA256` header is checked against a raw body.
+pub async fn handle_upload(State(state): State<Arc<AppState>>, request: Request) -> Response {
+    upload(&state, request, Kind::Markdown).await
+}
+
+/// API: Upload images and other attachments next to the documents of a directory
+///
+/// Takes the same headers and bodies as [`handle_upload`]. `X-File-Name` may
+/// include folders below `X-Directory-Path`, such as `img/logo.png`.
+pub async fn handle_attachment_upload(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+) -> Response {
+    upload(&state, request, Kind::Attachment).await
+}
+
+async fn upload(state: &AppState, request: Request, kind: Kind) -> Response {
+    match state {
+        AppState::Directory {
+            dir_path,
+            store,
+            language,
+        } if let Some(base_dir) = store.local_dir() => {
+            let is_multipart = header_str(request.headers(), "content-type")
+                .is_some_and(|value| value.starts_with("multipart/form-data"));
+            if is_multipart {
+                // Unlike the other bodies, a form can be posted by any site
+                if !ajax::is_same_origin(request.headers()) {
+                    return UploadError::CrossSite.response(language);
+                }
+                let headers = request.headers().clone();
+                let (response, written) =
+                    upload_multipart(store, language, base_dir, request, kind).await;
+                git::commit_changes(store, &headers, git::message("Upload", &written), written)
+                    .await;
+                return response;
+            }
+
+            let (parts, body) = request.into_parts();
+            let file_name = header_str(&parts.headers, "x-file-name").unwrap_or("");
+            let directory = header_str(&parts.headers, "x-directory-path").unwrap_or("");
+            let expected = header_str(&parts.headers, "x-content-sha256").map(str::to_lowercase);
+
+            if kind == Kind::Markdown && is_archive(file_name) {
+                let replace = match Replace::from_headers(&parts.headers) {
+                    Ok(replace) => replace.recorded_in(store, Reason::Upload),
+                    Err(err) => return err.response(language),
+                };
+                let body = body.into_data_stream();
+                let archive = ArchiveUpload {
+                    name: file_name,
+                    directory,
+                    replace: &replace,
+                };
+                let mut written = Vec::new();
+                let unpacked =
+                    upload_archive(store, language, base_dir, archive, body, &mut written).await;
+                let message = git::message("Upload", &written);
+                git::commit_changes(store, &parts.headers, message, written).await;
+                return match unpacked {
+                    Ok((results, first_error)) => summarize(results, first_error, language),
+                    Err(err) => err.response(language),
+                };
+            }
+
+            let target = match kind {
+                Kind::Markdown => Target::resolve(base_dir, directory, file_name, kind),
+                Kind::Attachment => Target::resolve_relative(base_dir, directory, file_name, kind),
+            };
+            let prepared = target.and_then(|target| {
+                let replace = Replace::from_headers(&parts.headers)?;
+                Ok((target, replace.recorded_in(store, Reason::Upload)))
+            });
+            let (target, replace) = match prepared {
+                Ok(prepared) => prepared,
+                Err(err) => return err.response(language),
+            };
+            let body = body.into_data_stream();
+            let written = write_upload(target, kind, body, expected.as_deref(), &replace).await;
+            let stored = match written {
+                Ok(stored) => stored,
+                Err(err) => return err.response(language),
+            };
+            register(store, &stored.target, kind).await;
+
+            tracing::info!(
+                directory = %dir_path,
+                file = %stored.target.name,
+                size = stored.size,
+                ?kind,
+                "File uploaded",
+            );
+            let names = vec![stored.target.name.clone()];
+            git::commit_changes(store, &parts.headers, git::message("Upload", &names), names).await;
+
+            (
+                StatusCode::OK,
+                Json(UploadResponse::stored(stored, kind, language)),
+            )
+                .into_response()
+        }
+        _ => (
+            StatusCode::BAD_REQUEST,
+            Json(UploadResponse::failure(
+                "Uploads are only available in directory mode",
+            )),
+        )
+            .into_response(),
+    }
+}
+
+/// Store every file part of a multipart form
+///
+/// Text fields named `directory` and `conflict` override `X-Directory-Path` and
+/// `X-Conflict-Policy` for the files after them. Zip archives among the files are
+/// unpacked, reporting each entry. See [`summarize`] for the status. Also
+/// returns the names of the files stored.
+async fn upload_multipart(
+    store: &DocumentStore,
+    language: &Language,
+    base_dir: &Path,
+    request: Request,
+    kind: Kind,
+) -> (Response, Vec<String>) {
+    let mut directory = header_str(request.headers(), "x-directory-path")
+        .unwrap_or("")
+        .to_string();
+    let mut replace = match Replace::from_headers(request.headers()) {
+        Ok(replace) => replace.recorded_in(store, Reason::Upload),
+        Err(err) => return (err.response(language), Vec::new()),
+    };
+    let Ok(mut multipart) = Multipart::from_request(request, &()).await else {
+        return (UploadError::Interrupted.response(language), Vec::new());
+    };
+
+    let mut written = Vec::new();
+    let mut results = Vec::new();
+    let mut first_error = None;
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(err) => {
+                tracing::warn!(error = %err, "Multipart upload interrupted");
+                first_error.get_or_insert(UploadError::Interrupted);
+                break;
+            }
+        };
+        let Some(submitted) = field.file_name().map(str::to_string) else {
+            let key = field.name().unwrap_or("").to_string();
+            let value = field.text().await.unwrap_or_default();
+            match key.as_str() {
+                "directory" => directory = value,
+                "conflict" => match ConflictPolicy::parse(&value) {
+                    Some(policy) => replace.policy = policy,
+                    None => return (UploadError::InvalidPolicy.response(language), written),
+                },
+                _ => {}
+            }
+            continue;
+        };
+
+        if kind == Kind::Markdown && is_archive(&submitted) {
+            let archive = ArchiveUpload {
+                name: &submitted,
+                directory: &directory,
+                replace: &replace,
+            };
+            match upload_archive(store, language, base_dir, archive, field, &mut written).await {
+                Ok((entries, error)) => {
+                    if let Some(err) = error {
+                        first_error.get_or_insert(err);
+                    }
+                    results.extend(entries);
+                }
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                    results.push(FileResult {
+                        name: submitted,
+                        result: err.envelope(language),
+                    });
+                }
+            }
+            continue;
+        }
+
+        let stored = match Target::resolve_relative(base_dir, &directory, &submitted, kind) {
+            Ok(target) => write_upload(target, kind, field, None, &replace).await,
+            Err(err) => Err(err),
+        };
+        let result = match stored {
+            Ok(stored) => {
+                register(store, &stored.target, kind).await;
+                tracing::info!(file = %stored.target.name, size = stored.size, ?kind, "File uploaded");
+                written.push(stored.target.name.clone());
+                UploadResponse::stored(stored, kind, language)
+            }
+            Err(err) => {
+                first_error.get_or_insert(err);
+                err.envelope(language)
+            }
+        };
+        results.push(FileResult {
+            name: submitted,
+            result,
+        });
+    }
+
+    (summarize(results, first_error, language), written)
+}
+
+/// Respond to an upload of several files with the outcome of each
+///
+/// `200` when all files were stored, `207 Multi-Status` when only some were,
+/// and otherwise the status of `first_error`.
+fn summarize(
+    results: Vec<FileResult>,
+    first_error: Option<UploadError>,
+    language: &Language,
+) -> Response {
+    let stored = results.iter().filter(|file| file.result.success).count();
+    let (status, message) = match first_error {
+        None if stored > 0 => (StatusCode::OK, language.text("upload_success")),
+        None => (StatusCode::BAD_REQUEST, language.text("upload_error")),
+        Some(_) if stored > 0 => (StatusCode::MULTI_STATUS, language.text("upload_partial")),
+        Some(err) => (err.status(), err.message(language)),
+    };
+    let mut response = UploadResponse::failure(message);
+    response.code = first_error.filter(|_| stored == 0).map(UploadError::code);
+    response.success = status == StatusCode::OK;
+    response.files = results;
+    (status, Json(response)).into_response()
+}
+
+fn is_archive(file_name: &str) -> bool {
+    file_name.trim().to_lowercase().ends_with(".zip")
+}
+
+/// A zip archive being uploaded
+struct ArchiveUpload<'a> {
+    /// Name of the archive as submitted
+    name: &'a str,
+    /// Directory the entries are unpacked into
+    directory: &'a str,
+    replace: &'a Replace,
+}
+
+/// Unpack the zip archive in `body` below `archive.directory`
+///
+/// The archive is spooled to a temporary file and read on a blocking thread,
+/// which hands over one inflated entry at a time. Every markdown file and
+/// attachment is then stored like a file of a multipart upload, under its path
+/// inside the archive; other entries are reported as refused. Fails as a whole
+/// only when the archive cannot be opened or has too many entries. The names of
+/// the files stored are added to `written`.
+async fn upload_archive<E: std::fmt::Display>(
+    store: &DocumentStore,
+    language: &Language,
+    base_dir: &Path,
+    archive: ArchiveUpload<'_>,
+    body: impl Stream<Item = Result<Bytes, E>>,
+    written: &mut Vec<String>,
+) -> Result<(Vec<FileResult>, Option<UploadError>), UploadError> {
+    let (temp, mut file) = TempFile::create_in(base_dir).await.map_err(storage_error)?;
+    let size = append_body(&mut file, body, 0, MAX_ARCHIVE_SIZE, &mut Sha256::new()).await?;
+    drop(file);
+    if size == 0 {
+        return Err(UploadError::Empty);
+    }
+
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+    let path = temp.path.clone();
+    let reader = tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::open(path).map_err(|_| ArchiveError::Invalid)?;
+        archive::read_entries(
+            std::io::BufReader::new(file),
+            |name| Kind::for_name(name).map(Kind::max_size),
+            |entry| sender.blocking_send(entry).is_ok(),
+        )
+    });
+
+    let mut results = Vec::new();
+    let mut first_error = None;
+    while let Some(entry) = receiver.recv().await {
+        let stored = match (entry.contents, Kind::for_name(&entry.name)) {
+            (Ok(contents), Some(kind)) => {
+                match Target::resolve_relative(base_dir, archive.directory, &entry.name, kind) {
+                    Ok(target) => write_upload(
+                        target,
+                        kind,
+                        in_memory(contents.into()),
+                        None,
+                        archive.replace,
+                    )
+                    .await
+                    .map(|stored| (stored, kind)),
+                    Err(err) => Err(err),
+                }
+            }
+            (Ok(_), None) => Err(UploadError::InvalidType),
+            (Err(err), _) => Err(err.into()),
+        };
+        let result = match stored {
+            Ok((stored, kind)) => {
+                register(store, &stored.target, kind).await;
+                written.push(stored.target.name.clone());
+                UploadResponse::stored(stored, kind, language)
+            }
+            Err(err) => {
+                first_error.get_or_insert(err);
+                err.envelope(language)
+            }
+        };
+        results.push(FileResult {
+            name: entry.name,
+            result,
+        });
+    }
+
+    let outcome = reader.await.unwrap_or(Err(ArchiveError::Invalid));
+    drop(temp);
+    match outcome {
+        Err(err) if results.is_empty() => return Err(err.into()),
+        Err(err) => {
+            let err = UploadError::from(err);
+            first_error.get_or_insert(err);
+            results.push(FileResult {
+                name: archive.name.to_string(),
+                result: err.envelope(language),
+            });
+        }
+        Ok(()) => {}
+    }
+    tracing::info!(
+        archive = %archive.name,
+        entries = results.len(),
+        stored = results.iter().filter(|file| file.result.success).count(),
+        "Archive unpacked",
+    );
+    Ok((results, first_error))
+}
+
+/// The first [`attachment::SNIFF_LEN`] bytes of the file at `path`
+async fn read_head(path: &Path) -> std::io::Result<Vec<u8>> {
+    use tokio::io::AsyncReadExt;
+
+    let mut head = Vec::with_capacity(attachment::SNIFF_LEN);
+    fs::File::open(path)
+        .await?
+        .take(attachment::SNIFF_LEN as u64)
+        .read_to_end(&mut head)
+        .await?;
+    Ok(head)
+}
+
+/// A file stored by an upload
+struct Stored {
+    target: Target,
+    size: u64,
+    digest: Vec<u8>,
+}
+
+/// Stream `body` into `target`
+///
+/// Attachments are checked against the signature of the type their name claims.
+async fn write_upload<E: std::fmt::Display>(
+    target: Target,
+    kind: Kind,
+    body: impl Stream<Item = Result<Bytes, E>>,
+    expected_sha256: Option<&str>,
+    replace: &Replace,
+) -> Result<Stored, UploadError> {
+    replace.check(&target).await?;
+    let parent = target.path.parent().ok_or(UploadError::InvalidPath)?;
+    fs::create_dir_all(parent).await.map_err(storage_error)?;
+
+    let (temp, mut file) = TempFile::create_in(parent).await.map_err(storage_error)?;
+    let mut hasher = Sha256::new();
+    let size = append_body(&mut file, body, 0, kind.max_size(), &mut hasher).await?;
+    file.sync_all().await.map_err(storage_error)?;
+    drop(file);
+
+    if size == 0 {
+        return Err(UploadError::Empty);
+    }
+    if kind == Kind::Attachment {
+        let head = read_head(&temp.path).await.map_err(storage_error)?;
+        let claimed = attachment::for_name(&target.name).map(|kind| kind.mime);
+        if attachment::sniff(&head).map(|kind| kind.mime) != claimed {
+            return Err(UploadError::ContentMismatch);
+        }
+    }
+    let digest = hasher.finalize().to_vec();
+    if expected_sha256.is_some_and(|expected| expected != conditional::hex(&digest)) {
+        return Err(UploadError::ChecksumMismatch);
+    }
+
+    let target = replace.commit(&temp, target, kind).await?;
+    Ok(Stored {
+        target,
+        size,
+        digest,
+    })
+}
+
+/// State of a resumable upload, stored next to its data
+#[derive(Serialize, Deserialize)]
+struct Session {
+    length: u64,
+    file_name: String,
+    directory: String,
+    #[serde(default)]
+    replace: Replace,
+}
+
+/// Paths of the data and state files of upload `id`
+fn session_paths(base_dir: &Path, id: &str) -> Option<(PathBuf, PathBuf)> {
+    if id.is_empty() || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let dir = base_dir.join(STAGING_DIR);
+    Some((
+        dir.join(format!("{}.part", id)),
+        dir.join(format!("{}.json", id)),
+    ))
+}
+
+/// Upload `id`, unless it does not exist or has expired
+async fn load_session(base_dir: &Path, id: &str) -> Option<(Session, PathBuf, PathBuf)> {
+    let (data, info) = session_paths(base_dir, id)?;
+    if expires_at(&data, &info).await? <= SystemTime::now() {
+        return None;
+    }
+    let session = serde_json::from_slice(&fs::read(&info).await.ok()?).ok()?;
+    Some((session, data, info))
+}
+
+/// When an upload expires: [`UPLOAD_EXPIRY`] after its data was last written,
+/// or its state if the data is missing
+async fn expires_at(data: &Path, info: &Path) -> Option<SystemTime> {
+    let metadata = match fs::metadata(data).await {
+        Ok(metadata) => metadata,
+        Err(_) => fs::metadata(info).await.ok()?,
+    };
+    Some(metadata.modified().ok()? + UPLOAD_EXPIRY)
+}
+
+/// Delete the expired uploads under `base_dir`, returning how many
+pub async fn sweep_expired_uploads(base_dir: &Path) -> std::io::Result<usize> {
+    let mut entries = match fs::read_dir(base_dir.join(STAGING_DIR)).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+    let mut ids = HashSet::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) {
+            ids.insert(id.to_string());
+        }
+    }
+
+    let now = SystemTime::now();
+    let mut deleted = 0;
+    for id in ids {
+        let Some((data, info)) = session_paths(base_dir, &id) else {
+            continue;
+        };
+        if expires_at(&data, &info).await.is_some_and(|at| at > now) {
+            continue;
+        }
+        let Some(_active) = ActiveUpload::acquire(&data) else {
+            continue;
+        };
+        let _ = fs::remove_file(&data).await;
+        let _ = fs::remove_file(&info).await;
+        tracing::debug!(id = %id, "Resumable upload expired");
+        deleted += 1;
+    }
+    Ok(deleted)
+}
+
+/// Run [`sweep_expired_uploads`] now and then every [`EXPIRY_SWEEP_INTERVAL`]
+pub fn spawn_upload_expiry(base_dir: PathBuf) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(EXPIRY_SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            match sweep_expired_uploads(&base_dir).await {
+                Ok(0) => {}
+                Ok(deleted) => tracing::info!(deleted, "Deleted expired resumable uploads"),
+                Err(err) => tracing::warn!(error = %err, "Failed to delete expired uploads"),
+            }
+        }
+    })
+}
+
+/// `Upload-Expires` of an upload written at `written`
+fn upload_expires(response: Response, written: SystemTime) -> Response {
+    with_header(
+        response,
+        "upload-expires",
+        httpdate::fmt_http_date(written + UPLOAD_EXPIRY),
+    )
+}
+
+/// Uploads currently receiving a `PATCH`
+static ACTIVE: LazyLock<Mutex<HashSet<PathBuf>>> = LazyLock::new(Default::default);
+
+/// Exclusive access to one upload while a `PATCH` appends to it
+struct ActiveUpload(PathBuf);
+
+impl ActiveUpload {
+    fn acquire(data: &Path) -> Option<Self> {
+        let mut active = ACTIVE.lock().unwrap_or_else(|err| err.into_inner());
+        active
+            .insert(data.to_path_buf())
+            .then(|| Self(data.to_path_buf()))
+    }
+}
+
+impl Drop for ActiveUpload {
+    fn drop(&mut self) {
+        let mut active = ACTIVE.lock().unwrap_or_else(|err| err.into_inner());
+        active.remove(&self.0);
+    }
+}
+
+/// Empty tus response with the protocol headers every reply carries
+fn tus_response(status: StatusCode) -> Response {
+    let mut response = status.into_response();
+    response
+        .headers_mut()
+        .insert("tus-resumable", HeaderValue::from_static(TUS_VERSION));
+    response
+}
+
+fn with_header(mut response: Response, name: &'static str, value: impl ToString) -> Response {
+    if let Ok(value) = HeaderValue::from_str(&value.to_string()) {
+        response.headers_mut().insert(name, value);
+    }
+    response
+}
+
+/// 412 for requests speaking another protocol version
+fn version_mismatch(headers: &HeaderMap) -> Option<Response> {
+    (header_str(headers, "tus-resumable") != Some(TUS_VERSION)).then(|| {
+        with_header(
+            tus_response(StatusCode::PRECONDITION_FAILED),
+            "tus-version",
+            TUS_VERSION,
+        )
+    })
+}
+
+/// Parse `Upload-Metadata`: comma-separated `key base64value` pairs
+fn parse_metadata(value: &str) -> Option<Vec<(String, String)>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, encoded) = pair.split_once(' ').unwrap_or((pair, ""));
+            let decoded = BASE64.decode(encoded.trim()).ok()?;
+            Some((key.to_string(), String::from_utf8(decoded).ok()?))
+        })
+        .collect()
+}
+
+fn directory_base(state: &AppState) -> Option<(&Path, &Arc<DocumentStore>)> {
+    match state {
+        AppState::Directory { store, .. } => Some((store.local_dir()?, store)),
+        _ => None,
+    }
+}
+
+/// tus: advertise supported versions, extensions and limits
+pub async fn tus_options() -> Response {
+    let response = tus_response(StatusCode::NO_CONTENT);
+    let response = with_header(response, "tus-version", TUS_VERSION);
+    let response = with_header(response, "tus-extension", TUS_EXTENSIONS);
+    let response = with_header(response, "tus-checksum-algorithm", TUS_CHECKSUM_ALGORITHMS);
+    with_header(response, "tus-max-size", MAX_RESUMABLE_UPLOAD_SIZE)
+}
+
+/// tus: create an upload from `Upload-Length` and `Upload-Metadata`
+///
+/// The metadata must contain `filename` and may contain `directory` and
+/// `conflict` (a conflict policy). An `If-Match` header is checked when the
+/// upload is created and again when it completes.
+pub async fn tus_create(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    if let Some(response) = version_mismatch(&headers) {
+        return response;
+    }
+    let Some((base_dir, _)) = directory_base(&state) else {
+        return tus_response(StatusCode::NOT_FOUND);
+    };
+
+    let Some(length) = header_str(&headers, "upload-length").and_then(|v| v.parse::<u64>().ok())
+    else {
+        return tus_response(StatusCode::BAD_REQUEST);
+    };
+    if length > MAX_RESUMABLE_UPLOAD_SIZE {
+        return tus_response(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    if length == 0 {
+        return tus_response(StatusCode::BAD_REQUEST);
+    }
+
+    let Some(metadata) = parse_metadata(header_str(&headers, "upload-metadata").unwrap_or(""))
+    else {
+        return tus_response(StatusCode::BAD_REQUEST);
+    };
+    let value = |key: &str| {
+        metadata
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.clone())
+            .unwrap_or_default()
+    };
+    let policy = match value("conflict").as_str() {
+        "" => Ok(ConflictPolicy::default()),
+        policy => ConflictPolicy::parse(policy).ok_or(UploadError::InvalidPolicy),
+    };
+    let replace = policy.map(|policy| Replace {
+        policy,
+        if_match: header_str(&headers, "if-match").map(str::to_string),
+        ..Replace::default()
+    });
+    let session = Session {
+        length,
+        file_name: value("filename"),
+        directory: value("directory"),
+        replace: replace.clone().unwrap_or_default(),
+    };
+    let checked = async {
+        let target = Target::resolve(
+            base_dir,
+            &session.directory,
+            &session.file_name,
+            Kind::Markdown,
+        )?;
+        replace?.check(&target).await
+    };
+    if let Err(err) = checked.await {
+        return tus_response(err.status());
+    }
+
+    let id = unique_id();
+    let Some((data, info)) = session_paths(base_dir, &id) else {
+        return tus_response(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let created = async {
+        fs::create_dir_all(base_dir.join(STAGING_DIR)).await?;
+        fs::File::create(&data).await?;
+        fs::write(&info, serde_json::to_vec(&session)?).await
+    };
+    if let Err(err) = created.await {
+        tracing::error!(error = %err, "Failed to create resumable upload");
+        return tus_response(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    tracing::debug!(id = %id, length, file = %session.file_name, "Resumable upload created");
+    let response = with_header(
+        tus_response(StatusCode::CREATED),
+        "location",
+        format!("{}/{}", UPLOADS_PATH, id),
+    );
+    upload_expires(response, SystemTime::now())
+}
+
+/// tus: report how much of an upload has been received
+pub async fn tus_head(
+    State(state): State<Arc<AppState>>,
+    UrlPath(id): UrlPath<String>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(response) = version_mismatch(&headers) {
+        return response;
+    }
+    let Some((base_dir, _)) = directory_base(&state) else {
+        return tus_response(StatusCode::NOT_FOUND);
+    };
+    let Some((session, data, _)) = load_session(base_dir, &id).await else {
+        return tus_response(StatusCode::NOT_FOUND);
+    };
+    let offset = fs::metadata(&data)
+        .await
+        .map_or(0, |metadata| metadata.len());
+
+    let response = with_header(tus_response(StatusCode::OK), "upload-offset", offset);
+    let response = with_header(response, "upload-length", session.length);
+    with_header(response, "cache-control", "no-store")
+}
+
+/// tus: append a chunk at `Upload-Offset`, completing the upload at its length
+///
+/// `X-Conflict-Policy` and `If-Match` replace the ones the upload was created
+/// with when it completes, which lets an empty `PATCH` retry a finished upload
+/// that conflicted with its target.
+pub async fn tus_patch(
+    State(state): State<Arc<AppState>>,
+    UrlPath(id): UrlPath<String>,
+    request: Request,
+) -> Response {
+    let (parts, body) = request.into_parts();
+    if let Some(response) = version_mismatch(&parts.headers) {
+        return response;
+    }
+    let Some((base_dir, store)) = directory_base(&state) else {
+        return tus_response(StatusCode::NOT_FOUND);
+    };
+    if header_str(&parts.headers, "content-type") != Some(OFFSET_CONTENT_TYPE) {
+        return tus_response(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+    let Some(offset) =
+        header_str(&parts.headers, "upload-offset").and_then(|v| v.parse::<u64>().ok())
+    else {
+        return tus_response(StatusCode::BAD_REQUEST);
+    };
+    let checksum = match header_str(&parts.headers, "upload-checksum") {
+        None => None,
+        Some(value) => match value.split_once(' ') {
+            Some(("sha256", encoded)) => match BASE64.decode(encoded.trim()) {
+                Ok(digest) => Some(digest),
+                Err(_) => return tus_response(StatusCode::BAD_REQUEST),
+            },
+            _ => return tus_response(StatusCode::BAD_REQUEST),
+        },
+    };
+
+    let retry = ["x-conflict-policy", "if-match"]
+        .iter()
+        .any(|name| parts.headers.contains_key(*name));
+    let retry = match retry.then(|| Replace::from_headers(&parts.headers)) {
+        Some(Err(err)) => return tus_response(err.status()),
+        Some(Ok(replace)) => Some(replace),
+        None => None,
+    };
+
+    let Some((session, data, info)) = load_session(base_dir, &id).await else {
+        return tus_response(StatusCode::NOT_FOUND);
+    };
+    let Some(_active) = ActiveUpload::acquire(&data) else {
+        return tus_response(StatusCode::LOCKED);
+    };
+
+    let current = fs::metadata(&data)
+        .await
+        .map_or(0, |metadata| metadata.len());
+    if offset != current {
+        return with_header(tus_response(StatusCode::CONFLICT), "upload-offset", current);
+    }
+
+    let mut file = match fs::OpenOptions::new().append(true).open(&data).await {
+        Ok(file) => file,
+        Err(err) => return tus_response(storage_error(err).status()),
+    };
+    let mut hasher = Sha256::new();
+    let body = body.into_data_stream();
+    let appended = append_body(&mut file, body, offset, session.length, &mut hasher).await;
+    let received = match appended {
+        Ok(received) => received,
+        // Keep what arrived so the client can resume from the new offset
+        Err(UploadError::Interrupted) => {
+            let offset = fs::metadata(&data).await.map_or(offset, |m| m.len());
+            return with_header(
+                tus_response(StatusCode::BAD_REQUEST),
+                "upload-offset",
+                offset,
+            );
+        }
+        Err(err) => {
+            let _ = file.set_len(offset).await;
+            return tus_response(err.status());
+        }
+    };
+
+    if checksum.is_some_and(|expected| expected[..] != hasher.finalize()[..]) {
+        let _ = file.set_len(offset).await;
+        let status = StatusCode::from_u16(CHECKSUM_MISMATCH).unwrap_or(StatusCode::BAD_REQUEST);
+        return tus_response(status);
+    }
+    drop(file);
+
+    let offset = offset + received;
+    if offset == session.length {
+        let target = match Target::resolve(
+            base_dir,
+            &session.directory,
+            &session.file_name,
+            Kind::Markdown,
+        ) {
+            Ok(target) => target,
+            Err(err) => return tus_response(err.status()),
+        };
+        let replace = retry
+            .unwrap_or(session.replace)
+            .recorded_in(store, Reason::Upload);
+        let target = match finish_session(&replace, target, &data, &info).await {
+            Ok(target) => target,
+            Err(err) => return tus_response(err.status()),
+        };
+        register(store, &target, Kind::Markdown).await;
+        tracing::info!(file = %target.name, size = offset, "Resumable upload completed");
+        let names = vec![target.name];
+        git::commit_changes(store, &parts.headers, git::message("Upload", &names), names).await;
+        return with_header(
+            tus_response(StatusCode::NO_CONTENT),
+            "upload-offset",
+            offset,
+        );
+    }
+
+    let response = with_header(
+        tus_response(StatusCode::NO_CONTENT),
+        "upload-offset",
+        offset,
+    );
+    upload_expires(response, SystemTime::now())
+}
+
+/// Move a completed upload into place and drop its state
+///
+/// If that fails, for instance because the upload conflicts with the file now
+/// at its target, the data is moved back and the upload kept.
+async fn finish_session(
+    replace: &Replace,
+    target: Target,
+    data: &Path,
+    info: &Path,
+) -> Result<Target, UploadError> {
+    let parent = target.path.parent().ok_or(UploadError::InvalidPath)?;
+    fs::create_dir_all(parent).await.map_err(storage_error)?;
+
+    // Stage next to the target: the staging directory may be on another
+    // filesystem if the target is a mount point
+    let (temp, file) = TempFile::create_in(parent).await.map_err(storage_error)?;
+    drop(file);
+    move_file(data, &temp.path).await.map_err(storage_error)?;
+
+    let committed = async {
+        let synced = async { fs::File::open(&temp.path).await?.sync_all().await };
+        synced.await.map_err(storage_error)?;
+        replace.commit(&temp, target, Kind::Markdown).await
+    };
+    match committed.await {
+        Ok(target) => {
+            let _ = fs::remove_file(info).await;
+            Ok(target)
+        }
+        Err(err) => {
+            if let Err(err) = move_file(&temp.path, data).await {
+                tracing::error!(error = %err, "Failed to keep a resumable upload");
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Rename `from` to `to`, copying where they are on different filesystems
+async fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if fs::rename(from, to).await.is_err() {
+        fs::copy(from, to).await?;
+        let _ = fs::remove_file(from).await;
+    }
+    Ok(())
+}
+
+/// tus: abandon an upload and delete what was received
+pub async fn tus_delete(
+    State(state): State<Arc<AppState>>,
+    UrlPath(id): UrlPath<String>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(response) = version_mismatch(&headers) {
+        return response;
+    }
+    let Some((base_dir, _)) = directory_base(&state) else {
+        return tus_response(StatusCode::NOT_FOUND);
+    };
+    let Some((_, data, info)) = load_session(base_dir, &id).await else {
+        return tus_response(StatusCode::NOT_FOUND);
+    };
+    let Some(_active) = ActiveUpload::acquire(&data) else {
+        return tus_response(StatusCode::LOCKED);
+    };
+
+    let _ = fs::remove_file(&data).await;
+    let _ = fs::remove_file(&info).await;
+    tus_response(StatusCode::NO_CONTENT)
+}
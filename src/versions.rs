@@ -0,0 +1,461 @@
+//! Documents served from git refs without checking them out
+//!
+//! A [`Snapshot`] is the [`DocumentSource`] of one commit, listing and reading
+//! its documents straight from the tree objects. [`Versions`] resolves the
+//! branches, tags and commits of the repository a served directory is in to
+//! snapshots, which [`serve_version`] serves read-only under `/v/{ref}/`. With
+//! `--git-ref` the root serves a snapshot as well, so even a bare repository
+//! can be served.
+
+use axum::{
+    Router,
+    extract::{Path as UrlPath, Request, State},
+    http::Uri,
+    response::{IntoResponse, Response},
+};
+use gix::bstr::ByteSlice;
+use gix::{ObjectId, ThreadSafeRepository};
+use serde::Deserialize;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+use tower::ServiceExt;
+
+use crate::directory::FileTree;
+use crate::error::AppError;
+use crate::git::{self, GitError};
+use crate::html::{self, VersionMenu};
+use crate::server::{self, AppState, ServerConfig};
+use crate::source::{DocumentSource, Entries, Stat};
+use crate::store::DocumentStore;
+
+/// Snapshots kept ready to serve; the least recently used is dropped for more
+const MAX_CACHED: usize = 8;
+
+/// Branches, and tags, offered by the version switcher
+const MAX_REFS: usize = 100;
+
+/// The documents of one commit
+pub struct Snapshot {
+    repo: ThreadSafeRepository,
+    /// The served directory in the commit's tree, `/`-terminated, or empty
+    prefix: String,
+    commit: ObjectId,
+    /// The tree of the served directory
+    tree: ObjectId,
+    /// Commit time in seconds since the Unix epoch
+    time: u64,
+}
+
+impl std::fmt::Debug for Snapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Snapshot")
+            .field("commit", &self.commit)
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Snapshot {
+    /// The files of `commit` below `prefix`
+    ///
+    /// Returns `None` if the served directory does not exist in the commit.
+    fn load(
+        repo: &ThreadSafeRepository,
+        prefix: &str,
+        commit: ObjectId,
+    ) -> Result<Option<Self>, GitError> {
+        let local = repo.to_thread_local();
+        let found = local.find_commit(commit)?;
+        let time = found.committer()?.seconds().max(0) as u64;
+        let mut tree = found.tree_id()?.detach();
+        if !prefix.is_empty() {
+            let Some(entry) = found
+                .tree()?
+                .lookup_entry_by_path(prefix.trim_end_matches('/'))?
+                .filter(|entry| entry.mode().is_tree())
+            else {
+                return Ok(None);
+            };
+            tree = entry.object_id();
+        }
+
+        Ok(Some(Self {
+            repo: repo.clone(),
+            prefix: prefix.to_string(),
+            commit,
+            tree,
+            time,
+        }))
+    }
+
+    /// The commit the documents are read from
+    pub fn commit(&self) -> ObjectId {
+        self.commit
+    }
+
+    /// Commit time in seconds since the Unix epoch, which stands in for the
+    /// modification time of every file
+    pub fn modified(&self) -> u64 {
+        self.time
+    }
+
+    /// The blob of the file `name` below the served directory
+    fn blob(&self, repo: &gix::Repository, name: &str) -> io::Result<ObjectId> {
+        let name = name.trim_matches('/');
+        let not_found = || io::Error::new(io::ErrorKind::NotFound, name.to_string());
+        if name.is_empty() {
+            return Err(not_found());
+        }
+        let entry = repo
+            .find_tree(self.tree)
+            .map_err(|err| git_error(err.into()))?
+            .lookup_entry_by_path(name)
+            .map_err(|err| git_error(err.into()))?
+            .filter(|entry| entry.mode().is_blob())
+            .ok_or_else(not_found)?;
+        Ok(entry.object_id())
+    }
+
+    /// A store serving the documents of this snapshot
+    ///
+    /// Like the work tree, its directories are listed as they are first
+    /// requested; see [`FileTree::spawn_scan`] to list the rest.
+    pub fn into_store(self) -> DocumentStore {
+        DocumentStore::new(Arc::new(FileTree::with_source(Arc::new(self))))
+    }
+}
+
+fn git_error(err: GitError) -> io::Error {
+    io::Error::other(err)
+}
+
+impl DocumentSource for Snapshot {
+    fn list(&self, dir: &str) -> io::Result<Entries> {
+        let local = self.repo.to_thread_local();
+        let id = match dir.trim_matches('/') {
+            "" => self.tree,
+            dir => {
+                let entry = local
+                    .find_tree(self.tree)
+                    .map_err(|err| git_error(err.into()))?
+                    .lookup_entry_by_path(dir)
+                    .map_err(|err| git_error(err.into()))?
+                    .filter(|entry| entry.mode().is_tree())
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, dir.to_string()))?;
+                entry.object_id()
+            }
+        };
+        let tree = local.find_tree(id).map_err(|err| git_error(err.into()))?;
+
+        let mut entries = Entries::default();
+        for entry in tree.iter() {
+            let entry = entry.map_err(|err| git_error(err.into()))?;
+            let name = entry.filename().to_str_lossy().into_owned();
+            if entry.mode().is_tree() {
+                entries.dirs.push(name);
+            } else if entry.mode().is_blob() {
+                entries.files.push(name);
+            }
+        }
+        Ok(entries)
+    }
+
+    fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let local = self.repo.to_thread_local();
+        let id = self.blob(&local, name)?;
+        let blob = local.find_blob(id).map_err(|err| git_error(err.into()))?;
+        Ok(blob.detach().data)
+    }
+
+    fn stat(&self, name: &str) -> io::Result<Stat> {
+        let local = self.repo.to_thread_local();
+        let id = self.blob(&local, name)?;
+        let header = local.find_header(id).map_err(|err| git_error(err.into()))?;
+        Ok(Stat {
+            size: header.size(),
+            modified: Some(UNIX_EPOCH + Duration::from_secs(self.time)),
+        })
+    }
+}
+
+/// The branches and tags of the repository a served directory is in
+pub struct Versions {
+    repo: ThreadSafeRepository,
+    /// The served directory relative to the work tree, `/`-terminated, or empty
+    prefix: String,
+    /// Ref served at the root with `--git-ref`; the work tree is otherwise
+    default: Option<String>,
+    /// Routers of recently served snapshots, least recently used first
+    routers: Mutex<Vec<CachedRouter>>,
+}
+
+/// The router of a snapshot and the scan listing its documents, which is
+/// stopped once the router is dropped from the cache
+struct CachedRouter {
+    commit: ObjectId,
+    router: Router,
+    scan: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for CachedRouter {
+    fn drop(&mut self) {
+        self.scan.abort();
+    }
+}
+
+impl std::fmt::Debug for Versions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Versions")
+            .field("path", &self.repo.path())
+            .field("default", &self.default)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Versions {
+    /// Open the repository containing `base_dir`, or the bare repository it is
+    ///
+    /// `default` is the ref served at the root, if that is not the work tree.
+    pub fn open(base_dir: &Path, default: Option<String>) -> Result<Self, GitError> {
+        let repo = ThreadSafeRepository::discover(base_dir)?;
+        let local = repo.to_thread_local();
+        let prefix = match local.workdir() {
+            Some(workdir) => git::served_prefix(workdir, base_dir)?,
+            None => String::new(),
+        };
+        drop(local);
+        Ok(Self {
+            repo,
+            prefix,
+            default,
+            routers: Mutex::default(),
+        })
+    }
+
+    /// The git directory
+    pub fn path(&self) -> &Path {
+        self.repo.path()
+    }
+
+    /// The ref served at the root, if that is not the work tree
+    pub fn default_ref(&self) -> Option<&str> {
+        self.default.as_deref()
+    }
+
+    /// The commit `reference` names, if any
+    async fn resolve(&self, reference: &str) -> Result<Option<ObjectId>, GitError> {
+        let repo = self.repo.clone();
+        let reference = reference.to_string();
+        tokio::task::spawn_blocking(move || {
+            Ok(git::resolve(&repo.to_thread_local(), &reference).map(|commit| commit.id))
+        })
+        .await?
+    }
+
+    /// Read the documents at `reference`, a branch, tag or full or abbreviated
+    /// commit id
+    ///
+    /// Returns `None` if the ref is unknown or the served directory does not
+    /// exist there.
+    pub async fn snapshot(&self, reference: &str) -> Result<Option<Snapshot>, GitError> {
+        let Some(commit) = self.resolve(reference).await? else {
+            return Ok(None);
+        };
+        self.load(commit).await
+    }
+
+    async fn load(&self, commit: ObjectId) -> Result<Option<Snapshot>, GitError> {
+        let repo = self.repo.clone();
+        let prefix = self.prefix.clone();
+        tokio::task::spawn_blocking(move || Snapshot::load(&repo, &prefix, commit)).await?
+    }
+
+    /// The read-only router of the snapshot at `reference`
+    async fn router(&self, reference: &str, state: &AppState) -> Result<Router, AppError> {
+        let AppState::Directory {
+            dir_path, language, ..
+        } = state
+        else {
+            return Err(AppError::WrongMode);
+        };
+        let commit = self
+            .resolve(reference)
+            .await
+            .map_err(read_failure)?
+            .ok_or(AppError::NotFound)?;
+        {
+            let mut routers = self.cached_routers();
+            if let Some(index) = routers.iter().position(|cached| cached.commit == commit) {
+                let cached = routers.remove(index);
+                let router = cached.router.clone();
+                routers.push(cached);
+                return Ok(router);
+            }
+        }
+
+        let snapshot = self
+            .load(commit)
+            .await
+            .map_err(read_failure)?
+            .ok_or(AppError::NotFound)?;
+        // Directories are read as requested while the scan lists the rest
+        let store = snapshot.into_store();
+        let scan = store.files().spawn_scan();
+        let router = server::version_router(Arc::new(AppState::Directory {
+            dir_path: dir_path.clone(),
+            store: Arc::new(store),
+            language: language.clone(),
+        }));
+        let mut routers = self.cached_routers();
+        // Another request may have built the same router meanwhile
+        routers.retain(|cached| cached.commit != commit);
+        let excess = (routers.len() + 1).saturating_sub(MAX_CACHED);
+        routers.drain(..excess);
+        routers.push(CachedRouter {
+            commit,
+            router: router.clone(),
+            scan,
+        });
+        Ok(router)
+    }
+
+    fn cached_routers(&self) -> std::sync::MutexGuard<'_, Vec<CachedRouter>> {
+        self.routers.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Branches and tags for the version switcher of a page showing `current`,
+    /// or the root if `None`
+    ///
+    /// The refs are read on a blocking thread.
+    pub async fn menu(&self, current: Option<&str>) -> VersionMenu {
+        let menu = VersionMenu {
+            current: current.map(str::to_string),
+            default: self.default.clone(),
+            ..VersionMenu::default()
+        };
+        let repo = self.repo.clone();
+        let fallback = menu.clone();
+        tokio::task::spawn_blocking(move || refs_menu(&repo.to_thread_local(), menu))
+            .await
+            .unwrap_or(fallback)
+    }
+}
+
+/// `menu` with the branches and tags of `repo`
+fn refs_menu(repo: &gix::Repository, mut menu: VersionMenu) -> VersionMenu {
+    let Ok(refs) = repo.references() else {
+        return menu;
+    };
+    let names = |iter: Result<gix::reference::iter::Iter<'_, '_>, _>| -> Vec<String> {
+        iter.map(|refs| {
+            refs.filter_map(Result::ok)
+                .map(|reference| reference.name().shorten().to_string())
+                .take(MAX_REFS)
+                .collect()
+        })
+        .unwrap_or_default()
+    };
+    menu.branches = names(refs.local_branches());
+    menu.tags = names(refs.tags());
+    menu
+}
+
+/// The version a request is served from, attached to the requests of a router
+/// serving a git repository
+#[derive(Clone, Debug)]
+pub struct VersionScope {
+    pub versions: Arc<Versions>,
+    /// Prefix of the version's URLs; empty at the root
+    pub root: String,
+    /// The ref served; `None` for the work tree
+    pub reference: Option<String>,
+}
+
+impl VersionScope {
+    /// The scope of the root of a server
+    pub fn root(versions: Arc<Versions>) -> Self {
+        let reference = versions.default.clone();
+        Self {
+            versions,
+            root: String::new(),
+            reference,
+        }
+    }
+
+    /// The version switcher of a page in this scope
+    pub async fn menu(&self) -> VersionMenu {
+        let current = match self.root.is_empty() {
+            true => None,
+            false => self.reference.as_deref(),
+        };
+        self.versions.menu(current).await
+    }
+}
+
+fn read_failure(err: GitError) -> AppError {
+    tracing::error!(error = %err, "Failed to read the git repository");
+    AppError::ReadFailure
+}
+
+/// The ref of a `/v/{ref}/…` URL
+#[derive(Deserialize)]
+pub struct VersionPath {
+    reference: String,
+}
+
+/// Handler for `/v/{ref}/…`: the pages of a branch, tag or commit, served
+/// read-only by that snapshot's router with the rest of the URL
+pub async fn serve_version(
+    State(state): State<Arc<AppState>>,
+    UrlPath(VersionPath { reference }): UrlPath<VersionPath>,
+    request: Request,
+) -> Response {
+    let AppState::Directory { store, .. } = state.as_ref() else {
+        return AppError::WrongMode.into_response();
+    };
+    let Some(versions) = store.versions() else {
+        return AppError::NotFound.into_response();
+    };
+    let router = match versions.router(&reference, &state).await {
+        Ok(router) => router,
+        Err(err) => return err.into_response(),
+    };
+
+    // The path after `/v/{ref}`, still percent-encoded
+    let rest = request
+        .uri()
+        .path()
+        .strip_prefix("/v/")
+        .and_then(|path| path.split_once('/'))
+        .map_or("", |(_, rest)| rest);
+    let uri = match request.uri().query() {
+        Some(query) => format!("/{}?{}", rest, query),
+        None => format!("/{}", rest),
+    };
+    let Ok(uri) = uri.parse::<Uri>() else {
+        return AppError::BadPath.into_response();
+    };
+
+    // A new request, since the router would see the parameters of this route
+    let (parts, body) = request.into_parts();
+    let mut request = Request::new(body);
+    *request.method_mut() = parts.method;
+    *request.uri_mut() = uri;
+    *request.version_mut() = parts.version;
+    *request.headers_mut() = parts.headers;
+    if let Some(config) = parts.extensions.get::<Arc<ServerConfig>>() {
+        request.extensions_mut().insert(config.clone());
+    }
+    request.extensions_mut().insert(VersionScope {
+        versions: versions.clone(),
+        root: format!("/v/{}", html::encode_segment(&reference)),
+        reference: Some(reference),
+    });
+
+    match router.oneshot(request).await {
+        Ok(response) => response,
+        Err(never) => match never {},
+    }
+}
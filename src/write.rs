@@ -0,0 +1,319 @@
+//! Crash-safe writes into the served directory
+//!
+//! New content is written to a [`TempFile`] in the destination directory, flushed
+//! to disk and then moved over the destination with [`commit`], so readers only
+//! ever see the old or the new file. [`ConflictPolicy`] decides what happens when
+//! the destination already exists, and an `If-Match` entity tag protects against
+//! overwriting a version the client has not seen.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs;
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::conditional;
+use crate::history::Snapshot;
+
+/// How many `name (n).md` candidates [`ConflictPolicy::Rename`] tries
+const MAX_RENAME_ATTEMPTS: usize = 1000;
+
+/// Serializes the check-and-replace step of concurrent commits
+static COMMIT_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// What to do when the destination of a write already exists
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Fail and leave the existing file alone
+    #[default]
+    Reject,
+    /// Replace the existing file
+    Overwrite,
+    /// Keep both by writing to `name (1).md`, `name (2).md`, …
+    Rename,
+}
+
+impl ConflictPolicy {
+    /// Parse `reject`, `overwrite` or `rename`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "reject" => Some(ConflictPolicy::Reject),
+            "overwrite" => Some(ConflictPolicy::Overwrite),
+            "rename" => Some(ConflictPolicy::Rename),
+            _ => None,
+        }
+    }
+}
+
+/// Why a write could not be committed
+#[derive(Debug)]
+pub enum WriteError {
+    /// The destination exists and the policy is [`ConflictPolicy::Reject`]
+    Exists,
+    /// The destination does not match the `If-Match` tag
+    Stale,
+    Io(io::Error),
+}
+
+impl From<io::Error> for WriteError {
+    fn from(err: io::Error) -> Self {
+        WriteError::Io(err)
+    }
+}
+
+/// A temporary file that is deleted when dropped, unless it was committed
+pub struct TempFile {
+    pub path: PathBuf,
+}
+
+impl TempFile {
+    /// Create an empty temporary file in `dir`, which keeps the final rename on
+    /// one filesystem
+    pub async fn create_in(dir: &Path) -> io::Result<(Self, fs::File)> {
+        let temp = Self {
+            path: dir.join(format!(".rsmd-write-{}.tmp", unique_id())),
+        };
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp.path)
+            .await?;
+        Ok((temp, file))
+    }
+
+    /// [`TempFile::create_in`] for blocking threads
+    pub fn create_in_blocking(dir: &Path) -> io::Result<(Self, std::fs::File)> {
+        let temp = Self {
+            path: dir.join(format!(".rsmd-write-{}.tmp", unique_id())),
+        };
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp.path)?;
+        Ok((temp, file))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // Gone already once committed
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Unguessable identifier for temporary files, trash entries and uploads
+///
+/// Made of 128 bits from the operating system's random number generator, hashed
+/// with the clock, a counter and the process id so ids stay unique even if the
+/// generator fails.
+pub fn unique_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut random = [0u8; 16];
+    if let Err(err) = getrandom::fill(&mut random) {
+        tracing::warn!(error = %err, "No random bytes for a new id");
+    }
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos());
+    let mut hasher = Sha256::new();
+    hasher.update(random);
+    hasher.update(nanos.to_le_bytes());
+    hasher.update(COUNTER.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+    hasher.update(std::process::id().to_le_bytes());
+    conditional::hex(&hasher.finalize()[..16])
+}
+
+/// Entity tag of the file at `path`, or `None` if it does not exist
+pub async fn current_etag(path: &Path) -> io::Result<Option<String>> {
+    match fs::read(path).await {
+        Ok(bytes) => Ok(Some(conditional::etag(&bytes))),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Keep commits out while the caller moves files around in the served directory
+///
+/// Must not be held across a call to [`commit`] or [`commit_blocking`], or a
+/// write to a [`DocumentSource`](crate::source::DocumentSource), which take the
+/// same lock.
+pub async fn lock() -> MutexGuard<'static, ()> {
+    COMMIT_LOCK.lock().await
+}
+
+/// Move a fully written and synced `temp` file to `destination`
+///
+/// With `if_match` the destination must match that tag (or exist, for `*`) and
+/// is then replaced whatever the policy. Returns the path actually written,
+/// which differs from `destination` under [`ConflictPolicy::Rename`]. A file
+/// being replaced is first recorded in the `snapshot`'s history, if any.
+pub async fn commit(
+    temp: &TempFile,
+    destination: &Path,
+    policy: ConflictPolicy,
+    if_match: Option<&str>,
+    snapshot: Option<Snapshot<'_>>,
+) -> Result<PathBuf, WriteError> {
+    let _guard = COMMIT_LOCK.lock().await;
+    let record = async || match &snapshot {
+        Some(snapshot) => {
+            snapshot
+                .history
+                .record(snapshot.name, destination, snapshot.reason)
+                .await
+        }
+        None => Ok(()),
+    };
+
+    let written = if let Some(if_match) = if_match {
+        let current = current_etag(destination).await?;
+        if !conditional::if_match(if_match, current.as_deref()) {
+            return Err(WriteError::Stale);
+        }
+        record().await?;
+        fs::rename(&temp.path, destination).await?;
+        destination.to_path_buf()
+    } else {
+        match policy {
+            ConflictPolicy::Overwrite => {
+                record().await?;
+                fs::rename(&temp.path, destination).await?;
+                destination.to_path_buf()
+            }
+            ConflictPolicy::Reject => {
+                link_new(&temp.path, destination).await?;
+                destination.to_path_buf()
+            }
+            ConflictPolicy::Rename => rename_free(&temp.path, destination).await?,
+        }
+    };
+
+    if let Some(parent) = written.parent() {
+        sync_dir(parent).await;
+    }
+    Ok(written)
+}
+
+/// [`commit`] for blocking threads, without `If-Match` or history
+///
+/// Takes the same lock, so it must not be called from an async task.
+pub fn commit_blocking(
+    temp: &TempFile,
+    destination: &Path,
+    policy: ConflictPolicy,
+) -> Result<PathBuf, WriteError> {
+    let _guard = COMMIT_LOCK.blocking_lock();
+    let written = match policy {
+        ConflictPolicy::Overwrite => {
+            std::fs::rename(&temp.path, destination)?;
+            destination.to_path_buf()
+        }
+        ConflictPolicy::Reject => {
+            link_new_blocking(&temp.path, destination)?;
+            destination.to_path_buf()
+        }
+        ConflictPolicy::Rename => rename_free_blocking(&temp.path, destination)?,
+    };
+
+    if let Some(parent) = written.parent()
+        && let Ok(dir) = std::fs::File::open(parent)
+    {
+        let _ = dir.sync_all();
+    }
+    Ok(written)
+}
+
+/// Move `source` to `destination` unless something already exists there
+async fn link_new(source: &Path, destination: &Path) -> Result<(), WriteError> {
+    match fs::hard_link(source, destination).await {
+        Ok(()) => {
+            fs::remove_file(source).await?;
+            Ok(())
+        }
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Err(WriteError::Exists),
+        // Filesystems without hard links; the commit lock keeps this race-free
+        // among our own writers
+        Err(_) => {
+            if fs::try_exists(destination).await? {
+                return Err(WriteError::Exists);
+            }
+            fs::rename(source, destination).await?;
+            Ok(())
+        }
+    }
+}
+
+/// [`link_new`] for blocking threads
+fn link_new_blocking(source: &Path, destination: &Path) -> Result<(), WriteError> {
+    match std::fs::hard_link(source, destination) {
+        Ok(()) => {
+            std::fs::remove_file(source)?;
+            Ok(())
+        }
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Err(WriteError::Exists),
+        Err(_) => {
+            if std::fs::exists(destination)? {
+                return Err(WriteError::Exists);
+            }
+            std::fs::rename(source, destination)?;
+            Ok(())
+        }
+    }
+}
+
+/// The paths [`ConflictPolicy::Rename`] tries: `destination`, then
+/// `name (1).ext`, `name (2).ext`, …
+fn rename_candidates(destination: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    let parent = destination.parent().unwrap_or(Path::new(""));
+    let stem = destination
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = destination
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+
+    (0..MAX_RENAME_ATTEMPTS).map(move |attempt| {
+        if attempt == 0 {
+            destination.to_path_buf()
+        } else {
+            parent.join(format!("{} ({}){}", stem, attempt, extension))
+        }
+    })
+}
+
+/// Move `source` to `destination` or the first free `name (n).ext` next to it
+async fn rename_free(source: &Path, destination: &Path) -> Result<PathBuf, WriteError> {
+    for candidate in rename_candidates(destination) {
+        match link_new(source, &candidate).await {
+            Ok(()) => return Ok(candidate),
+            Err(WriteError::Exists) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Err(WriteError::Exists)
+}
+
+/// [`rename_free`] for blocking threads
+fn rename_free_blocking(source: &Path, destination: &Path) -> Result<PathBuf, WriteError> {
+    for candidate in rename_candidates(destination) {
+        match link_new_blocking(source, &candidate) {
+            Ok(()) => return Ok(candidate),
+            Err(WriteError::Exists) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Err(WriteError::Exists)
+}
+
+/// Persist a rename by syncing its directory, where the platform supports it
+async fn sync_dir(dir: &Path) {
+    if let Ok(dir) = fs::File::open(dir).await {
+        let _ = dir.sync_all().await;
+    }
+}
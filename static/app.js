@@ -3,14 +3,6 @@
 const API_BASE = '/api';
 let currentFiles = [];
 
-// Initialize marked.js options
-marked.setOptions({
-    breaks: true,
-    gfm: true,
-    headerIds: true,
-    mangle: false
-});
-
 // Initialize app
 async function init() {
     await loadFiles();
@@ -24,11 +16,17 @@ async function loadFiles() {
     const fileListEl = document.getElementById('file-list');
 
     try {
-        const response = await fetch(`${API_BASE}/files`);
-        if (!response.ok) throw new Error('Failed to load files');
-
-        const data = await response.json();
-        currentFiles = data.files || [];
+        currentFiles = [];
+        let cursor = null;
+        do {
+            const query = cursor ? `?cursor=${encodeURIComponent(cursor)}` : '';
+            const response = await fetch(`${API_BASE}/files${query}`);
+            if (!response.ok) throw new Error('Failed to load files');
+
+            const data = await response.json();
+            currentFiles.push(...(data.files || []));
+            cursor = data.next_cursor;
+        } while (cursor);
 
         loadingEl.style.display = 'none';
 
@@ -61,24 +59,23 @@ async function loadMarkdownFile(filename) {
     contentArea.style.opacity = '0.6';
 
     try {
-        const response = await fetch(`${API_BASE}/markdown/${encodeURIComponent(filename)}`);
-        if (!response.ok) throw new Error('File not found');
+        const path = filename.split('/').map(encodeURIComponent).join('/');
+        const response = await fetch(`${API_BASE}/render/${path}`);
+        if (!response.ok) {
+            // API errors carry a { code, message } envelope
+            const error = await response.json().catch(() => ({}));
+            throw new Error(error.message || 'File not found');
+        }
 
+        // Rendered by the server with the same engine as /view/
         const data = await response.json();
-        const markdown = data.markdown || '';
-
-        // Convert markdown to HTML using marked.js
-        const html = marked.parse(markdown);
-
-        // Render content
-        contentArea.innerHTML = html;
+        contentArea.innerHTML = data.html || '';
         contentArea.style.opacity = '1';
 
         // Update page title
-        const h1 = contentArea.querySelector('h1');
-        if (h1) {
-            document.title = `${h1.textContent} - Markdown Viewer`;
-        }
+        document.title = data.title
+            ? `${data.title} - Markdown Viewer`
+            : 'Markdown Viewer';
 
         // Highlight active file in sidebar
         updateActiveFile(filename);
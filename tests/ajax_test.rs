@@ -43,3 +43,12 @@ fn test_is_dynamic_request_xhr() {
 fn test_is_dynamic_request_both() {
     assert!(is_dynamic_request(Some("true"), Some("XMLHttpRequest")));
 }
+
+#[test]
+fn test_dynamic_script_uploads_every_dropped_file() {
+    let script = dynamic_script();
+    assert!(script.contains("FormData"));
+    assert!(script.contains("webkitGetAsEntry"));
+    assert!(script.contains("webkitRelativePath"));
+    assert!(!script.contains("files[0]"));
+}
@@ -3,23 +3,26 @@ use axum::{
     http::{Request, StatusCode},
 };
 use rsmd::{
-    directory::MarkdownFile,
-    server::{AppState, FilesResponse, MarkdownResponse, create_router},
+    directory::FileTree,
+    directory::{ScanStatus, TreeNode},
+    error::ErrorResponse,
+    server::{AppState, FilesResponse, MarkdownResponse, RenderResponse, create_router},
+    source::FsSource,
+    store::{CacheStats, DocumentStore},
 };
-use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 use tower::util::ServiceExt;
 
 #[tokio::test]
 async fn test_api_get_files_empty() {
     let state = Arc::new(AppState::Directory {
         dir_path: "/test".to_string(),
-        files: Arc::new(RwLock::new(vec![])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(PathBuf::from("/test"))),
+            Vec::<String>::new(),
+        )))),
         language: rsmd::i18n::Language::English,
-        base_dir: PathBuf::from("/test"),
     });
 
     let app = create_router(state);
@@ -48,19 +51,11 @@ async fn test_api_get_files_empty() {
 async fn test_api_get_files_with_data() {
     let state = Arc::new(AppState::Directory {
         dir_path: "/test".to_string(),
-        files: Arc::new(RwLock::new(vec![
-            MarkdownFile {
-                name: "test1.md".to_string(),
-                path: PathBuf::from("/test/test1.md"),
-            },
-            MarkdownFile {
-                name: "test2.md".to_string(),
-                path: PathBuf::from("/test/test2.md"),
-            },
-        ])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(PathBuf::from("/test"))),
+            ["test1.md", "test2.md"],
+        )))),
         language: rsmd::i18n::Language::English,
-        base_dir: PathBuf::from("/test"),
     });
 
     let app = create_router(state);
@@ -89,29 +84,23 @@ async fn test_api_get_files_with_data() {
 
 #[tokio::test]
 async fn test_api_get_markdown_from_cache() {
-    let mut cache = HashMap::new();
-    cache.insert(
-        "test.md".to_string(),
-        (
-            "# Test Header\n\nContent".to_string(),
-            "<h1>Test Header</h1>".to_string(),
-        ),
-    );
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("test.md");
+    std::fs::write(&file_path, "# Test Header\n\nContent").unwrap();
+
+    let store = Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+        Arc::new(FsSource::new(temp_dir.path().to_path_buf())),
+        ["test.md"],
+    ))));
+    store.get("test.md").await.unwrap();
 
     let state = Arc::new(AppState::Directory {
         dir_path: "/test".to_string(),
-        files: Arc::new(RwLock::new(vec![MarkdownFile {
-            name: "test.md".to_string(),
-            path: PathBuf::from("/test/test.md"),
-        }])),
-        file_cache: Arc::new(RwLock::new(cache)),
+        store,
         language: rsmd::i18n::Language::English,
-        base_dir: PathBuf::from("/test"),
     });
 
-    let app = create_router(state);
-
-    let response = app
+    let response = create_router(state.clone())
         .oneshot(
             Request::builder()
                 .uri("/api/markdown/test.md")
@@ -129,16 +118,35 @@ async fn test_api_get_markdown_from_cache() {
     let json: MarkdownResponse = serde_json::from_slice(&body).unwrap();
 
     assert_eq!(json.markdown, "# Test Header\n\nContent");
+
+    let response = create_router(state)
+        .oneshot(
+            Request::builder()
+                .uri("/api/cache")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let stats: CacheStats = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(stats.hits, 1);
+    assert_eq!(stats.misses, 1);
+    assert_eq!(stats.entries, 1);
 }
 
 #[tokio::test]
 async fn test_api_get_markdown_not_found() {
     let state = Arc::new(AppState::Directory {
         dir_path: "/test".to_string(),
-        files: Arc::new(RwLock::new(vec![])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(PathBuf::from("/test"))),
+            Vec::<String>::new(),
+        )))),
         language: rsmd::i18n::Language::English,
-        base_dir: PathBuf::from("/test"),
     });
 
     let app = create_router(state);
@@ -153,15 +161,15 @@ async fn test_api_get_markdown_not_found() {
         .await
         .unwrap();
 
-    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
 
     let body = axum::body::to_bytes(response.into_body(), usize::MAX)
         .await
         .unwrap();
-    let json: MarkdownResponse = serde_json::from_slice(&body).unwrap();
+    let json: ErrorResponse = serde_json::from_slice(&body).unwrap();
 
-    assert!(json.markdown.contains("Error"));
-    assert!(json.markdown.contains("not found"));
+    assert_eq!(json.code, "not_found");
+    assert!(json.message.contains("not found"));
 }
 
 #[tokio::test]
@@ -177,13 +185,11 @@ async fn test_api_get_markdown_from_file() {
 
     let state = Arc::new(AppState::Directory {
         dir_path: temp_dir.path().to_str().unwrap().to_string(),
-        files: Arc::new(RwLock::new(vec![MarkdownFile {
-            name: "test.md".to_string(),
-            path: test_file.clone(),
-        }])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(temp_dir.path().to_path_buf())),
+            ["test.md"],
+        )))),
         language: rsmd::i18n::Language::English,
-        base_dir: temp_dir.path().to_path_buf(),
     });
 
     let app = create_router(state);
@@ -219,13 +225,11 @@ async fn test_api_get_markdown_read_error() {
 
     let state = Arc::new(AppState::Directory {
         dir_path: temp_dir.path().to_str().unwrap().to_string(),
-        files: Arc::new(RwLock::new(vec![MarkdownFile {
-            name: "gone.md".to_string(),
-            path: file_path.clone(),
-        }])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(temp_dir.path().to_path_buf())),
+            ["gone.md"],
+        )))),
         language: rsmd::i18n::Language::English,
-        base_dir: temp_dir.path().to_path_buf(),
     });
 
     // Remove file to trigger read error
@@ -243,12 +247,377 @@ async fn test_api_get_markdown_read_error() {
         .await
         .unwrap();
 
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let json: ErrorResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(json.code, "read_failure");
+}
+
+#[tokio::test]
+async fn test_api_scan_status_reports_progress() {
+    use tempfile::tempdir;
+
+    let temp_dir = tempdir().unwrap();
+    std::fs::create_dir_all(temp_dir.path().join("docs")).unwrap();
+    std::fs::write(temp_dir.path().join("docs/a.md"), "# A").unwrap();
+
+    let files = Arc::new(FileTree::new(temp_dir.path().to_path_buf()));
+    files.spawn_scan().await.unwrap();
+
+    let state = Arc::new(AppState::Directory {
+        dir_path: temp_dir.path().to_str().unwrap().to_string(),
+        store: Arc::new(DocumentStore::new(files)),
+        language: rsmd::i18n::Language::English,
+    });
+
+    let app = create_router(state);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/scan")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
     assert_eq!(response.status(), StatusCode::OK);
 
     let body = axum::body::to_bytes(response.into_body(), usize::MAX)
         .await
         .unwrap();
-    let json: MarkdownResponse = serde_json::from_slice(&body).unwrap();
+    let status: ScanStatus = serde_json::from_slice(&body).unwrap();
+
+    assert!(status.complete);
+    assert_eq!(status.files_found, 1);
+    assert_eq!(status.directories_scanned, 2);
+}
+
+fn state_with_files(names: &[&str]) -> Arc<AppState> {
+    let files = FileTree::with_files(
+        Arc::new(FsSource::new(PathBuf::from("/test"))),
+        names.iter().copied(),
+    );
+
+    Arc::new(AppState::Directory {
+        dir_path: "/test".to_string(),
+        store: Arc::new(DocumentStore::new(Arc::new(files))),
+        language: rsmd::i18n::Language::English,
+    })
+}
+
+async fn get_files(state: Arc<AppState>, uri: &str) -> (StatusCode, FilesResponse) {
+    let response = create_router(state)
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn test_api_get_files_paginates_with_cursor() {
+    let state = state_with_files(&["a.md", "b.md", "docs/c.md", "docs/d.md", "e.md"]);
+
+    let (status, first) = get_files(state.clone(), "/api/files?limit=2").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(first.files, vec!["a.md", "b.md"]);
+    let cursor = first.next_cursor.expect("more pages expected");
+
+    let (_, second) = get_files(
+        state.clone(),
+        &format!("/api/files?limit=2&cursor={cursor}"),
+    )
+    .await;
+    assert_eq!(second.files, vec!["docs/c.md", "docs/d.md"]);
+
+    let cursor = second.next_cursor.unwrap().replace('/', "%2F");
+    let (_, last) = get_files(state, &format!("/api/files?limit=2&cursor={cursor}")).await;
+    assert_eq!(last.files, vec!["e.md"]);
+    assert!(last.next_cursor.is_none());
+}
+
+#[tokio::test]
+async fn test_api_get_files_pages_follow_name_order() {
+    // Folders sort by their name with a slash: "a.b/…" < "a.md" < "a/…"
+    let names = [
+        "a/x.md",
+        "a.md",
+        "a.b/y.md",
+        "a-c.md",
+        "b/deep/z.md",
+        "b.md",
+    ];
+    let state = state_with_files(&names);
+    let mut expected: Vec<&str> = names.to_vec();
+    expected.sort();
+
+    let mut seen = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let uri = match &cursor {
+            Some(cursor) => format!("/api/files?limit=1&cursor={}", cursor.replace('/', "%2F")),
+            None => "/api/files?limit=1".to_string(),
+        };
+        let (_, page) = get_files(state.clone(), &uri).await;
+        assert!(page.complete);
+        assert!(page.files.len() <= 1);
+        seen.extend(page.files);
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(seen, expected);
+}
+
+#[tokio::test]
+async fn test_api_get_files_reports_an_unfinished_scan() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+    std::fs::write(temp_dir.path().join("top.md"), "# Top\n").unwrap();
+    std::fs::write(temp_dir.path().join("sub/page.md"), "# Page\n").unwrap();
+    let files = Arc::new(FileTree::new(temp_dir.path().to_path_buf()));
+    let state = Arc::new(AppState::Directory {
+        dir_path: "/test".to_string(),
+        store: Arc::new(DocumentStore::new(files.clone())),
+        language: rsmd::i18n::Language::English,
+    });
+
+    let (_, partial) = get_files(state.clone(), "/api/files").await;
+    assert_eq!(partial.files, vec!["top.md"]);
+    assert!(!partial.complete);
+
+    files.scan().await;
+    let (_, full) = get_files(state, "/api/files").await;
+    assert_eq!(full.files, vec!["sub/page.md", "top.md"]);
+    assert!(full.complete);
+}
+
+#[tokio::test]
+async fn test_api_get_files_filters_by_prefix_and_glob() {
+    let state = state_with_files(&[
+        "readme.md",
+        "guides/setup.md",
+        "guides/deploy.md",
+        "guides/advanced/tuning.md",
+    ]);
+
+    let (_, by_prefix) = get_files(state.clone(), "/api/files?prefix=guides/").await;
+    assert_eq!(
+        by_prefix.files,
+        vec![
+            "guides/advanced/tuning.md",
+            "guides/deploy.md",
+            "guides/setup.md"
+        ]
+    );
+
+    let (_, by_glob) = get_files(state.clone(), "/api/files?glob=guides/*.md").await;
+    assert_eq!(by_glob.files, vec!["guides/deploy.md", "guides/setup.md"]);
+
+    let (_, recursive) = get_files(state.clone(), "/api/files?glob=**/tuning.md").await;
+    assert_eq!(recursive.files, vec!["guides/advanced/tuning.md"]);
+
+    let response = create_router(state)
+        .oneshot(
+            Request::builder()
+                .uri("/api/files?glob=%5B")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let json: ErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json.code, "bad_path");
+}
+
+#[tokio::test]
+async fn test_api_errors_are_localized_json() {
+    let state = Arc::new(AppState::Directory {
+        dir_path: "/test".to_string(),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(PathBuf::from("/test"))),
+            Vec::<String>::new(),
+        )))),
+        language: rsmd::i18n::Language::Korean,
+    });
+
+    let response = create_router(state)
+        .oneshot(
+            Request::builder()
+                .uri("/api/render/missing.md")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let json: ErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        json,
+        ErrorResponse {
+            code: "not_found".to_string(),
+            message: rsmd::i18n::Language::Korean
+                .text("error_not_found")
+                .to_string(),
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_api_get_tree_returns_nested_folders() {
+    use tempfile::tempdir;
+
+    let temp_dir = tempdir().unwrap();
+    std::fs::create_dir_all(temp_dir.path().join("guides/advanced")).unwrap();
+    std::fs::write(temp_dir.path().join("readme.md"), "# Readme").unwrap();
+    std::fs::write(temp_dir.path().join("guides/setup.md"), "# Setup!").unwrap();
+    std::fs::write(temp_dir.path().join("guides/advanced/tuning.md"), "# T").unwrap();
 
-    assert!(json.markdown.contains("Failed to read file"));
+    let state = Arc::new(AppState::Directory {
+        dir_path: temp_dir.path().to_str().unwrap().to_string(),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::new(
+            temp_dir.path().to_path_buf(),
+        )))),
+        language: rsmd::i18n::Language::English,
+    });
+
+    let response = create_router(state.clone())
+        .oneshot(
+            Request::builder()
+                .uri("/api/tree?path=&depth=2")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let tree: TreeNode = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(tree.path, "");
+    assert_eq!(tree.folder_count, 1);
+    assert_eq!(tree.file_count, 1);
+    let files = tree.files.unwrap();
+    assert_eq!(files[0].name, "readme.md");
+    assert_eq!(files[0].size, 8);
+    assert!(files[0].modified.is_some());
+
+    let guides = &tree.folders.unwrap()[0];
+    assert_eq!(guides.path, "guides");
+    assert_eq!(guides.folder_count, 1);
+    assert_eq!(guides.file_count, 1);
+    assert_eq!(guides.files.as_ref().unwrap()[0].name, "guides/setup.md");
+
+    // The deepest level is summarised but not expanded
+    let advanced = &guides.folders.as_ref().unwrap()[0];
+    assert_eq!(advanced.path, "guides/advanced");
+    assert!(advanced.folders.is_none());
+    assert!(advanced.files.is_none());
+
+    // Backslashes and `.` segments are normalized like everywhere else
+    let response = create_router(state.clone())
+        .oneshot(
+            Request::builder()
+                .uri("/api/tree?path=.%5Cguides%2F.")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let tree: TreeNode = serde_json::from_slice(&body).unwrap();
+    assert_eq!(tree.path, "guides");
+
+    std::fs::create_dir_all(temp_dir.path().join(".rsmd-trash/x")).unwrap();
+    for (uri, status) in [
+        ("/api/tree?path=missing", StatusCode::NOT_FOUND),
+        ("/api/tree?path=.rsmd-trash", StatusCode::NOT_FOUND),
+        ("/api/tree?path=guides%5C..%5C..", StatusCode::BAD_REQUEST),
+    ] {
+        let response = create_router(state.clone())
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), status, "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn test_api_render_returns_server_html_and_metadata() {
+    use tempfile::tempdir;
+
+    let temp_dir = tempdir().unwrap();
+    std::fs::create_dir_all(temp_dir.path().join("guides")).unwrap();
+    let source = "# Setup Guide\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n~~old~~\n";
+    std::fs::write(temp_dir.path().join("guides/setup.md"), source).unwrap();
+
+    let state = Arc::new(AppState::Directory {
+        dir_path: temp_dir.path().to_str().unwrap().to_string(),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::new(
+            temp_dir.path().to_path_buf(),
+        )))),
+        language: rsmd::i18n::Language::English,
+    });
+
+    let response = create_router(state.clone())
+        .oneshot(
+            Request::builder()
+                .uri("/api/render/guides/setup.md")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let rendered: RenderResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(rendered.name, "guides/setup.md");
+    assert_eq!(rendered.title.as_deref(), Some("Setup Guide"));
+    assert_eq!(
+        rendered.html,
+        rsmd::MarkdownParser::new(source.to_string()).to_html()
+    );
+    assert!(rendered.html.contains("<table>"));
+    assert!(rendered.html.contains("<del>old</del>"));
+    assert_eq!(rendered.metadata.size, source.len() as u64);
+    assert!(rendered.metadata.modified.is_some());
+
+    let missing = create_router(state)
+        .oneshot(
+            Request::builder()
+                .uri("/api/render/missing.md")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
 }
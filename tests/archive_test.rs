@@ -0,0 +1,103 @@
+use rsmd::archive::{ArchiveError, Entry, MAX_ENTRIES, read_entries};
+use std::io::{Cursor, Write};
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+
+fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, contents) in files {
+        if name.ends_with('/') {
+            writer
+                .add_directory(*name, SimpleFileOptions::default())
+                .unwrap();
+        } else {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(contents).unwrap();
+        }
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+fn entries(archive: Vec<u8>, max_size: u64) -> Result<Vec<Entry>, ArchiveError> {
+    let mut entries = Vec::new();
+    read_entries(
+        Cursor::new(archive),
+        |name| name.ends_with(".md").then_some(max_size),
+        |entry| {
+            entries.push(entry);
+            true
+        },
+    )?;
+    Ok(entries)
+}
+
+#[test]
+fn test_read_entries_reports_files_in_order() {
+    let archive = zip(&[
+        ("guides/", b""),
+        ("guides/setup.md", b"# Setup"),
+        ("notes.txt", b"plain"),
+        ("index.md", b"# Index"),
+    ]);
+
+    let entries = entries(archive, 1024).unwrap();
+
+    let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["guides/setup.md", "notes.txt", "index.md"]);
+    assert_eq!(entries[0].contents.as_deref(), Ok(&b"# Setup"[..]));
+    assert_eq!(entries[1].contents, Err(ArchiveError::Skipped));
+}
+
+#[test]
+fn test_read_entries_flags_unsafe_paths() {
+    let archive = zip(&[
+        ("../escape.md", b"x"),
+        ("/etc/passwd.md", b"x"),
+        ("a/../../escape.md", b"x"),
+        ("C:/windows.md", b"x"),
+    ]);
+
+    for entry in entries(archive, 1024).unwrap() {
+        assert_eq!(
+            entry.contents,
+            Err(ArchiveError::UnsafePath),
+            "{}",
+            entry.name
+        );
+    }
+}
+
+#[test]
+fn test_read_entries_enforces_entry_limits() {
+    let bomb = vec![b'a'; 1024 * 1024];
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let deflated =
+        SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    writer.start_file("bomb.md", deflated).unwrap();
+    writer.write_all(&bomb).unwrap();
+    let archive = writer.finish().unwrap().into_inner();
+
+    let bombs = entries(archive, 1024 * 1024).unwrap();
+    assert_eq!(bombs[0].contents, Err(ArchiveError::CompressionRatio));
+
+    let archive = zip(&[("big.md", &[b'b'; 2048])]);
+    let big = entries(archive, 1024).unwrap();
+    assert_eq!(big[0].contents, Err(ArchiveError::TooLarge));
+}
+
+#[test]
+fn test_read_entries_rejects_invalid_and_crowded_archives() {
+    assert_eq!(
+        entries(b"not a zip".to_vec(), 1024).unwrap_err(),
+        ArchiveError::Invalid
+    );
+
+    let names: Vec<String> = (0..=MAX_ENTRIES).map(|i| format!("{}.md", i)).collect();
+    let files: Vec<(&str, &[u8])> = names.iter().map(|name| (name.as_str(), &b""[..])).collect();
+    assert_eq!(
+        entries(zip(&files), 1024).unwrap_err(),
+        ArchiveError::TooManyEntries
+    );
+}
@@ -0,0 +1,120 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode, header},
+};
+use rsmd::{
+    assets,
+    server::{AppState, create_router},
+};
+use std::sync::Arc;
+use tower::util::ServiceExt;
+
+mod common;
+
+async fn get(state: Arc<AppState>, uri: &str) -> axum::response::Response {
+    create_router(state)
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+#[test]
+fn test_fingerprinted_names_include_content_hash() {
+    let url = assets::url("app.js").unwrap();
+    assert!(url.starts_with("/_rsmd/assets/app."));
+    assert!(url.ends_with(".js"));
+    assert_ne!(url, "/_rsmd/assets/app.js");
+    assert!(assets::url("missing.js").is_none());
+}
+
+#[tokio::test]
+async fn test_fingerprinted_asset_is_cached_forever() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let response = get(
+        common::directory_state(temp_dir.path(), &[]),
+        &assets::url("style.css").unwrap(),
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/css; charset=utf-8"
+    );
+    assert_eq!(
+        response.headers()[header::CACHE_CONTROL],
+        assets::IMMUTABLE_CACHE_CONTROL
+    );
+}
+
+#[tokio::test]
+async fn test_plain_asset_name_revalidates() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let response = get(
+        common::directory_state(temp_dir.path(), &[]),
+        "/_rsmd/assets/app.js",
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/javascript; charset=utf-8"
+    );
+    assert_eq!(response.headers()[header::CACHE_CONTROL], "no-cache");
+
+    let missing = get(
+        common::directory_state(temp_dir.path(), &[]),
+        "/_rsmd/assets/nope.js",
+    )
+    .await;
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_app_shell_links_fingerprinted_assets() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let response = get(common::directory_state(temp_dir.path(), &[]), "/_rsmd/app").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body_str = String::from_utf8(body.to_vec()).unwrap();
+
+    assert!(body_str.contains(&format!(r#"src="{}""#, assets::url("app.js").unwrap())));
+    assert!(body_str.contains(&format!(r#"href="{}""#, assets::url("style.css").unwrap())));
+}
+
+#[tokio::test]
+async fn test_static_serves_document_assets_in_directory_mode() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(temp_dir.path().join("diagram.svg"), "<svg></svg>").unwrap();
+
+    let response = get(
+        common::directory_state(temp_dir.path(), &[]),
+        "/static/diagram.svg",
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let bundled = get(
+        common::directory_state(temp_dir.path(), &[]),
+        "/static/app.js",
+    )
+    .await;
+    assert_eq!(bundled.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_static_refuses_hidden_files() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(temp_dir.path().join(".git")).unwrap();
+    std::fs::write(temp_dir.path().join(".git/config"), "[core]").unwrap();
+    std::fs::write(temp_dir.path().join(".env"), "TOKEN=secret").unwrap();
+
+    for uri in ["/static/.git/config", "/static/.env", "/static/a/../.env"] {
+        let response = get(common::directory_state(temp_dir.path(), &[]), uri).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", uri);
+    }
+}
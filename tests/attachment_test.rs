@@ -0,0 +1,40 @@
+use rsmd::attachment::{for_name, sniff, snippet};
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR";
+
+#[test]
+fn test_for_name_matches_extension_case_insensitively() {
+    assert_eq!(for_name("logo.PNG").unwrap().mime, "image/png");
+    assert_eq!(for_name("photo.jpeg").unwrap().mime, "image/jpeg");
+    assert_eq!(for_name("docs/manual.pdf").unwrap().mime, "application/pdf");
+    assert!(for_name("script.html").is_none());
+    assert!(for_name("README").is_none());
+}
+
+#[test]
+fn test_sniff_recognizes_signatures() {
+    assert_eq!(sniff(PNG).unwrap().mime, "image/png");
+    assert_eq!(sniff(b"%PDF-1.7\n").unwrap().mime, "application/pdf");
+    assert_eq!(sniff(b"GIF89a\x01\x00").unwrap().mime, "image/gif");
+    assert_eq!(
+        sniff(b"RIFF\x24\x00\x00\x00WEBPVP8 ").unwrap().mime,
+        "image/webp"
+    );
+    assert_eq!(
+        sniff(b"\x00\x00\x00\x1cftypavif").unwrap().mime,
+        "image/avif"
+    );
+    assert_eq!(
+        sniff(b"\x00\x00\x00\x18ftypisom").unwrap().mime,
+        "video/mp4"
+    );
+    assert!(sniff(b"<html><script>").is_none());
+    assert!(sniff(b"").is_none());
+}
+
+#[test]
+fn test_snippet_embeds_images_and_links_other_files() {
+    assert_eq!(snippet("img/logo.png"), "![logo](img/logo.png)");
+    assert_eq!(snippet("my shot.png"), "![my shot](my%20shot.png)");
+    assert_eq!(snippet("manual.pdf"), "[manual.pdf](manual.pdf)");
+}
@@ -0,0 +1,126 @@
+use rsmd::cli::CliOptions;
+use rsmd::git::Identity;
+use std::path::PathBuf;
+use tower_http::CompressionLevel;
+
+fn parse(args: &[&str]) -> Result<CliOptions, String> {
+    CliOptions::parse(args.iter().map(|arg| arg.to_string()))
+}
+
+#[test]
+fn test_parse_path_only() {
+    let options = parse(&["docs"]).unwrap();
+    assert_eq!(options.path, "docs");
+    assert_eq!(options.theme_css, None);
+    assert_eq!(options.templates, None);
+}
+
+#[test]
+fn test_parse_theme_css_forms() {
+    let separate = parse(&["--theme-css", "brand.css", "docs"]).unwrap();
+    assert_eq!(separate.path, "docs");
+    assert_eq!(separate.theme_css, Some(PathBuf::from("brand.css")));
+
+    let inline = parse(&["docs", "--theme-css=brand.css"]).unwrap();
+    assert_eq!(inline, separate);
+}
+
+#[test]
+fn test_parse_templates_dir() {
+    let options = parse(&["docs", "--templates", "site", "--theme-css=brand.css"]).unwrap();
+    assert_eq!(options.templates, Some(PathBuf::from("site")));
+    assert_eq!(options.theme_css, Some(PathBuf::from("brand.css")));
+}
+
+#[test]
+fn test_parse_cache_size() {
+    assert_eq!(parse(&["docs"]).unwrap().cache_size, None);
+    assert_eq!(
+        parse(&["docs", "--cache-size", "16"]).unwrap().cache_size,
+        Some(16)
+    );
+    assert_eq!(
+        parse(&["--cache-size=256", "docs"]).unwrap().cache_size,
+        Some(256)
+    );
+}
+
+#[test]
+fn test_parse_cache_control() {
+    let options = parse(&["docs", "--cache-control", "private, max-age=60"]).unwrap();
+    assert_eq!(
+        options.cache_control.as_deref(),
+        Some("private, max-age=60")
+    );
+}
+
+#[test]
+fn test_parse_compression_level() {
+    assert_eq!(
+        parse(&["docs", "--compression-level", "best"])
+            .unwrap()
+            .compression_level,
+        Some(CompressionLevel::Best)
+    );
+    assert_eq!(
+        parse(&["docs", "--compression-level=4"])
+            .unwrap()
+            .compression_level,
+        Some(CompressionLevel::Precise(4))
+    );
+    assert!(parse(&["docs", "--compression-level", "max"]).is_err());
+}
+
+#[test]
+fn test_parse_history_retention() {
+    let options = parse(&["docs"]).unwrap();
+    assert_eq!(options.history_revisions, None);
+    assert_eq!(options.history_days, None);
+
+    let options = parse(&["docs", "--history-revisions", "0", "--history-days=30"]).unwrap();
+    assert_eq!(options.history_revisions, Some(0));
+    assert_eq!(options.history_days, Some(30));
+    assert!(parse(&["docs", "--history-days", "forever"]).is_err());
+}
+
+#[test]
+fn test_parse_git_options() {
+    let options = parse(&["docs"]).unwrap();
+    assert!(!options.git_commit);
+
+    let options = parse(&[
+        "--git-commit",
+        "docs",
+        "--git-author=Docs Bot <docs@example.com>",
+        "--git-user-header",
+        "X-Forwarded-User",
+    ])
+    .unwrap();
+    assert!(options.git_commit);
+    assert_eq!(options.path, "docs");
+    assert_eq!(
+        options.git_author,
+        Some(Identity {
+            name: "Docs Bot".to_string(),
+            email: "docs@example.com".to_string(),
+        })
+    );
+    assert_eq!(options.git_user_header.as_deref(), Some("X-Forwarded-User"));
+    assert!(parse(&["docs", "--git-author", "docs@example.com"]).is_err());
+    assert!(parse(&["docs", "--git-commit=yes"]).is_err());
+
+    let options = parse(&["--git-ref", "v2.1", "repo.git"]).unwrap();
+    assert_eq!(options.git_ref.as_deref(), Some("v2.1"));
+    assert!(parse(&["docs", "--git-ref"]).is_err());
+    assert!(parse(&["docs", "--git-ref=v2.1", "--git-commit"]).is_err());
+}
+
+#[test]
+fn test_parse_errors() {
+    assert!(parse(&[]).is_err());
+    assert!(parse(&["docs", "--theme-css"]).is_err());
+    assert!(parse(&["docs", "--templates"]).is_err());
+    assert!(parse(&["docs", "--cache-size", "lots"]).is_err());
+    assert!(parse(&["docs", "--unknown"]).is_err());
+    assert!(parse(&["docs", "extra"]).is_err());
+}
@@ -0,0 +1,31 @@
+//! Fixtures shared by the integration tests
+#![allow(dead_code)]
+
+use rsmd::{
+    directory::FileTree, i18n::Language, server::AppState, source::FsSource, store::DocumentStore,
+};
+use std::path::Path;
+use std::sync::Arc;
+
+/// A store over the directory `dir`, with `files` already in its tree
+pub fn store(dir: &Path, files: &[&str]) -> DocumentStore {
+    DocumentStore::new(Arc::new(FileTree::with_files(
+        Arc::new(FsSource::new(dir.to_path_buf())),
+        files.iter().copied(),
+    )))
+}
+
+/// The state of rsmd serving `store` from the directory `dir`
+pub fn served(dir: &Path, store: DocumentStore) -> Arc<AppState> {
+    Arc::new(AppState::Directory {
+        dir_path: dir.display().to_string(),
+        store: Arc::new(store),
+        language: Language::English,
+    })
+}
+
+/// The state of rsmd serving the directory `dir`, with `files` already in its
+/// tree
+pub fn directory_state(dir: &Path, files: &[&str]) -> Arc<AppState> {
+    served(dir, store(dir, files))
+}
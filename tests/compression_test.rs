@@ -0,0 +1,146 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode, header},
+};
+use rsmd::server::{AppState, ServerConfig, create_router, create_router_with_config};
+use std::sync::Arc;
+use tower::util::ServiceExt;
+use tower_http::CompressionLevel;
+
+mod common;
+
+fn doc_state(temp_dir: &tempfile::TempDir) -> Arc<AppState> {
+    std::fs::write(
+        temp_dir.path().join("doc.md"),
+        format!("# Doc\n\n{}", "Lorem ipsum dolor sit amet. ".repeat(100)),
+    )
+    .unwrap();
+    common::directory_state(temp_dir.path(), &["doc.md"])
+}
+
+fn request(uri: &str, accept_encoding: &str) -> Request<Body> {
+    Request::builder()
+        .uri(uri)
+        .header(header::ACCEPT_ENCODING, accept_encoding)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_responses_use_negotiated_encoding() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let state = doc_state(&temp_dir);
+
+    for encoding in ["gzip", "br", "zstd"] {
+        for uri in [
+            "/",
+            "/view/doc.md",
+            "/api/markdown/doc.md",
+            "/_rsmd/assets/app.js",
+        ] {
+            let response = create_router(state.clone())
+                .oneshot(request(uri, encoding))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+            assert_eq!(
+                response.headers()[header::CONTENT_ENCODING],
+                encoding,
+                "{}",
+                uri
+            );
+            assert!(
+                response
+                    .headers()
+                    .get_all(header::VARY)
+                    .iter()
+                    .any(|vary| vary.to_str().unwrap().contains("accept-encoding")),
+                "{}",
+                uri
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_identity_when_not_accepted() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let response = create_router(doc_state(&temp_dir))
+        .oneshot(
+            Request::builder()
+                .uri("/view/doc.md")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+}
+
+#[tokio::test]
+async fn test_small_and_precompressed_responses_are_not_compressed() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(temp_dir.path().join("photo.png"), vec![0u8; 4096]).unwrap();
+    let state = doc_state(&temp_dir);
+
+    for uri in ["/api/scan", "/static/photo.png"] {
+        let response = create_router(state.clone())
+            .oneshot(request(uri, "gzip"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+        assert!(
+            !response.headers().contains_key(header::CONTENT_ENCODING),
+            "{}",
+            uri
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_compressed_documents_revalidate() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let state = doc_state(&temp_dir);
+
+    let response = create_router(state.clone())
+        .oneshot(request("/view/doc.md", "gzip"))
+        .await
+        .unwrap();
+    let etag = response.headers()[header::ETAG].clone();
+    assert!(etag.to_str().unwrap().ends_with("-gzip\""));
+
+    let identity = create_router(state.clone())
+        .oneshot(request("/view/doc.md", "identity"))
+        .await
+        .unwrap();
+    assert_ne!(identity.headers()[header::ETAG], etag);
+
+    let mut revalidate = request("/view/doc.md", "gzip");
+    revalidate
+        .headers_mut()
+        .insert(header::IF_NONE_MATCH, etag.clone());
+    let response = create_router(state).oneshot(revalidate).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[header::ETAG], etag);
+}
+
+#[tokio::test]
+async fn test_compression_level_is_configurable() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let state = doc_state(&temp_dir);
+
+    let mut sizes = Vec::new();
+    for level in [CompressionLevel::Fastest, CompressionLevel::Best] {
+        let config = ServerConfig::default().with_compression_level(level);
+        let response = create_router_with_config(state.clone(), config)
+            .oneshot(request("/_rsmd/assets/app.js", "gzip"))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        sizes.push(body.len());
+    }
+    assert!(sizes[1] <= sizes[0]);
+}
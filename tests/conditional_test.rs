@@ -0,0 +1,259 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode, header},
+};
+use rsmd::{
+    conditional,
+    i18n::Language,
+    server::{AppState, ServerConfig, create_router, create_router_with_config},
+};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tower::util::ServiceExt;
+
+mod common;
+
+fn doc_state(temp_dir: &tempfile::TempDir) -> Arc<AppState> {
+    std::fs::write(temp_dir.path().join("doc.md"), "# Doc\n\nBody").unwrap();
+    common::directory_state(temp_dir.path(), &["doc.md"])
+}
+
+fn get(uri: &str) -> axum::http::request::Builder {
+    Request::builder().uri(uri)
+}
+
+#[tokio::test]
+async fn test_document_responses_carry_validators() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let state = doc_state(&temp_dir);
+
+    for uri in [
+        "/view/doc.md",
+        "/raw/doc.md",
+        "/api/markdown/doc.md",
+        "/api/render/doc.md",
+    ] {
+        let response = create_router(state.clone())
+            .oneshot(get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+
+        let headers = response.headers().clone();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        // The markdown API tags the file itself, for If-Match when saving
+        let tagged: &[u8] = if uri.starts_with("/api/markdown/") {
+            b"# Doc\n\nBody"
+        } else {
+            &body
+        };
+        assert_eq!(
+            headers[header::ETAG].to_str().unwrap(),
+            conditional::etag(tagged),
+            "{}",
+            uri
+        );
+        // The page also shows the tree and git history, newer than the file
+        assert_eq!(
+            headers.contains_key(header::LAST_MODIFIED),
+            uri != "/view/doc.md",
+            "{}",
+            uri
+        );
+        assert_eq!(headers[header::CACHE_CONTROL], "no-cache", "{}", uri);
+        let themed = uri == "/view/doc.md" || uri == "/raw/doc.md";
+        assert_eq!(
+            headers
+                .get(header::VARY)
+                .is_some_and(|vary| vary == "cookie"),
+            themed,
+            "{}",
+            uri
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_view_page_ignores_if_modified_since() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let state = doc_state(&temp_dir);
+
+    let response = create_router(state)
+        .oneshot(
+            get("/view/doc.md")
+                .header(header::IF_MODIFIED_SINCE, "Fri, 01 Jan 2100 00:00:00 GMT")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_large_bodies_are_streamed_without_etag() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let state = doc_state(&temp_dir);
+    let markdown = "word ".repeat(conditional::MAX_TAGGED_SIZE / 5 + 1);
+    std::fs::write(temp_dir.path().join("doc.md"), &markdown).unwrap();
+
+    let response = create_router(state)
+        .oneshot(get("/raw/doc.md").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.headers().contains_key(header::ETAG));
+    assert!(response.headers().contains_key(header::LAST_MODIFIED));
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert!(body.len() > conditional::MAX_TAGGED_SIZE);
+}
+
+#[tokio::test]
+async fn test_if_none_match_returns_304() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let state = doc_state(&temp_dir);
+
+    let response = create_router(state.clone())
+        .oneshot(get("/view/doc.md").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let etag = response.headers()[header::ETAG].clone();
+
+    let response = create_router(state.clone())
+        .oneshot(
+            get("/view/doc.md")
+                .header(header::IF_NONE_MATCH, etag.clone())
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[header::ETAG], etag);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert!(body.is_empty());
+
+    // A different tag gets the full document
+    let response = create_router(state)
+        .oneshot(
+            get("/view/doc.md")
+                .header(header::IF_NONE_MATCH, "\"stale\"")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_if_modified_since_returns_304() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let state = doc_state(&temp_dir);
+
+    let response = create_router(state.clone())
+        .oneshot(get("/api/markdown/doc.md").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let last_modified = response.headers()[header::LAST_MODIFIED].clone();
+
+    let response = create_router(state.clone())
+        .oneshot(
+            get("/api/markdown/doc.md")
+                .header(header::IF_MODIFIED_SINCE, last_modified)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    let response = create_router(state)
+        .oneshot(
+            get("/api/markdown/doc.md")
+                .header(header::IF_MODIFIED_SINCE, "Thu, 01 Jan 1970 00:00:00 GMT")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_cache_control_is_configurable() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = ServerConfig::default().with_document_cache_control("private, max-age=60");
+
+    let response = create_router_with_config(doc_state(&temp_dir), config)
+        .oneshot(get("/raw/doc.md").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers()[header::CACHE_CONTROL],
+        "private, max-age=60"
+    );
+}
+
+#[tokio::test]
+async fn test_single_file_page_has_etag_only() {
+    let state = Arc::new(AppState::SingleFile {
+        markdown_content: "# Hi".to_string(),
+        html_content: "<h1>Hi</h1>".to_string(),
+        language: Language::English,
+        base_dir: PathBuf::from("."),
+    });
+
+    let response = create_router(state)
+        .oneshot(get("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().contains_key(header::ETAG));
+    assert!(!response.headers().contains_key(header::LAST_MODIFIED));
+}
+
+#[tokio::test]
+async fn test_errors_and_listings_are_not_validated() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let state = doc_state(&temp_dir);
+
+    for uri in ["/view/missing.md", "/", "/api/files"] {
+        let response = create_router(state.clone())
+            .oneshot(get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(!response.headers().contains_key(header::ETAG), "{}", uri);
+    }
+}
+
+#[test]
+fn test_if_match_uses_strong_comparison() {
+    let tag = conditional::etag(b"# Doc");
+
+    assert!(conditional::if_match(&tag, Some(&tag)));
+    assert!(conditional::if_match(
+        &format!("\"other\", {}", tag),
+        Some(&tag)
+    ));
+    assert!(conditional::if_match("*", Some(&tag)));
+    assert!(!conditional::if_match(&format!("W/{}", tag), Some(&tag)));
+    assert!(!conditional::if_match("*", None));
+    assert!(!conditional::if_match(&tag, None));
+}
+
+#[test]
+fn test_if_match_accepts_compressed_variant_tags() {
+    let tag = conditional::etag(b"# Doc");
+    let gzip = format!("{}-gzip\"", tag.trim_end_matches('"'));
+
+    assert!(conditional::if_match(&gzip, Some(&tag)));
+    assert!(!conditional::if_match(&format!("W/{}", gzip), Some(&tag)));
+    assert!(!conditional::if_match("\"other-gzip\"", Some(&tag)));
+}
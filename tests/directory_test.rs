@@ -1,69 +1,20 @@
-use rsmd::directory::{MarkdownFile, list_directory_contents, scan_markdown_files};
+use rsmd::directory::{FileTree, MarkdownFile, normalize_path};
+use rsmd::source::{FsSource, MemorySource};
 use std::fs::File;
-use std::io::{ErrorKind, Write};
 use std::path::PathBuf;
-use tempfile::{NamedTempFile, tempdir};
+use std::sync::Arc;
+use tempfile::tempdir;
 
-#[test]
-fn test_scan_markdown_files() {
-    let temp_dir = tempdir().unwrap();
-    let dir_path = temp_dir.path();
-
-    // Create test .md files
-    let mut file1 = File::create(dir_path.join("test1.md")).unwrap();
-    writeln!(file1, "# Test 1").unwrap();
-
-    let mut file2 = File::create(dir_path.join("test2.md")).unwrap();
-    writeln!(file2, "# Test 2").unwrap();
-
-    // Create nested directory with markdown file
-    let nested_dir = dir_path.join("nested");
-    std::fs::create_dir(&nested_dir).unwrap();
-    let mut nested_file = File::create(nested_dir.join("inner.md")).unwrap();
-    writeln!(nested_file, "# Nested").unwrap();
-
-    // Create a non-markdown file
-    File::create(dir_path.join("readme.txt")).unwrap();
-
-    let result = scan_markdown_files(dir_path.to_str().unwrap()).unwrap();
-    assert_eq!(result.len(), 3);
-    assert!(result.iter().any(|f| f.name == "test1.md"));
-    assert!(result.iter().any(|f| f.name == "test2.md"));
-    assert!(result.iter().any(|f| f.name == "nested/inner.md"));
+/// A tree over `names`, all of them already indexed
+fn tree(names: &[&str]) -> FileTree {
+    FileTree::with_files(Arc::new(MemorySource::new()), names.iter().copied())
 }
 
-#[test]
-fn test_scan_empty_directory() {
-    let temp_dir = tempdir().unwrap();
-    let result = scan_markdown_files(temp_dir.path().to_str().unwrap()).unwrap();
-    assert_eq!(result.len(), 0);
-}
+#[tokio::test]
+async fn test_file_tree_lists_root() {
+    let tree = tree(&["readme.md", "docs/api.md", "docs/guide.md"]);
 
-#[test]
-fn test_scan_non_directory_returns_error() {
-    let tempfile = NamedTempFile::new().unwrap();
-    let err = scan_markdown_files(tempfile.path().to_str().unwrap()).unwrap_err();
-    assert_eq!(err.kind(), ErrorKind::NotFound);
-}
-
-#[test]
-fn test_list_directory_contents_root() {
-    let files = vec![
-        MarkdownFile {
-            name: "readme.md".to_string(),
-            path: PathBuf::from("/test/readme.md"),
-        },
-        MarkdownFile {
-            name: "docs/api.md".to_string(),
-            path: PathBuf::from("/test/docs/api.md"),
-        },
-        MarkdownFile {
-            name: "docs/guide.md".to_string(),
-            path: PathBuf::from("/test/docs/guide.md"),
-        },
-    ];
-
-    let listing = list_directory_contents(&files, "");
+    let listing = tree.list("").await.unwrap();
 
     assert_eq!(listing.current_path, "");
     assert_eq!(listing.parent, None);
@@ -74,28 +25,16 @@ fn test_list_directory_contents_root() {
     assert_eq!(listing.files[0].name, "readme.md");
 }
 
-#[test]
-fn test_list_directory_contents_nested() {
-    let files = vec![
-        MarkdownFile {
-            name: "docs/api.md".to_string(),
-            path: PathBuf::from("/test/docs/api.md"),
-        },
-        MarkdownFile {
-            name: "docs/guide.md".to_string(),
-            path: PathBuf::from("/test/docs/guide.md"),
-        },
-        MarkdownFile {
-            name: "docs/advanced/tutorial.md".to_string(),
-            path: PathBuf::from("/test/docs/advanced/tutorial.md"),
-        },
-        MarkdownFile {
-            name: "readme.md".to_string(),
-            path: PathBuf::from("/test/readme.md"),
-        },
-    ];
-
-    let listing = list_directory_contents(&files, "docs");
+#[tokio::test]
+async fn test_file_tree_lists_nested() {
+    let tree = tree(&[
+        "docs/api.md",
+        "docs/guide.md",
+        "docs/advanced/tutorial.md",
+        "readme.md",
+    ]);
+
+    let listing = tree.list("docs").await.unwrap();
 
     assert_eq!(listing.current_path, "docs");
     assert_eq!(listing.parent, Some("".to_string()));
@@ -107,20 +46,11 @@ fn test_list_directory_contents_nested() {
     assert!(listing.files.iter().any(|f| f.name == "docs/guide.md"));
 }
 
-#[test]
-fn test_list_directory_contents_deep_nested() {
-    let files = vec![
-        MarkdownFile {
-            name: "docs/api/v1/endpoints.md".to_string(),
-            path: PathBuf::from("/test/docs/api/v1/endpoints.md"),
-        },
-        MarkdownFile {
-            name: "docs/api/v2/endpoints.md".to_string(),
-            path: PathBuf::from("/test/docs/api/v2/endpoints.md"),
-        },
-    ];
-
-    let listing = list_directory_contents(&files, "docs/api");
+#[tokio::test]
+async fn test_file_tree_lists_deep_nested() {
+    let tree = tree(&["docs/api/v1/endpoints.md", "docs/api/v2/endpoints.md"]);
+
+    let listing = tree.list("/docs/api/").await.unwrap();
 
     assert_eq!(listing.current_path, "docs/api");
     assert_eq!(listing.parent, Some("docs".to_string()));
@@ -130,41 +60,245 @@ fn test_list_directory_contents_deep_nested() {
     assert_eq!(listing.files.len(), 0);
 }
 
-#[test]
-fn test_list_directory_contents_empty_path() {
-    let files = vec![MarkdownFile {
-        name: "docs/nested/deep/file.md".to_string(),
-        path: PathBuf::from("/test/docs/nested/deep/file.md"),
-    }];
+#[tokio::test]
+async fn test_file_tree_lists_only_direct_children() {
+    let tree = tree(&["docs/nested/deep/file.md"]);
 
-    let listing = list_directory_contents(&files, "");
+    let listing = tree.list("").await.unwrap();
 
     assert_eq!(listing.directories.len(), 1);
     assert_eq!(listing.directories[0].name, "docs");
     assert_eq!(listing.files.len(), 0);
 }
 
-#[test]
-fn test_list_directory_contents_sorted() {
-    let files = vec![
-        MarkdownFile {
-            name: "z.md".to_string(),
-            path: PathBuf::from("/test/z.md"),
-        },
-        MarkdownFile {
-            name: "a.md".to_string(),
-            path: PathBuf::from("/test/a.md"),
-        },
-        MarkdownFile {
-            name: "m.md".to_string(),
-            path: PathBuf::from("/test/m.md"),
-        },
-    ];
-
-    let listing = list_directory_contents(&files, "");
+#[tokio::test]
+async fn test_file_tree_lists_sorted() {
+    let tree = tree(&["z.md", "a.md", "m.md"]);
+
+    let listing = tree.list("").await.unwrap();
 
     // Files should be sorted alphabetically
     assert_eq!(listing.files[0].name, "a.md");
     assert_eq!(listing.files[1].name, "m.md");
     assert_eq!(listing.files[2].name, "z.md");
 }
+
+#[tokio::test]
+async fn test_file_tree_lists_lazily_before_scan() {
+    let temp_dir = tempdir().unwrap();
+    let dir_path = temp_dir.path();
+    std::fs::create_dir_all(dir_path.join("docs/api")).unwrap();
+    std::fs::create_dir_all(dir_path.join("assets")).unwrap();
+    std::fs::write(dir_path.join("readme.md"), "# Readme").unwrap();
+    std::fs::write(dir_path.join("docs/guide.md"), "# Guide").unwrap();
+    std::fs::write(dir_path.join("docs/api/v1.md"), "# V1").unwrap();
+    std::fs::write(dir_path.join("assets/logo.png"), "png").unwrap();
+
+    let tree = FileTree::new(dir_path.to_path_buf());
+    assert!(!tree.status().await.complete);
+
+    let listing = tree.list("docs").await.unwrap();
+    assert_eq!(listing.parent, Some(String::new()));
+    assert_eq!(listing.directories.len(), 1);
+    assert_eq!(listing.directories[0].path, "docs/api");
+    assert_eq!(listing.files.len(), 1);
+    assert_eq!(listing.files[0].name, "docs/guide.md");
+
+    let file = tree.get("docs/api/v1.md").await.unwrap();
+    assert_eq!(file.path, dir_path.join("docs/api/v1.md"));
+    assert!(tree.get("docs/missing.md").await.is_none());
+    assert!(tree.list("nope").await.is_none());
+}
+
+#[tokio::test]
+async fn test_file_tree_scan_indexes_everything() {
+    let temp_dir = tempdir().unwrap();
+    let dir_path = temp_dir.path();
+    std::fs::create_dir_all(dir_path.join("a/b/c")).unwrap();
+    std::fs::create_dir_all(dir_path.join("empty")).unwrap();
+    std::fs::write(dir_path.join("root.md"), "# Root").unwrap();
+    std::fs::write(dir_path.join("a/one.md"), "# One").unwrap();
+    std::fs::write(dir_path.join("a/b/c/deep.md"), "# Deep").unwrap();
+
+    let tree = FileTree::new(dir_path.to_path_buf());
+    tree.scan().await;
+
+    let status = tree.status().await;
+    assert!(status.complete);
+    assert_eq!(status.files_found, 3);
+    assert_eq!(status.directories_scanned, 5);
+
+    let names: Vec<String> = tree.files().await.into_iter().map(|f| f.name).collect();
+    assert_eq!(names, vec!["a/b/c/deep.md", "a/one.md", "root.md"]);
+
+    // Directories without any markdown are hidden once fully scanned
+    let root = tree.list("").await.unwrap();
+    assert_eq!(root.directories.len(), 1);
+    assert_eq!(root.directories[0].name, "a");
+    assert!(tree.list("empty").await.is_none());
+}
+
+#[tokio::test]
+async fn test_file_tree_skips_hidden_directories() {
+    let temp_dir = tempdir().unwrap();
+    let dir_path = temp_dir.path();
+    std::fs::create_dir_all(dir_path.join(".git/refs")).unwrap();
+    std::fs::create_dir_all(dir_path.join(".github")).unwrap();
+    std::fs::create_dir_all(dir_path.join("docs")).unwrap();
+    std::fs::write(dir_path.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+    std::fs::write(dir_path.join(".git/refs/notes.md"), "# Notes").unwrap();
+    std::fs::write(dir_path.join(".github/x.md"), "# Workflow").unwrap();
+    std::fs::write(dir_path.join(".hidden.md"), "# Hidden").unwrap();
+    std::fs::write(dir_path.join("docs/guide.md"), "# Guide").unwrap();
+
+    // Read lazily, before the scan
+    let tree = FileTree::new(dir_path.to_path_buf());
+    let root = tree.list("").await.unwrap();
+    assert!(root.files.is_empty());
+    assert_eq!(root.directories.len(), 1);
+    assert!(tree.get(".github/x.md").await.is_none());
+    assert!(tree.list(".github").await.is_none());
+
+    tree.scan().await;
+    let names: Vec<String> = tree.files().await.into_iter().map(|f| f.name).collect();
+    assert_eq!(names, vec!["docs/guide.md"]);
+    assert!(tree.subtree(".git", 1).await.is_none());
+    let root = tree.subtree("", 2).await.unwrap();
+    assert_eq!(root.total_files, 1);
+    assert_eq!(root.folder_count, 1);
+}
+
+#[tokio::test]
+async fn test_file_tree_insert_and_remove() {
+    let tree = FileTree::with_files(
+        Arc::new(FsSource::new(PathBuf::from("/test"))),
+        ["docs/a.md"],
+    );
+
+    tree.insert(MarkdownFile {
+        name: "docs/new/b.md".to_string(),
+        path: PathBuf::from("/test/docs/new/b.md"),
+    })
+    .await;
+    assert_eq!(tree.status().await.files_found, 2);
+
+    let listing = tree.list("docs").await.unwrap();
+    assert_eq!(listing.directories.len(), 1);
+    assert_eq!(listing.directories[0].path, "docs/new");
+
+    let removed = tree.remove("docs/new/b.md").await.unwrap();
+    assert_eq!(removed.path, PathBuf::from("/test/docs/new/b.md"));
+    assert!(tree.remove("docs/new/b.md").await.is_none());
+    assert_eq!(tree.status().await.files_found, 1);
+    assert!(tree.list("docs/new").await.is_none());
+    assert!(tree.list("docs").await.unwrap().directories.is_empty());
+}
+
+#[tokio::test]
+async fn test_file_tree_navigation_expands_current_path() {
+    let tree = FileTree::with_files(
+        Arc::new(MemorySource::new()),
+        [
+            "a.md",
+            "guides/setup.md",
+            "guides/deploy.md",
+            "guides/zeta.md",
+            "other/x.md",
+        ],
+    );
+
+    let nav = tree.navigation("guides/setup.md").await.unwrap();
+    assert_eq!(nav.file.name, "guides/setup.md");
+    assert_eq!(nav.previous.unwrap().name, "guides/deploy.md");
+    assert_eq!(nav.next.unwrap().name, "guides/zeta.md");
+
+    let folders = nav.tree.folders.unwrap();
+    assert_eq!(folders.len(), 2);
+    let guides = folders.iter().find(|f| f.path == "guides").unwrap();
+    assert_eq!(guides.files.as_ref().unwrap().len(), 3);
+    let other = folders.iter().find(|f| f.path == "other").unwrap();
+    assert!(other.files.is_none());
+
+    let first = tree.navigation("guides/deploy.md").await.unwrap();
+    assert!(first.previous.is_none());
+    assert!(tree.navigation("guides/missing.md").await.is_none());
+}
+
+#[tokio::test]
+async fn test_scan_skips_internal_directories() {
+    let temp_dir = tempdir().unwrap();
+    let dir_path = temp_dir.path();
+
+    File::create(dir_path.join("visible.md")).unwrap();
+    std::fs::create_dir(dir_path.join(".rsmd-uploads")).unwrap();
+    File::create(dir_path.join(".rsmd-uploads/staged.md")).unwrap();
+
+    let tree = FileTree::new(dir_path.to_path_buf());
+    tree.scan().await;
+    let names: Vec<String> = tree.files().await.into_iter().map(|f| f.name).collect();
+    assert_eq!(names, vec!["visible.md"]);
+}
+
+#[tokio::test]
+async fn test_file_tree_add_dir_reads_a_moved_folder() {
+    let temp_dir = tempdir().unwrap();
+    let dir_path = temp_dir.path();
+    std::fs::create_dir_all(dir_path.join("old/deep")).unwrap();
+    std::fs::write(dir_path.join("old/a.md"), "# A").unwrap();
+    std::fs::write(dir_path.join("old/deep/b.md"), "# B").unwrap();
+
+    let tree = FileTree::new(dir_path.to_path_buf());
+    tree.scan().await;
+    std::fs::create_dir(dir_path.join("new")).unwrap();
+    std::fs::rename(dir_path.join("old"), dir_path.join("new/moved")).unwrap();
+    assert_eq!(tree.remove_dir("old").await.len(), 2);
+
+    let added: Vec<String> = tree
+        .add_dir("new/moved")
+        .await
+        .into_iter()
+        .map(|f| f.name)
+        .collect();
+    assert_eq!(added, vec!["new/moved/a.md", "new/moved/deep/b.md"]);
+    let status = tree.status().await;
+    assert_eq!(status.files_found, 2);
+    let names: Vec<String> = tree.files().await.into_iter().map(|f| f.name).collect();
+    assert_eq!(names, vec!["new/moved/a.md", "new/moved/deep/b.md"]);
+    assert!(tree.add_dir(".git").await.is_empty());
+}
+
+#[tokio::test]
+async fn test_file_tree_remove_dir() {
+    let tree = FileTree::with_files(
+        Arc::new(MemorySource::new()),
+        ["top.md", "docs/a.md", "docs/deep/b.md"],
+    );
+
+    let mut removed: Vec<String> = tree
+        .remove_dir("docs")
+        .await
+        .into_iter()
+        .map(|file| file.name)
+        .collect();
+    removed.sort();
+    assert_eq!(removed, ["docs/a.md", "docs/deep/b.md"]);
+    assert_eq!(tree.status().await.files_found, 1);
+    assert!(tree.list("docs").await.is_none());
+    assert!(tree.remove_dir("docs").await.is_empty());
+    assert!(tree.get("top.md").await.is_some());
+}
+
+#[test]
+fn test_normalize_path() {
+    assert_eq!(
+        normalize_path("/docs//guide/").as_deref(),
+        Some("docs/guide")
+    );
+    assert_eq!(normalize_path("").as_deref(), Some(""));
+    assert_eq!(normalize_path("docs/../secret"), None);
+    assert_eq!(
+        normalize_path("./docs\\guide/./").as_deref(),
+        Some("docs/guide")
+    );
+    assert_eq!(normalize_path("docs\\..\\secret"), None);
+}
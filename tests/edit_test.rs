@@ -0,0 +1,284 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode, header},
+};
+use rsmd::{
+    conditional,
+    server::{AppState, create_router},
+};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use tower::util::ServiceExt;
+
+mod common;
+
+const DOC: &str = "# Doc\n\nBody <b>bold</b>";
+
+fn doc_state(temp_dir: &tempfile::TempDir) -> Arc<AppState> {
+    std::fs::write(temp_dir.path().join("doc.md"), DOC).unwrap();
+    common::directory_state(temp_dir.path(), &["doc.md"])
+}
+
+async fn body_string(response: axum::response::Response) -> String {
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+fn put(uri: &str) -> axum::http::request::Builder {
+    Request::builder()
+        .method("PUT")
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+}
+
+fn markdown_body(markdown: &str) -> Body {
+    Body::from(json!({ "markdown": markdown }).to_string())
+}
+
+#[tokio::test]
+async fn editor_page_contains_markdown_and_file_tag() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(doc_state(&temp_dir));
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/edit/doc.md")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let page = body_string(response).await;
+    assert!(page.contains("Body &lt;b&gt;bold&lt;/b&gt;</textarea>"));
+    assert!(page.contains("<h1>Doc</h1>"));
+    assert!(page.contains(r#"<base href="/view/doc.md">"#));
+    let etag = conditional::etag(DOC.as_bytes());
+    assert!(page.contains(&format!(r#"data-etag="{}""#, etag.replace('"', "&quot;"))));
+}
+
+#[tokio::test]
+async fn editor_page_is_linked_from_documents() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(doc_state(&temp_dir));
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/view/doc.md")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert!(
+        body_string(response)
+            .await
+            .contains(r#"href="/edit/doc.md""#)
+    );
+}
+
+#[tokio::test]
+async fn render_previews_unsaved_markdown() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(doc_state(&temp_dir));
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/render")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(markdown_body("# Draft\n\n*new*"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let payload: Value = serde_json::from_str(&body_string(response).await).unwrap();
+    assert_eq!(payload["title"], "Draft");
+    assert!(payload["html"].as_str().unwrap().contains("<em>new</em>"));
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("doc.md")).unwrap(),
+        DOC
+    );
+}
+
+#[tokio::test]
+async fn save_replaces_document_matching_if_match() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(doc_state(&temp_dir));
+
+    // Render once so the old version is cached
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/markdown/doc.md")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let etag = response.headers()[header::ETAG]
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let response = app
+        .clone()
+        .oneshot(
+            put("/api/markdown/doc.md")
+                .header(header::IF_MATCH, &etag)
+                .body(markdown_body("# Saved"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let new_etag = conditional::etag(b"# Saved");
+    assert_eq!(response.headers()[header::ETAG], new_etag.as_str());
+    let payload: Value = serde_json::from_str(&body_string(response).await).unwrap();
+    assert_eq!(payload["success"], true);
+    assert_eq!(payload["file"], "doc.md");
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("doc.md")).unwrap(),
+        "# Saved"
+    );
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/render/doc.md")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert!(body_string(response).await.contains("<h1>Saved</h1>"));
+
+    // The tag the editor started from is stale now
+    let response = app
+        .oneshot(
+            put("/api/markdown/doc.md")
+                .header(header::IF_MATCH, &etag)
+                .body(markdown_body("# Lost update"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    let payload: Value = serde_json::from_str(&body_string(response).await).unwrap();
+    assert_eq!(payload["code"], "stale");
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("doc.md")).unwrap(),
+        "# Saved"
+    );
+}
+
+#[tokio::test]
+async fn save_requires_a_precondition() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(doc_state(&temp_dir));
+
+    let response = app
+        .oneshot(
+            put("/api/markdown/doc.md")
+                .body(markdown_body("# Blind write"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+    let payload: Value = serde_json::from_str(&body_string(response).await).unwrap();
+    assert_eq!(payload["code"], "precondition_required");
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("doc.md")).unwrap(),
+        DOC
+    );
+}
+
+#[tokio::test]
+async fn save_with_if_none_match_creates_new_documents_only() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(doc_state(&temp_dir));
+
+    let response = app
+        .clone()
+        .oneshot(
+            put("/api/markdown/notes/new.md")
+                .header(header::IF_NONE_MATCH, "*")
+                .body(markdown_body("# New"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("notes/new.md")).unwrap(),
+        "# New"
+    );
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/files")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert!(body_string(response).await.contains("notes/new.md"));
+
+    let response = app
+        .oneshot(
+            put("/api/markdown/doc.md")
+                .header(header::IF_NONE_MATCH, "*")
+                .body(markdown_body("# Clobber"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("doc.md")).unwrap(),
+        DOC
+    );
+}
+
+#[tokio::test]
+async fn save_rejects_paths_outside_documents() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(doc_state(&temp_dir));
+
+    for (uri, code) in [
+        ("/api/markdown/a/%2E%2E/%2E%2E/escape.md", "invalid_path"),
+        ("/api/markdown/.rsmd-uploads/x.md", "invalid_path"),
+        ("/api/markdown/script.sh", "invalid_type"),
+    ] {
+        let response = app
+            .clone()
+            .oneshot(
+                put(uri)
+                    .header(header::IF_NONE_MATCH, "*")
+                    .body(markdown_body("# Nope"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+        let payload: Value = serde_json::from_str(&body_string(response).await).unwrap();
+        assert_eq!(payload["code"], code, "{}", uri);
+    }
+    assert!(!temp_dir.path().parent().unwrap().join("escape.md").exists());
+}
@@ -0,0 +1,689 @@
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode, header},
+};
+use gix::objs::tree::EntryKind;
+use gix::refs::transaction::PreviousValue;
+use rsmd::{
+    conditional,
+    git::{GitOptions, Identity, Repository, message},
+    server::create_router,
+};
+use serde_json::{Value, json};
+use std::path::Path;
+use tower::util::ServiceExt;
+
+mod common;
+
+const ORIGINAL: &str = "# Doc\n";
+
+/// A work tree with the served directory at `docs/`, holding `doc.md`
+fn work_tree() -> tempfile::TempDir {
+    let temp_dir = tempfile::tempdir().unwrap();
+    gix::init(temp_dir.path()).unwrap();
+    std::fs::create_dir(temp_dir.path().join("docs")).unwrap();
+    std::fs::write(temp_dir.path().join("docs/doc.md"), ORIGINAL).unwrap();
+    temp_dir
+}
+
+fn git_app(root: &Path, user_header: Option<&str>) -> Router {
+    let options = GitOptions {
+        commit: true,
+        author: Identity::parse("Docs Bot <bot@example.com>"),
+        user_header: user_header.map(str::to_string),
+    };
+    app_with(root, options)
+}
+
+fn app_with(root: &Path, options: GitOptions) -> Router {
+    let base_dir = root.join("docs");
+    let repository = Repository::open(&base_dir, options).unwrap();
+    let store = common::store(&base_dir, &["doc.md"]).with_git(repository);
+    create_router(common::served(&base_dir, store))
+}
+
+async fn send(
+    app: &Router,
+    request: axum::http::request::Builder,
+    body: Body,
+) -> (StatusCode, String) {
+    let response = app
+        .clone()
+        .oneshot(request.body(body).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, String::from_utf8_lossy(&bytes).into_owned())
+}
+
+async fn get(app: &Router, uri: &str) -> (StatusCode, String) {
+    send(app, Request::builder().uri(uri), Body::empty()).await
+}
+
+async fn get_json(app: &Router, uri: &str) -> Value {
+    let (status, body) = get(app, uri).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    serde_json::from_str(&body).unwrap()
+}
+
+/// Commit `versions` of `doc.md` one after another, returning the commit ids
+async fn commit_versions(app: &Router, versions: &[&str]) -> Vec<String> {
+    let mut previous = ORIGINAL;
+    let mut ids = Vec::new();
+    for version in versions {
+        assert_eq!(save(app, version, previous).await, StatusCode::OK);
+        let log = get_json(app, "/api/git/log/doc.md").await;
+        ids.push(log["commits"][0]["id"].as_str().unwrap().to_string());
+        previous = version;
+    }
+    ids
+}
+
+async fn save(app: &Router, markdown: &str, previous: &str) -> StatusCode {
+    let request = Request::builder()
+        .method("PUT")
+        .uri("/api/markdown/doc.md")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::IF_MATCH, conditional::etag(previous.as_bytes()));
+    let body = json!({ "markdown": markdown }).to_string();
+    send(app, request, Body::from(body)).await.0
+}
+
+fn upload(name: &str) -> axum::http::request::Builder {
+    Request::builder()
+        .method("POST")
+        .uri("/api/upload")
+        .header("x-file-name", name)
+}
+
+/// Message, author name and email of the commit at `HEAD`, if any
+fn head(root: &Path) -> Option<(String, String, String)> {
+    let repo = gix::open(root).unwrap();
+    let commit = repo.head_commit().ok()?;
+    let author = commit.author().unwrap();
+    Some((
+        commit
+            .message_raw_sloppy()
+            .to_string()
+            .trim_end()
+            .to_string(),
+        author.name.to_string(),
+        author.email.to_string(),
+    ))
+}
+
+/// Contents of `path` in the tree of `HEAD`, if it is there
+fn committed(root: &Path, path: &str) -> Option<String> {
+    let repo = gix::open(root).unwrap();
+    let tree = repo.head_commit().ok()?.tree().unwrap();
+    let entry = tree.lookup_entry_by_path(path).unwrap()?;
+    Some(String::from_utf8(entry.object().unwrap().data.clone()).unwrap())
+}
+
+/// Commit `files` as they are in the work tree, the way `git add -f` would
+fn commit_files(root: &Path, files: &[&str]) {
+    let repo = gix::open(root).unwrap();
+    let head = repo.head_commit().ok();
+    let tree = match &head {
+        Some(commit) => commit.tree_id().unwrap().detach(),
+        None => gix::ObjectId::empty_tree(repo.object_hash()),
+    };
+    let mut editor = repo.edit_tree(tree).unwrap();
+    for file in files {
+        let id = repo
+            .write_blob(std::fs::read(root.join(file)).unwrap())
+            .unwrap();
+        editor.upsert(*file, EntryKind::Blob, id).unwrap();
+    }
+    let tree = editor.write().unwrap();
+    let signature = gix::actor::Signature {
+        name: "Tester".into(),
+        email: "tester@example.com".into(),
+        time: gix::date::Time::now_utc(),
+    };
+    let (mut committer_time, mut author_time) = Default::default();
+    let parents: Vec<_> = head.iter().map(|commit| commit.id).collect();
+    repo.commit_as(
+        signature.to_ref(&mut committer_time),
+        signature.to_ref(&mut author_time),
+        "HEAD",
+        "Add files",
+        tree,
+        parents,
+    )
+    .unwrap();
+}
+
+fn commit_count(root: &Path) -> usize {
+    let repo = gix::open(root).unwrap();
+    match repo.head_id() {
+        Ok(id) => id.ancestors().all().unwrap().count(),
+        Err(_) => 0,
+    }
+}
+
+#[test]
+fn identity_parses_name_and_email() {
+    assert_eq!(
+        Identity::parse(" Jane Doe <jane@example.com> "),
+        Some(Identity {
+            name: "Jane Doe".to_string(),
+            email: "jane@example.com".to_string(),
+        })
+    );
+    assert_eq!(Identity::parse("jane@example.com"), None);
+    assert_eq!(Identity::parse("<jane@example.com>"), None);
+    assert_eq!(Identity::parse("Jane <>"), None);
+}
+
+#[test]
+fn message_names_the_first_file_and_counts_the_rest() {
+    let names = |names: &[&str]| {
+        names
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(message("Upload", &names(&["a.md"])), "Upload a.md");
+    assert_eq!(
+        message("Upload", &names(&["a.md", "b.md", "c.md"])),
+        "Upload a.md and 2 more files"
+    );
+}
+
+#[tokio::test]
+async fn saving_commits_the_document_and_updates_the_index() {
+    let temp_dir = work_tree();
+    let root = temp_dir.path();
+    std::fs::write(root.join("docs/draft.md"), "# Untracked\n").unwrap();
+    let app = git_app(root, None);
+
+    assert_eq!(
+        save(&app, "# Doc\n\nSaved\n", ORIGINAL).await,
+        StatusCode::OK
+    );
+
+    let (message, name, email) = head(root).unwrap();
+    assert_eq!(message, "Edit doc.md");
+    assert_eq!(
+        (name.as_str(), email.as_str()),
+        ("Docs Bot", "bot@example.com")
+    );
+    assert_eq!(
+        committed(root, "docs/doc.md").as_deref(),
+        Some("# Doc\n\nSaved\n")
+    );
+    // Other changes in the work tree are left alone
+    assert_eq!(committed(root, "docs/draft.md"), None);
+
+    let repo = gix::open(root).unwrap();
+    let index = repo.open_index().unwrap();
+    let entry = index.entry_by_path("docs/doc.md".into()).unwrap();
+    assert_eq!(
+        entry.id,
+        repo.head_commit()
+            .unwrap()
+            .tree()
+            .unwrap()
+            .lookup_entry_by_path("docs/doc.md")
+            .unwrap()
+            .unwrap()
+            .object_id()
+    );
+    assert!(index.entry_by_path("docs/draft.md".into()).is_none());
+
+    assert_eq!(
+        save(&app, "# Doc\n\nAgain\n", "# Doc\n\nSaved\n").await,
+        StatusCode::OK
+    );
+    assert_eq!(commit_count(root), 2);
+}
+
+#[tokio::test]
+async fn uploads_are_authored_by_the_user_header() {
+    let temp_dir = work_tree();
+    let root = temp_dir.path();
+    let app = git_app(root, Some("x-forwarded-user"));
+
+    let request = upload("guide.md").header("x-forwarded-user", "Jane <jane@example.com>");
+    let (status, _) = send(&app, request, Body::from("# Guide\n")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (message, name, email) = head(root).unwrap();
+    assert_eq!(message, "Upload guide.md");
+    assert_eq!(
+        (name.as_str(), email.as_str()),
+        ("Jane", "jane@example.com")
+    );
+    assert_eq!(
+        committed(root, "docs/guide.md").as_deref(),
+        Some("# Guide\n")
+    );
+
+    // Without the header the configured author is used
+    let (status, _) = send(&app, upload("other.md"), Body::from("# Other\n")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(head(root).unwrap().1, "Docs Bot");
+}
+
+#[tokio::test]
+async fn ignored_files_are_not_committed() {
+    let temp_dir = work_tree();
+    let root = temp_dir.path();
+    std::fs::write(root.join(".gitignore"), "secret.md\n").unwrap();
+    let app = git_app(root, None);
+
+    let (status, _) = send(&app, upload("secret.md"), Body::from("# Secret\n")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(root.join("docs/secret.md").exists());
+    assert_eq!(commit_count(root), 0);
+}
+
+#[tokio::test]
+async fn moves_and_deletes_are_committed() {
+    let temp_dir = work_tree();
+    let root = temp_dir.path();
+    let app = git_app(root, None);
+    assert_eq!(
+        save(&app, "# Doc\n\nSaved\n", ORIGINAL).await,
+        StatusCode::OK
+    );
+
+    let request = Request::builder()
+        .method("PATCH")
+        .uri("/api/files/doc.md")
+        .header(header::CONTENT_TYPE, "application/json");
+    let body = json!({ "to": "guides/doc.md" }).to_string();
+    let (status, _) = send(&app, request, Body::from(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(head(root).unwrap().0, "Move doc.md to guides/doc.md");
+    assert_eq!(committed(root, "docs/doc.md"), None);
+    assert!(committed(root, "docs/guides/doc.md").is_some());
+
+    let request = Request::builder()
+        .method("DELETE")
+        .uri("/api/folders/guides");
+    let (status, _) = send(&app, request, Body::empty()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(head(root).unwrap().0, "Delete guides");
+    assert_eq!(committed(root, "docs/guides/doc.md"), None);
+    // The trash itself is never committed
+    assert_eq!(commit_count(root), 3);
+    let repo = gix::open(root).unwrap();
+    let tree = repo.head_commit().unwrap().tree().unwrap();
+    assert!(tree.lookup_entry_by_path("docs").unwrap().is_none());
+}
+
+/// Move the folder `docs/guides` to `docs/moved`
+async fn move_guides(app: &Router, root: &Path) {
+    let request = Request::builder()
+        .method("PATCH")
+        .uri("/api/folders/guides")
+        .header(header::CONTENT_TYPE, "application/json");
+    let body = json!({ "to": "moved" }).to_string();
+    let (status, body) = send(app, request, Body::from(body)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(head(root).unwrap().0, "Move guides to moved");
+}
+
+#[tokio::test]
+async fn moving_a_folder_keeps_its_tracked_ignored_files() {
+    let temp_dir = work_tree();
+    let root = temp_dir.path();
+    std::fs::create_dir(root.join("docs/guides")).unwrap();
+    std::fs::write(root.join("docs/guides/setup.md"), "# Setup\n").unwrap();
+    std::fs::write(root.join("docs/guides/build.log"), "tracked anyway\n").unwrap();
+    std::fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+    commit_files(root, &["docs/guides/setup.md", "docs/guides/build.log"]);
+    let app = git_app(root, None);
+
+    move_guides(&app, root).await;
+    assert_eq!(
+        committed(root, "docs/moved/build.log").as_deref(),
+        Some("tracked anyway\n")
+    );
+    assert_eq!(
+        committed(root, "docs/moved/setup.md").as_deref(),
+        Some("# Setup\n")
+    );
+    assert_eq!(committed(root, "docs/guides/build.log"), None);
+}
+
+#[tokio::test]
+async fn moving_a_folder_leaves_unrelated_changes_uncommitted() {
+    let temp_dir = work_tree();
+    let root = temp_dir.path();
+    std::fs::create_dir(root.join("docs/guides")).unwrap();
+    std::fs::write(root.join("docs/guides/setup.md"), "# Setup\n").unwrap();
+    commit_files(root, &["docs/guides/setup.md"]);
+    std::fs::write(root.join("docs/guides/setup.md"), "# Setup\n\nDraft\n").unwrap();
+    std::fs::write(root.join("docs/guides/scratch.md"), "# Scratch\n").unwrap();
+    let app = git_app(root, None);
+
+    move_guides(&app, root).await;
+    assert_eq!(
+        committed(root, "docs/moved/setup.md").as_deref(),
+        Some("# Setup\n")
+    );
+    assert_eq!(committed(root, "docs/moved/scratch.md"), None);
+    assert_eq!(
+        std::fs::read_to_string(root.join("docs/moved/setup.md")).unwrap(),
+        "# Setup\n\nDraft\n"
+    );
+
+    // The index matches the commit, so the edit still shows as unstaged
+    let repo = gix::open(root).unwrap();
+    let index = repo.open_index().unwrap();
+    let entry = index.entry_by_path("docs/moved/setup.md".into()).unwrap();
+    assert_eq!(entry.id, repo.write_blob("# Setup\n").unwrap().detach());
+    assert!(
+        index
+            .entry_by_path("docs/moved/scratch.md".into())
+            .is_none()
+    );
+    assert!(index.entry_by_path("docs/guides/setup.md".into()).is_none());
+}
+
+#[tokio::test]
+async fn writes_are_refused_during_a_merge() {
+    let temp_dir = work_tree();
+    let root = temp_dir.path();
+    let app = git_app(root, None);
+    let repo = gix::open(root).unwrap();
+    std::fs::write(
+        repo.path().join("MERGE_HEAD"),
+        format!("{}\n", gix::ObjectId::empty_tree(repo.object_hash())),
+    )
+    .unwrap();
+
+    let request = Request::builder()
+        .method("PUT")
+        .uri("/api/markdown/doc.md")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::IF_MATCH, conditional::etag(ORIGINAL.as_bytes()));
+    let body = json!({ "markdown": "# Changed\n" }).to_string();
+    let (status, body) = send(&app, request, Body::from(body)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let body: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["code"], "repository_busy");
+    assert_eq!(
+        std::fs::read_to_string(root.join("docs/doc.md")).unwrap(),
+        ORIGINAL
+    );
+
+    // Reading and previewing still work
+    let (status, _) = send(&app, Request::builder().uri("/view/doc.md"), Body::empty()).await;
+    assert_eq!(status, StatusCode::OK);
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/render")
+        .header(header::CONTENT_TYPE, "application/json");
+    let body = json!({ "markdown": "# Preview" }).to_string();
+    let (status, _) = send(&app, request, Body::from(body)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[test]
+fn opening_outside_a_work_tree_fails() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    assert!(Repository::open(temp_dir.path(), GitOptions::default()).is_err());
+}
+
+const VERSIONS: [&str; 3] = [
+    "# Doc\n\nintro\n",
+    "# Doc\n\nintro\nappendix\n",
+    "# Title\n\nintro\nappendix\n",
+];
+
+#[tokio::test]
+async fn log_lists_the_commits_that_changed_a_document() {
+    let temp_dir = work_tree();
+    let root = temp_dir.path();
+    let app = git_app(root, None);
+    let ids = commit_versions(&app, &VERSIONS).await;
+    let (status, _) = send(&app, upload("other.md"), Body::from("# Other\n")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let log = get_json(&app, "/api/git/log/doc.md").await;
+    assert_eq!(log["path"], "doc.md");
+    let commits = log["commits"].as_array().unwrap();
+    let listed: Vec<&str> = commits.iter().map(|c| c["id"].as_str().unwrap()).collect();
+    assert_eq!(
+        listed,
+        vec![ids[2].as_str(), ids[1].as_str(), ids[0].as_str()]
+    );
+    assert_eq!(commits[0]["summary"], "Edit doc.md");
+    assert_eq!(commits[0]["author"], "Docs Bot");
+    assert_eq!(commits[0]["email"], "bot@example.com");
+
+    // The page loads the commits from the log instead of listing them
+    let (status, page) = get(&app, "/view/doc.md").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains("git-commits"));
+    assert!(page.contains("data-log=\"/api/git/log/doc.md\""));
+    assert!(!page.contains(&format!("/view/doc.md?commit={}", ids[1])));
+    assert!(page.contains("/blame/doc.md"));
+    assert!(!page.contains("class=\"git-banner\""));
+
+    let (status, _) = get(&app, "/api/git/log/missing/../../secret.md").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn documents_render_as_they_were_at_a_commit() {
+    let temp_dir = work_tree();
+    let root = temp_dir.path();
+    let app = git_app(root, None);
+    let ids = commit_versions(&app, &VERSIONS).await;
+
+    let (status, page) = get(&app, &format!("/view/doc.md?commit={}", ids[0])).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains("<p>intro</p>"));
+    assert!(!page.contains("appendix"));
+    assert!(page.contains("class=\"git-banner\""));
+    assert!(page.contains(&ids[0][..8]));
+    // The panel is loaded from the log, marking the commit shown
+    assert!(page.contains(&format!("data-active=\"{}\"", ids[0])));
+    assert!(!page.contains(&format!("/view/doc.md?commit={}", ids[1])));
+
+    // Abbreviated ids, branches and tags
+    let (status, page) = get(&app, &format!("/view/doc.md?commit={}", &ids[1][..7])).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains("appendix"));
+    assert!(page.contains("<h1>Doc</h1>"));
+    let repo = gix::open(root).unwrap();
+    let id = gix::ObjectId::from_hex(ids[0].as_bytes()).unwrap();
+    repo.tag_reference("v1", id, PreviousValue::Any).unwrap();
+    let (status, page) = get(&app, "/view/doc.md?commit=v1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!page.contains("appendix"));
+
+    // Nothing that walks or searches the history
+    for revision in [
+        "HEAD",
+        "HEAD~1",
+        "v1%5E%7Bcommit%7D",
+        "%3A%2FEdit",
+        "%40%7B0%7D",
+        "..%2Fconfig",
+    ] {
+        let uri = format!("/view/doc.md?commit={}", revision);
+        assert_eq!(
+            get(&app, &uri).await.0,
+            StatusCode::NOT_FOUND,
+            "{}",
+            revision
+        );
+    }
+    let (status, _) = get(&app, "/view/doc.md?commit=no-such-revision").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = get(&app, &format!("/view/other.md?commit={}", ids[2])).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn moved_and_deleted_documents_render_at_an_older_commit() {
+    let temp_dir = work_tree();
+    let root = temp_dir.path();
+    let app = git_app(root, None);
+    let ids = commit_versions(&app, &VERSIONS).await;
+
+    let request = Request::builder()
+        .method("PATCH")
+        .uri("/api/files/doc.md")
+        .header(header::CONTENT_TYPE, "application/json");
+    let body = json!({ "to": "archive/doc.md" }).to_string();
+    let (status, _) = send(&app, request, Body::from(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    let moved = gix::open(root).unwrap().head_id().unwrap().to_string();
+    let (status, page) = get(&app, &format!("/view/doc.md?commit={}", ids[0])).await;
+    assert_eq!(status, StatusCode::OK, "{}", page);
+    assert!(page.contains("<p>intro</p>"));
+    assert!(page.contains("class=\"git-banner\""));
+
+    let request = Request::builder()
+        .method("DELETE")
+        .uri("/api/folders/archive");
+    let (status, _) = send(&app, request, Body::empty()).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = get(&app, "/view/archive/doc.md").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, page) = get(&app, &format!("/view/archive/doc.md?commit={}", moved)).await;
+    assert_eq!(status, StatusCode::OK, "{}", page);
+    assert!(page.contains("appendix"));
+}
+
+#[tokio::test]
+async fn blame_attributes_lines_to_the_commit_that_last_changed_them() {
+    let temp_dir = work_tree();
+    let root = temp_dir.path();
+    let app = git_app(root, None);
+    let ids = commit_versions(&app, &VERSIONS).await;
+    // A commit not touching the document is skipped
+    let (status, _) = send(&app, upload("other.md"), Body::from("# Other\n")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let blame = get_json(&app, "/api/git/blame/doc.md").await;
+    let lines: Vec<(&str, &str)> = blame["lines"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|line| {
+            (
+                line["text"].as_str().unwrap(),
+                line["commit"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            ("# Title", ids[2].as_str()),
+            ("", ids[0].as_str()),
+            ("intro", ids[0].as_str()),
+            ("appendix", ids[1].as_str()),
+        ]
+    );
+    let commits: Vec<&str> = blame["commits"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|commit| commit["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        commits,
+        vec![ids[2].as_str(), ids[0].as_str(), ids[1].as_str()]
+    );
+
+    let blame = get_json(&app, &format!("/api/git/blame/doc.md?commit={}", ids[1])).await;
+    assert_eq!(blame["commit"]["id"], ids[1].as_str());
+    assert_eq!(blame["lines"][0]["commit"], ids[0].as_str());
+
+    let (status, page) = get(&app, "/blame/doc.md").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page.matches("class=\"blame-start\"").count(), 3);
+    assert!(page.contains(&format!("/view/doc.md?commit={}", ids[1])));
+}
+
+#[tokio::test]
+async fn blame_follows_the_parent_a_merge_took_the_document_from() {
+    let temp_dir = work_tree();
+    let root = temp_dir.path();
+    let app = git_app(root, None);
+    let ids = commit_versions(&app, &VERSIONS[..2]).await;
+
+    // Merge a side branch that left the document alone
+    let repo = gix::open(root).unwrap();
+    let head = repo.head_commit().unwrap();
+    let tree = head.tree_id().unwrap().detach();
+    let first: gix::ObjectId = ids[0].parse().unwrap();
+    let signature = gix::actor::Signature {
+        name: "Side".into(),
+        email: "side@example.com".into(),
+        time: gix::date::Time::now_utc(),
+    };
+    let mut time = Default::default();
+    let signature = signature.to_ref(&mut time);
+    let side_tree = repo.find_commit(first).unwrap().tree_id().unwrap();
+    let side = repo
+        .new_commit_as(signature, signature, "Side", side_tree, [first])
+        .unwrap();
+    let merge = repo
+        .commit_as(
+            signature,
+            signature,
+            "HEAD",
+            "Merge side",
+            tree,
+            [head.id, side.id],
+        )
+        .unwrap();
+
+    let blame = get_json(&app, "/api/git/blame/doc.md").await;
+    assert_eq!(blame["commit"]["id"], merge.to_string());
+    assert_eq!(blame["lines"][3]["commit"], ids[1].as_str());
+    assert_eq!(blame["lines"][0]["commit"], ids[0].as_str());
+
+    let log = get_json(&app, "/api/git/log/doc.md").await;
+    assert_eq!(log["commits"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn history_is_read_without_committing() {
+    let temp_dir = work_tree();
+    let root = temp_dir.path();
+    commit_versions(&git_app(root, None), &VERSIONS[..1]).await;
+
+    let app = app_with(root, GitOptions::default());
+    assert_eq!(save(&app, VERSIONS[1], VERSIONS[0]).await, StatusCode::OK);
+    assert_eq!(commit_count(root), 1);
+    let log = get_json(&app, "/api/git/log/doc.md").await;
+    assert_eq!(log["commits"].as_array().unwrap().len(), 1);
+
+    // Without commits, a merge in progress does not block writes
+    let repo = gix::open(root).unwrap();
+    let head = repo.head_id().unwrap();
+    std::fs::write(repo.path().join("MERGE_HEAD"), format!("{}\n", head)).unwrap();
+    assert_eq!(save(&app, VERSIONS[2], VERSIONS[1]).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn git_routes_need_a_repository() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(temp_dir.path().join("doc.md"), ORIGINAL).unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &["doc.md"]));
+
+    let (status, page) = get(&app, "/view/doc.md").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!page.contains("class=\"doc-sidebar__panel git-commits\""));
+    for uri in [
+        "/api/git/log/doc.md",
+        "/blame/doc.md",
+        "/view/doc.md?commit=HEAD",
+    ] {
+        assert_eq!(get(&app, uri).await.0, StatusCode::NOT_FOUND, "{}", uri);
+    }
+}
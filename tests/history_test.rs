@@ -0,0 +1,390 @@
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode, header},
+};
+use rsmd::{
+    conditional,
+    diff::{Change, hunks, line_diff, unified},
+    history::{History, Reason, Retention},
+    server::{AppState, create_router},
+};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use tower::util::ServiceExt;
+
+mod common;
+
+fn doc_state(temp_dir: &tempfile::TempDir, history: Option<History>) -> Arc<AppState> {
+    std::fs::write(temp_dir.path().join("doc.md"), "# Doc\n\nBody\n").unwrap();
+    let mut store = common::store(temp_dir.path(), &["doc.md"]);
+    if let Some(history) = history {
+        store = store.with_history(history);
+    }
+    common::served(temp_dir.path(), store)
+}
+
+fn history_app(temp_dir: &tempfile::TempDir) -> Router {
+    let history = History::new(temp_dir.path(), Retention::default());
+    create_router(doc_state(temp_dir, Some(history)))
+}
+
+async fn send(
+    app: &Router,
+    request: axum::http::request::Builder,
+    body: Body,
+) -> (StatusCode, axum::http::HeaderMap, String) {
+    let response = app
+        .clone()
+        .oneshot(request.body(body).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (
+        status,
+        headers,
+        String::from_utf8_lossy(&bytes).into_owned(),
+    )
+}
+
+async fn get(app: &Router, uri: &str) -> (StatusCode, String) {
+    let (status, _, body) = send(app, Request::builder().uri(uri), Body::empty()).await;
+    (status, body)
+}
+
+async fn save(app: &Router, name: &str, markdown: &str, previous: &str) -> StatusCode {
+    let request = Request::builder()
+        .method("PUT")
+        .uri(format!("/api/markdown/{}", name))
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::IF_MATCH, conditional::etag(previous.as_bytes()));
+    let body = json!({ "markdown": markdown }).to_string();
+    send(app, request, Body::from(body)).await.0
+}
+
+async fn revisions(app: &Router, name: &str) -> Vec<Value> {
+    let (status, body) = get(app, &format!("/api/history/{}", name)).await;
+    assert_eq!(status, StatusCode::OK);
+    let body: Value = serde_json::from_str(&body).unwrap();
+    body["revisions"].as_array().unwrap().clone()
+}
+
+#[test]
+fn line_diff_numbers_kept_removed_and_added_lines() {
+    let diff = line_diff("a\nb\nc\nd\n", "a\nc\nx\nd\n");
+    let summary: Vec<(Change, Option<usize>, Option<usize>, &str)> = diff
+        .iter()
+        .map(|line| (line.change, line.old, line.new, line.text.as_str()))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (Change::Same, Some(1), Some(1), "a"),
+            (Change::Removed, Some(2), None, "b"),
+            (Change::Same, Some(3), Some(2), "c"),
+            (Change::Added, None, Some(3), "x"),
+            (Change::Same, Some(4), Some(4), "d"),
+        ]
+    );
+    assert_eq!(line_diff("", "new").len(), 1);
+    assert!(
+        line_diff("same\n", "same\n")
+            .iter()
+            .all(|line| line.change == Change::Same)
+    );
+}
+
+#[test]
+fn hunks_keep_three_lines_of_context() {
+    let lines = |changed: &[usize]| -> String {
+        (1..=20)
+            .map(|n| match changed.contains(&n) {
+                true => format!("changed {}\n", n),
+                false => format!("{}\n", n),
+            })
+            .collect()
+    };
+    let (old, near, far) = (lines(&[]), lines(&[5, 9]), lines(&[2, 18]));
+
+    let grouped = hunks(&line_diff(&old, &near));
+    assert_eq!(grouped.len(), 1);
+    assert_eq!(grouped[0].first().unwrap().old, Some(2));
+    assert_eq!(grouped[0].last().unwrap().old, Some(12));
+
+    assert_eq!(hunks(&line_diff(&old, &far)).len(), 2);
+    assert!(hunks(&line_diff(&old, &old)).is_empty());
+}
+
+#[test]
+fn unified_diff_shows_hunks_with_context() {
+    let diff = unified(
+        "index.md",
+        "a\n[s](setup.md)\nb\n",
+        "a\n[s](ops/setup.md)\nb\nc\n",
+    );
+    assert_eq!(
+        diff,
+        "--- a/index.md\n+++ b/index.md\n@@ -1,3 +1,4 @@\n a\n-[s](setup.md)\n+[s](ops/setup.md)\n b\n+c\n"
+    );
+    assert_eq!(
+        unified("a.md", "", "new\n"),
+        "--- a/a.md\n+++ b/a.md\n@@ -0,0 +1,1 @@\n+new\n"
+    );
+    assert_eq!(
+        unified("a.md", "same\n", "same\n"),
+        "--- a/a.md\n+++ b/a.md\n"
+    );
+}
+
+#[tokio::test]
+async fn record_dedupes_and_prunes_old_revisions() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let retention = Retention {
+        max_revisions: 2,
+        max_age: None,
+    };
+    let history = History::new(temp_dir.path(), retention);
+
+    for contents in ["one", "one", "two", "three"] {
+        history
+            .record_contents("doc.md", contents.as_bytes(), Reason::Upload, None)
+            .await
+            .unwrap();
+    }
+    let revisions = history.revisions("doc.md").await.unwrap();
+    assert_eq!(revisions.len(), 2);
+    assert_eq!(
+        history.contents(&revisions[0].id).await.unwrap().unwrap(),
+        b"three"
+    );
+
+    // The pruned "one" is stored until garbage is collected
+    let objects = temp_dir.path().join(".rsmd/history/objects");
+    assert_eq!(walk_count(&objects), 3);
+    assert_eq!(history.collect_garbage().await.unwrap(), 1);
+    assert_eq!(walk_count(&objects), 2);
+    assert_eq!(history.collect_garbage().await.unwrap(), 0);
+    assert!(history.contents("not-an-id").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn unreadable_log_is_kept_and_stops_pruning() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let retention = Retention {
+        max_revisions: 1,
+        max_age: None,
+    };
+    let history = History::new(temp_dir.path(), retention);
+    history
+        .record_contents("kept.md", b"kept", Reason::Upload, None)
+        .await
+        .unwrap();
+    let id = history.revisions("kept.md").await.unwrap()[0].id.clone();
+    let log = temp_dir.path().join(".rsmd/history/log/kept.md.json");
+    std::fs::write(&log, "[{\"id\":").unwrap();
+
+    let err = history
+        .record_contents("kept.md", b"newer", Reason::Upload, None)
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), "[{\"id\":");
+    assert!(history.revisions("kept.md").await.is_err());
+
+    // Pruning another document must not delete what the broken log refers to
+    for contents in ["one", "two"] {
+        history
+            .record_contents("other.md", contents.as_bytes(), Reason::Upload, None)
+            .await
+            .unwrap();
+    }
+    let err = history.collect_garbage().await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(history.contents(&id).await.unwrap().is_some());
+}
+
+fn walk_count(dir: &std::path::Path) -> usize {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            if entry.file_type().unwrap().is_dir() {
+                walk_count(&entry.path())
+            } else {
+                1
+            }
+        })
+        .sum()
+}
+
+#[tokio::test]
+async fn rename_moves_the_log_of_a_document_or_folder() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let history = History::new(temp_dir.path(), Retention::default());
+    history
+        .record_contents("guides/a.md", b"a", Reason::Edit, None)
+        .await
+        .unwrap();
+
+    history.rename("guides", "manual", true).await.unwrap();
+    history
+        .rename("manual/a.md", "manual/b.md", false)
+        .await
+        .unwrap();
+    assert!(history.revisions("guides/a.md").await.unwrap().is_empty());
+    assert_eq!(history.revisions("manual/b.md").await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn saves_record_revisions_that_can_be_compared_and_restored() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = history_app(&temp_dir);
+    let original = "# Doc\n\nBody\n";
+    let edited = "# Doc\n\nNew body\n";
+
+    assert!(revisions(&app, "doc.md").await.is_empty());
+    assert_eq!(save(&app, "doc.md", edited, original).await, StatusCode::OK);
+    let recorded = revisions(&app, "doc.md").await;
+    assert_eq!(recorded.len(), 1);
+    assert_eq!(recorded[0]["reason"], "edit");
+
+    let (status, page) = get(&app, "/history/doc.md").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains(r#"<tr class="diff-removed">"#));
+    assert!(page.contains("-Body"));
+    assert!(page.contains("+New body"));
+
+    let id = recorded[0]["id"].as_str().unwrap();
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/history/doc.md")
+        .header(header::CONTENT_TYPE, "application/json");
+    let body = json!({ "revision": id }).to_string();
+    let (status, _, body) = send(&app, request, Body::from(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    let body: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["etag"], conditional::etag(original.as_bytes()));
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("doc.md")).unwrap(),
+        original
+    );
+    let (_, view) = get(&app, "/api/markdown/doc.md").await;
+    assert!(view.contains("Body") && !view.contains("New body"));
+
+    // The restore itself can be undone
+    let recorded = revisions(&app, "doc.md").await;
+    assert_eq!(recorded.len(), 2);
+    assert_eq!(recorded[0]["reason"], "restore");
+}
+
+/// A restore form posted by a browser with one of the headers it adds itself
+fn restore_form(browser_header: (&str, &str)) -> axum::http::request::Builder {
+    Request::builder()
+        .method("POST")
+        .uri("/history/doc.md")
+        .header(header::HOST, "localhost:3000")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .header(browser_header.0, browser_header.1)
+}
+
+#[tokio::test]
+async fn restore_form_redirects_back_to_the_history() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = history_app(&temp_dir);
+    save(&app, "doc.md", "# Changed\n", "# Doc\n\nBody\n").await;
+    let id = revisions(&app, "doc.md").await[0]["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let (status, headers, _) = send(
+        &app,
+        restore_form(("origin", "http://localhost:3000")),
+        Body::from(format!("revision={}", id)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    assert_eq!(headers[header::LOCATION], "/history/doc.md");
+
+    let request = restore_form(("sec-fetch-site", "same-origin"));
+    let unknown = format!("revision={}", "0".repeat(64));
+    let (status, _, _) = send(&app, request, Body::from(unknown)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn history_follows_renamed_documents() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = history_app(&temp_dir);
+    save(&app, "doc.md", "# Changed\n", "# Doc\n\nBody\n").await;
+
+    let request = Request::builder()
+        .method("PATCH")
+        .uri("/api/files/doc.md")
+        .header(header::CONTENT_TYPE, "application/json");
+    let body = json!({ "to": "notes/moved.md" }).to_string();
+    let (status, _, _) = send(&app, request, Body::from(body)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let recorded = revisions(&app, "notes/moved.md").await;
+    assert_eq!(recorded.len(), 2);
+    assert_eq!(recorded[0]["reason"], "rename");
+    assert_eq!(recorded[0]["from"], "doc.md");
+    assert!(revisions(&app, "doc.md").await.is_empty());
+}
+
+#[tokio::test]
+async fn history_is_not_served_when_disabled_or_as_static_files() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let disabled = create_router(doc_state(&temp_dir, None));
+    assert_eq!(
+        get(&disabled, "/history/doc.md").await.0,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        save(&disabled, "doc.md", "# Changed\n", "# Doc\n\nBody\n").await,
+        StatusCode::OK
+    );
+    assert!(!temp_dir.path().join(".rsmd").exists());
+
+    let app = history_app(&temp_dir);
+    save(&app, "doc.md", "# Again\n", "# Doc\n\nBody\n").await;
+    let id = revisions(&app, "doc.md").await[0]["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let object = format!("/static/.rsmd/history/objects/{}/{}", &id[..2], &id[2..]);
+    assert!(temp_dir.path().join(&object["/static/".len()..]).exists());
+    assert_eq!(get(&app, &object).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(get(&app, "/static/doc.md").await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn restore_form_refuses_cross_site_posts() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = history_app(&temp_dir);
+    save(&app, "doc.md", "# Changed\n", "# Doc\n\nBody\n").await;
+    let id = revisions(&app, "doc.md").await[0]["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    for browser_header in [
+        ("origin", "https://evil.example"),
+        ("sec-fetch-site", "cross-site"),
+        ("origin", "null"),
+    ] {
+        let request = restore_form(browser_header);
+        let (status, _, _) = send(&app, request, Body::from(format!("revision={}", id))).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{browser_header:?}");
+    }
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("doc.md")).unwrap(),
+        "# Changed\n"
+    );
+    assert_eq!(revisions(&app, "doc.md").await.len(), 1);
+}
@@ -1,7 +1,12 @@
-use rsmd::directory::{MarkdownFile, list_directory_contents};
-use rsmd::html::{escape_html, render_directory_page, render_page, render_raw_page};
+use rsmd::directory::{DirectoryListing, FileTree};
+use rsmd::html::{
+    Document, PageOptions, escape_html, render_directory_page, render_document_page, render_page,
+    render_raw_page,
+};
 use rsmd::i18n::Language;
-use std::path::PathBuf;
+use rsmd::source::MemorySource;
+use rsmd::theme::Theme;
+use std::sync::Arc;
 
 #[test]
 fn test_escape_html() {
@@ -14,7 +19,7 @@ fn test_escape_html() {
 #[test]
 fn test_render_page_contains_content() {
     let lang = Language::English;
-    let result = render_page("<h1>Test</h1>", &lang);
+    let result = render_page("<h1>Test</h1>", &lang, &PageOptions::default());
     assert!(result.contains("<h1>Test</h1>"));
     assert!(result.contains("<!DOCTYPE html>"));
     assert!(result.contains("Markdown Viewer"));
@@ -23,20 +28,28 @@ fn test_render_page_contains_content() {
 #[test]
 fn test_render_raw_page_escapes_html() {
     let lang = Language::English;
-    let result = render_raw_page("<script>alert('xss')</script>", &lang);
+    let result = render_raw_page(
+        "<script>alert('xss')</script>",
+        &lang,
+        &PageOptions::default(),
+    );
     assert!(result.contains("&lt;script&gt;"));
     assert!(!result.contains("<script>"));
 }
 
-#[test]
-fn test_render_directory_page_without_htmx() {
-    let files = vec![MarkdownFile {
-        name: "test.md".to_string(),
-        path: PathBuf::from("test.md"),
-    }];
-    let listing = list_directory_contents(&files, "");
+/// The listing of `path` in a tree over `names`
+async fn list_tree(names: &[&str], path: &str) -> DirectoryListing {
+    FileTree::with_files(Arc::new(MemorySource::new()), names.iter().copied())
+        .list(path)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_render_directory_page_without_htmx() {
+    let listing = list_tree(&["test.md"], "").await;
     let lang = Language::English;
-    let result = render_directory_page(&listing, "/test", &lang, false);
+    let result = render_directory_page(&listing, "/test", &lang, false, &PageOptions::default());
 
     assert!(result.contains("test.md"));
     assert!(result.contains("/view/test.md"));
@@ -47,15 +60,11 @@ fn test_render_directory_page_without_htmx() {
     assert!(result.contains("file-entry__path"));
 }
 
-#[test]
-fn test_render_directory_page_with_dynamic_loading() {
-    let files = vec![MarkdownFile {
-        name: "test.md".to_string(),
-        path: PathBuf::from("test.md"),
-    }];
-    let listing = list_directory_contents(&files, "");
+#[tokio::test]
+async fn test_render_directory_page_with_dynamic_loading() {
+    let listing = list_tree(&["test.md"], "").await;
     let lang = Language::English;
-    let result = render_directory_page(&listing, "/test", &lang, true);
+    let result = render_directory_page(&listing, "/test", &lang, true, &PageOptions::default());
 
     assert!(result.contains("test.md"));
     assert!(!result.contains("data-load"));
@@ -73,45 +82,30 @@ fn test_render_directory_page_with_dynamic_loading() {
     assert!(result.contains("upload-browse"));
 }
 
-#[test]
-fn test_render_directory_page_empty() {
-    let files = vec![];
-    let listing = list_directory_contents(&files, "");
+#[tokio::test]
+async fn test_render_directory_page_empty() {
+    let listing = DirectoryListing::default();
     let lang = Language::English;
-    let result = render_directory_page(&listing, "/test", &lang, true);
+    let result = render_directory_page(&listing, "/test", &lang, true, &PageOptions::default());
 
     assert!(result.contains("No markdown files found"));
 }
 
-#[test]
-fn test_render_directory_page_korean() {
-    let files = vec![MarkdownFile {
-        name: "test.md".to_string(),
-        path: PathBuf::from("test.md"),
-    }];
-    let listing = list_directory_contents(&files, "");
+#[tokio::test]
+async fn test_render_directory_page_korean() {
+    let listing = list_tree(&["test.md"], "").await;
     let lang = Language::Korean;
-    let result = render_directory_page(&listing, "/test", &lang, true);
+    let result = render_directory_page(&listing, "/test", &lang, true, &PageOptions::default());
 
     assert!(result.contains("마크다운"));
     assert!(result.contains("lang=\"ko\""));
 }
 
-#[test]
-fn test_render_directory_page_nested_path() {
-    let files = vec![
-        MarkdownFile {
-            name: "guides/docker.md".to_string(),
-            path: PathBuf::from("guides/docker.md"),
-        },
-        MarkdownFile {
-            name: "guides/workflows/ci.md".to_string(),
-            path: PathBuf::from("guides/workflows/ci.md"),
-        },
-    ];
-    let listing = list_directory_contents(&files, "guides");
+#[tokio::test]
+async fn test_render_directory_page_nested_path() {
+    let listing = list_tree(&["guides/docker.md", "guides/workflows/ci.md"], "guides").await;
     let lang = Language::English;
-    let result = render_directory_page(&listing, "/test", &lang, true);
+    let result = render_directory_page(&listing, "/test", &lang, true, &PageOptions::default());
 
     assert!(result.contains("data-current-path=\"guides\""));
     assert!(result.contains("data-path=\"guides/workflows\""));
@@ -119,3 +113,66 @@ fn test_render_directory_page_nested_path() {
     assert!(result.contains("guides/docker.md"));
     assert!(result.contains("file-entry__path"));
 }
+
+#[tokio::test]
+async fn test_render_document_page_with_navigation() {
+    let tree = FileTree::with_files(
+        Arc::new(MemorySource::new()),
+        [
+            "readme.md",
+            "guides/a b.md",
+            "guides/setup.md",
+            "guides/zz.md",
+        ],
+    );
+    let nav = tree.navigation("guides/setup.md").await.unwrap();
+    let result = render_document_page(
+        &Document {
+            name: "guides/setup.md",
+            html: "<h1>Setup</h1>",
+            metadata: None,
+        },
+        &nav,
+        &Language::English,
+        &PageOptions::default(),
+    );
+
+    assert!(result.contains("<h1>Setup</h1>"));
+    assert!(result.contains("doc-sidebar"));
+    assert!(result.contains(r#"data-path="guides" open data-loaded="true""#));
+    assert!(
+        result.contains(
+            r#"class="nav-file active" href="/view/guides/setup.md" aria-current="page""#
+        )
+    );
+    assert!(result.contains(r#"<a href="/">Home</a>"#));
+    assert!(result.contains(r#"<a href="/dir/guides">guides</a>"#));
+    assert!(result.contains(r#"<span class="breadcrumb-current">setup.md</span>"#));
+    assert!(result.contains(r#"class="doc-pager__prev" href="/view/guides/a%20b.md""#));
+    assert!(result.contains(r#"class="doc-pager__next" href="/view/guides/zz.md""#));
+}
+
+#[test]
+fn test_render_page_follows_system_theme_by_default() {
+    let result = render_page("<p>Body</p>", &Language::English, &PageOptions::default());
+
+    assert!(result.contains(r#"<html lang="en">"#));
+    assert!(result.contains("prefers-color-scheme: dark"));
+    assert!(result.contains(r#"[data-theme="sepia"]"#));
+    assert!(result.contains(r#"<option value="" selected>System</option>"#));
+    assert!(result.contains("background: var(--pre-bg)"));
+}
+
+#[test]
+fn test_render_page_with_selected_theme_and_custom_css() {
+    let options = PageOptions {
+        theme: Some(Theme::HighContrast),
+        custom_css: Some(".container { max-width: 60rem; }".into()),
+        ..PageOptions::default()
+    };
+    let result = render_raw_page("# Title", &Language::Korean, &options);
+
+    assert!(result.contains(r#"<html lang="ko" data-theme="high-contrast">"#));
+    assert!(result.contains(r#"<option value="high-contrast" selected>고대비</option>"#));
+    assert!(result.contains("<style>.container { max-width: 60rem; }</style>"));
+}
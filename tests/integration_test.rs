@@ -1,35 +1,28 @@
 use axum::{
     body::Body,
-    http::{Request, StatusCode},
+    http::{Request, StatusCode, header},
 };
 use rsmd::{
-    directory::MarkdownFile,
+    directory::FileTree,
+    error::ErrorResponse,
     i18n::Language,
-    server::{AppState, create_router},
+    server::{AppState, ServerConfig, create_router, create_router_with_config},
+    source::FsSource,
+    store::DocumentStore,
 };
-use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 use tower::util::ServiceExt;
 
 #[tokio::test]
 async fn test_directory_page_renders() {
     let state = Arc::new(AppState::Directory {
         dir_path: "/test".to_string(),
-        files: Arc::new(RwLock::new(vec![
-            MarkdownFile {
-                name: "test.md".to_string(),
-                path: PathBuf::from("/test/test.md"),
-            },
-            MarkdownFile {
-                name: "another.md".to_string(),
-                path: PathBuf::from("/test/another.md"),
-            },
-        ])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(PathBuf::from("/test"))),
+            ["test.md", "another.md"],
+        )))),
         language: Language::English,
-        base_dir: PathBuf::from("/test"),
     });
 
     let app = create_router(state);
@@ -62,23 +55,15 @@ async fn test_directory_page_renders() {
 async fn test_nested_directory_navigation() {
     let state = Arc::new(AppState::Directory {
         dir_path: "/content".to_string(),
-        files: Arc::new(RwLock::new(vec![
-            MarkdownFile {
-                name: "guides/docker.md".to_string(),
-                path: PathBuf::from("/content/guides/docker.md"),
-            },
-            MarkdownFile {
-                name: "guides/rust.md".to_string(),
-                path: PathBuf::from("/content/guides/rust.md"),
-            },
-            MarkdownFile {
-                name: "guides/workflows/ci.md".to_string(),
-                path: PathBuf::from("/content/guides/workflows/ci.md"),
-            },
-        ])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(PathBuf::from("/content"))),
+            [
+                "guides/docker.md",
+                "guides/rust.md",
+                "guides/workflows/ci.md",
+            ],
+        )))),
         language: Language::English,
-        base_dir: PathBuf::from("/content"),
     });
 
     let app = create_router(state);
@@ -117,24 +102,13 @@ async fn test_dynamic_loading_with_xhr_header() {
     let test_file = temp_dir.path().join("test.md");
     std::fs::write(&test_file, "# Test Header\n\nTest content").unwrap();
 
-    let mut cache = HashMap::new();
-    cache.insert(
-        "test.md".to_string(),
-        (
-            "# Test Header\n\nTest content".to_string(),
-            "<h1>Test Header</h1>\n<p>Test content</p>\n".to_string(),
-        ),
-    );
-
     let state = Arc::new(AppState::Directory {
         dir_path: temp_dir.path().to_str().unwrap().to_string(),
-        files: Arc::new(RwLock::new(vec![MarkdownFile {
-            name: "test.md".to_string(),
-            path: test_file.clone(),
-        }])),
-        file_cache: Arc::new(RwLock::new(cache)),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(temp_dir.path().to_path_buf())),
+            ["test.md"],
+        )))),
         language: Language::English,
-        base_dir: temp_dir.path().to_path_buf(),
     });
 
     let app = create_router(state);
@@ -171,13 +145,11 @@ async fn test_dynamic_loading_without_xhr_header() {
 
     let state = Arc::new(AppState::Directory {
         dir_path: temp_dir.path().to_str().unwrap().to_string(),
-        files: Arc::new(RwLock::new(vec![MarkdownFile {
-            name: "test.md".to_string(),
-            path: test_file.clone(),
-        }])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(temp_dir.path().to_path_buf())),
+            ["test.md"],
+        )))),
         language: Language::English,
-        base_dir: temp_dir.path().to_path_buf(),
     });
 
     let app = create_router(state);
@@ -210,10 +182,11 @@ async fn test_file_not_found() {
 
     let state = Arc::new(AppState::Directory {
         dir_path: temp_dir.path().to_str().unwrap().to_string(),
-        files: Arc::new(RwLock::new(vec![])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(temp_dir.path().to_path_buf())),
+            Vec::<String>::new(),
+        )))),
         language: Language::English,
-        base_dir: temp_dir.path().to_path_buf(),
     });
 
     let app = create_router(state);
@@ -229,15 +202,15 @@ async fn test_file_not_found() {
         .await
         .unwrap();
 
-    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
 
     let body = axum::body::to_bytes(response.into_body(), usize::MAX)
         .await
         .unwrap();
-    let body_str = String::from_utf8(body.to_vec()).unwrap();
+    let json: ErrorResponse = serde_json::from_slice(&body).unwrap();
 
-    // Should contain error message
-    assert!(body_str.contains("not found") || body_str.contains("Not found"));
+    assert_eq!(json.code, "not_found");
+    assert!(json.message.contains("not found"));
 }
 
 #[tokio::test]
@@ -248,13 +221,11 @@ async fn test_raw_route_serves_markdown() {
 
     let state = Arc::new(AppState::Directory {
         dir_path: temp_dir.path().to_str().unwrap().to_string(),
-        files: Arc::new(RwLock::new(vec![MarkdownFile {
-            name: "article.md".to_string(),
-            path: test_file.clone(),
-        }])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(temp_dir.path().to_path_buf())),
+            ["article.md"],
+        )))),
         language: Language::English,
-        base_dir: temp_dir.path().to_path_buf(),
     });
 
     let app = create_router(state);
@@ -288,13 +259,11 @@ async fn test_view_route_serves_markdown() {
 
     let state = Arc::new(AppState::Directory {
         dir_path: temp_dir.path().to_str().unwrap().to_string(),
-        files: Arc::new(RwLock::new(vec![MarkdownFile {
-            name: "view.md".to_string(),
-            path: test_file.clone(),
-        }])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(temp_dir.path().to_path_buf())),
+            ["view.md"],
+        )))),
         language: Language::English,
-        base_dir: temp_dir.path().to_path_buf(),
     });
 
     let app = create_router(state.clone());
@@ -319,9 +288,8 @@ async fn test_view_route_serves_markdown() {
     assert!(body_str.contains("View"));
     assert!(body_str.contains("Content"));
 
-    if let AppState::Directory { file_cache, .. } = state.as_ref() {
-        let guard = file_cache.read().await;
-        assert!(guard.contains_key("view.md"));
+    if let AppState::Directory { store, .. } = state.as_ref() {
+        assert_eq!(store.stats().await.entries, 1);
     }
 }
 
@@ -335,13 +303,11 @@ async fn test_view_route_serves_nested_markdown() {
 
     let state = Arc::new(AppState::Directory {
         dir_path: temp_dir.path().to_str().unwrap().to_string(),
-        files: Arc::new(RwLock::new(vec![MarkdownFile {
-            name: "docs/guide.md".to_string(),
-            path: test_file.clone(),
-        }])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(temp_dir.path().to_path_buf())),
+            ["docs/guide.md"],
+        )))),
         language: Language::English,
-        base_dir: temp_dir.path().to_path_buf(),
     });
 
     let app = create_router(state.clone());
@@ -366,9 +332,8 @@ async fn test_view_route_serves_nested_markdown() {
     assert!(body_str.contains("Guide"));
     assert!(body_str.contains("Details"));
 
-    if let AppState::Directory { file_cache, .. } = state.as_ref() {
-        let guard = file_cache.read().await;
-        assert!(guard.contains_key("docs/guide.md"));
+    if let AppState::Directory { store, .. } = state.as_ref() {
+        assert_eq!(store.stats().await.entries, 1);
     }
 }
 
@@ -380,13 +345,11 @@ async fn test_partial_content_loads_from_disk_and_caches() {
 
     let state = Arc::new(AppState::Directory {
         dir_path: temp_dir.path().to_str().unwrap().to_string(),
-        files: Arc::new(RwLock::new(vec![MarkdownFile {
-            name: "live.md".to_string(),
-            path: test_file.clone(),
-        }])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(temp_dir.path().to_path_buf())),
+            ["live.md"],
+        )))),
         language: Language::English,
-        base_dir: temp_dir.path().to_path_buf(),
     });
 
     let app = create_router(state.clone());
@@ -412,9 +375,8 @@ async fn test_partial_content_loads_from_disk_and_caches() {
     assert!(body_str.contains("Live"));
     assert!(!body_str.contains("<!DOCTYPE html>"));
 
-    if let AppState::Directory { file_cache, .. } = state.as_ref() {
-        let guard = file_cache.read().await;
-        assert!(guard.contains_key("live.md"));
+    if let AppState::Directory { store, .. } = state.as_ref() {
+        assert_eq!(store.stats().await.entries, 1);
     }
 }
 
@@ -426,13 +388,11 @@ async fn test_view_route_read_error() {
 
     let state = Arc::new(AppState::Directory {
         dir_path: temp_dir.path().to_str().unwrap().to_string(),
-        files: Arc::new(RwLock::new(vec![MarkdownFile {
-            name: "broken.md".to_string(),
-            path: missing_path.clone(),
-        }])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(temp_dir.path().to_path_buf())),
+            ["broken.md"],
+        )))),
         language: Language::English,
-        base_dir: temp_dir.path().to_path_buf(),
     });
 
     std::fs::remove_file(missing_path).unwrap();
@@ -449,7 +409,7 @@ async fn test_view_route_read_error() {
         .await
         .unwrap();
 
-    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
 
     let body = axum::body::to_bytes(response.into_body(), usize::MAX)
         .await
@@ -467,13 +427,11 @@ async fn test_partial_content_read_error() {
 
     let state = Arc::new(AppState::Directory {
         dir_path: temp_dir.path().to_str().unwrap().to_string(),
-        files: Arc::new(RwLock::new(vec![MarkdownFile {
-            name: "missing.md".to_string(),
-            path: missing_path.clone(),
-        }])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(temp_dir.path().to_path_buf())),
+            ["missing.md"],
+        )))),
         language: Language::English,
-        base_dir: temp_dir.path().to_path_buf(),
     });
 
     std::fs::remove_file(missing_path).unwrap();
@@ -491,7 +449,7 @@ async fn test_partial_content_read_error() {
         .await
         .unwrap();
 
-    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
 
     let body = axum::body::to_bytes(response.into_body(), usize::MAX)
         .await
@@ -507,10 +465,11 @@ async fn test_korean_language_support() {
 
     let state = Arc::new(AppState::Directory {
         dir_path: temp_dir.path().to_str().unwrap().to_string(),
-        files: Arc::new(RwLock::new(vec![])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(temp_dir.path().to_path_buf())),
+            Vec::<String>::new(),
+        )))),
         language: Language::Korean,
-        base_dir: temp_dir.path().to_path_buf(),
     });
 
     let app = create_router(state);
@@ -535,23 +494,11 @@ async fn test_korean_language_support() {
 async fn test_serve_directory_path_nested_folders() {
     let state = Arc::new(AppState::Directory {
         dir_path: "/test".to_string(),
-        files: Arc::new(RwLock::new(vec![
-            MarkdownFile {
-                name: "docs/api.md".to_string(),
-                path: PathBuf::from("/test/docs/api.md"),
-            },
-            MarkdownFile {
-                name: "docs/guide.md".to_string(),
-                path: PathBuf::from("/test/docs/guide.md"),
-            },
-            MarkdownFile {
-                name: "readme.md".to_string(),
-                path: PathBuf::from("/test/readme.md"),
-            },
-        ])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(PathBuf::from("/test"))),
+            ["docs/api.md", "docs/guide.md", "readme.md"],
+        )))),
         language: Language::English,
-        base_dir: PathBuf::from("/test"),
     });
 
     let app = create_router(state);
@@ -586,13 +533,11 @@ async fn test_serve_directory_path_nested_folders() {
 async fn test_directory_navigation_with_path_traversal_attempt() {
     let state = Arc::new(AppState::Directory {
         dir_path: "/test".to_string(),
-        files: Arc::new(RwLock::new(vec![MarkdownFile {
-            name: "test.md".to_string(),
-            path: PathBuf::from("/test/test.md"),
-        }])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(PathBuf::from("/test"))),
+            ["test.md"],
+        )))),
         language: Language::English,
-        base_dir: PathBuf::from("/test"),
     });
 
     let app = create_router(state);
@@ -616,13 +561,11 @@ async fn test_directory_navigation_with_path_traversal_attempt() {
 async fn test_directory_navigation_nonexistent_path() {
     let state = Arc::new(AppState::Directory {
         dir_path: "/test".to_string(),
-        files: Arc::new(RwLock::new(vec![MarkdownFile {
-            name: "test.md".to_string(),
-            path: PathBuf::from("/test/test.md"),
-        }])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(PathBuf::from("/test"))),
+            ["test.md"],
+        )))),
         language: Language::English,
-        base_dir: PathBuf::from("/test"),
     });
 
     let app = create_router(state);
@@ -651,13 +594,11 @@ async fn test_directory_mode_handles_file_requests() {
 
     let state = Arc::new(AppState::Directory {
         dir_path: temp_dir.path().to_str().unwrap().to_string(),
-        files: Arc::new(RwLock::new(vec![MarkdownFile {
-            name: "test.md".to_string(),
-            path: test_file.clone(),
-        }])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(temp_dir.path().to_path_buf())),
+            ["test.md"],
+        )))),
         language: Language::English,
-        base_dir: temp_dir.path().to_path_buf(),
     });
 
     let app = create_router(state);
@@ -687,18 +628,16 @@ async fn test_directory_mode_handles_file_requests() {
 async fn test_serve_raw_with_directory_state() {
     let state = Arc::new(AppState::Directory {
         dir_path: "/test".to_string(),
-        files: Arc::new(RwLock::new(vec![MarkdownFile {
-            name: "test.md".to_string(),
-            path: PathBuf::from("/test/test.md"),
-        }])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(PathBuf::from("/test"))),
+            ["test.md"],
+        )))),
         language: Language::English,
-        base_dir: PathBuf::from("/test"),
     });
 
     let app = create_router(state);
 
-    // Accessing /raw in directory mode should work via /raw/{filename}
+    // /raw/{filename} is routed in directory mode; the file itself is missing on disk
     let response = app
         .oneshot(
             Request::builder()
@@ -709,26 +648,18 @@ async fn test_serve_raw_with_directory_state() {
         .await
         .unwrap();
 
-    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
 }
 
 #[tokio::test]
 async fn test_directory_navigation_root_path() {
     let state = Arc::new(AppState::Directory {
         dir_path: "/test".to_string(),
-        files: Arc::new(RwLock::new(vec![
-            MarkdownFile {
-                name: "root.md".to_string(),
-                path: PathBuf::from("/test/root.md"),
-            },
-            MarkdownFile {
-                name: "docs/nested.md".to_string(),
-                path: PathBuf::from("/test/docs/nested.md"),
-            },
-        ])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(PathBuf::from("/test"))),
+            ["root.md", "docs/nested.md"],
+        )))),
         language: Language::English,
-        base_dir: PathBuf::from("/test"),
     });
 
     let app = create_router(state);
@@ -750,3 +681,159 @@ async fn test_directory_navigation_root_path() {
     assert!(body_str.contains("root.md"));
     assert!(body_str.contains("docs"));
 }
+
+#[tokio::test]
+async fn test_view_route_renders_navigation() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(temp_dir.path().join("docs")).unwrap();
+    std::fs::write(temp_dir.path().join("docs/one.md"), "# One").unwrap();
+    std::fs::write(temp_dir.path().join("docs/two.md"), "# Two").unwrap();
+
+    let state = Arc::new(AppState::Directory {
+        dir_path: temp_dir.path().to_str().unwrap().to_string(),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::new(
+            temp_dir.path().to_path_buf(),
+        )))),
+        language: Language::English,
+    });
+
+    let response = create_router(state)
+        .oneshot(
+            Request::builder()
+                .uri("/view/docs/one.md")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body_str = String::from_utf8(body.to_vec()).unwrap();
+
+    assert!(body_str.contains("<h1>One</h1>"));
+    assert!(body_str.contains(r#"<a href="/dir/docs">docs</a>"#));
+    assert!(body_str.contains("nav-file active"));
+    assert!(body_str.contains(r#"class="doc-pager__next" href="/view/docs/two.md""#));
+}
+
+#[tokio::test]
+async fn test_theme_cookie_and_custom_css_applied() {
+    let state = Arc::new(AppState::Directory {
+        dir_path: "/test".to_string(),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(PathBuf::from("/test"))),
+            Vec::<String>::new(),
+        )))),
+        language: Language::English,
+    });
+    let config = ServerConfig::default().with_theme_css("body { letter-spacing: 0.01em; }");
+
+    let response = create_router_with_config(state, config)
+        .oneshot(
+            Request::builder()
+                .uri("/")
+                .header("cookie", "rsmd-theme=dark")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body_str = String::from_utf8(body.to_vec()).unwrap();
+
+    assert!(body_str.contains(r#"<html lang="en" data-theme="dark">"#));
+    assert!(body_str.contains(r#"<option value="dark" selected>Dark</option>"#));
+    assert!(body_str.contains("<style>body { letter-spacing: 0.01em; }</style>"));
+}
+
+#[tokio::test]
+async fn test_missing_page_renders_localized_error_page() {
+    let state = Arc::new(AppState::Directory {
+        dir_path: "/test".to_string(),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(PathBuf::from("/test"))),
+            Vec::<String>::new(),
+        )))),
+        language: Language::Korean,
+    });
+
+    let response = create_router(state)
+        .oneshot(
+            Request::builder()
+                .uri("/view/missing.md")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(
+        response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html")
+    );
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body_str = String::from_utf8(body.to_vec()).unwrap();
+
+    assert!(body_str.contains(r#"<html lang="ko">"#));
+    assert!(body_str.contains(Language::Korean.text("error_not_found")));
+}
+
+#[tokio::test]
+async fn test_error_statuses_for_bad_path_and_unknown_route() {
+    let directory = Arc::new(AppState::Directory {
+        dir_path: "/test".to_string(),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(PathBuf::from("/test"))),
+            Vec::<String>::new(),
+        )))),
+        language: Language::English,
+    });
+    let response = create_router(directory)
+        .oneshot(
+            Request::builder()
+                .uri("/api/tree?path=../etc")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Directory routes do not exist in single file mode
+    let single = Arc::new(AppState::SingleFile {
+        markdown_content: "# Hi".to_string(),
+        html_content: "<h1>Hi</h1>".to_string(),
+        language: Language::English,
+        base_dir: PathBuf::from("/test"),
+    });
+    let response = create_router(single)
+        .oneshot(
+            Request::builder()
+                .uri("/api/tree")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let json: ErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json.code, "not_found");
+}
@@ -0,0 +1,81 @@
+use rsmd::links::{Move, rewrite};
+
+fn file_move(from: &str, to: &str) -> Move {
+    Move {
+        from: from.to_string(),
+        to: to.to_string(),
+        folder: false,
+    }
+}
+
+#[test]
+fn rewrites_relative_links_and_keeps_everything_else() {
+    let markdown = "# Index\n\nSee [setup](setup.md#install \"Setup\") and [other](other.md).\n\
+                    ```\n[setup](setup.md)\n```\n";
+    let moved = file_move("guides/setup.md", "ops/setup.md");
+
+    let (rewritten, links) = rewrite(markdown, "guides/index.md", &moved).unwrap();
+    assert_eq!(links, 1);
+    assert_eq!(
+        rewritten,
+        "# Index\n\nSee [setup](../ops/setup.md#install \"Setup\") and [other](other.md).\n\
+         ```\n[setup](setup.md)\n```\n"
+    );
+}
+
+#[test]
+fn rewrites_images_references_wiki_links_and_absolute_links() {
+    let markdown = "![diagram](<guides/img/flow chart.png>)\n\n\
+                    [ref]: guides/setup.md\n\n\
+                    [[guides/setup]] [[guides/setup.md|Setup]] [abs](/view/guides/setup.md)\n";
+    let moved = Move {
+        from: "guides".to_string(),
+        to: "manual".to_string(),
+        folder: true,
+    };
+
+    let (rewritten, links) = rewrite(markdown, "index.md", &moved).unwrap();
+    assert_eq!(links, 5);
+    assert_eq!(
+        rewritten,
+        "![diagram](<manual/img/flow chart.png>)\n\n\
+         [ref]: manual/setup.md\n\n\
+         [[manual/setup]] [[manual/setup.md|Setup]] [abs](/view/manual/setup.md)\n"
+    );
+}
+
+#[test]
+fn rewrites_links_out_of_a_moved_document() {
+    let markdown =
+        "[home](../index.md) [self](setup.md) [web](https://example.com/setup.md) [top](#top)";
+    let moved = file_move("guides/setup.md", "ops/deep/setup.md");
+
+    let (rewritten, links) = rewrite(markdown, "guides/setup.md", &moved).unwrap();
+    assert_eq!(links, 1);
+    assert_eq!(
+        rewritten,
+        "[home](../../index.md) [self](setup.md) [web](https://example.com/setup.md) [top](#top)"
+    );
+}
+
+#[test]
+fn links_within_a_moved_folder_stay() {
+    let markdown = "[sibling](b.md) [out](../top.md)";
+    let moved = Move {
+        from: "guides".to_string(),
+        to: "manual/guides".to_string(),
+        folder: true,
+    };
+
+    let (rewritten, links) = rewrite(markdown, "guides/a.md", &moved).unwrap();
+    assert_eq!(links, 1);
+    assert_eq!(rewritten, "[sibling](b.md) [out](../../top.md)");
+    assert!(rewrite("[sibling](b.md)", "guides/a.md", &moved).is_none());
+}
+
+#[test]
+fn percent_encoded_links_are_matched_and_encoded() {
+    let moved = file_move("my notes.md", "archive/my notes.md");
+    let (rewritten, _) = rewrite("[n](my%20notes.md)", "index.md", &moved).unwrap();
+    assert_eq!(rewritten, "[n](archive/my%20notes.md)");
+}
@@ -0,0 +1,524 @@
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode, header},
+};
+use rsmd::{
+    conditional,
+    directory::FileTree,
+    server::{AppState, create_router},
+    store::DocumentStore,
+    trash::TRASH_DIR,
+};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use tower::util::ServiceExt;
+
+mod common;
+
+const FILES: &[(&str, &str)] = &[
+    ("doc.md", "# Doc\n\nBody"),
+    ("template.md", "# {{title}}\n\n## Notes\n"),
+    ("guides/a.md", "# A"),
+    ("guides/deep/b.md", "# B"),
+];
+
+fn tree_state(temp_dir: &tempfile::TempDir) -> Arc<AppState> {
+    for (name, contents) in FILES {
+        let path = temp_dir.path().join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+    }
+    let files = FileTree::new(temp_dir.path().to_path_buf());
+    common::served(temp_dir.path(), DocumentStore::new(Arc::new(files)))
+}
+
+async fn send(app: &Router, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("x-requested-with", "XMLHttpRequest");
+    let request = match body {
+        Some(body) => request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string())),
+        None => request.body(Body::empty()),
+    };
+    let response = app.clone().oneshot(request.unwrap()).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+async fn view_status(app: &Router, name: &str) -> StatusCode {
+    send(app, "GET", &format!("/view/{}", name), None).await.0
+}
+
+async fn listed_files(app: &Router) -> Vec<String> {
+    let (_, body) = send(app, "GET", "/api/files", None).await;
+    serde_json::from_value(body["files"].clone()).unwrap()
+}
+
+#[tokio::test]
+async fn create_file_starts_from_a_heading() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(tree_state(&temp_dir));
+
+    let (status, body) = send(
+        &app,
+        "POST",
+        "/api/files",
+        Some(json!({ "path": "/notes//new.md" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body["path"], "notes/new.md");
+    let written = std::fs::read(temp_dir.path().join("notes/new.md")).unwrap();
+    assert_eq!(written, b"# new\n");
+    assert_eq!(body["etag"], conditional::etag(&written));
+    assert_eq!(view_status(&app, "notes/new.md").await, StatusCode::OK);
+    assert!(
+        listed_files(&app)
+            .await
+            .contains(&"notes/new.md".to_string())
+    );
+
+    let (status, body) = send(
+        &app,
+        "POST",
+        "/api/files",
+        Some(json!({ "path": "notes/new.md" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["code"], "conflict");
+}
+
+#[tokio::test]
+async fn create_file_from_template_document() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(tree_state(&temp_dir));
+
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/files",
+        Some(json!({ "path": "meeting.md", "template": "template.md", "title": "Standup" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("meeting.md")).unwrap(),
+        "# Standup\n\n## Notes\n"
+    );
+
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/files",
+        Some(json!({ "path": "other.md", "template": "missing.md" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(!temp_dir.path().join("other.md").exists());
+}
+
+#[tokio::test]
+async fn move_file_updates_tree_and_cache() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(tree_state(&temp_dir));
+    // Cache the document under its old name
+    assert_eq!(view_status(&app, "doc.md").await, StatusCode::OK);
+
+    let (status, body) = send(
+        &app,
+        "PATCH",
+        "/api/files/doc.md",
+        Some(json!({ "to": "archive/moved.md" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["path"], "archive/moved.md");
+    assert!(!temp_dir.path().join("doc.md").exists());
+    assert!(temp_dir.path().join("archive/moved.md").exists());
+
+    assert_eq!(view_status(&app, "doc.md").await, StatusCode::NOT_FOUND);
+    assert_eq!(view_status(&app, "archive/moved.md").await, StatusCode::OK);
+    let files = listed_files(&app).await;
+    assert!(files.contains(&"archive/moved.md".to_string()));
+    assert!(!files.contains(&"doc.md".to_string()));
+}
+
+#[tokio::test]
+async fn move_file_refuses_to_overwrite() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(tree_state(&temp_dir));
+
+    let (status, _) = send(
+        &app,
+        "PATCH",
+        "/api/files/doc.md",
+        Some(json!({ "to": "guides/a.md" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("guides/a.md")).unwrap(),
+        "# A"
+    );
+    assert_eq!(view_status(&app, "doc.md").await, StatusCode::OK);
+
+    let (status, _) = send(
+        &app,
+        "PATCH",
+        "/api/files/missing.md",
+        Some(json!({ "to": "found.md" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn delete_file_moves_it_to_the_trash_and_restores_it() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(tree_state(&temp_dir));
+    assert_eq!(view_status(&app, "guides/a.md").await, StatusCode::OK);
+
+    let (status, body) = send(&app, "DELETE", "/api/files/guides/a.md", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["trash"]["path"], "guides/a.md");
+    assert_eq!(body["trash"]["kind"], "file");
+    let id = body["trash"]["id"].as_str().unwrap().to_string();
+    assert!(!temp_dir.path().join("guides/a.md").exists());
+    assert!(temp_dir.path().join(TRASH_DIR).join(&id).exists());
+    assert_eq!(
+        view_status(&app, "guides/a.md").await,
+        StatusCode::NOT_FOUND
+    );
+    assert!(
+        !listed_files(&app)
+            .await
+            .contains(&"guides/a.md".to_string())
+    );
+
+    let (_, trash) = send(&app, "GET", "/api/trash", None).await;
+    assert_eq!(trash.as_array().unwrap().len(), 1);
+    assert_eq!(trash[0]["id"], id.as_str());
+
+    let (status, body) = send(&app, "POST", &format!("/api/trash/{}/restore", id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["path"], "guides/a.md");
+    assert_eq!(view_status(&app, "guides/a.md").await, StatusCode::OK);
+    let (_, trash) = send(&app, "GET", "/api/trash", None).await;
+    assert!(trash.as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn restore_refuses_requests_from_other_sites() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(tree_state(&temp_dir));
+    let (_, body) = send(&app, "DELETE", "/api/files/doc.md", None).await;
+    let id = body["trash"]["id"].as_str().unwrap().to_string();
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri(format!("/api/trash/{}/restore", id))
+                .header(header::ORIGIN, "https://evil.example")
+                .header(header::HOST, "localhost")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert!(!temp_dir.path().join("doc.md").exists());
+}
+
+#[tokio::test]
+async fn restore_refuses_to_overwrite_and_purge_deletes() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(tree_state(&temp_dir));
+
+    let (_, body) = send(&app, "DELETE", "/api/files/doc.md", None).await;
+    let id = body["trash"]["id"].as_str().unwrap().to_string();
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/files",
+        Some(json!({ "path": "doc.md" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, _) = send(&app, "POST", &format!("/api/trash/{}/restore", id), None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("doc.md")).unwrap(),
+        "# doc\n"
+    );
+
+    let (status, _) = send(&app, "DELETE", &format!("/api/trash/{}", id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!temp_dir.path().join(TRASH_DIR).join(&id).exists());
+    let (status, _) = send(&app, "DELETE", &format!("/api/trash/{}", id), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(&app, "POST", "/api/trash/..%2Fdoc.md/restore", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn folders_can_be_created_moved_deleted_and_restored() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(tree_state(&temp_dir));
+    assert_eq!(view_status(&app, "guides/deep/b.md").await, StatusCode::OK);
+
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/folders",
+        Some(json!({ "path": "empty" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert!(temp_dir.path().join("empty").is_dir());
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/folders",
+        Some(json!({ "path": "empty" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, body) = send(
+        &app,
+        "PATCH",
+        "/api/folders/guides",
+        Some(json!({ "to": "manual/guides" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["path"], "manual/guides");
+    assert_eq!(
+        view_status(&app, "guides/deep/b.md").await,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        view_status(&app, "manual/guides/deep/b.md").await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        send(&app, "GET", "/dir/guides", None).await.0,
+        StatusCode::NOT_FOUND
+    );
+
+    let (status, body) = send(&app, "DELETE", "/api/folders/manual", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["trash"]["kind"], "folder");
+    let id = body["trash"]["id"].as_str().unwrap().to_string();
+    assert!(!temp_dir.path().join("manual").exists());
+    assert_eq!(
+        view_status(&app, "manual/guides/a.md").await,
+        StatusCode::NOT_FOUND
+    );
+    assert!(
+        listed_files(&app)
+            .await
+            .iter()
+            .all(|file| !file.starts_with("manual/"))
+    );
+
+    let (status, _) = send(&app, "POST", &format!("/api/trash/{}/restore", id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        view_status(&app, "manual/guides/a.md").await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        view_status(&app, "manual/guides/deep/b.md").await,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn folder_cannot_move_into_itself() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(tree_state(&temp_dir));
+
+    let (status, _) = send(
+        &app,
+        "PATCH",
+        "/api/folders/guides",
+        Some(json!({ "to": "guides/deep/guides" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(
+        &app,
+        "PATCH",
+        "/api/folders/missing",
+        Some(json!({ "to": "found" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(temp_dir.path().join("guides/a.md").exists());
+}
+
+#[tokio::test]
+async fn management_rejects_bad_paths() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(tree_state(&temp_dir));
+
+    for path in [
+        "../escape.md",
+        ".rsmd-trash/x.md",
+        ".git/hooks/x.md",
+        "notes.txt",
+        "/",
+        "a/./b.md",
+    ] {
+        let (status, body) = send(&app, "POST", "/api/files", Some(json!({ "path": path }))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", path);
+        assert_eq!(body["code"], "bad_path");
+    }
+    let (status, _) = send(
+        &app,
+        "PATCH",
+        "/api/files/doc.md",
+        Some(json!({ "to": "../doc.md" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&app, "DELETE", "/api/folders/.rsmd-trash", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    std::fs::create_dir(temp_dir.path().join(".git")).unwrap();
+    let (status, _) = send(&app, "DELETE", "/api/folders/.git", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(temp_dir.path().join(".git").is_dir());
+    let (status, _) = send(
+        &app,
+        "PATCH",
+        "/api/files/doc.md",
+        Some(json!({ "to": ".git/hooks/doc.md" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&app, "POST", "/api/folders", Some(json!({ "path": ".." }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(temp_dir.path().join("doc.md").exists());
+}
+
+#[tokio::test]
+async fn move_dry_run_reports_link_rewrites_without_changes() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(tree_state(&temp_dir));
+    let index = "# Index\n\nRead [A](guides/a.md) first.\n";
+    std::fs::write(temp_dir.path().join("index.md"), index).unwrap();
+
+    let (status, body) = send(
+        &app,
+        "PATCH",
+        "/api/files/guides/a.md",
+        Some(json!({ "to": "ops/a.md", "dry_run": true })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["dry_run"], true);
+    assert_eq!(body["links"][0]["file"], "index.md");
+    assert_eq!(body["links"][0]["links"], 1);
+    assert_eq!(
+        body["links"][0]["diff"],
+        "--- a/index.md\n+++ b/index.md\n@@ -1,3 +1,3 @@\n # Index\n \n\
+         -Read [A](guides/a.md) first.\n+Read [A](ops/a.md) first.\n"
+    );
+    assert!(temp_dir.path().join("guides/a.md").exists());
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("index.md")).unwrap(),
+        index
+    );
+}
+
+#[tokio::test]
+async fn moves_see_links_added_since_the_last_move() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(tree_state(&temp_dir));
+    std::fs::write(temp_dir.path().join("index.md"), "# Index\n").unwrap();
+
+    let (status, body) = send(
+        &app,
+        "PATCH",
+        "/api/files/guides/a.md",
+        Some(json!({ "to": "ops/a.md" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.get("links").is_none());
+
+    std::fs::write(
+        temp_dir.path().join("index.md"),
+        "# Index\n\nRead [A](ops/a.md).\n",
+    )
+    .unwrap();
+    let (status, body) = send(
+        &app,
+        "PATCH",
+        "/api/files/ops/a.md",
+        Some(json!({ "to": "guides/a.md" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["links"][0]["file"], "index.md");
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("index.md")).unwrap(),
+        "# Index\n\nRead [A](guides/a.md).\n"
+    );
+}
+
+#[tokio::test]
+async fn moves_rewrite_links_pointing_at_them() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(tree_state(&temp_dir));
+    std::fs::write(
+        temp_dir.path().join("index.md"),
+        "[A](guides/a.md) [B](guides/deep/b.md) [[doc]]\n",
+    )
+    .unwrap();
+    std::fs::write(temp_dir.path().join("guides/deep/b.md"), "[up](../a.md)").unwrap();
+
+    let (status, body) = send(
+        &app,
+        "PATCH",
+        "/api/files/doc.md",
+        Some(json!({ "to": "notes/doc.md" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["links"].as_array().unwrap().len(), 1);
+
+    let (status, body) = send(
+        &app,
+        "PATCH",
+        "/api/folders/guides",
+        Some(json!({ "to": "manual" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["links"][0]["file"], "index.md");
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("index.md")).unwrap(),
+        "[A](manual/a.md) [B](manual/deep/b.md) [[notes/doc]]\n"
+    );
+    // Links between documents that moved together are left alone
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("manual/deep/b.md")).unwrap(),
+        "[up](../a.md)"
+    );
+}
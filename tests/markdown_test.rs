@@ -55,3 +55,13 @@ fn test_from_file_missing_returns_error() {
     let result = MarkdownParser::from_file(path.to_str().expect("path utf8"));
     assert!(result.is_err());
 }
+
+#[test]
+fn test_title_uses_first_h1() {
+    let parser =
+        MarkdownParser::new("intro\n\n## Sub\n\n# The `rsmd` *guide*\n\n# Second".to_string());
+    assert_eq!(parser.title().as_deref(), Some("The rsmd guide"));
+
+    let untitled = MarkdownParser::new("## Only a subheading".to_string());
+    assert_eq!(untitled.title(), None);
+}
@@ -1,8 +1,9 @@
-use rsmd::{i18n::Language, server::AppState, server::ServerConfig};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use rsmd::{
+    directory::FileTree, i18n::Language, server::AppState, server::ServerConfig, source::FsSource,
+    store::DocumentStore,
+};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
 #[test]
 fn test_server_config_default() {
@@ -48,23 +49,23 @@ fn test_app_state_single_file_creation() {
 async fn test_app_state_directory_creation() {
     let state = AppState::Directory {
         dir_path: "/test".to_string(),
-        files: Arc::new(RwLock::new(vec![])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
+        store: Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+            Arc::new(FsSource::new(PathBuf::from("/test"))),
+            Vec::<String>::new(),
+        )))),
         language: Language::Korean,
-        base_dir: PathBuf::from("/test"),
     };
     match state {
         AppState::Directory {
             dir_path,
-            files,
+            store,
             language,
-            base_dir,
             ..
         } => {
             assert_eq!(dir_path, "/test");
-            assert_eq!(files.read().await.len(), 0);
+            assert_eq!(store.files().files().await.len(), 0);
             assert_eq!(language, Language::Korean);
-            assert_eq!(base_dir, PathBuf::from("/test"));
+            assert_eq!(store.local_dir(), Some(Path::new("/test")));
         }
         _ => panic!("Expected Directory state"),
     }
@@ -0,0 +1,398 @@
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode, header},
+};
+use rsmd::{
+    directory::FileTree,
+    i18n::Language,
+    server::{AppState, create_router},
+    source::{Change, DocumentSource, Entries, FsSource, MemorySource, TarSource, ZipSource},
+    store::DocumentStore,
+};
+use serde_json::Value;
+use std::io::{Cursor, Write};
+use std::sync::Arc;
+use tower::util::ServiceExt;
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+
+fn guide() -> MemorySource {
+    MemorySource::new()
+        .with_file("index.md", "# Index\n")
+        .with_file("guide/setup.md", "# Setup\n\nInstall it.\n")
+        .with_file("guide/img/logo.png", b"\x89PNG\r\n\x1a\n".to_vec())
+        .with_file("notes.txt", "Plain notes\n")
+}
+
+fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, contents) in files {
+        writer
+            .start_file(*name, SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(contents).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+/// The routes over `source`, once its tree has been scanned
+async fn app(source: Arc<dyn DocumentSource>) -> Router {
+    let files = Arc::new(FileTree::with_source(source));
+    files.scan().await;
+    create_router(Arc::new(AppState::Directory {
+        dir_path: "docs".to_string(),
+        store: Arc::new(DocumentStore::new(files)),
+        language: Language::English,
+    }))
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, String) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, String::from_utf8_lossy(&body).to_string())
+}
+
+async fn get(app: &Router, uri: &str) -> (StatusCode, String) {
+    send(app, Request::get(uri).body(Body::empty()).unwrap()).await
+}
+
+async fn get_json(app: &Router, uri: &str) -> Value {
+    let (status, body) = get(app, uri).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    serde_json::from_str(&body).unwrap()
+}
+
+async fn save(app: &Router, name: &str, markdown: &str, precondition: (&str, &str)) -> StatusCode {
+    let request = Request::put(format!("/api/markdown/{}", name))
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(precondition.0, precondition.1)
+        .body(Body::from(
+            serde_json::json!({ "markdown": markdown }).to_string(),
+        ))
+        .unwrap();
+    send(app, request).await.0
+}
+
+#[test]
+fn test_memory_source_lists_direct_children() {
+    let source = guide();
+    assert_eq!(
+        source.list("").unwrap(),
+        Entries {
+            dirs: vec!["guide".to_string()],
+            files: vec!["index.md".to_string(), "notes.txt".to_string()],
+        }
+    );
+    assert_eq!(
+        source.list("guide/").unwrap(),
+        Entries {
+            dirs: vec!["img".to_string()],
+            files: vec!["setup.md".to_string()],
+        }
+    );
+    assert_eq!(
+        source.list("missing").unwrap_err().kind(),
+        std::io::ErrorKind::NotFound
+    );
+    assert_eq!(source.stat("index.md").unwrap().size, 8);
+    assert!(source.read("../index.md").is_err());
+}
+
+#[test]
+fn test_memory_source_reports_changes() {
+    let source = guide();
+    let mut changes = source.watch().unwrap();
+    source.write("new.md", b"# New\n").unwrap();
+    assert!(source.remove("index.md"));
+    assert!(!source.remove("index.md"));
+
+    assert_eq!(
+        changes.try_recv().unwrap(),
+        Change::Written("new.md".to_string())
+    );
+    assert_eq!(
+        changes.try_recv().unwrap(),
+        Change::Removed("index.md".to_string())
+    );
+    assert!(changes.try_recv().is_err());
+}
+
+#[test]
+fn test_zip_source_is_read_only() {
+    let bytes = archive(&[
+        ("docs/readme.md", b"# Readme\n"),
+        ("docs/deep/page.md", b"# Page\n"),
+        ("../escape.md", b"# Escape\n"),
+    ]);
+    let source = ZipSource::new(Cursor::new(bytes)).unwrap();
+
+    assert_eq!(
+        source.list("").unwrap(),
+        Entries {
+            dirs: vec!["docs".to_string()],
+            files: Vec::new(),
+        }
+    );
+    assert_eq!(source.read("docs/deep/page.md").unwrap(), b"# Page\n");
+    assert!(source.stat("docs/readme.md").unwrap().modified.is_some());
+    assert!(source.read("escape.md").is_err());
+    assert!(!source.writable());
+    assert_eq!(
+        source.write("docs/new.md", b"# New\n").unwrap_err().kind(),
+        std::io::ErrorKind::PermissionDenied
+    );
+}
+
+fn tarball(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (name, contents) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(1_700_000_000);
+        // Written raw, so unsafe names make it into the archive
+        let field = &mut header.as_old_mut().name;
+        field[..name.len()].copy_from_slice(name.as_bytes());
+        header.set_cksum();
+        builder.append(&header, *contents).unwrap();
+    }
+    builder.into_inner().unwrap()
+}
+
+#[test]
+fn test_tar_source_is_read_only() {
+    let bytes = tarball(&[
+        ("docs/readme.md", b"# Readme\n"),
+        ("docs/deep/page.md", b"# Old\n"),
+        ("docs/deep/page.md", b"# Page\n"),
+        ("../escape.md", b"# Escape\n"),
+        ("/etc/rooted.md", b"# Rooted\n"),
+    ]);
+    let source = TarSource::new(Cursor::new(bytes)).unwrap();
+
+    assert_eq!(
+        source.list("").unwrap(),
+        Entries {
+            dirs: vec!["docs".to_string()],
+            files: Vec::new(),
+        }
+    );
+    assert_eq!(source.read("docs/deep/page.md").unwrap(), b"# Page\n");
+    assert_eq!(source.read("docs/readme.md").unwrap(), b"# Readme\n");
+    assert_eq!(
+        source.stat("docs/readme.md").unwrap().modified,
+        Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000))
+    );
+    assert!(source.read("escape.md").is_err());
+    assert!(source.read("etc/rooted.md").is_err());
+    assert_eq!(
+        source.write("docs/new.md", b"# New\n").unwrap_err().kind(),
+        std::io::ErrorKind::PermissionDenied
+    );
+}
+
+#[test]
+fn test_fs_source_writes_in_place() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let source = FsSource::new(temp_dir.path().to_path_buf());
+    source.write("sub/page.md", b"# Page\n").unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("sub/page.md")).unwrap(),
+        "# Page\n"
+    );
+    assert_eq!(source.list("sub").unwrap().files, vec!["page.md"]);
+    assert_eq!(source.local_path(), Some(temp_dir.path()));
+    assert!(source.write("../outside.md", b"").is_err());
+}
+
+#[tokio::test]
+async fn test_routes_serve_a_memory_source() {
+    let app = app(Arc::new(guide())).await;
+
+    let (status, body) = get(&app, "/view/guide/setup.md").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Install it."));
+
+    let files = get_json(&app, "/api/files").await;
+    assert_eq!(
+        files["files"],
+        serde_json::json!(["guide/setup.md", "index.md"])
+    );
+    let tree = get_json(&app, "/api/tree?depth=2").await;
+    assert_eq!(tree["folders"][0]["files"][0]["size"], 21);
+
+    let (status, body) = get(&app, "/dir/guide").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("setup.md"));
+
+    let (status, body) = get(&app, "/static/notes.txt").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "Plain notes\n");
+    let (status, _) = get(&app, "/view/guide/img/logo.png").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = get(&app, "/static/missing.txt").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_memory_source_is_saved_through_the_api() {
+    let source = Arc::new(guide());
+    let app = app(source.clone()).await;
+
+    assert_eq!(
+        save(
+            &app,
+            "index.md",
+            "# Index\n\nEdited\n",
+            ("if-match", "\"nope\"")
+        )
+        .await,
+        StatusCode::PRECONDITION_FAILED
+    );
+    let etag = rsmd::conditional::etag(b"# Index\n");
+    assert_eq!(
+        save(&app, "index.md", "# Index\n\nEdited\n", ("if-match", &etag)).await,
+        StatusCode::OK
+    );
+    assert_eq!(source.read("index.md").unwrap(), b"# Index\n\nEdited\n");
+    let (_, body) = get(&app, "/view/index.md").await;
+    assert!(body.contains("Edited"));
+
+    assert_eq!(
+        save(&app, "fresh.md", "# Fresh\n", ("if-none-match", "*")).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        save(&app, "fresh.md", "# Again\n", ("if-none-match", "*")).await,
+        StatusCode::PRECONDITION_FAILED
+    );
+    let files = get_json(&app, "/api/files").await;
+    assert_eq!(
+        files["files"],
+        serde_json::json!(["fresh.md", "guide/setup.md", "index.md"])
+    );
+
+    // Pages offer no editing, and writes that need a directory are not routed
+    let (_, body) = get(&app, "/view/index.md").await;
+    assert!(!body.contains("/edit/index.md"));
+    let (status, _) = get(&app, "/edit/index.md").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+/// A directory on disk that does not say where it is, like a remote store
+#[derive(Debug)]
+struct Unmounted(FsSource);
+
+impl DocumentSource for Unmounted {
+    fn list(&self, dir: &str) -> std::io::Result<Entries> {
+        self.0.list(dir)
+    }
+
+    fn read(&self, name: &str) -> std::io::Result<Vec<u8>> {
+        self.0.read(name)
+    }
+
+    fn stat(&self, name: &str) -> std::io::Result<rsmd::source::Stat> {
+        self.0.stat(name)
+    }
+
+    fn write(&self, name: &str, contents: &[u8]) -> std::io::Result<()> {
+        self.0.write(name, contents)
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn watch(&self) -> Option<tokio::sync::broadcast::Receiver<Change>> {
+        self.0.watch()
+    }
+}
+
+#[tokio::test]
+async fn test_writable_source_without_a_directory_is_saved_through_the_api() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(temp_dir.path().join("index.md"), "# Index\n").unwrap();
+    let source = Arc::new(Unmounted(FsSource::new(temp_dir.path().to_path_buf())));
+    let app = app(source).await;
+
+    let etag = rsmd::conditional::etag(b"# Index\n");
+    assert_eq!(
+        save(&app, "index.md", "# Index\n\nEdited\n", ("if-match", &etag)).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        save(&app, "sub/new.md", "# New\n", ("if-none-match", "*")).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("index.md")).unwrap(),
+        "# Index\n\nEdited\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("sub/new.md")).unwrap(),
+        "# New\n"
+    );
+    let (_, body) = get(&app, "/view/sub/new.md").await;
+    assert!(body.contains("New"));
+}
+
+#[tokio::test]
+async fn test_tree_follows_changes_to_the_source() {
+    let source = Arc::new(guide());
+    let app = app(source.clone()).await;
+    let (status, _) = get(&app, "/view/index.md").await;
+    assert_eq!(status, StatusCode::OK);
+
+    source.write("index.md", b"# Index\n\nRewritten\n").unwrap();
+    source.write("guide/later.md", b"# Later\n").unwrap();
+    assert!(source.remove("guide/setup.md"));
+
+    let (_, body) = get(&app, "/view/index.md").await;
+    assert!(body.contains("Rewritten"));
+    let files = get_json(&app, "/api/files").await;
+    assert_eq!(
+        files["files"],
+        serde_json::json!(["guide/later.md", "index.md"])
+    );
+    let (status, _) = get(&app, "/view/guide/setup.md").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_routes_serve_a_zip_archive_read_only() {
+    let bytes = archive(&[
+        ("readme.md", b"# Readme\n\nFrom the archive\n"),
+        ("sub/page.md", b"# Page\n"),
+        ("sub/data.csv", b"a,b\n"),
+    ]);
+    let app = app(Arc::new(ZipSource::new(Cursor::new(bytes)).unwrap())).await;
+
+    let (status, body) = get(&app, "/view/readme.md").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("From the archive"));
+    let markdown = get_json(&app, "/api/markdown/sub/page.md").await;
+    assert_eq!(markdown["markdown"], "# Page\n");
+    let (status, body) = get(&app, "/static/sub/data.csv").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "a,b\n");
+
+    assert_eq!(
+        save(&app, "readme.md", "# Changed\n", ("if-match", "*")).await,
+        StatusCode::METHOD_NOT_ALLOWED
+    );
+    let (status, _) = send(
+        &app,
+        Request::post("/api/upload")
+            .header("x-file-name", "new.md")
+            .body(Body::from("# New\n"))
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
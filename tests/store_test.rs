@@ -0,0 +1,185 @@
+use rsmd::{
+    directory::FileTree, error::AppError, markdown::Heading, source::FsSource, store::DocumentStore,
+};
+use std::sync::Arc;
+
+fn store_with(temp_dir: &tempfile::TempDir, name: &str, contents: &str) -> Arc<DocumentStore> {
+    std::fs::write(temp_dir.path().join(name), contents).unwrap();
+    Arc::new(DocumentStore::new(Arc::new(FileTree::with_files(
+        Arc::new(FsSource::new(temp_dir.path().to_path_buf())),
+        [name],
+    ))))
+}
+
+#[tokio::test]
+async fn test_get_renders_document_with_outline_and_metadata() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let store = store_with(
+        &temp_dir,
+        "guide.md",
+        "# Guide\n\n## Install {#install}\n\nText",
+    );
+
+    let document = store.get("guide.md").await.unwrap();
+
+    assert_eq!(document.name, "guide.md");
+    assert!(document.markdown.starts_with("# Guide"));
+    assert!(document.html.contains("<h1>Guide</h1>"));
+    assert_eq!(document.metadata.size, document.markdown.len() as u64);
+    assert_eq!(document.title(), Some("Guide"));
+    assert_eq!(
+        document.outline,
+        vec![
+            Heading {
+                level: 1,
+                text: "Guide".to_string(),
+                id: None,
+            },
+            Heading {
+                level: 2,
+                text: "Install".to_string(),
+                id: Some("install".to_string()),
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_get_reports_missing_and_unreadable_files() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let store = store_with(&temp_dir, "gone.md", "# Gone");
+    std::fs::remove_file(temp_dir.path().join("gone.md")).unwrap();
+
+    assert_eq!(store.get("other.md").await.unwrap_err(), AppError::NotFound);
+    assert_eq!(
+        store.get("gone.md").await.unwrap_err(),
+        AppError::ReadFailure
+    );
+
+    // Failures are not cached
+    std::fs::write(temp_dir.path().join("gone.md"), "# Back").unwrap();
+    assert_eq!(store.get("gone.md").await.unwrap().markdown, "# Back");
+}
+
+#[tokio::test]
+async fn test_concurrent_misses_share_one_render() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let store = store_with(&temp_dir, "shared.md", "# Shared");
+
+    let mut tasks = tokio::task::JoinSet::new();
+    for _ in 0..16 {
+        let store = store.clone();
+        tasks.spawn(async move { store.get("shared.md").await.unwrap() });
+    }
+    let documents = tasks.join_all().await;
+
+    for document in &documents {
+        assert!(Arc::ptr_eq(&documents[0], document));
+    }
+}
+
+#[tokio::test]
+async fn test_invalidate_drops_cached_render() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let store = store_with(&temp_dir, "notes.md", "# Old");
+    store.get("notes.md").await.unwrap();
+    assert_eq!(store.stats().await.entries, 1);
+
+    store.invalidate("notes.md").await;
+    assert_eq!(store.stats().await.entries, 0);
+    assert_eq!(store.stats().await.bytes, 0);
+}
+
+#[tokio::test]
+async fn test_edited_file_is_rendered_again() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let store = store_with(&temp_dir, "notes.md", "# Old");
+    assert_eq!(store.get("notes.md").await.unwrap().markdown, "# Old");
+    assert_eq!(store.get("notes.md").await.unwrap().markdown, "# Old");
+
+    std::fs::write(temp_dir.path().join("notes.md"), "# New and longer").unwrap();
+    assert_eq!(
+        store.get("notes.md").await.unwrap().markdown,
+        "# New and longer"
+    );
+
+    let stats = store.stats().await;
+    assert_eq!(stats.hits, 1);
+    assert_eq!(stats.misses, 2);
+    assert_eq!(stats.stale, 1);
+    assert_eq!(stats.entries, 1);
+}
+
+#[tokio::test]
+async fn test_cache_evicts_least_recently_used_within_budget() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let names = ["a.md", "b.md", "c.md"];
+    for name in names {
+        std::fs::write(temp_dir.path().join(name), "x".repeat(100)).unwrap();
+    }
+    let files = FileTree::with_files(
+        Arc::new(FsSource::new(temp_dir.path().to_path_buf())),
+        names,
+    );
+    // Room for two documents of ~100 bytes markdown plus ~108 bytes html each
+    let store = DocumentStore::new(Arc::new(files)).with_cache_limit(500);
+
+    store.get("a.md").await.unwrap();
+    store.get("b.md").await.unwrap();
+    store.get("a.md").await.unwrap();
+    store.get("c.md").await.unwrap();
+
+    let stats = store.stats().await;
+    assert_eq!(stats.entries, 2);
+    assert_eq!(stats.evictions, 1);
+    assert!(stats.bytes <= stats.capacity);
+
+    // `b.md` was least recently used, so it is the one rendered again
+    store.get("a.md").await.unwrap();
+    assert_eq!(store.stats().await.hits, 2);
+    store.get("b.md").await.unwrap();
+    assert_eq!(store.stats().await.misses, 4);
+}
+
+#[tokio::test]
+async fn test_documents_larger_than_budget_are_not_cached() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(temp_dir.path().join("big.md"), "x".repeat(1000)).unwrap();
+    let store = DocumentStore::new(Arc::new(FileTree::with_files(
+        Arc::new(FsSource::new(temp_dir.path().to_path_buf())),
+        ["big.md"],
+    )))
+    .with_cache_limit(100);
+
+    assert_eq!(store.get("big.md").await.unwrap().markdown.len(), 1000);
+    assert_eq!(store.stats().await.entries, 0);
+}
+
+#[tokio::test]
+async fn test_warm_renders_recent_files() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(temp_dir.path().join("one.md"), "# One").unwrap();
+    std::fs::write(temp_dir.path().join("two.md"), "# Two").unwrap();
+    let older = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+    std::fs::File::options()
+        .write(true)
+        .open(temp_dir.path().join("two.md"))
+        .unwrap()
+        .set_modified(older)
+        .unwrap();
+    let files = Arc::new(FileTree::new(temp_dir.path().to_path_buf()));
+    files.scan().await;
+    let recent: Vec<String> = files.recent(5).await.into_iter().map(|f| f.name).collect();
+    assert_eq!(recent, vec!["one.md", "two.md"]);
+    let store = DocumentStore::new(files);
+
+    store.warm(1).await;
+    let stats = store.stats().await;
+    assert_eq!(stats.entries, 1);
+    assert_eq!(stats.misses, 1);
+    store.get("one.md").await.unwrap();
+    assert_eq!(store.stats().await.hits, 1);
+
+    store.warm(10).await;
+    assert_eq!(store.stats().await.entries, 2);
+}
@@ -0,0 +1,82 @@
+use rsmd::directory::{FileMetadata, FileTree};
+use rsmd::html::{Document, PageOptions, render_document_page, render_page};
+use rsmd::i18n::Language;
+use rsmd::source::MemorySource;
+use rsmd::templates::{BUILTIN_TEMPLATES, Templates};
+use std::sync::Arc;
+
+fn options_with(templates: Templates) -> PageOptions {
+    PageOptions {
+        templates: Arc::new(templates),
+        ..PageOptions::default()
+    }
+}
+
+#[test]
+fn test_builtin_templates_are_embedded() {
+    let names: Vec<&str> = BUILTIN_TEMPLATES.iter().map(|(name, _)| *name).collect();
+    for name in ["base.html", "document.html", "raw.html", "directory.html"] {
+        assert!(names.contains(&name), "missing {}", name);
+    }
+}
+
+#[test]
+fn test_override_replaces_single_template() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("partials")).unwrap();
+    std::fs::write(
+        dir.path().join("partials/theme-switcher.html"),
+        r#"<img class="logo" src="/static/logo.png">"#,
+    )
+    .unwrap();
+
+    let templates = Templates::with_overrides(dir.path()).unwrap();
+    let result = render_page("<h1>Hi</h1>", &Language::English, &options_with(templates));
+
+    assert!(result.contains(r#"<img class="logo" src="/static/logo.png">"#));
+    assert!(!result.contains("theme-select"));
+    assert!(result.contains("<h1>Hi</h1>"));
+}
+
+#[tokio::test]
+async fn test_context_exposes_document_metadata_navigation_and_i18n() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("document.html"),
+        "{{ document.name }}|{{ document.metadata.size }}|{{ nav.next }}|\
+         {{ nav.breadcrumbs|length }}|{{ i18n.nav_next }}|{{ document.html|safe }}",
+    )
+    .unwrap();
+
+    let tree = FileTree::with_files(Arc::new(MemorySource::new()), ["docs/a.md", "docs/b.md"]);
+    let nav = tree.navigation("docs/a.md").await.unwrap();
+    let document = Document {
+        name: "docs/a.md",
+        html: "<p>A</p>",
+        metadata: Some(FileMetadata {
+            name: "docs/a.md".to_string(),
+            size: 42,
+            modified: None,
+        }),
+    };
+
+    let templates = Templates::with_overrides(dir.path()).unwrap();
+    let result = render_document_page(&document, &nav, &Language::Korean, &options_with(templates));
+
+    assert_eq!(result, "docs/a.md|42|docs/b.md|3|다음|<p>A</p>");
+}
+
+#[test]
+fn test_override_syntax_error_is_reported_at_load() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("raw.html"), "{% block body %}").unwrap();
+
+    let err = Templates::with_overrides(dir.path()).unwrap_err();
+    assert!(err.to_string().contains("raw.html"));
+}
+
+#[test]
+fn test_override_requires_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(Templates::with_overrides(&dir.path().join("missing")).is_err());
+}
@@ -0,0 +1,23 @@
+use rsmd::theme::{THEME_COOKIE, Theme};
+
+#[test]
+fn test_theme_ids_round_trip() {
+    for theme in Theme::ALL {
+        assert_eq!(Theme::from_id(theme.id()), Some(theme));
+    }
+    assert_eq!(Theme::from_id("solarized"), None);
+}
+
+#[test]
+fn test_theme_from_cookie_header() {
+    assert_eq!(
+        Theme::from_cookie_header(&format!("session=abc; {}=dark", THEME_COOKIE)),
+        Some(Theme::Dark)
+    );
+    assert_eq!(
+        Theme::from_cookie_header("rsmd-theme=sepia"),
+        Some(Theme::Sepia)
+    );
+    assert_eq!(Theme::from_cookie_header("rsmd-theme=unknown"), None);
+    assert_eq!(Theme::from_cookie_header("other-theme=dark"), None);
+}
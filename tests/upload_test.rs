@@ -3,31 +3,25 @@ use axum::{
     http::{Request, StatusCode},
 };
 use rsmd::{
-    directory::MarkdownFile,
+    directory::FileTree,
     i18n::Language,
     server::{AppState, create_router},
+    source::FsSource,
+    store::DocumentStore,
+    upload,
 };
 use serde_json::Value;
-use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::{Duration, SystemTime};
 use tower::util::ServiceExt;
 
-fn directory_state(temp_dir: &tempfile::TempDir) -> Arc<AppState> {
-    Arc::new(AppState::Directory {
-        dir_path: temp_dir.path().display().to_string(),
-        files: Arc::new(RwLock::new(vec![])),
-        file_cache: Arc::new(RwLock::new(HashMap::new())),
-        language: Language::English,
-        base_dir: temp_dir.path().to_path_buf(),
-    })
-}
+mod common;
 
 #[tokio::test]
 async fn upload_requires_file_name_header() {
     let temp_dir = tempfile::tempdir().unwrap();
-    let state = directory_state(&temp_dir);
+    let state = common::directory_state(temp_dir.path(), &[]);
     let app = create_router(state);
 
     let response = app
@@ -60,7 +54,7 @@ async fn upload_requires_file_name_header() {
 #[tokio::test]
 async fn upload_rejects_empty_file_name() {
     let temp_dir = tempfile::tempdir().unwrap();
-    let state = directory_state(&temp_dir);
+    let state = common::directory_state(temp_dir.path(), &[]);
     let app = create_router(state);
 
     let response = app
@@ -94,7 +88,7 @@ async fn upload_rejects_empty_file_name() {
 #[tokio::test]
 async fn upload_rejects_invalid_extension() {
     let temp_dir = tempfile::tempdir().unwrap();
-    let state = directory_state(&temp_dir);
+    let state = common::directory_state(temp_dir.path(), &[]);
     let app = create_router(state);
 
     let response = app
@@ -128,7 +122,7 @@ async fn upload_rejects_invalid_extension() {
 #[tokio::test]
 async fn upload_rejects_empty_body() {
     let temp_dir = tempfile::tempdir().unwrap();
-    let state = directory_state(&temp_dir);
+    let state = common::directory_state(temp_dir.path(), &[]);
     let app = create_router(state);
 
     let response = app
@@ -164,23 +158,23 @@ async fn upload_succeeds_and_updates_state() {
     let temp_dir = tempfile::tempdir().unwrap();
     let base_dir = temp_dir.path().to_path_buf();
 
-    let files = Arc::new(RwLock::new(vec![MarkdownFile {
-        name: "existing.md".to_string(),
-        path: base_dir.join("existing.md"),
-    }]));
+    std::fs::create_dir_all(base_dir.join("guides")).unwrap();
+    std::fs::write(base_dir.join("guides/notes.md"), "# old").unwrap();
 
-    let mut cache_map = HashMap::new();
-    cache_map.insert(
-        "guides/notes.md".to_string(),
-        ("# old".to_string(), "<p>old</p>".to_string()),
+    let files = Arc::new(FileTree::with_files(
+        Arc::new(FsSource::new(base_dir.clone())),
+        ["existing.md", "guides/notes.md"],
+    ));
+    let store = Arc::new(DocumentStore::new(files.clone()));
+    assert_eq!(
+        store.get("guides/notes.md").await.unwrap().markdown,
+        "# old"
     );
 
     let state = Arc::new(AppState::Directory {
         dir_path: base_dir.display().to_string(),
-        files: files.clone(),
-        file_cache: Arc::new(RwLock::new(cache_map)),
+        store: store.clone(),
         language: Language::English,
-        base_dir: base_dir.clone(),
     });
 
     let app = create_router(state.clone());
@@ -192,6 +186,7 @@ async fn upload_succeeds_and_updates_state() {
                 .uri("/api/upload")
                 .header("x-file-name", "../notes.md")
                 .header("x-directory-path", "guides")
+                .header("x-conflict-policy", "overwrite")
                 .body(Body::from("# New content"))
                 .unwrap(),
         )
@@ -217,17 +212,12 @@ async fn upload_succeeds_and_updates_state() {
     let contents = tokio::fs::read_to_string(&expected_path).await.unwrap();
     assert!(contents.contains("New content"));
 
-    if let AppState::Directory { file_cache, .. } = state.as_ref() {
-        let cache_guard = file_cache.read().await;
-        assert!(!cache_guard.contains_key("guides/notes.md"));
-    } else {
-        panic!("expected directory state");
-    }
+    let cached = store.get("guides/notes.md").await.unwrap();
+    assert_eq!(cached.markdown, "# New content");
 
-    let guard = files.read().await;
-    let uploaded = guard
-        .iter()
-        .find(|item| item.name == "guides/notes.md")
+    let uploaded = files
+        .get("guides/notes.md")
+        .await
         .expect("uploaded file should be present");
     assert_eq!(uploaded.path, expected_path);
 }
@@ -261,7 +251,7 @@ async fn upload_rejected_in_single_file_mode() {
 #[tokio::test]
 async fn upload_rejects_invalid_directory_path() {
     let temp_dir = tempfile::tempdir().unwrap();
-    let state = directory_state(&temp_dir);
+    let state = common::directory_state(temp_dir.path(), &[]);
     let app = create_router(state);
 
     let response = app
@@ -283,9 +273,10 @@ async fn upload_rejects_invalid_directory_path() {
 #[tokio::test]
 async fn upload_with_nested_directory_path() {
     let temp_dir = tempfile::tempdir().unwrap();
-    let state = directory_state(&temp_dir);
+    let state = common::directory_state(temp_dir.path(), &[]);
 
-    if let AppState::Directory { files, .. } = state.as_ref() {
+    if let AppState::Directory { store, .. } = state.as_ref() {
+        let files = store.files();
         let app = create_router(state.clone());
 
         let response = app
@@ -303,8 +294,7 @@ async fn upload_with_nested_directory_path() {
 
         assert_eq!(response.status(), StatusCode::OK);
 
-        let guard = files.read().await;
-        let uploaded = guard.iter().find(|item| item.name == "docs/api/v2/deep.md");
+        let uploaded = files.get("docs/api/v2/deep.md").await;
         assert!(uploaded.is_some(), "File should be uploaded to nested path");
 
         let expected_path = temp_dir.path().join("docs/api/v2/deep.md");
@@ -320,9 +310,10 @@ async fn upload_with_nested_directory_path() {
 #[tokio::test]
 async fn upload_with_empty_directory_path() {
     let temp_dir = tempfile::tempdir().unwrap();
-    let state = directory_state(&temp_dir);
+    let state = common::directory_state(temp_dir.path(), &[]);
 
-    if let AppState::Directory { files, .. } = state.as_ref() {
+    if let AppState::Directory { store, .. } = state.as_ref() {
+        let files = store.files();
         let app = create_router(state.clone());
 
         let response = app
@@ -340,8 +331,7 @@ async fn upload_with_empty_directory_path() {
 
         assert_eq!(response.status(), StatusCode::OK);
 
-        let guard = files.read().await;
-        let uploaded = guard.iter().find(|item| item.name == "root.md");
+        let uploaded = files.get("root.md").await;
         assert!(uploaded.is_some(), "File should be uploaded to root");
     } else {
         panic!("expected directory state");
@@ -351,7 +341,7 @@ async fn upload_with_empty_directory_path() {
 #[tokio::test]
 async fn upload_rejects_directory_path_with_dots() {
     let temp_dir = tempfile::tempdir().unwrap();
-    let state = directory_state(&temp_dir);
+    let state = common::directory_state(temp_dir.path(), &[]);
     let app = create_router(state);
 
     // Test with single dot
@@ -388,12 +378,35 @@ async fn upload_rejects_directory_path_with_dots() {
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
 }
 
+#[tokio::test]
+async fn upload_allows_dots_inside_folder_names() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/upload")
+                .header("x-file-name", "notes.md")
+                .header("x-directory-path", "v1..2/notes...")
+                .body(Body::from("# Release notes"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(temp_dir.path().join("v1..2/notes.../notes.md").exists());
+}
+
 #[tokio::test]
 async fn upload_with_whitespace_in_directory_path() {
     let temp_dir = tempfile::tempdir().unwrap();
-    let state = directory_state(&temp_dir);
+    let state = common::directory_state(temp_dir.path(), &[]);
 
-    if let AppState::Directory { files, .. } = state.as_ref() {
+    if let AppState::Directory { store, .. } = state.as_ref() {
+        let files = store.files();
         let app = create_router(state.clone());
 
         let response = app
@@ -411,11 +424,1103 @@ async fn upload_with_whitespace_in_directory_path() {
 
         assert_eq!(response.status(), StatusCode::OK);
 
-        let guard = files.read().await;
         // Whitespace should be trimmed
-        let uploaded = guard.iter().find(|item| item.name == "docs/guides/doc.md");
+        let uploaded = files.get("docs/guides/doc.md").await;
         assert!(uploaded.is_some(), "Path should be normalized");
     } else {
         panic!("expected directory state");
     }
 }
+
+async fn json_body(response: axum::response::Response) -> Value {
+    serde_json::from_slice(
+        &axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap(),
+    )
+    .unwrap()
+}
+
+fn leftover_temp_files(dir: &std::path::Path) -> Vec<String> {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .filter(|name| name.ends_with(".tmp"))
+        .collect()
+}
+
+#[tokio::test]
+async fn upload_reports_sha256_of_stored_file() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/upload")
+                .header("x-file-name", "hash.md")
+                .body(Body::from("abc"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let payload = json_body(response).await;
+    assert_eq!(
+        payload["sha256"],
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert!(leftover_temp_files(temp_dir.path()).is_empty());
+}
+
+#[tokio::test]
+async fn upload_rejects_checksum_mismatch_without_writing() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/upload")
+                .header("x-file-name", "hash.md")
+                .header("x-content-sha256", "00")
+                .body(Body::from("abc"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(!temp_dir.path().join("hash.md").exists());
+    assert!(leftover_temp_files(temp_dir.path()).is_empty());
+}
+
+#[tokio::test]
+async fn upload_rejects_oversized_body_while_streaming() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let chunks = (0..=upload::MAX_UPLOAD_SIZE / (1024 * 1024))
+        .map(|_| Ok::<_, std::io::Error>(vec![b'#'; 1024 * 1024]));
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/upload")
+                .header("x-file-name", "big.md")
+                .body(Body::from_stream(futures_util::stream::iter(chunks)))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(
+        json_body(response).await["message"],
+        "The file is too large to upload."
+    );
+    assert!(!temp_dir.path().join("big.md").exists());
+    assert!(leftover_temp_files(temp_dir.path()).is_empty());
+}
+
+fn tus_request(method: &str, uri: &str) -> axum::http::request::Builder {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("tus-resumable", upload::TUS_VERSION)
+}
+
+fn patch(uri: &str, offset: u64, chunk: &'static str) -> Request<Body> {
+    tus_request("PATCH", uri)
+        .header("upload-offset", offset)
+        .header("content-type", "application/offset+octet-stream")
+        .body(Body::from(chunk))
+        .unwrap()
+}
+
+fn header<'a>(response: &'a axum::response::Response, name: &str) -> &'a str {
+    response.headers()[name].to_str().unwrap()
+}
+
+/// Create a resumable upload of `length` bytes and return its URL
+async fn create_upload(app: &axum::Router, length: u64, metadata: &str) -> String {
+    let response = app
+        .clone()
+        .oneshot(
+            tus_request("POST", upload::UPLOADS_PATH)
+                .header("upload-length", length)
+                .header("upload-metadata", metadata)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    header(&response, "location").to_string()
+}
+
+// "notes.md" and "guides" in base64
+const NOTES_METADATA: &str = "filename bm90ZXMubWQ=,directory Z3VpZGVz";
+
+#[tokio::test]
+async fn tus_options_advertises_protocol() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("OPTIONS")
+                .uri(upload::UPLOADS_PATH)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(header(&response, "tus-version"), "1.0.0");
+    assert!(header(&response, "tus-extension").contains("creation"));
+    assert!(header(&response, "tus-extension").contains("expiration"));
+    assert_eq!(
+        header(&response, "tus-max-size"),
+        upload::MAX_RESUMABLE_UPLOAD_SIZE.to_string()
+    );
+}
+
+#[tokio::test]
+async fn tus_upload_resumes_and_completes() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let state = common::directory_state(temp_dir.path(), &[]);
+    let app = create_router(state.clone());
+    let url = create_upload(&app, 13, NOTES_METADATA).await;
+
+    let response = app
+        .clone()
+        .oneshot(patch(&url, 0, "# Hello"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(header(&response, "upload-offset"), "7");
+
+    // A client that lost track of the offset is told where to continue
+    let response = app
+        .clone()
+        .oneshot(patch(&url, 0, "# Hello"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(header(&response, "upload-offset"), "7");
+
+    let response = app
+        .clone()
+        .oneshot(tus_request("HEAD", &url).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(header(&response, "upload-offset"), "7");
+    assert_eq!(header(&response, "upload-length"), "13");
+    assert!(!temp_dir.path().join("guides/notes.md").exists());
+
+    // HEAD without the protocol version is refused like every other tus request
+    let response = app
+        .clone()
+        .oneshot(Request::head(&url).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+    let response = app.clone().oneshot(patch(&url, 7, " world")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(header(&response, "upload-offset"), "13");
+
+    let contents = std::fs::read_to_string(temp_dir.path().join("guides/notes.md")).unwrap();
+    assert_eq!(contents, "# Hello world");
+    let AppState::Directory { store, .. } = state.as_ref() else {
+        panic!("expected directory state");
+    };
+    assert!(store.files().get("guides/notes.md").await.is_some());
+
+    // The finished upload is gone
+    let response = app
+        .oneshot(tus_request("HEAD", &url).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn tus_patch_rejects_bad_checksum_and_keeps_offset() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+    let url = create_upload(&app, 3, NOTES_METADATA).await;
+
+    let mut request = patch(&url, 0, "abc");
+    request.headers_mut().insert(
+        "upload-checksum",
+        "sha256 AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="
+            .parse()
+            .unwrap(),
+    );
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status().as_u16(), 460);
+
+    let response = app
+        .clone()
+        .oneshot(tus_request("HEAD", &url).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(header(&response, "upload-offset"), "0");
+
+    let mut request = patch(&url, 0, "abc");
+    request.headers_mut().insert(
+        "upload-checksum",
+        "sha256 ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0="
+            .parse()
+            .unwrap(),
+    );
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(temp_dir.path().join("guides/notes.md").exists());
+}
+
+#[tokio::test]
+async fn tus_create_validates_request() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let send = |request: Request<Body>| app.clone().oneshot(request);
+
+    let response = send(
+        Request::builder()
+            .method("POST")
+            .uri(upload::UPLOADS_PATH)
+            .header("upload-length", 3)
+            .header("upload-metadata", NOTES_METADATA)
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+    let response = send(
+        tus_request("POST", upload::UPLOADS_PATH)
+            .header("upload-length", upload::MAX_RESUMABLE_UPLOAD_SIZE + 1)
+            .header("upload-metadata", NOTES_METADATA)
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    // "notes.txt"
+    let response = send(
+        tus_request("POST", upload::UPLOADS_PATH)
+            .header("upload-length", 3)
+            .header("upload-metadata", "filename bm90ZXMudHh0")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn tus_delete_terminates_upload() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+    let url = create_upload(&app, 10, NOTES_METADATA).await;
+    app.clone().oneshot(patch(&url, 0, "# Part")).await.unwrap();
+
+    let response = app
+        .clone()
+        .oneshot(tus_request("DELETE", &url).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = app.oneshot(patch(&url, 6, "done")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let staged = std::fs::read_dir(temp_dir.path().join(upload::STAGING_DIR)).unwrap();
+    assert_eq!(staged.count(), 0);
+}
+
+#[tokio::test]
+async fn tus_uploads_expire_after_a_day_without_data() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+    let staging = temp_dir.path().join(upload::STAGING_DIR);
+    let fresh = create_upload(&app, 10, NOTES_METADATA).await;
+    let response = app
+        .clone()
+        .oneshot(patch(&fresh, 0, "# Part"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(httpdate::parse_http_date(header(&response, "upload-expires")).is_ok());
+
+    let stale = create_upload(&app, 10, NOTES_METADATA).await;
+    let id = stale.rsplit('/').next().unwrap();
+    let written = SystemTime::now() - upload::UPLOAD_EXPIRY - Duration::from_secs(60);
+    std::fs::File::options()
+        .write(true)
+        .open(staging.join(format!("{}.part", id)))
+        .unwrap()
+        .set_modified(written)
+        .unwrap();
+
+    let response = app
+        .clone()
+        .oneshot(tus_request("HEAD", &stale).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    assert_eq!(
+        upload::sweep_expired_uploads(temp_dir.path())
+            .await
+            .unwrap(),
+        1
+    );
+    let mut staged: Vec<String> = std::fs::read_dir(&staging)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    staged.sort();
+    let fresh_id = fresh.rsplit('/').next().unwrap();
+    assert_eq!(
+        staged,
+        vec![format!("{}.json", fresh_id), format!("{}.part", fresh_id)]
+    );
+    let response = app.oneshot(patch(&fresh, 6, "done")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+}
+
+fn upload_request(name: &str) -> axum::http::request::Builder {
+    Request::builder()
+        .method("POST")
+        .uri("/api/upload")
+        .header("x-file-name", name)
+}
+
+#[tokio::test]
+async fn upload_rejects_existing_file_by_default() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(temp_dir.path().join("notes.md"), "# Original").unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let response = app
+        .oneshot(
+            upload_request("notes.md")
+                .body(Body::from("# Replacement"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(
+        json_body(response).await["message"],
+        "A file with this name already exists."
+    );
+    let contents = std::fs::read_to_string(temp_dir.path().join("notes.md")).unwrap();
+    assert_eq!(contents, "# Original");
+    assert!(leftover_temp_files(temp_dir.path()).is_empty());
+}
+
+#[tokio::test]
+async fn upload_rename_policy_keeps_both_files() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(temp_dir.path().join("notes.md"), "# Original").unwrap();
+    std::fs::write(temp_dir.path().join("notes (1).md"), "# Copy").unwrap();
+    let state = common::directory_state(temp_dir.path(), &[]);
+    let app = create_router(state.clone());
+
+    let response = app
+        .oneshot(
+            upload_request("notes.md")
+                .header("x-conflict-policy", "rename")
+                .body(Body::from("# Second copy"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json_body(response).await["file"], "notes (2).md");
+    let contents = std::fs::read_to_string(temp_dir.path().join("notes (2).md")).unwrap();
+    assert_eq!(contents, "# Second copy");
+    let original = std::fs::read_to_string(temp_dir.path().join("notes.md")).unwrap();
+    assert_eq!(original, "# Original");
+
+    let AppState::Directory { store, .. } = state.as_ref() else {
+        panic!("expected directory state");
+    };
+    assert!(store.files().get("notes (2).md").await.is_some());
+}
+
+#[tokio::test]
+async fn upload_rejects_unknown_conflict_policy() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let response = app
+        .oneshot(
+            upload_request("notes.md")
+                .header("x-conflict-policy", "merge")
+                .body(Body::from("# Notes"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(!temp_dir.path().join("notes.md").exists());
+}
+
+#[tokio::test]
+async fn upload_if_match_protects_newer_version() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let response = app
+        .clone()
+        .oneshot(
+            upload_request("notes.md")
+                .body(Body::from("# First"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let first_tag = json_body(response).await["etag"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // Someone else edits the file on disk
+    std::fs::write(temp_dir.path().join("notes.md"), "# Edited elsewhere").unwrap();
+
+    let response = app
+        .clone()
+        .oneshot(
+            upload_request("notes.md")
+                .header("if-match", &first_tag)
+                .body(Body::from("# Stale update"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    let contents = std::fs::read_to_string(temp_dir.path().join("notes.md")).unwrap();
+    assert_eq!(contents, "# Edited elsewhere");
+
+    let current_tag = rsmd::conditional::etag(b"# Edited elsewhere");
+    let response = app
+        .oneshot(
+            upload_request("notes.md")
+                .header("if-match", &current_tag)
+                .body(Body::from("# Fresh update"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let contents = std::fs::read_to_string(temp_dir.path().join("notes.md")).unwrap();
+    assert_eq!(contents, "# Fresh update");
+}
+
+#[tokio::test]
+async fn tus_create_rejects_existing_target() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(temp_dir.path().join("guides")).unwrap();
+    std::fs::write(temp_dir.path().join("guides/notes.md"), "# Original").unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let response = app
+        .clone()
+        .oneshot(
+            tus_request("POST", upload::UPLOADS_PATH)
+                .header("upload-length", 3)
+                .header("upload-metadata", NOTES_METADATA)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    // "overwrite"
+    let metadata = format!("{},conflict b3ZlcndyaXRl", NOTES_METADATA);
+    let url = create_upload(&app, 3, &metadata).await;
+    let response = app.oneshot(patch(&url, 0, "new")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let contents = std::fs::read_to_string(temp_dir.path().join("guides/notes.md")).unwrap();
+    assert_eq!(contents, "new");
+}
+
+#[tokio::test]
+async fn tus_upload_conflicting_on_completion_can_be_retried() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+    let url = create_upload(&app, 3, NOTES_METADATA).await;
+
+    // Another upload takes the name before this one completes
+    std::fs::create_dir(temp_dir.path().join("guides")).unwrap();
+    std::fs::write(temp_dir.path().join("guides/notes.md"), "# Original").unwrap();
+    let response = app.clone().oneshot(patch(&url, 0, "new")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert!(!response.headers().contains_key("upload-offset"));
+
+    let response = app
+        .clone()
+        .oneshot(tus_request("HEAD", &url).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(header(&response, "upload-offset"), "3");
+    assert_eq!(
+        leftover_temp_files(&temp_dir.path().join("guides")).len(),
+        0
+    );
+
+    let response = app
+        .clone()
+        .oneshot(
+            tus_request("PATCH", &url)
+                .header("upload-offset", 3)
+                .header("content-type", "application/offset+octet-stream")
+                .header("x-conflict-policy", "overwrite")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let contents = std::fs::read_to_string(temp_dir.path().join("guides/notes.md")).unwrap();
+    assert_eq!(contents, "new");
+    let staged = std::fs::read_dir(temp_dir.path().join(upload::STAGING_DIR)).unwrap();
+    assert_eq!(staged.count(), 0);
+}
+
+const BOUNDARY: &str = "rsmd-test-boundary";
+
+/// A `multipart/form-data` body with text fields and `(file name, contents)` parts
+fn multipart_body(fields: &[(&str, &str)], files: &[(&str, &str)]) -> Body {
+    let mut body = String::new();
+    for (name, value) in fields {
+        body.push_str(&format!(
+            "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n"
+        ));
+    }
+    for (file_name, contents) in files {
+        body.push_str(&format!(
+            "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{file_name}\"\r\nContent-Type: text/markdown\r\n\r\n{contents}\r\n"
+        ));
+    }
+    body.push_str(&format!("--{BOUNDARY}--\r\n"));
+    Body::from(body)
+}
+
+fn multipart_request(body: Body) -> Request<Body> {
+    Request::builder()
+        .method("POST")
+        .uri("/api/upload")
+        .header(
+            "content-type",
+            format!("multipart/form-data; boundary={BOUNDARY}"),
+        )
+        .header("x-requested-with", "XMLHttpRequest")
+        .body(body)
+        .unwrap()
+}
+
+/// A form posted by a browser, with the headers it adds itself
+fn form_post(body: Body, browser_headers: &[(&str, &str)]) -> Request<Body> {
+    let mut request = Request::builder()
+        .method("POST")
+        .uri("/api/upload")
+        .header("host", "localhost:3000")
+        .header(
+            "content-type",
+            format!("multipart/form-data; boundary={BOUNDARY}"),
+        )
+        .header("x-conflict-policy", "overwrite");
+    for (name, value) in browser_headers {
+        request = request.header(*name, *value);
+    }
+    request.body(body).unwrap()
+}
+
+#[tokio::test]
+async fn multipart_upload_refuses_cross_site_forms() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(temp_dir.path().join("index.md"), "# Mine").unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let refused: [&[(&str, &str)]; 4] = [
+        &[
+            ("origin", "https://evil.example"),
+            ("sec-fetch-site", "cross-site"),
+        ],
+        &[("origin", "https://evil.example")],
+        &[("origin", "null")],
+        &[],
+    ];
+    for browser_headers in refused {
+        let body = multipart_body(&[("conflict", "overwrite")], &[("index.md", "# Theirs")]);
+        let response = app
+            .clone()
+            .oneshot(form_post(body, browser_headers))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.status(),
+            StatusCode::FORBIDDEN,
+            "{browser_headers:?}"
+        );
+        assert_eq!(json_body(response).await["code"], "cross_site");
+    }
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("index.md")).unwrap(),
+        "# Mine"
+    );
+
+    let accepted: [&[(&str, &str)]; 3] = [
+        &[
+            ("origin", "http://localhost:3000"),
+            ("sec-fetch-site", "same-origin"),
+        ],
+        &[("origin", "http://localhost:3000")],
+        &[("x-requested-with", "XMLHttpRequest")],
+    ];
+    for browser_headers in accepted {
+        let body = multipart_body(&[], &[("index.md", "# Ours")]);
+        let response = app
+            .clone()
+            .oneshot(form_post(body, browser_headers))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{browser_headers:?}");
+    }
+}
+
+#[tokio::test]
+async fn multipart_upload_stores_every_file_with_folders() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let state = common::directory_state(temp_dir.path(), &[]);
+    let app = create_router(state.clone());
+
+    let body = multipart_body(
+        &[("directory", "docs")],
+        &[
+            ("intro.md", "# Intro"),
+            ("guides/setup.md", "# Setup"),
+            ("guides/deep/faq.md", "# FAQ"),
+        ],
+    );
+    let response = app.oneshot(multipart_request(body)).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let payload = json_body(response).await;
+    assert_eq!(payload["success"], true);
+    let files = payload["files"].as_array().unwrap();
+    assert_eq!(files.len(), 3);
+    assert_eq!(files[1]["name"], "guides/setup.md");
+    assert_eq!(files[1]["file"], "docs/guides/setup.md");
+    assert_eq!(files[1]["success"], true);
+    assert!(files[1]["etag"].is_string());
+
+    let base = temp_dir.path();
+    assert_eq!(
+        std::fs::read_to_string(base.join("docs/guides/deep/faq.md")).unwrap(),
+        "# FAQ"
+    );
+    let AppState::Directory { store, .. } = state.as_ref() else {
+        panic!("expected directory state");
+    };
+    assert!(store.files().get("docs/intro.md").await.is_some());
+    assert!(store.files().get("docs/guides/setup.md").await.is_some());
+}
+
+#[tokio::test]
+async fn multipart_upload_reports_each_failure() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(temp_dir.path().join("taken.md"), "# Taken").unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let body = multipart_body(
+        &[],
+        &[
+            ("ok.md", "# Fine"),
+            ("image.png", "not markdown"),
+            ("../escape/x.md", "# Escape"),
+            ("taken.md", "# Again"),
+        ],
+    );
+    let response = app.oneshot(multipart_request(body)).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+    let payload = json_body(response).await;
+    assert_eq!(payload["success"], false);
+    assert_eq!(payload["message"], "Some files could not be uploaded.");
+    let files = payload["files"].as_array().unwrap();
+    let outcomes: Vec<bool> = files
+        .iter()
+        .map(|file| file["success"].as_bool().unwrap())
+        .collect();
+    assert_eq!(outcomes, [true, false, false, false]);
+    assert_eq!(
+        files[1]["message"],
+        "Only markdown (.md) files are supported."
+    );
+    assert_eq!(files[3]["message"], "A file with this name already exists.");
+    assert!(temp_dir.path().join("ok.md").exists());
+    assert!(!temp_dir.path().join("escape").exists());
+}
+
+#[tokio::test]
+async fn multipart_upload_applies_conflict_field() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(temp_dir.path().join("taken.md"), "# Taken").unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let body = multipart_body(&[("conflict", "rename")], &[("taken.md", "# Again")]);
+    let response = app.oneshot(multipart_request(body)).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let payload = json_body(response).await;
+    assert_eq!(payload["files"][0]["file"], "taken (1).md");
+}
+
+#[tokio::test]
+async fn multipart_upload_without_files_fails() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let body = multipart_body(&[("directory", "docs")], &[]);
+    let response = app.oneshot(multipart_request(body)).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn upload_rejects_internal_directories() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let response = app
+        .oneshot(
+            upload_request("notes.md")
+                .header("x-directory-path", upload::STAGING_DIR)
+                .body(Body::from("# Hidden"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR\x00\x00\x00\x01";
+
+fn attachment_request(name: &str) -> axum::http::request::Builder {
+    Request::builder()
+        .method("POST")
+        .uri("/api/assets")
+        .header("x-file-name", name)
+}
+
+#[tokio::test]
+async fn attachment_upload_returns_snippet_and_serves_file() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let state = common::directory_state(temp_dir.path(), &[]);
+    let app = create_router(state.clone());
+
+    let response = app
+        .clone()
+        .oneshot(
+            attachment_request("img/logo.png")
+                .header("x-directory-path", "docs")
+                .body(Body::from(PNG))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let payload = json_body(response).await;
+    assert_eq!(payload["file"], "docs/img/logo.png");
+    assert_eq!(payload["snippet"], "![logo](img/logo.png)");
+    assert_eq!(
+        std::fs::read(temp_dir.path().join("docs/img/logo.png")).unwrap(),
+        PNG
+    );
+
+    // Attachments are not documents
+    let AppState::Directory { store, .. } = state.as_ref() else {
+        panic!("expected directory state");
+    };
+    assert!(store.files().get("docs/img/logo.png").await.is_none());
+
+    // A document in docs/ can embed it with the relative snippet
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/view/docs/img/logo.png")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "image/png");
+}
+
+#[tokio::test]
+async fn attachment_upload_sniffs_contents() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let response = app
+        .clone()
+        .oneshot(
+            attachment_request("logo.png")
+                .body(Body::from("<html><script>alert(1)</script></html>"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let payload = json_body(response).await;
+    assert_eq!(payload["code"], "content_mismatch");
+    assert!(!temp_dir.path().join("logo.png").exists());
+    assert!(leftover_temp_files(temp_dir.path()).is_empty());
+
+    // A real PDF renamed to .png is rejected as well
+    let response = app
+        .oneshot(
+            attachment_request("logo.png")
+                .body(Body::from("%PDF-1.7\n"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn attachment_upload_rejects_unlisted_types() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let response = app
+        .oneshot(
+            attachment_request("page.html")
+                .body(Body::from("<html></html>"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let payload = json_body(response).await;
+    assert_eq!(payload["code"], "invalid_attachment");
+    assert_eq!(payload["message"], "This file type cannot be uploaded.");
+}
+
+#[tokio::test]
+async fn attachment_multipart_upload_reports_snippets() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let mut body = Vec::new();
+    for (name, contents) in [("manual.pdf", &b"%PDF-1.4\n"[..]), ("shots/a b.png", PNG)] {
+        body.extend_from_slice(
+            format!(
+                "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{name}\"\r\n\r\n"
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(contents);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{BOUNDARY}--\r\n").as_bytes());
+
+    let mut request = multipart_request(Body::from(body));
+    *request.uri_mut() = "/api/assets".parse().unwrap();
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let payload = json_body(response).await;
+    assert_eq!(payload["files"][0]["snippet"], "[manual.pdf](manual.pdf)");
+    assert_eq!(payload["files"][1]["snippet"], "![a b](shots/a%20b.png)");
+}
+
+fn zip_archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+    use std::io::Write;
+
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for (name, contents) in files {
+        writer
+            .start_file(*name, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(contents).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+#[tokio::test]
+async fn zip_upload_unpacks_documents_and_assets() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let state = common::directory_state(temp_dir.path(), &[]);
+    let app = create_router(state.clone());
+    let archive = zip_archive(&[
+        ("guides/setup.md", b"# Setup\n\n![logo](../img/logo.png)"),
+        ("img/logo.png", PNG),
+        ("build.sh", b"#!/bin/sh"),
+    ]);
+
+    let response = app
+        .oneshot(
+            upload_request("bundle.zip")
+                .header("x-directory-path", "docs")
+                .body(Body::from(archive))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+    let payload = json_body(response).await;
+    let files = payload["files"].as_array().unwrap();
+    assert_eq!(files.len(), 3);
+    assert_eq!(files[0]["name"], "guides/setup.md");
+    assert_eq!(files[0]["file"], "docs/guides/setup.md");
+    assert_eq!(files[1]["file"], "docs/img/logo.png");
+    assert_eq!(files[2]["name"], "build.sh");
+    assert_eq!(files[2]["code"], "invalid_type");
+
+    let docs = temp_dir.path().join("docs");
+    assert_eq!(
+        std::fs::read_to_string(docs.join("guides/setup.md")).unwrap(),
+        "# Setup\n\n![logo](../img/logo.png)"
+    );
+    assert_eq!(std::fs::read(docs.join("img/logo.png")).unwrap(), PNG);
+    assert!(!docs.join("build.sh").exists());
+    assert!(leftover_temp_files(temp_dir.path()).is_empty());
+
+    let AppState::Directory { store, .. } = state.as_ref() else {
+        panic!("expected directory state");
+    };
+    assert!(store.files().get("docs/guides/setup.md").await.is_some());
+}
+
+#[tokio::test]
+async fn zip_upload_refuses_entries_escaping_directory() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+    let archive = zip_archive(&[
+        ("../escape.md", b"# Escape"),
+        ("ok/../../escape.md", b"# Escape"),
+        (".rsmd-uploads/session.md", b"# Hidden"),
+        ("safe.md", b"# Safe"),
+    ]);
+
+    let response = app
+        .oneshot(
+            upload_request("bundle.zip")
+                .header("x-directory-path", "docs")
+                .body(Body::from(archive))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+    let payload = json_body(response).await;
+    let codes: Vec<&str> = payload["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|file| file["code"].as_str().unwrap_or("stored"))
+        .collect();
+    assert_eq!(
+        codes,
+        ["invalid_path", "invalid_path", "invalid_path", "stored"]
+    );
+    assert!(!temp_dir.path().join("escape.md").exists());
+    assert!(!temp_dir.path().join(".rsmd-uploads").exists());
+    assert!(temp_dir.path().join("docs/safe.md").exists());
+}
+
+#[tokio::test]
+async fn zip_upload_rejects_unreadable_and_crowded_archives() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let response = app
+        .clone()
+        .oneshot(
+            upload_request("bundle.zip")
+                .body(Body::from("not a zip"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(json_body(response).await["code"], "invalid_archive");
+
+    let names: Vec<String> = (0..=rsmd::archive::MAX_ENTRIES)
+        .map(|i| format!("{}.md", i))
+        .collect();
+    let files: Vec<(&str, &[u8])> = names
+        .iter()
+        .map(|name| (name.as_str(), &b"x"[..]))
+        .collect();
+    let response = app
+        .oneshot(
+            upload_request("bundle.zip")
+                .body(Body::from(zip_archive(&files)))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(json_body(response).await["code"], "too_many_entries");
+    assert!(!temp_dir.path().join("0.md").exists());
+    assert!(leftover_temp_files(temp_dir.path()).is_empty());
+}
+
+#[tokio::test]
+async fn zip_upload_in_multipart_form_reports_each_entry() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(temp_dir.path().join("a.md"), "# Existing").unwrap();
+    let app = create_router(common::directory_state(temp_dir.path(), &[]));
+
+    let mut body = format!(
+        "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"bundle.zip\"\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend(zip_archive(&[("a.md", b"# New"), ("b.md", b"# B")]));
+    body.extend(format!("\r\n--{BOUNDARY}--\r\n").into_bytes());
+    let response = app
+        .oneshot(multipart_request(Body::from(body)))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+    let payload = json_body(response).await;
+    assert_eq!(payload["files"][0]["code"], "conflict");
+    assert_eq!(payload["files"][1]["file"], "b.md");
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("a.md")).unwrap(),
+        "# Existing"
+    );
+}
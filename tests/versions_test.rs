@@ -0,0 +1,365 @@
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode, header},
+};
+use gix::ObjectId;
+use gix::refs::transaction::PreviousValue;
+use rsmd::{
+    directory::FileTree,
+    i18n::Language,
+    server::{AppState, create_router},
+    store::DocumentStore,
+    versions::Versions,
+};
+use serde_json::Value;
+use std::path::Path;
+use std::sync::Arc;
+use tower::util::ServiceExt;
+
+/// Commit `files` on top of `HEAD` of the repository at `root`
+fn commit(root: &Path, files: &[(&str, &str)]) -> ObjectId {
+    let repo = gix::open(root).unwrap();
+    let parent = repo.head_id().ok().map(|id| id.detach());
+    let tree = match parent {
+        Some(id) => repo.find_commit(id).unwrap().tree_id().unwrap().detach(),
+        None => ObjectId::empty_tree(repo.object_hash()),
+    };
+    let mut editor = repo.edit_tree(tree).unwrap();
+    for (path, contents) in files {
+        let blob = repo.write_blob(contents.as_bytes()).unwrap();
+        editor
+            .upsert(*path, gix::objs::tree::EntryKind::Blob, blob)
+            .unwrap();
+    }
+    let tree = editor.write().unwrap();
+    let signature = gix::actor::Signature {
+        name: "Docs Bot".into(),
+        email: "bot@example.com".into(),
+        time: gix::date::Time::now_utc(),
+    };
+    let mut time = Default::default();
+    let signature = signature.to_ref(&mut time);
+    repo.commit_as(signature, signature, "HEAD", "Update", tree, parent)
+        .unwrap()
+        .detach()
+}
+
+fn tag(root: &Path, name: &str, id: ObjectId) {
+    let repo = gix::open(root).unwrap();
+    repo.tag_reference(name, id, PreviousValue::Any).unwrap();
+}
+
+fn branch(root: &Path, name: &str, id: ObjectId) {
+    let path = gix::open(root)
+        .unwrap()
+        .path()
+        .join("refs/heads")
+        .join(name);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, format!("{}\n", id)).unwrap();
+}
+
+/// A work tree serving `docs/`, whose `v1` tag and `HEAD` hold different
+/// editions of `guide.md` and whose files on disk hold a third
+fn work_tree() -> tempfile::TempDir {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path();
+    gix::init(root).unwrap();
+    let first = commit(
+        root,
+        &[
+            ("README.md", "# Outside\n"),
+            ("docs/guide.md", "# Guide\n\nFirst edition\n"),
+            ("docs/sub/page.md", "# Page\n"),
+            ("docs/notes.txt", "Committed notes\n"),
+        ],
+    );
+    tag(root, "v1", first);
+    let second = commit(root, &[("docs/guide.md", "# Guide\n\nSecond edition\n")]);
+    branch(root, "feature/next", second);
+
+    std::fs::create_dir_all(root.join("docs/sub")).unwrap();
+    std::fs::write(root.join("docs/guide.md"), "# Guide\n\nDraft edition\n").unwrap();
+    std::fs::write(root.join("docs/sub/page.md"), "# Page\n").unwrap();
+    temp_dir
+}
+
+fn state(dir_path: &str, store: DocumentStore) -> Arc<AppState> {
+    Arc::new(AppState::Directory {
+        dir_path: dir_path.to_string(),
+        store: Arc::new(store),
+        language: Language::English,
+    })
+}
+
+/// The files on disk at `/`, and the git refs under `/v/`
+fn work_tree_app(root: &Path) -> Router {
+    let base_dir = root.join("docs");
+    let files = Arc::new(FileTree::new(base_dir.clone()));
+    let versions = Versions::open(&base_dir, None).unwrap();
+    let store = DocumentStore::new(files).with_versions(versions);
+    create_router(state("docs", store))
+}
+
+/// `reference` at `/`, as served with `--git-ref`
+async fn ref_app(base_dir: &Path, reference: &str) -> Router {
+    let versions = Versions::open(base_dir, Some(reference.to_string())).unwrap();
+    let snapshot = versions.snapshot(reference).await.unwrap().unwrap();
+    let store = snapshot.into_store().with_versions(versions);
+    store.files().scan().await;
+    create_router(state("docs", store))
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, String) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, String::from_utf8_lossy(&bytes).into_owned())
+}
+
+async fn get(app: &Router, uri: &str) -> (StatusCode, String) {
+    send(app, Request::get(uri).body(Body::empty()).unwrap()).await
+}
+
+async fn get_json(app: &Router, uri: &str) -> Value {
+    let (status, body) = get(app, uri).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    serde_json::from_str(&body).unwrap()
+}
+
+/// Wait for the background scan of the version at `root`
+async fn scanned(app: &Router, root: &str) {
+    for _ in 0..500 {
+        if get_json(app, &format!("{}/api/scan", root)).await["complete"] == true {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("{} was not scanned", root);
+}
+
+#[tokio::test]
+async fn test_version_shows_documents_at_the_ref() {
+    let temp_dir = work_tree();
+    let app = work_tree_app(temp_dir.path());
+
+    let (status, body) = get(&app, "/view/guide.md").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Draft edition"));
+
+    let (status, body) = get(&app, "/v/v1/view/guide.md").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("First edition"));
+    let head = gix::open(temp_dir.path())
+        .unwrap()
+        .head_id()
+        .unwrap()
+        .detach();
+    let (_, body) = get(&app, &format!("/v/{}/view/guide.md", head)).await;
+    assert!(body.contains("Second edition"));
+    let (status, body) = get(&app, "/v/feature%2Fnext/view/guide.md").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Second edition"));
+
+    // Only commit ids, branches and tags
+    for reference in ["HEAD", "HEAD~1", "v1%5E%7Bcommit%7D", "%3A%2FGuide"] {
+        let uri = format!("/v/{}/view/guide.md", reference);
+        assert_eq!(get(&app, &uri).await.0, StatusCode::NOT_FOUND, "{}", uri);
+    }
+
+    // Only the served directory is in the version
+    scanned(&app, "/v/v1").await;
+    let files = get_json(&app, "/v/v1/api/files").await;
+    assert_eq!(
+        files["files"],
+        serde_json::json!(["guide.md", "sub/page.md"])
+    );
+    let markdown = get_json(&app, "/v/v1/api/markdown/sub/page.md").await;
+    assert_eq!(markdown["markdown"], "# Page\n");
+}
+
+#[tokio::test]
+async fn test_version_pages_link_within_the_version() {
+    let temp_dir = work_tree();
+    let app = work_tree_app(temp_dir.path());
+
+    let (status, body) = get(&app, "/v/v1/").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains(r#"href="/v/v1/view/guide.md""#));
+    assert!(body.contains(r#"href="/v/v1/dir/sub""#));
+    assert!(!body.contains(r#"id="upload-area""#));
+
+    let (_, body) = get(&app, "/v/v1/view/sub/page.md").await;
+    assert!(body.contains(r#"data-root="/v/v1""#));
+    assert!(body.contains(r#"<a href="/v/v1/">"#));
+    assert!(body.contains(r#"<a href="/v/v1/dir/sub">"#));
+    assert!(!body.contains(r#"class="doc-edit""#));
+
+    // The work tree links as before
+    let (_, body) = get(&app, "/view/sub/page.md").await;
+    assert!(!body.contains("data-root"));
+    assert!(body.contains(r#"<a href="/dir/sub">"#));
+    assert!(body.contains(r#"class="doc-edit""#));
+}
+
+#[tokio::test]
+async fn test_version_switcher_lists_branches_and_tags() {
+    let temp_dir = work_tree();
+    let app = work_tree_app(temp_dir.path());
+
+    let (_, body) = get(&app, "/view/guide.md").await;
+    assert!(body.contains(r#"<option value="/" selected>Working copy</option>"#));
+    assert!(body.contains(r#"<option value="/v/v1/">v1</option>"#));
+    assert!(body.contains(r#"<option value="/v/feature%2Fnext/">feature/next</option>"#));
+
+    let (_, body) = get(&app, "/v/v1/view/guide.md").await;
+    assert!(body.contains(r#"<option value="/">Working copy</option>"#));
+    assert!(body.contains(r#"<option value="/v/v1/" selected>v1</option>"#));
+
+    // Commits that are neither get an entry of their own
+    let head = gix::open(temp_dir.path())
+        .unwrap()
+        .head_id()
+        .unwrap()
+        .detach();
+    let short = &head.to_string()[..7];
+    let (_, body) = get(&app, &format!("/v/{}/", short)).await;
+    assert!(body.contains(&format!(
+        r#"<option value="/v/{0}/" selected>{0}</option>"#,
+        short
+    )));
+}
+
+#[tokio::test]
+async fn test_versions_are_read_only() {
+    let temp_dir = work_tree();
+    let app = work_tree_app(temp_dir.path());
+
+    let request = Request::put("/v/v1/api/markdown/guide.md")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r##"{"markdown":"# Changed\n"}"##))
+        .unwrap();
+    assert_eq!(send(&app, request).await.0, StatusCode::METHOD_NOT_ALLOWED);
+    let request = Request::post("/v/v1/api/upload")
+        .header("x-file-name", "new.md")
+        .body(Body::from("# New\n"))
+        .unwrap();
+    assert_eq!(send(&app, request).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(
+        get(&app, "/v/v1/edit/guide.md").await.0,
+        StatusCode::NOT_FOUND
+    );
+    assert!(
+        std::fs::read_to_string(temp_dir.path().join("docs/guide.md"))
+            .unwrap()
+            .contains("Draft edition")
+    );
+}
+
+#[tokio::test]
+async fn test_unknown_versions_and_files_are_not_found() {
+    let temp_dir = work_tree();
+    let app = work_tree_app(temp_dir.path());
+
+    let (status, body) = get(&app, "/v/v9/view/guide.md").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body.contains("<!DOCTYPE html>"));
+    let (status, body) = get(&app, "/v/v1/api/markdown/missing.md").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(
+        serde_json::from_str::<Value>(&body).unwrap()["code"],
+        "not_found"
+    );
+    assert_eq!(
+        get(&app, "/v/v1/static/.rsmd/secret").await.0,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn test_version_static_files_come_from_git() {
+    let temp_dir = work_tree();
+    let app = work_tree_app(temp_dir.path());
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::get("/v/v1/static/notes.txt")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(
+        response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain")
+    );
+    assert!(response.headers().contains_key(header::ETAG));
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body[..], b"Committed notes\n");
+
+    // Not on disk, so the work tree has no such file
+    assert_eq!(
+        get(&app, "/static/notes.txt").await.0,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn test_git_ref_serves_a_bare_repository() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path();
+    gix::init_bare(root).unwrap();
+    let first = commit(root, &[("guide.md", "# Guide\n\nFirst edition\n")]);
+    tag(root, "v2.1", first);
+    let second = commit(root, &[("guide.md", "# Guide\n\nSecond edition\n")]);
+
+    let app = ref_app(root, "v2.1").await;
+    let (status, body) = get(&app, "/").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains(r#"href="/view/guide.md""#));
+    assert!(!body.contains(r#"id="upload-area""#));
+
+    let (_, body) = get(&app, "/view/guide.md").await;
+    assert!(body.contains("First edition"));
+    assert!(!body.contains(r#"class="doc-edit""#));
+    assert!(body.contains(r#"<option value="/" selected>v2.1</option>"#));
+    let (status, body) = get(&app, &format!("/v/{}/view/guide.md", second)).await;
+    assert!(body.contains("Second edition"), "{} {}", status, body);
+
+    let scan = get_json(&app, "/api/scan").await;
+    assert_eq!(scan["complete"], true);
+    assert_eq!(scan["files_found"], 1);
+
+    let request = Request::put("/api/markdown/guide.md")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r##"{"markdown":"# Changed\n"}"##))
+        .unwrap();
+    assert_eq!(send(&app, request).await.0, StatusCode::METHOD_NOT_ALLOWED);
+    let request = Request::delete("/api/files/guide.md")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(send(&app, request).await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_git_ref_documents_are_cached() {
+    let temp_dir = work_tree();
+    let app = ref_app(&temp_dir.path().join("docs"), "v1").await;
+
+    let (_, first) = get(&app, "/view/guide.md").await;
+    let (_, second) = get(&app, "/view/guide.md").await;
+    assert_eq!(first, second);
+    let stats = get_json(&app, "/api/cache").await;
+    assert_eq!(stats["misses"], 1);
+    assert_eq!(stats["hits"], 1);
+    assert_eq!(stats["stale"], 0);
+}